@@ -1,22 +1,108 @@
-use std::env;
+use std::{
+    collections::HashMap,
+    env,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use askama::Template;
 use axum::{
+    extract::State,
     http::StatusCode,
     response::{Html, IntoResponse},
     routing::get,
     Json, Router,
 };
 use axum_extra::extract::CookieJar;
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
 use serde::Serialize;
+use tokio::sync::RwLock;
 use tower_http::services::ServeDir;
 
+// How long to trust the fetched JWKS for, so keys the auth service retires stop being accepted
+const JWKS_MAX_AGE: Duration = Duration::from_secs(300);
+
+// The least time between fetches of the JWKS, so tokens with made-up `kid`s can't have us
+// fetch it on every request
+const JWKS_MIN_REFETCH_INTERVAL: Duration = Duration::from_secs(30);
+
+// How long a token the auth service has confirmed is still accepted without asking again, so
+// a token banned by logout, a password reset or an ended session is turned away soon after
+const REVOCATION_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Clone)]
+struct AppState {
+    // The auth service's public signing keys, fetched on first use and again whenever they
+    // are stale or a token is signed with a key we haven't seen yet
+    jwks: Arc<RwLock<CachedJwks>>,
+    jwks_url: String,
+    // When the auth service last confirmed each token hadn't been revoked
+    confirmed_tokens: Arc<RwLock<HashMap<String, Instant>>>,
+    verify_token_url: String,
+    issuer: String,
+}
+
+impl AppState {
+    fn new(jwks_url: String, verify_token_url: String, issuer: String) -> Self {
+        Self {
+            jwks: Arc::new(RwLock::new(CachedJwks::default())),
+            jwks_url,
+            confirmed_tokens: Arc::new(RwLock::new(HashMap::new())),
+            verify_token_url,
+            issuer,
+        }
+    }
+}
+
+#[derive(Default)]
+struct CachedJwks {
+    keys: Vec<Jwk>,
+    fetched_at: Option<Instant>,
+    // Failed fetches count towards the refetch interval too
+    last_attempt_at: Option<Instant>,
+}
+
+impl CachedJwks {
+    fn is_fresh(&self) -> bool {
+        self.fetched_at
+            .is_some_and(|fetched_at| fetched_at.elapsed() < JWKS_MAX_AGE)
+    }
+
+    fn can_refetch(&self) -> bool {
+        self.last_attempt_at
+            .is_none_or(|attempt_at| attempt_at.elapsed() >= JWKS_MIN_REFETCH_INTERVAL)
+    }
+
+    fn find(&self, kid: &str) -> Option<Jwk> {
+        self.keys
+            .iter()
+            .find(|jwk| jwk.common.key_id.as_deref() == Some(kid))
+            .cloned()
+    }
+}
+
 #[tokio::main]
 async fn main() {
+    // Tokens signed with a published key are only trusted if they come from our auth service
+    let issuer = env::var("JWT_ISSUER")
+        .ok()
+        .filter(|issuer| !issuer.is_empty())
+        .expect("JWT_ISSUER must be set to the issuer the auth service puts in its tokens");
+
     let app = Router::new()
         .nest_service("/assets", ServeDir::new("assets"))
         .route("/", get(root))
-        .route("/protected", get(protected));
+        .route("/protected", get(protected))
+        .with_state(AppState::new(
+            auth_service_url("/.well-known/jwks.json"),
+            auth_service_url("/verify-token"),
+            issuer,
+        ));
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8000").await.unwrap();
 
@@ -46,7 +132,7 @@ async fn root() -> impl IntoResponse {
     Html(template.render().unwrap())
 }
 
-async fn protected(jar: CookieJar) -> impl IntoResponse {
+async fn protected(State(state): State<AppState>, jar: CookieJar) -> impl IntoResponse {
     let jwt_cookie = match jar.get("jwt") {
         Some(cookie) => cookie,
         None => {
@@ -54,31 +140,142 @@ async fn protected(jar: CookieJar) -> impl IntoResponse {
         }
     };
 
-    let api_client = reqwest::Client::builder().build().unwrap();
+    let header = match decode_header(jwt_cookie.value()) {
+        Ok(header) => header,
+        Err(_) => {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+    };
 
-    let verify_token_body = serde_json::json!({
-        "token": &jwt_cookie.value(),
-    });
+    // Tokens with a `kid` are signed with a published key and can be checked locally, though
+    // only the auth service knows whether they have been revoked since. Tokens signed with
+    // the auth service's shared secret have to be sent back to it.
+    let verified = match header.kid {
+        Some(kid) => match verify_token_with_jwks(&state, jwt_cookie.value(), &kid).await {
+            Ok(true) => check_token_not_revoked(&state, jwt_cookie.value()).await,
+            other => other,
+        },
+        None => verify_token_with_auth_service(&state, jwt_cookie.value()).await,
+    };
 
+    match verified {
+        Ok(true) => Json(ProtectedRouteResponse {
+            img_url: "https://i.ibb.co/bYJLkPW/Gary-Smith-LGR-cert.png".to_owned(),
+        })
+        .into_response(),
+        Ok(false) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+fn auth_service_url(path: &str) -> String {
     let auth_hostname = env::var("AUTH_SERVICE_HOST_NAME").unwrap_or("0.0.0.0".to_owned());
-    let url = format!("http://{}:3000/verify-token", auth_hostname);
+    format!("http://{}:3000{}", auth_hostname, path)
+}
 
-    let response = match api_client.post(&url).json(&verify_token_body).send().await {
-        Ok(response) => response,
-        Err(_) => {
-            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+async fn verify_token_with_jwks(
+    state: &AppState,
+    token: &str,
+    kid: &str,
+) -> Result<bool, reqwest::Error> {
+    let mut jwk = {
+        let jwks = state.jwks.read().await;
+        jwks.is_fresh().then(|| jwks.find(kid)).flatten()
+    };
+
+    // The auth service may have rotated its keys since we last fetched the JWKS. Unknown keys
+    // are turned away until the refetch interval has passed.
+    if jwk.is_none() {
+        let mut cached = state.jwks.write().await;
+        jwk = cached.is_fresh().then(|| cached.find(kid)).flatten();
+
+        if jwk.is_none() && cached.can_refetch() {
+            cached.last_attempt_at = Some(Instant::now());
+            let jwks = reqwest::get(&state.jwks_url)
+                .await?
+                .error_for_status()?
+                .json::<JwkSet>()
+                .await?;
+            cached.keys = jwks.keys;
+            cached.fetched_at = cached.last_attempt_at;
+            jwk = cached.find(kid);
         }
+    }
+
+    let jwk = match jwk {
+        Some(jwk) => jwk,
+        None => return Ok(false),
+    };
+
+    // Only accept the algorithm the key is published for, not whatever the token claims
+    let algorithm = match jwk
+        .common
+        .key_algorithm
+        .and_then(|alg| Algorithm::from_str(&alg.to_string()).ok())
+    {
+        Some(algorithm) => algorithm,
+        None => return Ok(false),
+    };
+
+    let decoding_key = match DecodingKey::from_jwk(&jwk) {
+        Ok(decoding_key) => decoding_key,
+        Err(_) => return Ok(false),
     };
 
+    // Tokens are only for us if they name us as their audience and our auth service as their
+    // issuer
+    let mut validation = Validation::new(algorithm);
+    let audience = env::var("JWT_AUDIENCE").unwrap_or("app-service".to_owned());
+    validation.set_audience(&[audience]);
+    validation.set_issuer(&[&state.issuer]);
+
+    Ok(decode::<serde_json::Value>(token, &decoding_key, &validation).is_ok())
+}
+
+async fn check_token_not_revoked(state: &AppState, token: &str) -> Result<bool, reqwest::Error> {
+    let recently_confirmed = state
+        .confirmed_tokens
+        .read()
+        .await
+        .get(token)
+        .is_some_and(|confirmed_at| confirmed_at.elapsed() < REVOCATION_CHECK_INTERVAL);
+    if recently_confirmed {
+        return Ok(true);
+    }
+
+    let verified = verify_token_with_auth_service(state, token).await?;
+
+    let mut confirmed_tokens = state.confirmed_tokens.write().await;
+    confirmed_tokens.retain(|_, confirmed_at| confirmed_at.elapsed() < REVOCATION_CHECK_INTERVAL);
+    if verified {
+        confirmed_tokens.insert(token.to_owned(), Instant::now());
+    } else {
+        confirmed_tokens.remove(token);
+    }
+
+    Ok(verified)
+}
+
+async fn verify_token_with_auth_service(
+    state: &AppState,
+    token: &str,
+) -> Result<bool, reqwest::Error> {
+    let api_client = reqwest::Client::builder().build().unwrap();
+
+    let verify_token_body = serde_json::json!({
+        "token": token,
+    });
+
+    let response = api_client
+        .post(&state.verify_token_url)
+        .json(&verify_token_body)
+        .send()
+        .await?;
+
     match response.status() {
-        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::BAD_REQUEST => {
-            StatusCode::UNAUTHORIZED.into_response()
-        }
-        reqwest::StatusCode::OK => Json(ProtectedRouteResponse {
-            img_url: "https://i.ibb.co/bYJLkPW/Gary-Smith-LGR-cert.png".to_owned(),
-        })
-        .into_response(),
-        _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::BAD_REQUEST => Ok(false),
+        reqwest::StatusCode::OK => Ok(true),
+        _ => response.error_for_status().map(|_| false),
     }
 }
 
@@ -86,3 +283,163 @@ async fn protected(jar: CookieJar) -> impl IntoResponse {
 pub struct ProtectedRouteResponse {
     pub img_url: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    use jsonwebtoken::{encode, EncodingKey, Header};
+
+    use super::*;
+
+    const ISSUER: &str = "https://auth.example.com";
+
+    // Stands in for the auth service: serves a JWKS with one shared key and answers
+    // `/verify-token` according to `revoked`, counting how many times each is called
+    struct AuthService {
+        url: String,
+        jwks_fetches: Arc<AtomicUsize>,
+        verify_calls: Arc<AtomicUsize>,
+        revoked: Arc<AtomicBool>,
+    }
+
+    impl AuthService {
+        fn state(&self) -> AppState {
+            AppState::new(
+                format!("{}/.well-known/jwks.json", self.url),
+                format!("{}/verify-token", self.url),
+                ISSUER.to_owned(),
+            )
+        }
+    }
+
+    async fn spawn_auth_service() -> AuthService {
+        let jwks_fetches = Arc::new(AtomicUsize::new(0));
+        let verify_calls = Arc::new(AtomicUsize::new(0));
+        let revoked = Arc::new(AtomicBool::new(false));
+
+        let app = Router::new()
+            .route(
+                "/.well-known/jwks.json",
+                get({
+                    let jwks_fetches = jwks_fetches.clone();
+                    move || async move {
+                        jwks_fetches.fetch_add(1, Ordering::SeqCst);
+                        Json(serde_json::json!({
+                            "keys": [{ "kty": "oct", "kid": "test-kid", "alg": "HS256", "k": "c2VjcmV0" }]
+                        }))
+                    }
+                }),
+            )
+            .route(
+                "/verify-token",
+                axum::routing::post({
+                    let verify_calls = verify_calls.clone();
+                    let revoked = revoked.clone();
+                    move || async move {
+                        verify_calls.fetch_add(1, Ordering::SeqCst);
+                        if revoked.load(Ordering::SeqCst) {
+                            StatusCode::UNAUTHORIZED
+                        } else {
+                            StatusCode::OK
+                        }
+                    }
+                }),
+            );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        AuthService {
+            url: format!("http://{}", address),
+            jwks_fetches,
+            verify_calls,
+            revoked,
+        }
+    }
+
+    fn sign_token(issuer: &str) -> String {
+        let exp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 600;
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("test-kid".to_owned());
+        let claims = serde_json::json!({ "aud": "app-service", "iss": issuer, "exp": exp });
+        encode(&header, &claims, &EncodingKey::from_secret(b"secret")).unwrap()
+    }
+
+    async fn get_protected(state: &AppState, token: &str) -> StatusCode {
+        let jar = CookieJar::new().add(axum_extra::extract::cookie::Cookie::new(
+            "jwt",
+            token.to_owned(),
+        ));
+        protected(State(state.clone()), jar)
+            .await
+            .into_response()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn unknown_kids_are_rejected_without_refetching() {
+        let auth_service = spawn_auth_service().await;
+        let state = auth_service.state();
+
+        for kid in ["unknown-kid-1", "unknown-kid-2"] {
+            let verified = verify_token_with_jwks(&state, "token", kid).await.unwrap();
+            assert!(!verified);
+        }
+
+        assert_eq!(auth_service.jwks_fetches.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn tokens_from_another_issuer_are_rejected() {
+        let auth_service = spawn_auth_service().await;
+        let state = auth_service.state();
+
+        let token = sign_token("https://somewhere-else.example.com");
+
+        assert_eq!(
+            get_protected(&state, &token).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(auth_service.verify_calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn revocation_is_checked_with_the_auth_service_and_cached_briefly() {
+        let auth_service = spawn_auth_service().await;
+        let state = auth_service.state();
+
+        let token = sign_token(ISSUER);
+
+        assert_eq!(get_protected(&state, &token).await, StatusCode::OK);
+        assert_eq!(get_protected(&state, &token).await, StatusCode::OK);
+        assert_eq!(auth_service.verify_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn revoked_tokens_are_rejected() {
+        let auth_service = spawn_auth_service().await;
+        let state = auth_service.state();
+        auth_service.revoked.store(true, Ordering::SeqCst);
+
+        let token = sign_token(ISSUER);
+
+        assert_eq!(
+            get_protected(&state, &token).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            get_protected(&state, &token).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(auth_service.verify_calls.load(Ordering::SeqCst), 2);
+    }
+}
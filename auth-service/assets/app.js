@@ -1,33 +1,57 @@
 const loginSection = document.getElementById("login-section");
 const twoFASection = document.getElementById("2fa-section");
 const signupSection = document.getElementById("signup-section");
+const resetRequestSection = document.getElementById("reset-request-section");
+const resetSection = document.getElementById("reset-section");
 
 const signupLink = document.getElementById("signup-link");
 const twoFALoginLink = document.getElementById("2fa-login-link");
 const signupLoginLink = document.getElementById("signup-login-link");
+const resetRequestLink = document.getElementById("reset-request-link");
+const resetRequestLoginLink = document.getElementById("reset-request-login-link");
+const resetNewLink = document.getElementById("reset-new-link");
+
+// Only one section is shown at a time
+function showSection(section) {
+    for (const other of [loginSection, twoFASection, signupSection, resetRequestSection, resetSection]) {
+        other.style.display = other === section ? "block" : "none";
+    }
+}
 
 signupLink.addEventListener("click", (e) => {
     e.preventDefault();
 
-    loginSection.style.display = "none";
-    twoFASection.style.display = "none";
-    signupSection.style.display = "block";
+    showSection(signupSection);
 });
 
 twoFALoginLink.addEventListener("click", (e) => {
     e.preventDefault();
 
-    loginSection.style.display = "block";
-    twoFASection.style.display = "none";
-    signupSection.style.display = "none";
+    showSection(loginSection);
 });
 
 signupLoginLink.addEventListener("click", (e) => {
     e.preventDefault();
 
-    loginSection.style.display = "block";
-    twoFASection.style.display = "none";
-    signupSection.style.display = "none";
+    showSection(loginSection);
+});
+
+resetRequestLink.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    showSection(resetRequestSection);
+});
+
+resetRequestLoginLink.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    showSection(loginSection);
+});
+
+resetNewLink.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    showSection(resetRequestSection);
 });
 
 // -----------------------------------------------------
@@ -58,9 +82,7 @@ loginButton.addEventListener("click", (e) => {
             loginForm.email.value = "";
             loginForm.password.value = "";
 
-            loginSection.style.display = "none";
-            twoFASection.style.display = "block";
-            signupSection.style.display = "none";
+            showSection(twoFASection);
             loginErrAlter.style.display = "none";
         } else if (response.status === 200) {
             loginForm.email.value = "";
@@ -105,9 +127,7 @@ signupButton.addEventListener("click", (e) => {
             signupForm.twoFA.checked = false;
             signupErrAlter.style.display = "none";
             alert("You have successfully created a user.");
-            loginSection.style.display = "block";
-            twoFASection.style.display = "none";
-            signupSection.style.display = "none";
+            showSection(loginSection);
         } else {
             response.json().then(data => {
                 let error_msg = data.error;
@@ -146,9 +166,7 @@ TwoFAButton.addEventListener("click", (e) => {
             TwoFAForm.login_attempt_id.value = "";
             TwoFAErrAlter.style.display = "none";
             alert("You have successfully logged in.");
-            loginSection.style.display = "block";
-            twoFASection.style.display = "none";
-            signupSection.style.display = "none";
+            showSection(loginSection);
         } else {
             response.json().then(data => {
                 let error_msg = data.error;
@@ -161,4 +179,85 @@ TwoFAButton.addEventListener("click", (e) => {
             });
         }
     });
-});
\ No newline at end of file
+});
+
+const resetRequestForm = document.getElementById("reset-request-form");
+const resetRequestButton = document.getElementById("reset-request-form-submit");
+const resetRequestErrAlter = document.getElementById("reset-request-err-alert");
+
+resetRequestButton.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    const email = resetRequestForm.email.value;
+
+    fetch('/password-reset/request', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ email }),
+    }).then(response => {
+        if (response.ok) {
+            resetRequestForm.email.value = "";
+            resetRequestErrAlter.style.display = "none";
+            response.json().then(data => alert(data.message));
+            showSection(loginSection);
+        } else {
+            response.json().then(data => {
+                let error_msg = data.error;
+                if (error_msg !== undefined && error_msg !== null && error_msg !== "") {
+                    resetRequestErrAlter.innerHTML = `<span><strong>Error: </strong>${error_msg}</span>`;
+                    resetRequestErrAlter.style.display = "block";
+                } else {
+                    resetRequestErrAlter.style.display = "none";
+                }
+            });
+        }
+    });
+});
+
+const resetForm = document.getElementById("reset-form");
+const resetButton = document.getElementById("reset-form-submit");
+const resetErrAlter = document.getElementById("reset-err-alert");
+
+resetButton.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    const token = resetForm.token.value;
+    const password = resetForm.password.value;
+
+    fetch('/password-reset/confirm', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ token, password }),
+    }).then(response => {
+        if (response.ok) {
+            resetForm.token.value = "";
+            resetForm.password.value = "";
+            resetErrAlter.style.display = "none";
+            alert("Your password has been reset. Log in with your new password.");
+            showSection(loginSection);
+        } else {
+            response.json().then(data => {
+                let error_msg = data.error;
+                if (error_msg !== undefined && error_msg !== null && error_msg !== "") {
+                    resetErrAlter.innerHTML = `<span><strong>Error: </strong>${error_msg}</span>`;
+                    resetErrAlter.style.display = "block";
+                } else {
+                    resetErrAlter.style.display = "none";
+                }
+            });
+        }
+    });
+});
+
+// Password reset emails link here with the token in `reset_token`. It's taken out of the address
+// bar straight away, so it doesn't linger in the browser history.
+const resetToken = new URLSearchParams(window.location.search).get("reset_token");
+if (resetToken) {
+    resetForm.token.value = resetToken;
+    window.history.replaceState(null, "", window.location.pathname);
+    showSection(resetSection);
+}
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- Existing accounts predate email verification, so they are treated as verified
+alter table users add column if not exists verified boolean not null default true;
+alter table users alter column verified set default false;
@@ -0,0 +1,11 @@
+-- Add up migration script here
+create table if not exists totp_secrets (
+    email text not null primary key references users (email) on delete cascade on update cascade,
+    -- base64 encoded AES-256-GCM nonce followed by the ciphertext of the base32 secret
+    encrypted_secret text not null,
+    confirmed boolean not null default false,
+    -- the most recent time step a code was accepted for, to stop codes being replayed
+    last_used_step bigint,
+    created_at timestamptz not null default now(),
+    updated_at timestamptz not null default now()
+);
@@ -0,0 +1,10 @@
+-- Add up migration script here
+create table if not exists recovery_codes (
+    id bigserial primary key,
+    email text not null references users (email) on delete cascade on update cascade,
+    -- Argon2 hash of the code - the codes themselves are only ever shown to the user
+    code_hash text not null,
+    created_at timestamptz not null default now()
+);
+
+create index if not exists recovery_codes_email_idx on recovery_codes (email);
@@ -0,0 +1,13 @@
+-- Add up migration script here
+create table if not exists webauthn_credentials (
+    credential_id bytea not null primary key,
+    email text not null references users (email) on delete cascade on update cascade,
+    -- uncompressed SEC1 encoding of the ES256 (P-256) public key
+    public_key bytea not null,
+    -- the authenticator's signature counter, which must increase with every login
+    sign_count bigint not null default 0,
+    created_at timestamptz not null default now(),
+    last_used_at timestamptz
+);
+
+create index if not exists webauthn_credentials_email_idx on webauthn_credentials (email);
@@ -0,0 +1,14 @@
+-- Add up migration script here
+create table if not exists sessions (
+    -- shared with the session's refresh token family
+    id text not null primary key,
+    email text not null references users (email) on delete cascade on update cascade,
+    -- the jti of the auth token most recently issued to the session
+    token_id text not null,
+    created_at timestamptz not null default now(),
+    last_seen timestamptz not null default now(),
+    ip_address text,
+    user_agent text
+);
+
+create index if not exists sessions_email_idx on sessions (email);
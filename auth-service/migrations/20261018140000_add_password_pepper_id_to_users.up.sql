@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- The pepper the password hash was made with, if any - existing hashes are only salted
+alter table users add column if not exists password_pepper_id text;
@@ -0,0 +1,39 @@
+-- Add down migration script here
+alter table totp_secrets drop constraint if exists totp_secrets_user_id_fkey;
+alter table recovery_codes drop constraint if exists recovery_codes_user_id_fkey;
+alter table webauthn_credentials drop constraint if exists webauthn_credentials_user_id_fkey;
+alter table sessions drop constraint if exists sessions_user_id_fkey;
+
+alter table users drop constraint if exists users_email_key;
+alter table users drop constraint if exists users_pkey;
+alter table users add primary key (email);
+
+alter table totp_secrets add column email text;
+update totp_secrets set email = users.email from users where users.id = totp_secrets.user_id;
+alter table totp_secrets drop column user_id;
+alter table totp_secrets alter column email set not null;
+alter table totp_secrets add primary key (email);
+alter table totp_secrets add foreign key (email) references users (email) on delete cascade on update cascade;
+
+alter table recovery_codes add column email text;
+update recovery_codes set email = users.email from users where users.id = recovery_codes.user_id;
+alter table recovery_codes drop column user_id;
+alter table recovery_codes alter column email set not null;
+alter table recovery_codes add foreign key (email) references users (email) on delete cascade on update cascade;
+create index if not exists recovery_codes_email_idx on recovery_codes (email);
+
+alter table webauthn_credentials add column email text;
+update webauthn_credentials set email = users.email from users where users.id = webauthn_credentials.user_id;
+alter table webauthn_credentials drop column user_id;
+alter table webauthn_credentials alter column email set not null;
+alter table webauthn_credentials add foreign key (email) references users (email) on delete cascade on update cascade;
+create index if not exists webauthn_credentials_email_idx on webauthn_credentials (email);
+
+alter table sessions add column email text;
+update sessions set email = users.email from users where users.id = sessions.user_id;
+alter table sessions drop column user_id;
+alter table sessions alter column email set not null;
+alter table sessions add foreign key (email) references users (email) on delete cascade on update cascade;
+create index if not exists sessions_email_idx on sessions (email);
+
+alter table users drop column if exists id;
@@ -0,0 +1,38 @@
+-- Add up migration script here
+-- Users are identified by a generated id rather than their email address, which can change
+alter table users add column if not exists id uuid not null default gen_random_uuid();
+
+-- Everything stored about a user is keyed on their id instead of their email address
+alter table totp_secrets add column user_id uuid;
+update totp_secrets set user_id = users.id from users where users.email = totp_secrets.email;
+alter table totp_secrets drop column email;
+alter table totp_secrets alter column user_id set not null;
+alter table totp_secrets add primary key (user_id);
+
+alter table recovery_codes add column user_id uuid;
+update recovery_codes set user_id = users.id from users where users.email = recovery_codes.email;
+alter table recovery_codes drop column email;
+alter table recovery_codes alter column user_id set not null;
+create index if not exists recovery_codes_user_id_idx on recovery_codes (user_id);
+
+alter table webauthn_credentials add column user_id uuid;
+update webauthn_credentials set user_id = users.id from users where users.email = webauthn_credentials.email;
+alter table webauthn_credentials drop column email;
+alter table webauthn_credentials alter column user_id set not null;
+create index if not exists webauthn_credentials_user_id_idx on webauthn_credentials (user_id);
+
+alter table sessions add column user_id uuid;
+update sessions set user_id = users.id from users where users.email = sessions.email;
+alter table sessions drop column email;
+alter table sessions alter column user_id set not null;
+create index if not exists sessions_user_id_idx on sessions (user_id);
+
+-- Nothing references the email address any more, so it only has to be unique
+alter table users drop constraint users_pkey;
+alter table users add primary key (id);
+alter table users add constraint users_email_key unique (email);
+
+alter table totp_secrets add foreign key (user_id) references users (id) on delete cascade;
+alter table recovery_codes add foreign key (user_id) references users (id) on delete cascade;
+alter table webauthn_credentials add foreign key (user_id) references users (id) on delete cascade;
+alter table sessions add foreign key (user_id) references users (id) on delete cascade;
@@ -0,0 +1,37 @@
+use std::time::Duration;
+
+use auth_service::utils::{
+    constants::PASSWORD_HASH_PARAMS, password_hashing::recommend_argon2_params,
+};
+
+const DEFAULT_TARGET_MILLIS: u64 = 250;
+
+// Recommend Argon2 parameters for hashing a password in about the given number of milliseconds on
+// this host, e.g. `cargo run --release --bin argon2-params -- 500`. Run it on the same kind of host
+// as the service, as the result depends on its CPU and memory.
+fn main() -> color_eyre::Result<()> {
+    color_eyre::install()?;
+
+    let target_millis = match std::env::args().nth(1) {
+        Some(millis) => millis
+            .parse()
+            .map_err(|_| color_eyre::eyre::eyre!("the target must be a number of milliseconds"))?,
+        None => DEFAULT_TARGET_MILLIS,
+    };
+    let current = &*PASSWORD_HASH_PARAMS;
+
+    println!(
+        "Finding Argon2id parameters for {}ms with a parallelism of {}...",
+        target_millis,
+        current.p_cost()
+    );
+    let (params, elapsed) =
+        recommend_argon2_params(Duration::from_millis(target_millis), current.p_cost())?;
+
+    println!("Hashing took {}ms with:\n", elapsed.as_millis());
+    println!("ARGON2_MEMORY_KIB={}", params.m_cost());
+    println!("ARGON2_ITERATIONS={}", params.t_cost());
+    println!("ARGON2_PARALLELISM={}", params.p_cost());
+
+    Ok(())
+}
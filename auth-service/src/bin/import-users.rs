@@ -0,0 +1,47 @@
+use auth_service::{
+    get_postgres_pool,
+    services::PostgresUserStore,
+    utils::{
+        constants::{DATABASE_URL, PASSWORD_HASH_PARAMS, PASSWORD_PEPPERS},
+        user_import::{parse_user_import, UserImportFormat},
+    },
+};
+use color_eyre::eyre::{eyre, Context};
+
+// Import users from another system into the database in DATABASE_URL, e.g.
+// `cargo run --release --bin import-users -- users.csv`. Every user in the file is checked before
+// any are imported, and the import is all or nothing.
+#[tokio::main]
+async fn main() -> color_eyre::Result<()> {
+    color_eyre::install()?;
+
+    let path = std::env::args()
+        .nth(1)
+        .ok_or_else(|| eyre!("usage: import-users <users.csv|users.jsonl>"))?;
+    let format = UserImportFormat::from_path(&path)?;
+    let contents =
+        std::fs::read_to_string(&path).wrap_err_with(|| format!("failed to read {}", path))?;
+    let users = parse_user_import(&contents, format)?;
+
+    let pg_pool = get_postgres_pool(&DATABASE_URL)
+        .await
+        .wrap_err("failed to connect to Postgres")?;
+    sqlx::migrate!("./migrations")
+        .run(&pg_pool)
+        .await
+        .wrap_err("failed to migrate the database")?;
+
+    let mut user_store = PostgresUserStore::new(
+        pg_pool,
+        PASSWORD_HASH_PARAMS.clone(),
+        PASSWORD_PEPPERS.clone(),
+    );
+    let summary = user_store.import_users(&users).await?;
+
+    println!(
+        "Imported {} users, skipped {} that already have an account",
+        summary.imported, summary.skipped
+    );
+
+    Ok(())
+}
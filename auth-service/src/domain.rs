@@ -3,7 +3,9 @@ pub mod email;
 pub mod email_client;
 pub mod error;
 pub mod password;
+pub mod password_policy;
 pub mod user;
+pub mod user_id;
 
 // re-export items from sub-modules
 pub use app_state::*;
@@ -11,4 +13,6 @@ pub use email::*;
 pub use email_client::*;
 pub use error::*;
 pub use password::*;
+pub use password_policy::*;
 pub use user::*;
+pub use user_id::*;
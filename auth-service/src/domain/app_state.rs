@@ -1,15 +1,33 @@
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-use crate::domain::EmailClient;
-use crate::services::{BannedTokenStore, TwoFACodeStore, UserStore};
+use crate::domain::{EmailClient, PasswordPolicy};
+use crate::services::{
+    BannedTokenStore, ConfirmationCodeStore, EmailChangeStore, EmailVerificationTokenStore,
+    LoginAttemptStore, PasswordResetTokenStore, RecoveryCodeStore, RefreshTokenStore, SessionStore,
+    TotpSecretStore, TwoFACodeStore, UserStore, WebAuthnChallengeStore, WebAuthnCredentialStore,
+};
+use crate::utils::constants::{ENUMERATION_SAFE_MODE, PASSWORD_POLICY};
 
 // Using a type alias to improve readability!
 pub type UserStoreType = Arc<RwLock<dyn UserStore + Send + Sync>>;
 pub type BannedTokenStoreType = Arc<RwLock<dyn BannedTokenStore + Send + Sync>>;
 
 pub type TwoFACodeStoreType = Arc<RwLock<dyn TwoFACodeStore + Send + Sync>>;
+pub type RefreshTokenStoreType = Arc<RwLock<dyn RefreshTokenStore + Send + Sync>>;
+pub type PasswordResetTokenStoreType = Arc<RwLock<dyn PasswordResetTokenStore + Send + Sync>>;
+pub type EmailVerificationTokenStoreType =
+    Arc<RwLock<dyn EmailVerificationTokenStore + Send + Sync>>;
+pub type TotpSecretStoreType = Arc<RwLock<dyn TotpSecretStore + Send + Sync>>;
+pub type RecoveryCodeStoreType = Arc<RwLock<dyn RecoveryCodeStore + Send + Sync>>;
+pub type WebAuthnCredentialStoreType = Arc<RwLock<dyn WebAuthnCredentialStore + Send + Sync>>;
+pub type WebAuthnChallengeStoreType = Arc<RwLock<dyn WebAuthnChallengeStore + Send + Sync>>;
+pub type SessionStoreType = Arc<RwLock<dyn SessionStore + Send + Sync>>;
+pub type LoginAttemptStoreType = Arc<RwLock<dyn LoginAttemptStore + Send + Sync>>;
+pub type EmailChangeStoreType = Arc<RwLock<dyn EmailChangeStore + Send + Sync>>;
+pub type ConfirmationCodeStoreType = Arc<RwLock<dyn ConfirmationCodeStore + Send + Sync>>;
 pub type EmailClientType = Arc<dyn EmailClient + Send + Sync>;
+pub type PasswordPolicyType = Arc<dyn PasswordPolicy + Send + Sync>;
 
 #[derive(Clone)]
 pub struct AppState {
@@ -17,20 +35,60 @@ pub struct AppState {
     pub banned_token_store: BannedTokenStoreType,
     pub two_fa_code_store: TwoFACodeStoreType,
     pub email_client: EmailClientType,
+    pub refresh_token_store: RefreshTokenStoreType,
+    pub password_reset_token_store: PasswordResetTokenStoreType,
+    pub email_verification_token_store: EmailVerificationTokenStoreType,
+    pub totp_secret_store: TotpSecretStoreType,
+    pub recovery_code_store: RecoveryCodeStoreType,
+    pub webauthn_credential_store: WebAuthnCredentialStoreType,
+    pub webauthn_challenge_store: WebAuthnChallengeStoreType,
+    pub session_store: SessionStoreType,
+    pub login_attempt_store: LoginAttemptStoreType,
+    pub email_change_store: EmailChangeStoreType,
+    pub confirmation_code_store: ConfirmationCodeStoreType,
+    // Whether login and signup hide which email addresses have an account
+    pub enumeration_safe: bool,
+    // What new passwords must meet, at signup and when they are changed
+    pub password_policy: PasswordPolicyType,
 }
 
 impl AppState {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         user_store: UserStoreType,
         banned_token_store: BannedTokenStoreType,
         two_fa_code_store: TwoFACodeStoreType,
         email_client: EmailClientType,
+        refresh_token_store: RefreshTokenStoreType,
+        password_reset_token_store: PasswordResetTokenStoreType,
+        email_verification_token_store: EmailVerificationTokenStoreType,
+        totp_secret_store: TotpSecretStoreType,
+        recovery_code_store: RecoveryCodeStoreType,
+        webauthn_credential_store: WebAuthnCredentialStoreType,
+        webauthn_challenge_store: WebAuthnChallengeStoreType,
+        session_store: SessionStoreType,
+        login_attempt_store: LoginAttemptStoreType,
+        email_change_store: EmailChangeStoreType,
+        confirmation_code_store: ConfirmationCodeStoreType,
     ) -> Self {
         Self {
             user_store,
             banned_token_store,
             two_fa_code_store,
             email_client,
+            refresh_token_store,
+            password_reset_token_store,
+            email_verification_token_store,
+            totp_secret_store,
+            recovery_code_store,
+            webauthn_credential_store,
+            webauthn_challenge_store,
+            session_store,
+            login_attempt_store,
+            email_change_store,
+            confirmation_code_store,
+            enumeration_safe: *ENUMERATION_SAFE_MODE,
+            password_policy: PASSWORD_POLICY.clone(),
         }
     }
 }
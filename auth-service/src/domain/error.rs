@@ -1,6 +1,8 @@
 use color_eyre::eyre::Report;
 use thiserror::Error;
 
+use super::PasswordPolicyViolation;
+
 #[derive(Debug, Error)]
 pub enum AuthAPIError {
     #[error("User already exists")]
@@ -9,8 +11,12 @@ pub enum AuthAPIError {
     UserNotFound,
     #[error("Invalid credentials")]
     InvalidCredentials,
+    #[error("Password does not meet requirements")]
+    PasswordRejected(Vec<PasswordPolicyViolation>),
     #[error("Incorrect credentials")]
     IncorrectCredentials,
+    #[error("Email not verified")]
+    EmailNotVerified,
     #[error("Missing token")]
     MissingToken,
     #[error("Invalid token")]
@@ -19,6 +25,20 @@ pub enum AuthAPIError {
     TokenAlreadyBanned,
     #[error("Failed to ban token")]
     TokenBanFailed,
+    #[error("TOTP already enrolled")]
+    TotpAlreadyEnrolled,
+    #[error("2FA not enabled")]
+    TwoFANotEnabled,
+    #[error("2FA already enabled")]
+    TwoFAAlreadyEnabled,
+    #[error("Invalid client")]
+    InvalidClient,
+    #[error("Session not found")]
+    SessionNotFound,
+    #[error("Too many requests")]
+    TooManyRequests,
+    #[error("Too many login attempts")]
+    TooManyLoginAttempts { retry_after_seconds: u64 },
     #[error("Unexpected error: {0}")]
     UnexpectedError(#[source] Report),
 }
@@ -1,14 +1,20 @@
 use color_eyre::eyre::{eyre, Result};
 use secrecy::{ExposeSecret, Secret};
+use subtle::ConstantTimeEq;
+
+use crate::utils::constants::PASSWORD_LENGTH_LIMIT;
 
 #[derive(Debug, Clone)]
 pub struct Password(Secret<String>);
 
 impl PartialEq for Password {
     fn eq(&self, other: &Self) -> bool {
-        // We can use the expose_secret method to expose the secret in a
-        // controlled manner when needed!
-        self.0.expose_secret() == other.0.expose_secret() // Updated!
+        // Compared in constant time, so how long it takes doesn't give away the password
+        self.0
+            .expose_secret()
+            .as_bytes()
+            .ct_eq(other.0.expose_secret().as_bytes())
+            .into()
     }
 }
 
@@ -26,17 +32,19 @@ impl Password {
     }
 
     pub fn verify(&self, candidate: Secret<String>) -> bool {
-        tracing::info!(
-            "Verifying password: {} against {}",
-            &candidate.expose_secret(),
-            &self.0.expose_secret()
-        );
-        self.0.expose_secret() == candidate.expose_secret()
+        self.0
+            .expose_secret()
+            .as_bytes()
+            .ct_eq(candidate.expose_secret().as_bytes())
+            .into()
     }
 }
 
+// Only the bounds every password has to be within are checked here. How long a new password
+// must be is up to the password policy, which can say why one was turned away.
 fn validate_password(s: &Secret<String>) -> bool {
-    s.expose_secret().len() >= 8
+    let length = s.expose_secret().chars().count();
+    length > 0 && length <= PASSWORD_LENGTH_LIMIT
 }
 
 impl AsRef<Secret<String>> for Password {
@@ -48,6 +56,7 @@ impl AsRef<Secret<String>> for Password {
 #[cfg(test)]
 mod tests {
     use super::Password;
+    use crate::{test_helpers::capture_logs, utils::constants::PASSWORD_LENGTH_LIMIT};
 
     use fake::faker::internet::en::Password as FakePassword;
     use fake::Fake;
@@ -59,8 +68,13 @@ mod tests {
         assert!(Password::parse(password).is_err());
     }
     #[test]
-    fn string_less_than_8_characters_is_rejected() {
+    fn short_string_is_left_to_the_policy() {
         let password = Secret::new("1234567".to_owned());
+        assert!(Password::parse(password).is_ok());
+    }
+    #[test]
+    fn string_longer_than_the_limit_is_rejected() {
+        let password = Secret::new("a".repeat(PASSWORD_LENGTH_LIMIT + 1));
         assert!(Password::parse(password).is_err());
     }
 
@@ -91,4 +105,20 @@ mod tests {
         let password = Password::parse(password.clone()).unwrap();
         assert!(password.verify(password.0.to_owned()));
     }
+
+    #[test]
+    fn password_is_not_logged() {
+        let password =
+            Password::parse(Secret::new("correcthorsebatterystaple".to_owned())).unwrap();
+
+        assert!(!format!("{:?}", password).contains("correcthorsebatterystaple"));
+
+        let logs = capture_logs(|| {
+            tracing::info!(?password, "parsed password");
+            password.verify(Secret::new("wrongpassword".to_owned()));
+        });
+        assert!(logs.contains("parsed password"));
+        assert!(!logs.contains("correcthorsebatterystaple"));
+        assert!(!logs.contains("wrongpassword"));
+    }
 }
@@ -0,0 +1,291 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use color_eyre::eyre::{eyre, Context, Result};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use crate::domain::Email;
+use crate::utils::{
+    constants::{
+        DEFAULT_PASSWORD_MAX_LENGTH, DEFAULT_PASSWORD_MIN_LENGTH, DEFAULT_PASSWORD_MIN_STRENGTH,
+    },
+    password_strength::strength_score,
+};
+
+// This trait represents the interface all password policies should implement
+pub trait PasswordPolicy: std::fmt::Debug {
+    // Every way a new password for the account with `email` falls short, if any
+    fn check(&self, password: &Secret<String>, email: &Email) -> Vec<PasswordPolicyViolation>;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "code",
+    rename_all = "snake_case",
+    rename_all_fields = "camelCase"
+)]
+pub enum PasswordPolicyViolation {
+    TooShort { min_length: usize },
+    TooLong { max_length: usize },
+    // Scored from 0 (too guessable) to 4 (very unguessable)
+    TooWeak { score: u8, min_score: u8 },
+    ContainsEmail,
+    Breached,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PasswordPolicySettings {
+    pub min_length: usize,
+    pub max_length: usize,
+    // The lowest strength score allowed, from 0 to 4 - 0 allows any password
+    pub min_strength: u8,
+}
+
+impl Default for PasswordPolicySettings {
+    fn default() -> Self {
+        Self {
+            min_length: DEFAULT_PASSWORD_MIN_LENGTH,
+            max_length: DEFAULT_PASSWORD_MAX_LENGTH,
+            min_strength: DEFAULT_PASSWORD_MIN_STRENGTH,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct StandardPasswordPolicy {
+    settings: PasswordPolicySettings,
+    breached_passwords: Option<BreachedPasswords>,
+}
+
+impl StandardPasswordPolicy {
+    pub fn new(
+        settings: PasswordPolicySettings,
+        breached_passwords: Option<BreachedPasswords>,
+    ) -> Self {
+        Self {
+            settings,
+            breached_passwords,
+        }
+    }
+}
+
+impl PasswordPolicy for StandardPasswordPolicy {
+    fn check(&self, password: &Secret<String>, email: &Email) -> Vec<PasswordPolicyViolation> {
+        let password = password.expose_secret();
+        let length = password.chars().count();
+        let mut violations = Vec::new();
+
+        if length < self.settings.min_length {
+            violations.push(PasswordPolicyViolation::TooShort {
+                min_length: self.settings.min_length,
+            });
+        }
+        if length > self.settings.max_length {
+            violations.push(PasswordPolicyViolation::TooLong {
+                max_length: self.settings.max_length,
+            });
+        }
+
+        let email_parts = email_parts(email);
+        let lowercase = password.to_lowercase();
+        if email_parts.iter().any(|part| lowercase.contains(part)) {
+            violations.push(PasswordPolicyViolation::ContainsEmail);
+        }
+
+        let user_inputs: Vec<&str> = email_parts.iter().map(String::as_str).collect();
+        let score = strength_score(password, &user_inputs);
+        if score < self.settings.min_strength {
+            violations.push(PasswordPolicyViolation::TooWeak {
+                score,
+                min_score: self.settings.min_strength,
+            });
+        }
+
+        if let Some(breached_passwords) = &self.breached_passwords {
+            if breached_passwords.contains(password) {
+                violations.push(PasswordPolicyViolation::Breached);
+            }
+        }
+
+        violations
+    }
+}
+
+// The parts of an email address that are too easy to guess to be in its password: the local
+// part, without any `+tag`, and the words in it
+fn email_parts(email: &Email) -> Vec<String> {
+    let address = email.as_ref().expose_secret().to_lowercase();
+    let local_part = address.split('@').next().unwrap_or_default();
+    let local_part = local_part.split('+').next().unwrap_or_default();
+
+    let words = local_part
+        .split(['.', '_', '-'])
+        .filter(|word| word.len() >= 4 && *word != local_part);
+
+    std::iter::once(local_part)
+        .filter(|local_part| local_part.len() >= 3)
+        .chain(words)
+        .map(str::to_owned)
+        .collect()
+}
+
+// Passwords known from data breaches, as upper case hex SHA-1 hashes grouped by their first five
+// characters like the Have I Been Pwned range API. Lines are a hash, optionally followed by a
+// colon and a count, as in its downloads, so a list can be checked without any network access.
+#[derive(Default)]
+pub struct BreachedPasswords {
+    ranges: HashMap<String, HashSet<String>>,
+}
+
+impl std::fmt::Debug for BreachedPasswords {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BreachedPasswords")
+            .field("ranges", &self.ranges.len())
+            .finish()
+    }
+}
+
+impl BreachedPasswords {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read breached passwords from {:?}", path))?;
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut ranges: HashMap<String, HashSet<String>> = HashMap::new();
+
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let hash = line.split(':').next().unwrap_or_default().to_uppercase();
+            if hash.len() != 40 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(eyre!("invalid SHA-1 hash in breached passwords: {}", line));
+            }
+
+            let (prefix, suffix) = hash.split_at(5);
+            ranges
+                .entry(prefix.to_owned())
+                .or_default()
+                .insert(suffix.to_owned());
+        }
+
+        Ok(Self { ranges })
+    }
+
+    pub fn contains(&self, password: &str) -> bool {
+        let hash = format!("{:X}", Sha1::digest(password.as_bytes()));
+        let (prefix, suffix) = hash.split_at(5);
+
+        self.ranges
+            .get(prefix)
+            .is_some_and(|suffixes| suffixes.contains(suffix))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // SHA-1 of "password123"
+    const BREACHED_HASH: &str = "CBFDAC6008F9CAB4083784CBD1874F76618D2A97";
+
+    fn email(address: &str) -> Email {
+        Email::parse(Secret::new(address.to_owned())).unwrap()
+    }
+
+    fn check(policy: &StandardPasswordPolicy, password: &str) -> Vec<PasswordPolicyViolation> {
+        policy.check(
+            &Secret::new(password.to_owned()),
+            &email("jane.doe+news@example.com"),
+        )
+    }
+
+    #[test]
+    fn strong_passwords_are_allowed() {
+        let policy = StandardPasswordPolicy::default();
+        assert_eq!(check(&policy, "correct horse battery staple"), vec![]);
+    }
+
+    #[test]
+    fn length_is_limited() {
+        let policy = StandardPasswordPolicy::new(
+            PasswordPolicySettings {
+                min_length: 10,
+                max_length: 12,
+                min_strength: 0,
+            },
+            None,
+        );
+
+        assert_eq!(
+            check(&policy, "x7#Qm9!vL"),
+            vec![PasswordPolicyViolation::TooShort { min_length: 10 }]
+        );
+        assert_eq!(
+            check(&policy, "x7#Qm9!vLp2@Zq"),
+            vec![PasswordPolicyViolation::TooLong { max_length: 12 }]
+        );
+    }
+
+    #[test]
+    fn weak_passwords_are_rejected() {
+        let policy = StandardPasswordPolicy::default();
+        assert!(matches!(
+            check(&policy, "password123")[..],
+            [PasswordPolicyViolation::TooWeak { min_score: 2, .. }]
+        ));
+    }
+
+    #[test]
+    fn passwords_containing_the_email_are_rejected() {
+        let policy = StandardPasswordPolicy::default();
+
+        for password in ["Jane.Doe-x7#Qm9!vL", "x7#Qm9!vL-jane"] {
+            assert!(
+                check(&policy, password).contains(&PasswordPolicyViolation::ContainsEmail),
+                "{}",
+                password
+            );
+        }
+        assert_eq!(check(&policy, "x7#Qm9!vLp2@news"), vec![]);
+    }
+
+    #[test]
+    fn breached_passwords_are_rejected() {
+        let breached_passwords =
+            BreachedPasswords::parse(&format!("# comment\n\n{}:2254650\n", BREACHED_HASH)).unwrap();
+        let policy = StandardPasswordPolicy::new(
+            PasswordPolicySettings {
+                min_strength: 0,
+                ..Default::default()
+            },
+            Some(breached_passwords),
+        );
+
+        assert_eq!(
+            check(&policy, "password123"),
+            vec![PasswordPolicyViolation::Breached]
+        );
+        assert_eq!(check(&policy, "password1234"), vec![]);
+    }
+
+    #[test]
+    fn invalid_breached_passwords_are_rejected() {
+        assert!(BreachedPasswords::parse("not a hash").is_err());
+    }
+
+    #[test]
+    fn violations_are_serialized_with_their_details() {
+        let violation = PasswordPolicyViolation::TooShort { min_length: 8 };
+        assert_eq!(
+            serde_json::to_value(violation).unwrap(),
+            serde_json::json!({ "code": "too_short", "minLength": 8 })
+        );
+    }
+}
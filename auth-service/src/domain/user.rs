@@ -1,20 +1,32 @@
-use super::{Email, Password};
+use chrono::{DateTime, Utc};
+
+use super::{Email, Password, UserId};
 
 // The User struct should contain 3 fields. email, which is a String;
 // password, which is also a String; and requires_2fa, which is a boolean.
 #[derive(Debug, Clone, PartialEq)]
 pub struct User {
+    pub id: UserId,
     pub email: Email,
     pub password: Password,
     pub requires_2fa: bool,
+    // New users must confirm they own their email address before they can log in
+    pub verified: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
 }
 
 impl User {
     pub fn new(email: Email, password: Password, requires_2fa: bool) -> Self {
+        let now = Utc::now();
         Self {
+            id: UserId::default(),
             email,
             password,
             requires_2fa,
+            verified: false,
+            created_at: now,
+            updated_at: now,
         }
     }
 }
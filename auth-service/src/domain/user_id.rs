@@ -0,0 +1,57 @@
+use std::fmt;
+
+use color_eyre::eyre::{Context, Result};
+use uuid::Uuid;
+
+// The permanent id of an account. Everything stored about a user is keyed on it rather than
+// on their email address, which can change and shouldn't be carried around in tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UserId(Uuid);
+
+impl UserId {
+    pub fn parse(id: &str) -> Result<Self> {
+        let parsed_id = Uuid::parse_str(id).wrap_err("Invalid user id")?;
+        Ok(Self(parsed_id))
+    }
+}
+
+impl Default for UserId {
+    fn default() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+impl From<Uuid> for UserId {
+    fn from(id: Uuid) -> Self {
+        Self(id)
+    }
+}
+
+impl fmt::Display for UserId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl AsRef<Uuid> for UserId {
+    fn as_ref(&self) -> &Uuid {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UserId;
+
+    #[test]
+    fn user_id_round_trips_through_a_string() {
+        let id = UserId::default();
+        assert_eq!(UserId::parse(&id.to_string()).unwrap(), id);
+    }
+
+    #[test]
+    fn non_uuid_is_rejected() {
+        assert!(UserId::parse("test@example.com").is_err());
+        assert!(UserId::parse("").is_err());
+    }
+}
@@ -1,8 +1,10 @@
 use axum::{
     body::Body,
+    extract::connect_info::{ConnectInfo, IntoMakeServiceWithConnectInfo},
     http::{header, Method, StatusCode},
+    middleware::AddExtension,
     response::{IntoResponse, Response},
-    routing::post,
+    routing::{delete, get, post},
     serve::Serve,
     Json, Router,
 };
@@ -10,7 +12,7 @@ use redis::{Client, RedisResult};
 use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
 use sqlx::{postgres::PgPoolOptions, PgPool};
-use std::error::Error;
+use std::{error::Error, net::SocketAddr};
 use tower_http::{cors::CorsLayer, services::ServeDir, trace::TraceLayer};
 
 pub mod domain;
@@ -18,13 +20,24 @@ pub mod routes;
 pub mod services;
 pub mod utils;
 
-use domain::{AppState, AuthAPIError};
-use routes::{login, logout, signup, verify_2fa, verify_token};
+use domain::{AppState, AuthAPIError, PasswordPolicyViolation};
+use routes::{
+    cancel_email_change, change_password, confirm_email_2fa_disable, confirm_email_2fa_enable,
+    confirm_email_change, confirm_password_reset, confirm_totp, delete_account, delete_session,
+    enroll_totp, export_account, finish_webauthn_registration, get_recovery_codes, introspect,
+    jwks, list_sessions, login, logout, logout_all, refresh, regenerate_recovery_codes,
+    request_email_2fa_disable, request_email_2fa_enable, request_email_change,
+    request_password_reset, resend_verification_email, revoke, signup, start_webauthn_registration,
+    verify_2fa, verify_email, verify_token, verify_webauthn,
+};
 use utils::tracing::{make_span_with_request_id, on_request, on_response};
 
 // This struct encapsulates our application-related logic.
 pub struct Application {
-    server: Serve<Router, Router>,
+    server: Serve<
+        IntoMakeServiceWithConnectInfo<Router, SocketAddr>,
+        AddExtension<Router, ConnectInfo<SocketAddr>>,
+    >,
     // address is exposed as a public field
     // so we have access to it in tests.
     pub address: String,
@@ -39,8 +52,8 @@ impl Application {
         ];
 
         let cors = CorsLayer::new()
-            // Allow GET and POST requests from allowed origins
-            .allow_methods([Method::GET, Method::POST])
+            // Allow GET, POST and DELETE requests from allowed origins
+            .allow_methods([Method::GET, Method::POST, Method::DELETE])
             // Allow cookies to be sent
             .allow_credentials(true)
             // Allow the specified origins
@@ -51,8 +64,82 @@ impl Application {
             .route("/signup", post(signup).options(options_handler))
             .route("/login", post(login).options(options_handler))
             .route("/logout", post(logout).options(options_handler))
+            .route("/logout/all", post(logout_all).options(options_handler))
+            .route("/refresh", post(refresh).options(options_handler))
+            .route(
+                "/account/password",
+                post(change_password).options(options_handler),
+            )
+            .route(
+                "/account/email",
+                post(request_email_change).options(options_handler),
+            )
+            .route("/account", delete(delete_account).options(options_handler))
+            .route("/account/export", get(export_account))
+            .route("/account/email/confirm", get(confirm_email_change))
+            .route("/account/email/cancel", get(cancel_email_change))
+            .route(
+                "/password-reset/request",
+                post(request_password_reset).options(options_handler),
+            )
+            .route(
+                "/password-reset/confirm",
+                post(confirm_password_reset).options(options_handler),
+            )
+            .route(
+                "/2fa/totp/enroll",
+                post(enroll_totp).options(options_handler),
+            )
+            .route(
+                "/2fa/totp/confirm",
+                post(confirm_totp).options(options_handler),
+            )
+            .route(
+                "/2fa/email/enable",
+                post(request_email_2fa_enable).options(options_handler),
+            )
+            .route(
+                "/2fa/email/enable/confirm",
+                post(confirm_email_2fa_enable).options(options_handler),
+            )
+            .route(
+                "/2fa/email/disable",
+                post(request_email_2fa_disable).options(options_handler),
+            )
+            .route(
+                "/2fa/email/disable/confirm",
+                post(confirm_email_2fa_disable).options(options_handler),
+            )
+            .route(
+                "/2fa/recovery-codes",
+                get(get_recovery_codes)
+                    .post(regenerate_recovery_codes)
+                    .options(options_handler),
+            )
+            .route(
+                "/2fa/webauthn/register/start",
+                post(start_webauthn_registration).options(options_handler),
+            )
+            .route(
+                "/2fa/webauthn/register/finish",
+                post(finish_webauthn_registration).options(options_handler),
+            )
+            .route(
+                "/2fa/webauthn/verify",
+                post(verify_webauthn).options(options_handler),
+            )
             .route("/verify-2fa", post(verify_2fa).options(options_handler))
+            .route("/verify-email", get(verify_email))
+            .route(
+                "/verify-email/resend",
+                post(resend_verification_email).options(options_handler),
+            )
+            .route("/sessions", get(list_sessions))
+            .route("/sessions/:id", delete(delete_session))
             .route("/verify-token", post(verify_token).options(options_handler))
+            .route("/introspect", post(introspect).options(options_handler))
+            .route("/revoke", post(revoke).options(options_handler))
+            .route("/.well-known/jwks.json", get(jwks))
             .with_state(app_state)
             .layer(cors)
             .layer(
@@ -67,7 +154,11 @@ impl Application {
 
         let listener = tokio::net::TcpListener::bind(address).await?;
         let address = listener.local_addr()?.to_string();
-        let server = axum::serve(listener, router);
+        // Connect info gives handlers the client's address - see `ClientInfo`
+        let server = axum::serve(
+            listener,
+            router.into_make_service_with_connect_info::<SocketAddr>(),
+        );
 
         // Create a new Application instance and return it
         Ok(Application { server, address })
@@ -90,32 +181,64 @@ async fn options_handler() -> impl IntoResponse {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ErrorResponse {
     pub error: String,
+    // Why a request was rejected, where there can be more than one reason
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reasons: Vec<PasswordPolicyViolation>,
 }
 
 impl IntoResponse for AuthAPIError {
     fn into_response(self) -> Response {
         log_error_chain(&self);
+        // Clients that fail to authenticate are told how to (RFC 6749 section 5.2)
+        let challenge = matches!(self, AuthAPIError::InvalidClient)
+            .then_some([(header::WWW_AUTHENTICATE, "Basic realm=\"auth-service\"")]);
+        // Throttled logins are told when they may try again
+        let retry_after = match self {
+            AuthAPIError::TooManyLoginAttempts {
+                retry_after_seconds,
+            } => Some([(header::RETRY_AFTER, retry_after_seconds.to_string())]),
+            _ => None,
+        };
+        let reasons = match &self {
+            AuthAPIError::PasswordRejected(violations) => violations.clone(),
+            _ => Vec::new(),
+        };
         let (status, error_message) = match self {
             AuthAPIError::UserAlreadyExists => (StatusCode::CONFLICT, "User already exists"),
             AuthAPIError::UserNotFound => (StatusCode::NOT_FOUND, "User not found"),
             AuthAPIError::InvalidCredentials => (StatusCode::BAD_REQUEST, "Invalid credentials"),
+            AuthAPIError::PasswordRejected(_) => (
+                StatusCode::BAD_REQUEST,
+                "Password does not meet requirements",
+            ),
             AuthAPIError::IncorrectCredentials => {
                 (StatusCode::UNAUTHORIZED, "Incorrect credentials")
             }
+            AuthAPIError::EmailNotVerified => (StatusCode::FORBIDDEN, "Email not verified"),
             AuthAPIError::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid auth token"),
             AuthAPIError::MissingToken => (StatusCode::BAD_REQUEST, "Missing auth token"),
             AuthAPIError::TokenAlreadyBanned => (StatusCode::CONFLICT, "Token already banned"),
             AuthAPIError::TokenBanFailed => {
                 (StatusCode::UNPROCESSABLE_ENTITY, "Failed to ban token")
             }
+            AuthAPIError::TotpAlreadyEnrolled => (StatusCode::CONFLICT, "TOTP already enrolled"),
+            AuthAPIError::TwoFANotEnabled => (StatusCode::BAD_REQUEST, "2FA not enabled"),
+            AuthAPIError::TwoFAAlreadyEnabled => (StatusCode::CONFLICT, "2FA already enabled"),
+            AuthAPIError::InvalidClient => (StatusCode::UNAUTHORIZED, "Invalid client"),
+            AuthAPIError::SessionNotFound => (StatusCode::NOT_FOUND, "Session not found"),
+            AuthAPIError::TooManyRequests => (StatusCode::TOO_MANY_REQUESTS, "Too many requests"),
+            AuthAPIError::TooManyLoginAttempts { .. } => {
+                (StatusCode::TOO_MANY_REQUESTS, "Too many login attempts")
+            }
             AuthAPIError::UnexpectedError(_) => {
                 (StatusCode::INTERNAL_SERVER_ERROR, "Unexpected error")
             }
         };
         let body = Json(ErrorResponse {
             error: error_message.to_string(),
+            reasons,
         });
-        (status, body).into_response()
+        (status, challenge, retry_after, body).into_response()
     }
 }
 
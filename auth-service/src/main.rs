@@ -1,15 +1,26 @@
 use reqwest::Client;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::RwLock,
+};
 
 use auth_service::{
     domain::{AppState, Email},
     get_postgres_pool, get_redis_client,
     services::{
-        PostgresUserStore, PostmarkEmailClient, RedisBannedTokenStore, RedisTwoFACodeStore,
+        PostgresRecoveryCodeStore, PostgresSessionStore, PostgresTotpSecretStore,
+        PostgresUserStore, PostgresWebAuthnCredentialStore, PostmarkEmailClient,
+        RedisBannedTokenStore, RedisConfirmationCodeStore, RedisEmailChangeStore,
+        RedisEmailVerificationTokenStore, RedisLoginAttemptStore, RedisPasswordResetTokenStore,
+        RedisRefreshTokenStore, RedisTwoFACodeStore, RedisWebAuthnChallengeStore,
     },
     utils::{
-        constants::{prod, DATABASE_URL, REDIS_HOST_NAME},
+        auth::reload_jwt_key_ring,
+        constants::{
+            prod, require_auth_service_base_url, DATABASE_URL, JWT_KEY_RING,
+            LOGIN_THROTTLE_SETTINGS, PASSWORD_HASH_PARAMS, PASSWORD_PEPPERS, REDIS_HOST_NAME,
+        },
         init_tracing, POSTMARK_AUTH_TOKEN,
     },
     Application,
@@ -20,14 +31,54 @@ async fn main() {
     color_eyre::install().expect("Failed to install color_eyre");
     init_tracing().expect("Failed to initialize tracing");
 
+    // Fail at startup rather than on the first login if the signing keys are misconfigured
+    lazy_static::initialize(&JWT_KEY_RING);
+    spawn_jwt_key_ring_reloader();
+    require_auth_service_base_url();
+
     let pg_pool = configure_postgresql().await;
     let redis_connection = Arc::new(RwLock::new(configure_redis()));
 
-    let user_store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool)));
+    let user_store = Arc::new(RwLock::new(PostgresUserStore::new(
+        pg_pool.clone(),
+        PASSWORD_HASH_PARAMS.clone(),
+        PASSWORD_PEPPERS.clone(),
+    )));
+    let totp_secret_store = Arc::new(RwLock::new(PostgresTotpSecretStore::new(pg_pool.clone())));
+    let recovery_code_store =
+        Arc::new(RwLock::new(PostgresRecoveryCodeStore::new(pg_pool.clone())));
+    let webauthn_credential_store = Arc::new(RwLock::new(PostgresWebAuthnCredentialStore::new(
+        pg_pool.clone(),
+    )));
+    let session_store = Arc::new(RwLock::new(PostgresSessionStore::new(pg_pool)));
     let banned_token_store = Arc::new(RwLock::new(RedisBannedTokenStore::new(
         redis_connection.clone(),
     )));
-    let two_fa_code_store = Arc::new(RwLock::new(RedisTwoFACodeStore::new(redis_connection)));
+    let two_fa_code_store = Arc::new(RwLock::new(RedisTwoFACodeStore::new(
+        redis_connection.clone(),
+    )));
+    let refresh_token_store = Arc::new(RwLock::new(RedisRefreshTokenStore::new(
+        redis_connection.clone(),
+    )));
+    let password_reset_token_store = Arc::new(RwLock::new(RedisPasswordResetTokenStore::new(
+        redis_connection.clone(),
+    )));
+    let email_verification_token_store = Arc::new(RwLock::new(
+        RedisEmailVerificationTokenStore::new(redis_connection.clone()),
+    ));
+    let webauthn_challenge_store = Arc::new(RwLock::new(RedisWebAuthnChallengeStore::new(
+        redis_connection.clone(),
+    )));
+    let email_change_store = Arc::new(RwLock::new(RedisEmailChangeStore::new(
+        redis_connection.clone(),
+    )));
+    let confirmation_code_store = Arc::new(RwLock::new(RedisConfirmationCodeStore::new(
+        redis_connection.clone(),
+    )));
+    let login_attempt_store = Arc::new(RwLock::new(RedisLoginAttemptStore::new(
+        redis_connection,
+        *LOGIN_THROTTLE_SETTINGS,
+    )));
 
     let email_client = Arc::new(configure_postmark_email_client());
 
@@ -36,6 +87,17 @@ async fn main() {
         banned_token_store,
         two_fa_code_store,
         email_client,
+        refresh_token_store,
+        password_reset_token_store,
+        email_verification_token_store,
+        totp_secret_store,
+        recovery_code_store,
+        webauthn_credential_store,
+        webauthn_challenge_store,
+        session_store,
+        login_attempt_store,
+        email_change_store,
+        confirmation_code_store,
     );
 
     let app = Application::build(app_state, prod::APP_ADDRESS)
@@ -60,6 +122,21 @@ async fn configure_postgresql() -> sqlx::PgPool {
     pg_pool
 }
 
+// Reload the JWT signing keys on SIGHUP, so keys can be promoted and retired without dropping
+// connections
+fn spawn_jwt_key_ring_reloader() {
+    let mut hangups = signal(SignalKind::hangup()).expect("Failed to listen for SIGHUP");
+
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            match reload_jwt_key_ring() {
+                Ok(()) => tracing::info!("reloaded JWT key ring"),
+                Err(e) => tracing::error!("kept the current JWT key ring: {:?}", e),
+            }
+        }
+    });
+}
+
 fn configure_redis() -> redis::Connection {
     get_redis_client(REDIS_HOST_NAME.to_owned())
         .expect("Failed to get Redis client")
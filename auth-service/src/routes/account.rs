@@ -0,0 +1,613 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL, Engine};
+use chrono::Utc;
+use color_eyre::eyre::Report;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    domain::{AppState, AuthAPIError, Email, Password, User, UserId},
+    routes::{login_attempt_error, start_session, SessionResponse},
+    services::{
+        EmailChange, EmailChangeStoreError, EmailChangeToken, EmailVerificationTokenStoreError,
+        SessionId, TotpSecretStoreError, TwoFACodeStoreError, UserStoreError,
+    },
+    utils::{
+        auth::{authenticate, authenticate_claims, remove_auth_cookies, revoke_all_sessions},
+        client::ClientInfo,
+        constants::{AUTH_SERVICE_BASE_URL, EMAIL_CHANGE_TOKEN_TTL_SECONDS},
+    },
+};
+
+#[instrument(name = "Change password", skip_all)]
+pub async fn change_password(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    client: ClientInfo,
+    Json(request): Json<ChangePasswordRequest>,
+) -> Result<(CookieJar, impl IntoResponse), AuthAPIError> {
+    let claims = authenticate_claims(&jar, &state).await?;
+    let user_id = UserId::parse(&claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+    let user = get_authenticated_user(&user_id, &state).await?;
+
+    let current_password =
+        Password::parse(request.current_password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let new_password =
+        Password::parse(request.new_password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let violations = state
+        .password_policy
+        .check(new_password.as_ref(), &user.email);
+    if !violations.is_empty() {
+        return Err(AuthAPIError::PasswordRejected(violations));
+    }
+
+    reauthenticate(&user.email, &current_password, &client, &state).await?;
+
+    match state
+        .user_store
+        .write()
+        .await
+        .update_password(&user_id, new_password)
+        .await
+    {
+        Ok(()) => (),
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    // Whoever knew the old password may still be logged in - sign every session out, then start
+    // a new one for the caller so they stay logged in here
+    revoke_all_sessions(
+        &user_id,
+        state.banned_token_store.clone(),
+        state.refresh_token_store.clone(),
+    )
+    .await
+    .map_err(AuthAPIError::UnexpectedError)?;
+
+    if let Ok(session_id) = SessionId::parse(claims.sid) {
+        state
+            .session_store
+            .write()
+            .await
+            .remove_session(&session_id)
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    }
+    let jar = start_session(&user_id, client, &state, jar).await?;
+
+    notify_password_changed(&user.email, &state).await;
+
+    let response = Json(ChangePasswordResponse {
+        message: "Password has been changed".to_owned(),
+    });
+
+    Ok((jar, (StatusCode::OK, response)))
+}
+
+// Start moving the logged in user to a new email address. Nothing changes until the link sent
+// to the new address is followed, and the old address is sent a link to cancel the change.
+#[instrument(name = "Request email change", skip_all)]
+pub async fn request_email_change(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    client: ClientInfo,
+    Json(request): Json<ChangeEmailRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let user_id = authenticate(&jar, &state).await?;
+    let user = get_authenticated_user(&user_id, &state).await?;
+
+    let new_email =
+        Email::parse(request.new_email).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let password =
+        Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    if new_email == user.email {
+        return Err(AuthAPIError::InvalidCredentials);
+    }
+
+    reauthenticate(&user.email, &password, &client, &state).await?;
+
+    let response = Json(ChangeEmailResponse {
+        message: "A confirmation link has been sent to the new email address".to_owned(),
+    });
+
+    match state
+        .user_store
+        .read()
+        .await
+        .get_user_by_email(&new_email)
+        .await
+    {
+        // Don't reveal that the address has an account, unless that's not a concern
+        Ok(_) if state.enumeration_safe => return Ok((StatusCode::OK, response)),
+        Ok(_) => return Err(AuthAPIError::UserAlreadyExists),
+        Err(UserStoreError::UserNotFound) => (),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    // Counts towards the new address's verification email limit, so it can't be flooded
+    match state
+        .email_verification_token_store
+        .write()
+        .await
+        .record_email_sent(&new_email)
+        .await
+    {
+        Ok(()) => (),
+        Err(EmailVerificationTokenStoreError::TooManyEmails) => {
+            return Err(AuthAPIError::TooManyRequests)
+        }
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let change = EmailChange::new(user_id, new_email);
+    state
+        .email_change_store
+        .write()
+        .await
+        .add_change(change.clone())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let content = format!(
+        "Confirm that you want to use this email address for your account using the link below. It expires in {} hours.\n\n{}/account/email/confirm?token={}",
+        EMAIL_CHANGE_TOKEN_TTL_SECONDS / 3600,
+        AUTH_SERVICE_BASE_URL.as_str(),
+        change.confirm_token.as_ref().expose_secret()
+    );
+    state
+        .email_client
+        .send_email(
+            &change.new_email,
+            "Confirm your new email address",
+            &content,
+        )
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    let content = format!(
+        "Someone asked to move your account to a new email address. It won't change until the new address is confirmed. If this wasn't you, cancel the change using the link below and reset your password.\n\n{}/account/email/cancel?token={}",
+        AUTH_SERVICE_BASE_URL.as_str(),
+        change.cancel_token.as_ref().expose_secret()
+    );
+    if let Err(e) = state
+        .email_client
+        .send_email(&user.email, "Your email address is being changed", &content)
+        .await
+    {
+        tracing::error!("failed to send email change notice: {:?}", e);
+    }
+
+    Ok((StatusCode::OK, response))
+}
+
+// Follow the link sent to the new address. Everything about the user is keyed on their id, so
+// only the address itself needs to change and every session stays logged in.
+#[instrument(name = "Confirm email change", skip_all)]
+pub async fn confirm_email_change(
+    State(state): State<AppState>,
+    Query(params): Query<EmailChangeParams>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let token = EmailChangeToken::parse(params.token).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let change = match state
+        .email_change_store
+        .write()
+        .await
+        .take_change(&token)
+        .await
+    {
+        Ok(change) => change,
+        Err(EmailChangeStoreError::ChangeNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    match state
+        .user_store
+        .write()
+        .await
+        .change_email(&change.user_id, &change.new_email)
+        .await
+    {
+        Ok(()) => (),
+        Err(UserStoreError::UserAlreadyExists) => return Err(AuthAPIError::UserAlreadyExists),
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let response = Json(ChangeEmailResponse {
+        message: "Email address has been changed".to_owned(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+// Follow the link sent to the old address, so the change can no longer be confirmed
+#[instrument(name = "Cancel email change", skip_all)]
+pub async fn cancel_email_change(
+    State(state): State<AppState>,
+    Query(params): Query<EmailChangeParams>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let token = EmailChangeToken::parse(params.token).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    match state
+        .email_change_store
+        .write()
+        .await
+        .cancel_change(&token)
+        .await
+    {
+        Ok(_) => (),
+        Err(EmailChangeStoreError::ChangeNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let response = Json(ChangeEmailResponse {
+        message: "Email change has been cancelled".to_owned(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+// Delete the logged in user's account. Their authenticator app, recovery codes, passkeys and
+// sessions are deleted with them in the database, and everything they are still logged in with
+// is revoked.
+#[instrument(name = "Delete account", skip_all)]
+pub async fn delete_account(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    client: ClientInfo,
+    Json(request): Json<DeleteAccountRequest>,
+) -> Result<(CookieJar, impl IntoResponse), AuthAPIError> {
+    let user_id = authenticate(&jar, &state).await?;
+    let user = get_authenticated_user(&user_id, &state).await?;
+
+    let password =
+        Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    reauthenticate(&user.email, &password, &client, &state).await?;
+
+    revoke_all_sessions(
+        &user_id,
+        state.banned_token_store.clone(),
+        state.refresh_token_store.clone(),
+    )
+    .await
+    .map_err(AuthAPIError::UnexpectedError)?;
+
+    // A login waiting on a 2FA code can't be finished any more
+    match state
+        .two_fa_code_store
+        .write()
+        .await
+        .remove_code(&user_id)
+        .await
+    {
+        Ok(()) | Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => (),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    // Nor can a change to their email address or 2FA settings, and nothing is kept of the
+    // addresses they asked to move to or of their logins
+    state
+        .email_change_store
+        .write()
+        .await
+        .remove_changes(&user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    state
+        .confirmation_code_store
+        .write()
+        .await
+        .remove_codes(&user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    state
+        .login_attempt_store
+        .write()
+        .await
+        .remove_account(&user.email)
+        .await
+        .map_err(login_attempt_error)?;
+
+    match state.user_store.write().await.delete_user(&user_id).await {
+        Ok(()) => (),
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let content = "Your account and everything stored with it has been deleted. If this wasn't you, sign up again and contact us right away.";
+    if let Err(e) = state
+        .email_client
+        .send_email(&user.email, "Your account was deleted", content)
+        .await
+    {
+        tracing::error!("failed to send account deletion notice: {:?}", e);
+    }
+
+    let jar = remove_auth_cookies(jar);
+
+    let response = Json(DeleteAccountResponse {
+        message: "Account has been deleted".to_owned(),
+    });
+
+    Ok((jar, (StatusCode::OK, response)))
+}
+
+// Everything stored about the logged in user, so they can take a copy of it
+#[instrument(name = "Export account", skip_all)]
+pub async fn export_account(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let claims = authenticate_claims(&jar, &state).await?;
+    let user_id = UserId::parse(&claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+    let user = get_authenticated_user(&user_id, &state).await?;
+
+    let authenticator_app = match state
+        .totp_secret_store
+        .read()
+        .await
+        .get_secret(&user_id)
+        .await
+    {
+        Ok(record) => record.confirmed,
+        Err(TotpSecretStoreError::SecretNotFound) => false,
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    let recovery_codes_remaining = state
+        .recovery_code_store
+        .read()
+        .await
+        .count_codes(&user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let passkeys = state
+        .webauthn_credential_store
+        .read()
+        .await
+        .get_credentials(&user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?
+        .iter()
+        .map(|credential| ExportedPasskey {
+            credential_id: BASE64_URL.encode(&credential.credential_id),
+            public_key: BASE64_URL.encode(&credential.public_key),
+            sign_count: credential.sign_count,
+            created_at: credential.created_at.to_rfc3339(),
+            last_used_at: credential
+                .last_used_at
+                .map(|last_used_at| last_used_at.to_rfc3339()),
+        })
+        .collect();
+
+    let pending_email_changes = state
+        .email_change_store
+        .read()
+        .await
+        .get_changes(&user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?
+        .iter()
+        .map(|change| ExportedEmailChange {
+            new_email: change.new_email.as_ref().expose_secret().to_owned(),
+        })
+        .collect();
+
+    let sessions = state
+        .session_store
+        .read()
+        .await
+        .get_sessions(&user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?
+        .iter()
+        .map(|session| SessionResponse::new(session, &claims.sid))
+        .collect();
+
+    // Secrets - the password hash, authenticator app secret and recovery code hashes - are left
+    // out, only whether they are set up. So are the tokens and codes emailed to the user or held
+    // by their browser, which are credentials too and expire on their own.
+    let response = Json(AccountExportResponse {
+        exported_at: Utc::now().to_rfc3339(),
+        user: ExportedUser {
+            id: user.id.to_string(),
+            email: user.email.as_ref().expose_secret().to_owned(),
+            verified: user.verified,
+            requires_2fa: user.requires_2fa,
+            created_at: user.created_at.to_rfc3339(),
+            updated_at: user.updated_at.to_rfc3339(),
+            pending_email_changes,
+        },
+        two_factor: ExportedTwoFactor {
+            authenticator_app,
+            recovery_codes_remaining,
+            passkeys,
+        },
+        sessions,
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+// Look up the user an auth token was issued to
+pub async fn get_authenticated_user(
+    user_id: &UserId,
+    state: &AppState,
+) -> Result<User, AuthAPIError> {
+    match state.user_store.read().await.get_user(user_id).await {
+        Ok(user) => Ok(user),
+        // The account was deleted after the token was issued
+        Err(UserStoreError::UserNotFound) => Err(AuthAPIError::InvalidToken),
+        Err(e) => Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+}
+
+// Check the password of a user who is already logged in before a sensitive change to their
+// account, so a stolen auth cookie isn't enough. Counts towards the same limits as logging in.
+#[instrument(name = "Reauthenticate", skip_all)]
+pub async fn reauthenticate(
+    email: &Email,
+    password: &Password,
+    client: &ClientInfo,
+    state: &AppState,
+) -> Result<(), AuthAPIError> {
+    state
+        .login_attempt_store
+        .write()
+        .await
+        .record_attempt(client.ip_address.as_deref(), email)
+        .await
+        .map_err(login_attempt_error)?;
+
+    match state
+        .user_store
+        .read()
+        .await
+        .validate_user(email, password)
+        .await
+    {
+        Ok(()) => (),
+        Err(UserStoreError::IncorrectCredentials) => {
+            state
+                .login_attempt_store
+                .write()
+                .await
+                .record_failure(email)
+                .await
+                .map_err(login_attempt_error)?;
+            return Err(AuthAPIError::IncorrectCredentials);
+        }
+        // The account was deleted after the token was issued
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => {
+            return Err(AuthAPIError::UnexpectedError(Report::msg(format!(
+                "Unexpected error during user validation: {:?}",
+                e
+            ))))
+        }
+    }
+
+    state
+        .login_attempt_store
+        .write()
+        .await
+        .clear_failures(email)
+        .await
+        .map_err(login_attempt_error)
+}
+
+// Let the user know their password was changed, in case it wasn't them
+async fn notify_password_changed(email: &Email, state: &AppState) {
+    let content = format!(
+        "The password for your account was just changed, and every other session was logged out. If this wasn't you, reset your password at {} right away.",
+        AUTH_SERVICE_BASE_URL.as_str()
+    );
+
+    // The password has already been changed, so a delivery failure shouldn't fail the request
+    if let Err(e) = state
+        .email_client
+        .send_email(email, "Your password was changed", &content)
+        .await
+    {
+        tracing::error!("failed to send password change notification: {:?}", e);
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangePasswordRequest {
+    pub current_password: Secret<String>,
+    pub new_password: Secret<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ChangePasswordResponse {
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeEmailRequest {
+    pub password: Secret<String>,
+    pub new_email: Secret<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteAccountRequest {
+    pub password: Secret<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct DeleteAccountResponse {
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountExportResponse {
+    pub exported_at: String,
+    pub user: ExportedUser,
+    pub two_factor: ExportedTwoFactor,
+    pub sessions: Vec<SessionResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedUser {
+    pub id: String,
+    pub email: String,
+    pub verified: bool,
+    #[serde(rename = "requires2FA")]
+    pub requires_2fa: bool,
+    pub created_at: String,
+    pub updated_at: String,
+    // Changes of address that haven't been confirmed yet
+    pub pending_email_changes: Vec<ExportedEmailChange>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedEmailChange {
+    pub new_email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedTwoFactor {
+    // Whether an authenticator app has been enrolled and confirmed
+    pub authenticator_app: bool,
+    pub recovery_codes_remaining: usize,
+    pub passkeys: Vec<ExportedPasskey>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedPasskey {
+    pub credential_id: String,
+    pub public_key: String,
+    pub sign_count: u32,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct EmailChangeParams {
+    pub token: Secret<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ChangeEmailResponse {
+    pub message: String,
+}
@@ -0,0 +1,295 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    domain::{AppState, AuthAPIError, User, UserId},
+    routes::{get_authenticated_user, get_two_fa_method, issue_recovery_codes},
+    services::data_stores::{
+        ConfirmationCodeStoreError, ConfirmationPurpose, EmailVerificationTokenStoreError,
+        TwoFACode, UserStoreError,
+    },
+    utils::{auth::authenticate, constants::AUTH_SERVICE_BASE_URL},
+};
+
+// Turning emailed 2FA codes on or off is confirmed with a code sent to the user's address, so a
+// stolen session isn't enough to do it. Authenticator apps and passkeys are used instead of
+// emailed codes whenever the user has one, whichever way this is set.
+
+#[instrument(name = "Request email 2FA enable", skip_all)]
+pub async fn request_email_2fa_enable(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let user_id = authenticate(&jar, &state).await?;
+    let user = get_authenticated_user(&user_id, &state).await?;
+
+    if user.requires_2fa {
+        return Err(AuthAPIError::TwoFAAlreadyEnabled);
+    }
+
+    send_confirmation_code(
+        &user,
+        ConfirmationPurpose::EnableEmail2FA,
+        "Confirm turning on 2FA",
+        &state,
+    )
+    .await?;
+
+    let response = Json(Email2FAResponse {
+        message: "A confirmation code has been sent to your email address".to_owned(),
+        recovery_codes: Vec::new(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[instrument(name = "Confirm email 2FA enable", skip_all)]
+pub async fn confirm_email_2fa_enable(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<ConfirmEmail2FARequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let user_id = authenticate(&jar, &state).await?;
+    let user = get_authenticated_user(&user_id, &state).await?;
+
+    let code =
+        TwoFACode::parse(request.two_fa_code).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    if user.requires_2fa {
+        return Err(AuthAPIError::TwoFAAlreadyEnabled);
+    }
+
+    check_confirmation_code(&user_id, ConfirmationPurpose::EnableEmail2FA, &code, &state).await?;
+
+    // Users who already have an authenticator app or passkey keep the recovery codes they have
+    let had_2fa = get_two_fa_method(&user, &state).await?.is_some();
+
+    update_requires_2fa(user, true, &state).await?;
+
+    let recovery_codes = match had_2fa {
+        true => Vec::new(),
+        false => issue_recovery_codes(&user_id, &state)
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?,
+    };
+
+    let response = Json(Email2FAResponse {
+        message: "2FA has been turned on".to_owned(),
+        recovery_codes,
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[instrument(name = "Request email 2FA disable", skip_all)]
+pub async fn request_email_2fa_disable(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let user_id = authenticate(&jar, &state).await?;
+    let user = get_authenticated_user(&user_id, &state).await?;
+
+    if !user.requires_2fa {
+        return Err(AuthAPIError::TwoFANotEnabled);
+    }
+
+    send_confirmation_code(
+        &user,
+        ConfirmationPurpose::DisableEmail2FA,
+        "Confirm turning off 2FA",
+        &state,
+    )
+    .await?;
+
+    let response = Json(Email2FAResponse {
+        message: "A 2FA code has been sent to your email address".to_owned(),
+        recovery_codes: Vec::new(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[instrument(name = "Confirm email 2FA disable", skip_all)]
+pub async fn confirm_email_2fa_disable(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<ConfirmEmail2FARequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let user_id = authenticate(&jar, &state).await?;
+    let user = get_authenticated_user(&user_id, &state).await?;
+
+    let code =
+        TwoFACode::parse(request.two_fa_code).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    if !user.requires_2fa {
+        return Err(AuthAPIError::TwoFANotEnabled);
+    }
+
+    check_confirmation_code(
+        &user_id,
+        ConfirmationPurpose::DisableEmail2FA,
+        &code,
+        &state,
+    )
+    .await?;
+
+    let user = update_requires_2fa(user, false, &state).await?;
+
+    // Recovery codes are only any use while the user has some other 2FA method
+    if get_two_fa_method(&user, &state).await?.is_none() {
+        state
+            .recovery_code_store
+            .write()
+            .await
+            .replace_codes(&user_id, Vec::new())
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    }
+
+    notify_2fa_disabled(&user, &state).await;
+
+    let response = Json(Email2FAResponse {
+        message: "2FA has been turned off".to_owned(),
+        recovery_codes: Vec::new(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+// Sending a code counts towards the user's verification email limit, so codes can't be asked
+// for over and over to flood their inbox or to get more guesses
+async fn send_confirmation_code(
+    user: &User,
+    purpose: ConfirmationPurpose,
+    subject: &str,
+    state: &AppState,
+) -> Result<(), AuthAPIError> {
+    match state
+        .email_verification_token_store
+        .write()
+        .await
+        .record_email_sent(&user.email)
+        .await
+    {
+        Ok(()) => (),
+        Err(EmailVerificationTokenStoreError::TooManyEmails) => {
+            return Err(AuthAPIError::TooManyRequests)
+        }
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let code = TwoFACode::default();
+
+    state
+        .confirmation_code_store
+        .write()
+        .await
+        .add_code(&user.id, purpose, code.clone())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    state
+        .email_client
+        .send_email(&user.email, subject, code.as_ref().expose_secret())
+        .await
+        .map_err(AuthAPIError::UnexpectedError)
+}
+
+// Only a code sent for the same purpose is accepted. Wrong codes count towards the same limit
+// as at login, and the user is locked out until the count expires once they reach it.
+async fn check_confirmation_code(
+    user_id: &UserId,
+    purpose: ConfirmationPurpose,
+    code: &TwoFACode,
+    state: &AppState,
+) -> Result<(), AuthAPIError> {
+    let mut confirmation_code_store = state.confirmation_code_store.write().await;
+
+    let expected_code = match confirmation_code_store.get_code(user_id, purpose).await {
+        Ok(expected_code) => expected_code,
+        Err(ConfirmationCodeStoreError::CodeNotFound) => {
+            return Err(AuthAPIError::IncorrectCredentials)
+        }
+        Err(ConfirmationCodeStoreError::TooManyAttempts) => {
+            return Err(AuthAPIError::TooManyRequests)
+        }
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    if !expected_code.eq(code) {
+        return Err(
+            match confirmation_code_store.record_failed_attempt(user_id).await {
+                Ok(()) => AuthAPIError::IncorrectCredentials,
+                Err(ConfirmationCodeStoreError::TooManyAttempts) => AuthAPIError::TooManyRequests,
+                Err(e) => AuthAPIError::UnexpectedError(e.into()),
+            },
+        );
+    }
+
+    confirmation_code_store
+        .remove_code(user_id, purpose)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))
+}
+
+async fn update_requires_2fa(
+    user: User,
+    requires_2fa: bool,
+    state: &AppState,
+) -> Result<User, AuthAPIError> {
+    let user = User {
+        requires_2fa,
+        ..user
+    };
+
+    match state
+        .user_store
+        .write()
+        .await
+        .update_user(user.clone())
+        .await
+    {
+        Ok(()) => Ok(user),
+        // The account was deleted after the token was issued
+        Err(UserStoreError::UserNotFound) => Err(AuthAPIError::InvalidToken),
+        Err(e) => Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+}
+
+// Let the user know 2FA was turned off, in case it wasn't them
+async fn notify_2fa_disabled(user: &User, state: &AppState) {
+    let content = format!(
+        "2FA codes are no longer emailed when you log in to your account. If this wasn't you, reset your password at {} right away.",
+        AUTH_SERVICE_BASE_URL.as_str()
+    );
+
+    // 2FA has already been turned off, so a delivery failure shouldn't fail the request
+    if let Err(e) = state
+        .email_client
+        .send_email(&user.email, "2FA was turned off", &content)
+        .await
+    {
+        tracing::error!("failed to send 2FA disabled notification: {:?}", e);
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmEmail2FARequest {
+    #[serde(rename = "2FACode")]
+    pub two_fa_code: Secret<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Email2FAResponse {
+    pub message: String,
+    // Only returned when turning 2FA on gives the user their first 2FA method
+    #[serde(
+        rename = "recoveryCodes",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub recovery_codes: Vec<String>,
+}
@@ -0,0 +1,11 @@
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use tracing::instrument;
+
+use crate::utils::auth::jwt_key_ring;
+
+// Publish the public keys tokens are signed with, so other services can verify them locally.
+// Keys stay published until they are retired, so tokens signed before a rotation still verify.
+#[instrument(name = "JWKS", skip_all)]
+pub async fn jwks() -> impl IntoResponse {
+    (StatusCode::OK, Json(jwt_key_ring().jwk_set()))
+}
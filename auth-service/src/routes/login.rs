@@ -1,7 +1,10 @@
 use crate::{
     domain::{AppState, AuthAPIError, Email, Password, User},
-    services::data_stores::{LoginAttemptId, TwoFACode, UserStoreError},
-    utils::auth::generate_auth_cookie,
+    routes::{start_session, start_webauthn_authentication},
+    services::data_stores::{
+        LoginAttemptId, LoginAttemptStoreError, TotpSecretStoreError, TwoFACode, UserStoreError,
+    },
+    utils::{client::ClientInfo, webauthn::PublicKeyCredentialRequestOptions},
 };
 use axum::{extract::State, http::StatusCode, Json};
 use axum_extra::extract::CookieJar;
@@ -15,6 +18,7 @@ use tracing::instrument;
 pub async fn login(
     State(state): State<AppState>,
     jar: CookieJar,
+    client: ClientInfo,
     Json(request): Json<LoginRequest>,
 ) -> Result<(CookieJar, (StatusCode, Json<LoginResponse>)), AuthAPIError> {
     let email =
@@ -22,36 +26,122 @@ pub async fn login(
     let password =
         Password::parse(request.password.clone()).map_err(|_| AuthAPIError::InvalidCredentials)?;
 
+    // Throttle before checking the password, so that guessing is slow and costs us nothing
+    state
+        .login_attempt_store
+        .write()
+        .await
+        .record_attempt(client.ip_address.as_deref(), &email)
+        .await
+        .map_err(login_attempt_error)?;
+
     let user_store = &state.user_store.read().await;
 
-    user_store
-        .validate_user(&email, &password)
-        .await
-        .map_err(|e| match e {
+    if let Err(e) = user_store.validate_user(&email, &password).await {
+        if matches!(
+            e,
+            UserStoreError::UserNotFound | UserStoreError::IncorrectCredentials
+        ) {
+            state
+                .login_attempt_store
+                .write()
+                .await
+                .record_failure(&email)
+                .await
+                .map_err(login_attempt_error)?;
+        }
+        return Err(match e {
+            // Unknown users get the same error as wrong passwords, unless that's not a concern
+            UserStoreError::UserNotFound if state.enumeration_safe => {
+                AuthAPIError::IncorrectCredentials
+            }
             UserStoreError::UserNotFound => AuthAPIError::UserNotFound,
             UserStoreError::IncorrectCredentials => AuthAPIError::IncorrectCredentials,
             _ => AuthAPIError::UnexpectedError(Report::msg(format!(
                 "Unexpected error during user validation: {:?}",
                 e
             ))),
-        })?; // early-returns Err(AuthAPIError::...)
+        });
+    }
+
+    state
+        .login_attempt_store
+        .write()
+        .await
+        .clear_failures(&email)
+        .await
+        .map_err(login_attempt_error)?;
 
     let user: User = user_store
-        .get_user(&email)
+        .get_user_by_email(&email)
         .await
         .map_err(|_| AuthAPIError::IncorrectCredentials)?;
 
+    if !user.verified {
+        return Err(AuthAPIError::EmailNotVerified);
+    }
+
     // Handle request based on user's 2FA configuration
-    match user.requires_2fa {
-        true => handle_2fa(&user.email, &state, jar).await,
-        false => handle_no_2fa(&user.email, jar).await,
+    match get_two_fa_method(&user, &state).await? {
+        Some(method) => handle_2fa(&user, method, &state, jar).await,
+        None => handle_no_2fa(&user, client, &state, jar).await,
+    }
+}
+
+// Turned away logins are told when to try again, without saying which limit they hit
+pub fn login_attempt_error(e: LoginAttemptStoreError) -> AuthAPIError {
+    match e {
+        LoginAttemptStoreError::TooManyAttempts {
+            retry_after_seconds,
+        }
+        | LoginAttemptStoreError::AccountLocked {
+            retry_after_seconds,
+        } => AuthAPIError::TooManyLoginAttempts {
+            retry_after_seconds,
+        },
+        LoginAttemptStoreError::UnexpectedError(e) => AuthAPIError::UnexpectedError(e),
+    }
+}
+
+// Users with a passkey or an authenticator app enrolled use it instead of an emailed code
+#[instrument(name = "Get 2FA method", skip_all)]
+pub async fn get_two_fa_method(
+    user: &User,
+    state: &AppState,
+) -> Result<Option<TwoFAMethod>, AuthAPIError> {
+    let has_passkey = !state
+        .webauthn_credential_store
+        .read()
+        .await
+        .get_credentials(&user.id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?
+        .is_empty();
+
+    if has_passkey {
+        return Ok(Some(TwoFAMethod::WebAuthn));
+    }
+
+    match state
+        .totp_secret_store
+        .read()
+        .await
+        .get_secret(&user.id)
+        .await
+    {
+        Ok(record) if record.confirmed => Ok(Some(TwoFAMethod::Totp)),
+        Ok(_) | Err(TotpSecretStoreError::SecretNotFound) => {
+            Ok(user.requires_2fa.then_some(TwoFAMethod::Email))
+        }
+        Err(e) => Err(AuthAPIError::UnexpectedError(e.into())),
     }
 }
 
 // New!
 #[instrument(name = "Handle 2fa", skip_all)]
 async fn handle_2fa(
-    email: &Email,
+    user: &User,
+    method: TwoFAMethod,
     state: &AppState,
     jar: CookieJar,
 ) -> Result<(CookieJar, (StatusCode, Json<LoginResponse>)), AuthAPIError> {
@@ -63,23 +153,38 @@ async fn handle_2fa(
         .two_fa_code_store
         .write()
         .await
-        .add_code(email.clone(), login_attempt_id.clone(), two_fa_code.clone())
+        .add_code(user.id, login_attempt_id.clone(), two_fa_code.clone())
         .await
     {
         return Err(AuthAPIError::UnexpectedError(e.into()));
     }
 
-    if let Err(e) = state
-        .email_client
-        .send_email(email, "2FA Code", two_fa_code.as_ref().expose_secret())
-        .await
-    {
-        return Err(AuthAPIError::UnexpectedError(e));
-    }
+    // TOTP users read their code from their authenticator and passkey users sign a challenge
+    // instead, so the generated code is only sent to users who receive their codes by email
+    let public_key = match method {
+        TwoFAMethod::Email => {
+            if let Err(e) = state
+                .email_client
+                .send_email(
+                    &user.email,
+                    "2FA Code",
+                    two_fa_code.as_ref().expose_secret(),
+                )
+                .await
+            {
+                return Err(AuthAPIError::UnexpectedError(e));
+            }
+            None
+        }
+        TwoFAMethod::Totp => None,
+        TwoFAMethod::WebAuthn => Some(start_webauthn_authentication(&user.id, state).await?),
+    };
 
     let response = Json(LoginResponse::TwoFactorAuth(TwoFactorAuthResponse {
         message: "2FA required".to_owned(),
         login_attempt_id: login_attempt_id.as_ref().expose_secret().to_owned(),
+        two_fa_method: method,
+        public_key,
     }));
 
     Ok((jar, (StatusCode::PARTIAL_CONTENT, response)))
@@ -87,17 +192,12 @@ async fn handle_2fa(
 
 #[instrument(name = "Handle no 2fa", skip_all)]
 async fn handle_no_2fa(
-    email: &Email,
+    user: &User,
+    client: ClientInfo,
+    state: &AppState,
     jar: CookieJar,
 ) -> Result<(CookieJar, (StatusCode, Json<LoginResponse>)), AuthAPIError> {
-    let auth_cookie = match generate_auth_cookie(email) {
-        Ok(cookie) => cookie,
-        Err(e) => {
-            return Err(AuthAPIError::UnexpectedError(e));
-        }
-    };
-
-    let updated_jar = jar.add(auth_cookie);
+    let updated_jar = start_session(&user.id, client, state, jar).await?;
 
     Ok((
         updated_jar,
@@ -126,4 +226,18 @@ pub struct TwoFactorAuthResponse {
     pub message: String,
     #[serde(rename = "loginAttemptId")]
     pub login_attempt_id: String,
+    #[serde(rename = "2FAMethod")]
+    pub two_fa_method: TwoFAMethod,
+    // The options to pass to `navigator.credentials.get()` when the method is WebAuthn
+    #[serde(rename = "publicKey", default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<PublicKeyCredentialRequestOptions>,
+}
+
+// Where the user should get their 2FA code from
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TwoFAMethod {
+    Email,
+    Totp,
+    WebAuthn,
 }
@@ -1,11 +1,15 @@
 use axum::{extract::State, http::StatusCode, response::IntoResponse};
-use axum_extra::extract::{cookie, CookieJar};
+use axum_extra::extract::CookieJar;
 use secrecy::Secret;
 use tracing::instrument;
 
 use crate::{
     domain::{AppState, AuthAPIError},
-    utils::{auth::validate_token, constants::JWT_COOKIE_NAME},
+    services::{SessionId, TokenId},
+    utils::{
+        auth::{authenticate, remove_auth_cookies, revoke_all_sessions, validate_token},
+        constants::JWT_COOKIE_NAME,
+    },
 };
 
 #[instrument(name = "Logout", skip_all)]
@@ -23,8 +27,13 @@ pub async fn logout(
     let token = Secret::new(cookie.value().to_owned());
 
     // validate the token
-    match validate_token(&token, state.banned_token_store.clone()).await {
-        Ok(_) => (),
+    let claims = match validate_token(&token, &state).await {
+        Ok(claims) => claims,
+        Err(_) => return Err(AuthAPIError::InvalidToken),
+    };
+
+    let token_id = match TokenId::parse(claims.jti) {
+        Ok(token_id) => token_id,
         Err(_) => return Err(AuthAPIError::InvalidToken),
     };
 
@@ -33,7 +42,7 @@ pub async fn logout(
         .banned_token_store
         .write()
         .await
-        .is_token_banned(&token.to_owned())
+        .is_token_banned(&token_id)
         .await
         .is_err()
     {
@@ -44,15 +53,57 @@ pub async fn logout(
         .banned_token_store
         .write()
         .await
-        .ban_token(token.to_owned())
+        .ban_token(&token_id)
         .await
         .is_err()
     {
         return Err(AuthAPIError::TokenBanFailed);
     }
 
-    // Remove the JWT cookie from the CookieJar
-    let jar = jar.remove(cookie::Cookie::from(JWT_COOKIE_NAME));
+    // The session is over, whether or not the client sent its refresh token. Its refresh
+    // tokens are all in the family named after it.
+    state
+        .refresh_token_store
+        .write()
+        .await
+        .revoke_family(&claims.sid)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    if let Ok(session_id) = SessionId::parse(claims.sid) {
+        state
+            .session_store
+            .write()
+            .await
+            .remove_session(&session_id)
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    }
+
+    // Remove the JWT and refresh cookies from the CookieJar
+    let jar = remove_auth_cookies(jar);
+
+    Ok((jar, StatusCode::OK.into_response()))
+}
+
+// Sign out everywhere: invalidate every auth and refresh token issued to the logged in user,
+// for example when they suspect their account has been compromised
+#[instrument(name = "Logout all sessions", skip_all)]
+pub async fn logout_all(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<(CookieJar, impl IntoResponse), AuthAPIError> {
+    let user_id = authenticate(&jar, &state).await?;
+
+    revoke_all_sessions(
+        &user_id,
+        state.banned_token_store.clone(),
+        state.refresh_token_store.clone(),
+    )
+    .await
+    .map_err(AuthAPIError::UnexpectedError)?;
+
+    let jar = remove_auth_cookies(jar);
 
     Ok((jar, StatusCode::OK.into_response()))
 }
@@ -0,0 +1,164 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use tracing::{instrument, Instrument};
+
+use crate::{
+    domain::{AppState, AuthAPIError, Email, Password, User},
+    services::data_stores::{PasswordResetToken, PasswordResetTokenStoreError, UserStoreError},
+    utils::{
+        auth::revoke_all_sessions,
+        constants::{AUTH_SERVICE_BASE_URL, PASSWORD_RESET_TOKEN_TTL_SECONDS},
+    },
+};
+
+#[instrument(name = "Request password reset", skip_all)]
+pub async fn request_password_reset(
+    State(state): State<AppState>,
+    Json(request): Json<PasswordResetRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(request.email).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    // Always respond the same way, so the endpoint can't be used to find out who has an account
+    let response = Json(PasswordResetResponse {
+        message: "If an account exists for this email, a password reset link has been sent"
+            .to_owned(),
+    });
+
+    let user = match state
+        .user_store
+        .read()
+        .await
+        .get_user_by_email(&email)
+        .await
+    {
+        Ok(user) => user,
+        Err(UserStoreError::UserNotFound) => return Ok((StatusCode::OK, response)),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    // The token and email are seen to after responding, so known addresses are answered as
+    // quickly as unknown ones
+    tokio::spawn(send_password_reset_email(state, user).in_current_span());
+
+    Ok((StatusCode::OK, response))
+}
+
+// Failures are only logged - the caller has already had the same response as everyone else
+async fn send_password_reset_email(state: AppState, user: User) {
+    let token = PasswordResetToken::default();
+
+    if let Err(e) = state
+        .password_reset_token_store
+        .write()
+        .await
+        .add_token(token.clone(), user.id)
+        .await
+    {
+        tracing::error!("failed to store password reset token: {:?}", e);
+        return;
+    }
+
+    let content = format!(
+        "Use the link below to reset your password. It expires in {} minutes.\n\n{}/?reset_token={}",
+        PASSWORD_RESET_TOKEN_TTL_SECONDS / 60,
+        AUTH_SERVICE_BASE_URL.as_str(),
+        token.as_ref().expose_secret()
+    );
+
+    if let Err(e) = state
+        .email_client
+        .send_email(&user.email, "Password reset", &content)
+        .await
+    {
+        tracing::error!("failed to send password reset email: {:?}", e);
+    }
+}
+
+#[instrument(name = "Confirm password reset", skip_all)]
+pub async fn confirm_password_reset(
+    State(state): State<AppState>,
+    Json(request): Json<PasswordResetConfirmRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    // Validate the new password first, so a weak password doesn't burn the token
+    let password =
+        Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let token = PasswordResetToken::parse(request.token).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    // The policy depends on whose password it is, so look the token up without using it first
+    let user_id = match state
+        .password_reset_token_store
+        .read()
+        .await
+        .get_token(&token)
+        .await
+    {
+        Ok(user_id) => user_id,
+        Err(PasswordResetTokenStoreError::TokenNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+    let user = match state.user_store.read().await.get_user(&user_id).await {
+        Ok(user) => user,
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+    let violations = state.password_policy.check(password.as_ref(), &user.email);
+    if !violations.is_empty() {
+        return Err(AuthAPIError::PasswordRejected(violations));
+    }
+
+    let user_id = match state
+        .password_reset_token_store
+        .write()
+        .await
+        .take_token(&token)
+        .await
+    {
+        Ok(user_id) => user_id,
+        Err(PasswordResetTokenStoreError::TokenNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    match state
+        .user_store
+        .write()
+        .await
+        .update_password(&user_id, password)
+        .await
+    {
+        Ok(()) => (),
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    // Whoever knew the old password may still be logged in - sign every session out
+    revoke_all_sessions(
+        &user_id,
+        state.banned_token_store.clone(),
+        state.refresh_token_store.clone(),
+    )
+    .await
+    .map_err(AuthAPIError::UnexpectedError)?;
+
+    let response = Json(PasswordResetResponse {
+        message: "Password has been reset".to_owned(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[derive(Deserialize)]
+pub struct PasswordResetRequest {
+    pub email: Secret<String>,
+}
+
+#[derive(Deserialize)]
+pub struct PasswordResetConfirmRequest {
+    pub token: Secret<String>,
+    pub password: Secret<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct PasswordResetResponse {
+    pub message: String,
+}
@@ -0,0 +1,104 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    domain::{AppState, AuthAPIError, Password, UserId},
+    routes::{get_authenticated_user, get_two_fa_method, reauthenticate},
+    services::data_stores::{RecoveryCode, RecoveryCodeStoreError},
+    utils::{auth::authenticate, client::ClientInfo, constants::RECOVERY_CODE_COUNT},
+};
+
+#[instrument(name = "Get recovery codes", skip_all)]
+pub async fn get_recovery_codes(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let user_id = authenticate(&jar, &state).await?;
+
+    // The codes themselves can't be shown again, only how many are left
+    let remaining = state
+        .recovery_code_store
+        .read()
+        .await
+        .count_codes(&user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    Ok((StatusCode::OK, Json(RecoveryCodesResponse { remaining })))
+}
+
+#[instrument(name = "Regenerate recovery codes", skip_all)]
+pub async fn regenerate_recovery_codes(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    client: ClientInfo,
+    Json(request): Json<RegenerateRecoveryCodesRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let user_id = authenticate(&jar, &state).await?;
+    let user = get_authenticated_user(&user_id, &state).await?;
+
+    let password =
+        Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    reauthenticate(&user.email, &password, &client, &state).await?;
+
+    // Recovery codes back up whichever 2FA method the user has, passkeys included
+    if get_two_fa_method(&user, &state).await?.is_none() {
+        return Err(AuthAPIError::TwoFANotEnabled);
+    }
+
+    let codes = issue_recovery_codes(&user_id, &state)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let response = Json(RegenerateRecoveryCodesResponse {
+        recovery_codes: codes,
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+// Generate a new set of recovery codes for the user, replacing any they already had. The codes
+// are returned so they can be shown to the user once - only their hashes are kept.
+#[instrument(name = "Issue recovery codes", skip_all)]
+pub async fn issue_recovery_codes(
+    user_id: &UserId,
+    state: &AppState,
+) -> Result<Vec<String>, RecoveryCodeStoreError> {
+    let codes: Vec<RecoveryCode> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| RecoveryCode::default())
+        .collect();
+
+    let response = codes
+        .iter()
+        .map(|code| code.as_ref().expose_secret().to_owned())
+        .collect();
+
+    state
+        .recovery_code_store
+        .write()
+        .await
+        .replace_codes(user_id, codes)
+        .await?;
+
+    Ok(response)
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct RecoveryCodesResponse {
+    pub remaining: usize,
+}
+
+#[derive(Deserialize)]
+pub struct RegenerateRecoveryCodesRequest {
+    pub password: Secret<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct RegenerateRecoveryCodesResponse {
+    #[serde(rename = "recoveryCodes")]
+    pub recovery_codes: Vec<String>,
+}
@@ -0,0 +1,77 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use axum_extra::extract::CookieJar;
+use secrecy::Secret;
+use tracing::instrument;
+
+use crate::{
+    domain::{AppState, AuthAPIError},
+    routes::continue_session,
+    services::{RefreshToken, RefreshTokenStoreError},
+    utils::{
+        auth::{generate_auth_cookie, generate_refresh_cookie},
+        client::ClientInfo,
+        constants::REFRESH_COOKIE_NAME,
+    },
+};
+
+#[instrument(name = "Refresh", skip_all)]
+pub async fn refresh(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    client: ClientInfo,
+) -> Result<(CookieJar, impl IntoResponse), AuthAPIError> {
+    let cookie = match jar.get(REFRESH_COOKIE_NAME) {
+        Some(cookie) => cookie,
+        None => return Err(AuthAPIError::MissingToken),
+    };
+
+    let token = RefreshToken::parse(Secret::new(cookie.value().to_owned()))
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    // Hold the write lock for the whole check-and-rotate so a token can only be used once
+    let mut refresh_token_store = state.refresh_token_store.write().await;
+
+    let record = match refresh_token_store.get_token(&token).await {
+        Ok(record) => record,
+        Err(RefreshTokenStoreError::TokenNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    match refresh_token_store
+        .is_family_revoked(&record.family_id)
+        .await
+    {
+        Ok(false) => (),
+        Ok(true) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    // A token that has already been rotated should never be presented again. If it is,
+    // assume it was stolen and revoke every token descended from the same login.
+    if record.used {
+        tracing::warn!("refresh token reuse detected, revoking token family");
+        if let Err(e) = refresh_token_store.revoke_family(&record.family_id).await {
+            return Err(AuthAPIError::UnexpectedError(e.into()));
+        }
+        return Err(AuthAPIError::InvalidToken);
+    }
+
+    if let Err(e) = refresh_token_store.mark_token_used(&token).await {
+        return Err(AuthAPIError::UnexpectedError(e.into()));
+    }
+
+    drop(refresh_token_store);
+
+    let session = continue_session(&record, client, &state).await?;
+
+    let auth_cookie = generate_auth_cookie(&session, state.banned_token_store.clone())
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+    let refresh_cookie = generate_refresh_cookie(record.rotate(), state.refresh_token_store)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    let updated_jar = jar.add(auth_cookie).add(refresh_cookie);
+
+    Ok((updated_jar, StatusCode::OK.into_response()))
+}
@@ -0,0 +1,69 @@
+use axum::{extract::State, http::StatusCode, Form};
+use color_eyre::eyre::Result;
+use secrecy::Secret;
+use serde::Deserialize;
+use tracing::instrument;
+
+use crate::{
+    domain::{AppState, AuthAPIError},
+    services::TokenId,
+    utils::auth::{revoke_refresh_token, validate_token},
+};
+
+// OAuth 2.0 token revocation (RFC 7009). Holding a token is enough to revoke it, so unlike
+// `/logout` the token can come from anywhere, not just the `jwt` cookie.
+#[instrument(name = "Revoke", skip_all)]
+pub async fn revoke(
+    State(state): State<AppState>,
+    Form(request): Form<RevokeRequest>,
+) -> Result<StatusCode, AuthAPIError> {
+    // The hint only decides which kind of token to look for first
+    let revoked = match request.token_type_hint.as_deref() {
+        Some("refresh_token") => {
+            revoke_refresh_token(request.token.clone(), state.refresh_token_store.clone())
+                .await
+                .map_err(AuthAPIError::UnexpectedError)?
+                || revoke_auth_token(&state, &request.token).await?
+        }
+        _ => {
+            revoke_auth_token(&state, &request.token).await?
+                || revoke_refresh_token(request.token, state.refresh_token_store.clone())
+                    .await
+                    .map_err(AuthAPIError::UnexpectedError)?
+        }
+    };
+
+    // Invalid, expired and already revoked tokens are still a success, so callers learn
+    // nothing about the token (RFC 7009 section 2.2)
+    if !revoked {
+        tracing::debug!("nothing to revoke");
+    }
+
+    Ok(StatusCode::OK)
+}
+
+// Ban an auth token by its `jti`, returning whether it was still valid
+async fn revoke_auth_token(state: &AppState, token: &Secret<String>) -> Result<bool, AuthAPIError> {
+    let claims = match validate_token(token, state).await {
+        Ok(claims) => claims,
+        Err(_) => return Ok(false),
+    };
+
+    let token_id = TokenId::parse(claims.jti).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    state
+        .banned_token_store
+        .write()
+        .await
+        .ban_token(&token_id)
+        .await
+        .map_err(|_| AuthAPIError::TokenBanFailed)?;
+
+    Ok(true)
+}
+
+#[derive(Deserialize)]
+pub struct RevokeRequest {
+    pub token: Secret<String>,
+    pub token_type_hint: Option<String>,
+}
@@ -0,0 +1,251 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    domain::{AppState, AuthAPIError, UserId},
+    services::{RefreshTokenRecord, Session, SessionId, SessionStoreError, TokenId},
+    utils::{
+        auth::{
+            authenticate_claims, generate_auth_cookie, generate_refresh_cookie,
+            remove_auth_cookies, REFRESH_TOKEN_TTL_SECONDS,
+        },
+        client::ClientInfo,
+    },
+};
+
+#[instrument(name = "List sessions", skip_all)]
+pub async fn list_sessions(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let claims = authenticate_claims(&jar, &state).await?;
+    let user_id = UserId::parse(&claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let sessions = state
+        .session_store
+        .read()
+        .await
+        .get_sessions(&user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let mut active_sessions = Vec::with_capacity(sessions.len());
+    for session in sessions {
+        if is_session_active(&session, &state).await? {
+            active_sessions.push(SessionResponse::new(&session, &claims.sid));
+        } else {
+            // Sessions end when their refresh tokens do, so there's nothing left to revoke
+            state
+                .session_store
+                .write()
+                .await
+                .remove_session(&session.id)
+                .await
+                .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+        }
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(ListSessionsResponse {
+            sessions: active_sessions,
+        }),
+    ))
+}
+
+// Log a session out remotely, banning its auth token and revoking its refresh tokens
+#[instrument(name = "Delete session", skip_all)]
+pub async fn delete_session(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Path(id): Path<String>,
+) -> Result<(CookieJar, impl IntoResponse), AuthAPIError> {
+    let claims = authenticate_claims(&jar, &state).await?;
+    let user_id = UserId::parse(&claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let session_id = SessionId::parse(id).map_err(|_| AuthAPIError::SessionNotFound)?;
+
+    let session = match state
+        .session_store
+        .read()
+        .await
+        .get_session(&session_id)
+        .await
+    {
+        // Other users' sessions are treated as if they don't exist
+        Ok(session) if session.user_id == user_id => session,
+        Ok(_) | Err(SessionStoreError::SessionNotFound) => {
+            return Err(AuthAPIError::SessionNotFound)
+        }
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    state
+        .banned_token_store
+        .write()
+        .await
+        .ban_token(&session.token_id)
+        .await
+        .map_err(|_| AuthAPIError::TokenBanFailed)?;
+
+    state
+        .refresh_token_store
+        .write()
+        .await
+        .revoke_family(session.id.as_ref())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    state
+        .session_store
+        .write()
+        .await
+        .remove_session(&session.id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    // Ending the current session logs the caller out
+    let jar = if session.id.as_ref() == claims.sid {
+        remove_auth_cookies(jar)
+    } else {
+        jar
+    };
+
+    Ok((jar, StatusCode::OK))
+}
+
+// Record a new session for a user who has fully authenticated and issue its auth and refresh
+// cookies - called from the login, verify_2fa and WebAuthn route handlers
+#[instrument(name = "Start session", skip_all)]
+pub async fn start_session(
+    user_id: &UserId,
+    client: ClientInfo,
+    state: &AppState,
+    jar: CookieJar,
+) -> Result<CookieJar, AuthAPIError> {
+    let session = Session::new(*user_id, client.ip_address, client.user_agent);
+
+    let auth_cookie = generate_auth_cookie(&session, state.banned_token_store.clone())
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    let refresh_cookie = generate_refresh_cookie(
+        RefreshTokenRecord::new(*user_id, &session.id),
+        state.refresh_token_store.clone(),
+    )
+    .await
+    .map_err(AuthAPIError::UnexpectedError)?;
+
+    state
+        .session_store
+        .write()
+        .await
+        .add_session(session)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    Ok(jar.add(auth_cookie).add(refresh_cookie))
+}
+
+// Move a session on to a new auth token - called from the refresh route handler
+#[instrument(name = "Continue session", skip_all)]
+pub async fn continue_session(
+    record: &RefreshTokenRecord,
+    client: ClientInfo,
+    state: &AppState,
+) -> Result<Session, AuthAPIError> {
+    let session_id =
+        SessionId::parse(record.family_id.clone()).map_err(|_| AuthAPIError::InvalidToken)?;
+    let token_id = TokenId::default();
+
+    let mut session_store = state.session_store.write().await;
+
+    match session_store.touch_session(&session_id, &token_id).await {
+        Ok(()) => session_store
+            .get_session(&session_id)
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into())),
+        // Logins from before sessions were recorded get one the next time they refresh, but
+        // sessions that have been ended stay ended
+        Err(SessionStoreError::SessionNotFound) => {
+            let is_revoked = state
+                .refresh_token_store
+                .read()
+                .await
+                .is_family_revoked(&record.family_id)
+                .await
+                .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+            if is_revoked {
+                return Err(AuthAPIError::InvalidToken);
+            }
+
+            let session = Session {
+                id: session_id,
+                token_id,
+                ..Session::new(record.user_id, client.ip_address, client.user_agent)
+            };
+            session_store
+                .add_session(session.clone())
+                .await
+                .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+            Ok(session)
+        }
+        Err(e) => Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+}
+
+// A session is over once its refresh tokens have been revoked or have expired
+async fn is_session_active(session: &Session, state: &AppState) -> Result<bool, AuthAPIError> {
+    let expires_at = session.last_seen + Duration::seconds(REFRESH_TOKEN_TTL_SECONDS);
+    if expires_at < Utc::now() {
+        return Ok(false);
+    }
+
+    let is_revoked = state
+        .refresh_token_store
+        .read()
+        .await
+        .is_family_revoked(session.id.as_ref())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    Ok(!is_revoked)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListSessionsResponse {
+    pub sessions: Vec<SessionResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionResponse {
+    pub id: String,
+    pub created_at: String,
+    pub last_seen: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    // Whether this is the session the request was made from
+    pub current: bool,
+}
+
+impl SessionResponse {
+    pub(crate) fn new(session: &Session, current_session_id: &str) -> Self {
+        Self {
+            id: session.id.as_ref().to_owned(),
+            created_at: session.created_at.to_rfc3339(),
+            last_seen: session.last_seen.to_rfc3339(),
+            ip_address: session.ip_address.clone(),
+            user_agent: session.user_agent.clone(),
+            current: session.id.as_ref() == current_session_id,
+        }
+    }
+}
@@ -1,9 +1,17 @@
 use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
-use secrecy::Secret;
+use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
-use crate::domain::{AppState, AuthAPIError, Email, Password, User};
+use crate::{
+    domain::{AppState, AuthAPIError, Email, Password, User},
+    routes::{issue_recovery_codes, send_verification_email},
+    services::{
+        data_stores::{postgres_user_store::compute_password_hash, UserStoreError},
+        RecoveryCode,
+    },
+    utils::constants::{AUTH_SERVICE_BASE_URL, PASSWORD_HASH_PARAMS, RECOVERY_CODE_COUNT},
+};
 
 #[instrument(name = "Signup", skip_all)]
 pub async fn signup(
@@ -15,24 +23,105 @@ pub async fn signup(
     let password =
         Password::parse(request.password.clone()).map_err(|_| AuthAPIError::InvalidCredentials)?;
 
+    // Checked before looking the user up, so the response doesn't depend on whether they exist
+    let violations = state.password_policy.check(&request.password, &email);
+    if !violations.is_empty() {
+        return Err(AuthAPIError::PasswordRejected(violations));
+    }
+
     // create a new 'User' from the request data
     let user = User::new(email.clone(), password, request.requires_2fa);
 
     let mut user_store = state.user_store.write().await;
 
-    if user_store.get_user(&user.email).await.is_ok() {
-        return Err(AuthAPIError::UserAlreadyExists);
+    // The password is hashed before the email address is found to be taken, so signing up with
+    // an existing address takes as long as creating an account. A signup that loses a race for
+    // the same address gets the same response.
+    let user_id = user.id;
+    match user_store.add_user(user).await {
+        Ok(()) => (),
+        Err(UserStoreError::UserAlreadyExists) if state.enumeration_safe => {
+            drop(user_store);
+            return existing_user_response(&email, request.requires_2fa, &state).await;
+        }
+        Err(UserStoreError::UserAlreadyExists) => return Err(AuthAPIError::UserAlreadyExists),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    drop(user_store);
+
+    // Recovery codes let the user back in if they lose access to their 2FA codes
+    let recovery_codes = match request.requires_2fa {
+        true => issue_recovery_codes(&user_id, &state)
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?,
+        false => Vec::new(),
+    };
+
+    // The account exists at this point - if the email can't be sent the user can ask for another
+    if let Err(e) = send_verification_email(&user_id, &email, &state).await {
+        tracing::error!("failed to send verification email: {:?}", e);
     }
 
-    if let Err(e) = user_store.add_user(user).await {
-        return Err(AuthAPIError::UnexpectedError(e.into()));
+    Ok((StatusCode::CREATED, signup_response(&email, recovery_codes)))
+}
+
+// Respond as if the account was created, and let the owner know instead
+async fn existing_user_response(
+    email: &Email,
+    requires_2fa: bool,
+    state: &AppState,
+) -> Result<(StatusCode, Json<SignupResponse>), AuthAPIError> {
+    notify_existing_user(email, state).await;
+
+    // Hashed and thrown away, since real recovery codes are hashed before they're returned
+    let mut recovery_codes = Vec::new();
+    if requires_2fa {
+        for _ in 0..RECOVERY_CODE_COUNT {
+            let code = RecoveryCode::default();
+            compute_password_hash(code.as_ref().to_owned(), PASSWORD_HASH_PARAMS.clone(), None)
+                .await
+                .map_err(AuthAPIError::UnexpectedError)?;
+            recovery_codes.push(code.as_ref().expose_secret().to_owned());
+        }
     }
 
-    let response = Json(SignupResponse {
+    Ok((StatusCode::CREATED, signup_response(email, recovery_codes)))
+}
+
+fn signup_response(email: &Email, recovery_codes: Vec<String>) -> Json<SignupResponse> {
+    Json(SignupResponse {
         message: format!("User {:?} created successfully", email),
-    });
+        recovery_codes,
+    })
+}
 
-    Ok((StatusCode::CREATED, response))
+// Tell the owner of an existing account that someone tried to sign up with their address. These
+// emails count towards the verification email limit, so signups can't be used to flood an inbox.
+async fn notify_existing_user(email: &Email, state: &AppState) {
+    if let Err(e) = state
+        .email_verification_token_store
+        .write()
+        .await
+        .record_email_sent(email)
+        .await
+    {
+        tracing::warn!("not telling existing user about signup: {:?}", e);
+        return;
+    }
+
+    let content = format!(
+        "Someone tried to sign up with this email address, but it already has an account. If it was you, log in at {} or reset your password. Otherwise you can ignore this email.",
+        AUTH_SERVICE_BASE_URL.as_str()
+    );
+
+    if let Err(e) = state
+        .email_client
+        .send_email(email, "You already have an account", &content)
+        .await
+    {
+        tracing::error!("failed to tell existing user about signup: {:?}", e);
+    }
 }
 
 #[derive(Deserialize)]
@@ -46,4 +135,11 @@ pub struct SignupRequest {
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct SignupResponse {
     pub message: String,
+    // Only returned to users who sign up with 2FA
+    #[serde(
+        rename = "recoveryCodes",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub recovery_codes: Vec<String>,
 }
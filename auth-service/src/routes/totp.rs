@@ -0,0 +1,168 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    domain::{AppState, AuthAPIError, Password},
+    routes::{get_authenticated_user, issue_recovery_codes, reauthenticate},
+    services::data_stores::{
+        ConfirmationCodeStoreError, TotpSecret, TotpSecretStoreError, TwoFACode,
+    },
+    utils::{
+        auth::authenticate,
+        client::ClientInfo,
+        totp::{check_totp_code, totp_uri},
+    },
+};
+
+#[instrument(name = "Enroll TOTP", skip_all)]
+pub async fn enroll_totp(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    client: ClientInfo,
+    Json(request): Json<EnrollTotpRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let user_id = authenticate(&jar, &state).await?;
+    let user = get_authenticated_user(&user_id, &state).await?;
+
+    let password =
+        Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    reauthenticate(&user.email, &password, &client, &state).await?;
+
+    let mut totp_secret_store = state.totp_secret_store.write().await;
+
+    // An enrolled authenticator has to stay in place - replacing it here would let anyone
+    // holding a stolen session swap in their own
+    match totp_secret_store.get_secret(&user_id).await {
+        Ok(record) if record.confirmed => return Err(AuthAPIError::TotpAlreadyEnrolled),
+        Ok(_) | Err(TotpSecretStoreError::SecretNotFound) => (),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let secret = TotpSecret::default();
+    let otpauth_uri = totp_uri(&secret, &user.email).map_err(AuthAPIError::UnexpectedError)?;
+
+    if let Err(e) = totp_secret_store.add_secret(&user_id, secret.clone()).await {
+        return Err(AuthAPIError::UnexpectedError(e.into()));
+    }
+
+    let response = Json(EnrollTotpResponse {
+        secret: secret.as_ref().expose_secret().to_owned(),
+        otpauth_uri,
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[instrument(name = "Confirm TOTP", skip_all)]
+pub async fn confirm_totp(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<ConfirmTotpRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let user_id = authenticate(&jar, &state).await?;
+
+    let code = TwoFACode::parse(request.code).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    // Wrong codes count towards the same limit as codes emailed to confirm account changes
+    match state
+        .confirmation_code_store
+        .read()
+        .await
+        .check_attempts(&user_id)
+        .await
+    {
+        Ok(()) => (),
+        Err(ConfirmationCodeStoreError::TooManyAttempts) => {
+            return Err(AuthAPIError::TooManyRequests)
+        }
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let record = match state
+        .totp_secret_store
+        .read()
+        .await
+        .get_secret(&user_id)
+        .await
+    {
+        Ok(record) if record.confirmed => return Err(AuthAPIError::TotpAlreadyEnrolled),
+        Ok(record) => record,
+        Err(TotpSecretStoreError::SecretNotFound) => return Err(AuthAPIError::InvalidCredentials),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    let is_valid = check_totp_code(
+        &user_id,
+        &record.secret,
+        &code,
+        state.totp_secret_store.clone(),
+    )
+    .await
+    .map_err(AuthAPIError::UnexpectedError)?;
+
+    if !is_valid {
+        return Err(
+            match state
+                .confirmation_code_store
+                .write()
+                .await
+                .record_failed_attempt(&user_id)
+                .await
+            {
+                Ok(()) => AuthAPIError::IncorrectCredentials,
+                Err(ConfirmationCodeStoreError::TooManyAttempts) => AuthAPIError::TooManyRequests,
+                Err(e) => AuthAPIError::UnexpectedError(e.into()),
+            },
+        );
+    }
+
+    if let Err(e) = state
+        .totp_secret_store
+        .write()
+        .await
+        .confirm_secret(&user_id)
+        .await
+    {
+        return Err(AuthAPIError::UnexpectedError(e.into()));
+    }
+
+    // Enrolling an authenticator starts a new set of recovery codes for it
+    let recovery_codes = issue_recovery_codes(&user_id, &state)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let response = Json(ConfirmTotpResponse {
+        message: "TOTP enrolled".to_owned(),
+        recovery_codes,
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[derive(Deserialize)]
+pub struct EnrollTotpRequest {
+    pub password: Secret<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct EnrollTotpResponse {
+    pub secret: String,
+    #[serde(rename = "otpauthUri")]
+    pub otpauth_uri: String,
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmTotpRequest {
+    pub code: Secret<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ConfirmTotpResponse {
+    pub message: String,
+    #[serde(rename = "recoveryCodes")]
+    pub recovery_codes: Vec<String>,
+}
@@ -5,15 +5,19 @@ use serde::Deserialize;
 use tracing::instrument;
 
 use crate::{
-    domain::{AppState, AuthAPIError, Email},
-    services::{LoginAttemptId, TwoFACode},
-    utils::auth::generate_auth_cookie,
+    domain::{AppState, AuthAPIError, Email, User},
+    routes::{get_two_fa_method, start_session, TwoFAMethod},
+    services::{
+        LoginAttemptId, RecoveryCode, RecoveryCodeStoreError, TwoFACode, TwoFACodeStoreError,
+    },
+    utils::{client::ClientInfo, totp::check_totp_code},
 };
 
 #[instrument(name = "Verify 2FA", skip_all)]
 pub async fn verify_2fa(
     State(state): State<AppState>,
     jar: CookieJar,
+    client: ClientInfo,
     Json(request): Json<Verify2FARequest>,
 ) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
     let email = match Email::parse(request.email.clone().into()) {
@@ -26,34 +30,117 @@ pub async fn verify_2fa(
         Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
     };
 
-    let two_fa_code = match TwoFACode::parse(request.two_fa_code) {
-        Ok(two_fa_code) => two_fa_code,
-        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+    // Users who have lost access to their 2FA method can send a recovery code instead
+    let submitted_code = match TwoFACode::parse(request.two_fa_code.clone()) {
+        Ok(two_fa_code) => SubmittedCode::TwoFA(two_fa_code),
+        Err(_) => match RecoveryCode::parse(request.two_fa_code) {
+            Ok(recovery_code) => SubmittedCode::Recovery(recovery_code),
+            Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+        },
+    };
+
+    let user = match state
+        .user_store
+        .read()
+        .await
+        .get_user_by_email(&email)
+        .await
+    {
+        Ok(user) => user,
+        Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
     };
 
     let mut two_fa_code_store = state.two_fa_code_store.write().await;
 
-    let code_tuple = match two_fa_code_store.get_code(&email).await {
+    let code_tuple = match two_fa_code_store.get_code(&user.id).await {
         Ok(code_tuple) => code_tuple,
         Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
     };
 
-    if !code_tuple.0.eq(&login_attempt_id) || !code_tuple.1.eq(&two_fa_code) {
+    if !code_tuple.0.eq(&login_attempt_id) {
         return (jar, Err(AuthAPIError::IncorrectCredentials));
     }
 
-    if let Err(e) = two_fa_code_store.remove_code(&email).await {
+    let is_valid = match submitted_code {
+        SubmittedCode::TwoFA(two_fa_code) => {
+            match check_two_fa_code(&user, &two_fa_code, &code_tuple.1, &state).await {
+                Ok(is_valid) => is_valid,
+                Err(e) => return (jar, Err(e)),
+            }
+        }
+        // Recovery codes are burnt as they are used
+        SubmittedCode::Recovery(recovery_code) => match state
+            .recovery_code_store
+            .write()
+            .await
+            .use_code(&user.id, &recovery_code)
+            .await
+        {
+            Ok(()) => true,
+            Err(RecoveryCodeStoreError::CodeNotFound) => false,
+            Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+        },
+    };
+
+    // Every wrong code counts against the login attempt, which is thrown away after too many
+    if !is_valid {
+        let e = match two_fa_code_store.record_failed_attempt(&user.id).await {
+            Ok(()) | Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => {
+                AuthAPIError::IncorrectCredentials
+            }
+            Err(TwoFACodeStoreError::TooManyAttempts) => AuthAPIError::TooManyRequests,
+            Err(e) => AuthAPIError::UnexpectedError(e.into()),
+        };
+        return (jar, Err(e));
+    }
+
+    if let Err(e) = two_fa_code_store.remove_code(&user.id).await {
         return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
     }
 
-    let cookie = match generate_auth_cookie(&email) {
-        Ok(cookie) => cookie,
-        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
-    };
+    // Hold on to the jar in case starting the session fails
+    match start_session(&user.id, client, &state, jar.clone()).await {
+        Ok(updated_jar) => (updated_jar, Ok(())),
+        Err(e) => (jar, Err(e)),
+    }
+}
 
-    let updated_jar = jar.add(cookie);
+// Check a code against the user's 2FA method. Passkey users answer their challenge at
+// `/2fa/webauthn/verify`, and the code generated for them at login is never sent anywhere, so
+// it can't be used here.
+async fn check_two_fa_code(
+    user: &User,
+    two_fa_code: &TwoFACode,
+    emailed_code: &TwoFACode,
+    state: &AppState,
+) -> Result<bool, AuthAPIError> {
+    match get_two_fa_method(user, state).await? {
+        Some(TwoFAMethod::Email) => Ok(emailed_code.eq(two_fa_code)),
+        Some(TwoFAMethod::Totp) => {
+            let record = state
+                .totp_secret_store
+                .read()
+                .await
+                .get_secret(&user.id)
+                .await
+                .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+            check_totp_code(
+                &user.id,
+                &record.secret,
+                two_fa_code,
+                state.totp_secret_store.clone(),
+            )
+            .await
+            .map_err(AuthAPIError::UnexpectedError)
+        }
+        Some(TwoFAMethod::WebAuthn) | None => Ok(false),
+    }
+}
 
-    (updated_jar, Ok(()))
+enum SubmittedCode {
+    TwoFA(TwoFACode),
+    Recovery(RecoveryCode),
 }
 
 #[derive(Debug, Deserialize)]
@@ -0,0 +1,154 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use color_eyre::eyre::Result;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use tracing::{instrument, Instrument};
+
+use crate::{
+    domain::{AppState, AuthAPIError, Email, UserId},
+    services::data_stores::{
+        EmailVerificationToken, EmailVerificationTokenStoreError, UserStoreError,
+    },
+    utils::constants::{AUTH_SERVICE_BASE_URL, EMAIL_VERIFICATION_TOKEN_TTL_SECONDS},
+};
+
+#[instrument(name = "Verify email", skip_all)]
+pub async fn verify_email(
+    State(state): State<AppState>,
+    Query(params): Query<VerifyEmailParams>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let token =
+        EmailVerificationToken::parse(params.token).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let user_id = match state
+        .email_verification_token_store
+        .write()
+        .await
+        .take_token(&token)
+        .await
+    {
+        Ok(user_id) => user_id,
+        Err(EmailVerificationTokenStoreError::TokenNotFound) => {
+            return Err(AuthAPIError::InvalidToken)
+        }
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    match state
+        .user_store
+        .write()
+        .await
+        .mark_email_verified(&user_id)
+        .await
+    {
+        Ok(()) => (),
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let response = Json(VerifyEmailResponse {
+        message: "Email verified".to_owned(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[instrument(name = "Resend verification email", skip_all)]
+pub async fn resend_verification_email(
+    State(state): State<AppState>,
+    Json(request): Json<ResendVerificationEmailRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(request.email).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    // Respond the same way for unknown, already verified and rate limited users, so the
+    // endpoint can't be used to find out who has an account waiting to be verified
+    let response = Json(VerifyEmailResponse {
+        message: "If the email needs verifying, a verification link has been sent".to_owned(),
+    });
+
+    let user = match state
+        .user_store
+        .read()
+        .await
+        .get_user_by_email(&email)
+        .await
+    {
+        Ok(user) => user,
+        Err(UserStoreError::UserNotFound) => return Ok((StatusCode::OK, response)),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    if user.verified {
+        return Ok((StatusCode::OK, response));
+    }
+
+    // Like password reset emails, the email is sent after responding so it doesn't take longer
+    tokio::spawn(
+        async move {
+            match send_verification_email(&user.id, &user.email, &state).await {
+                Ok(()) => (),
+                Err(EmailVerificationTokenStoreError::TooManyEmails) => {
+                    tracing::warn!("too many verification emails requested")
+                }
+                Err(e) => tracing::error!("failed to resend verification email: {:?}", e),
+            }
+        }
+        .in_current_span(),
+    );
+
+    Ok((StatusCode::OK, response))
+}
+
+// Issue a new verification token and email the link to the user - called on signup and resend
+#[instrument(name = "Send verification email", skip_all)]
+pub async fn send_verification_email(
+    user_id: &UserId,
+    email: &Email,
+    state: &AppState,
+) -> Result<(), EmailVerificationTokenStoreError> {
+    let token = EmailVerificationToken::default();
+
+    {
+        let mut email_verification_token_store = state.email_verification_token_store.write().await;
+        email_verification_token_store
+            .record_email_sent(email)
+            .await?;
+        email_verification_token_store
+            .add_token(token.clone(), *user_id)
+            .await?;
+    }
+
+    let content = format!(
+        "Confirm your email address using the link below. It expires in {} hours.\n\n{}/verify-email?token={}",
+        EMAIL_VERIFICATION_TOKEN_TTL_SECONDS / 3600,
+        AUTH_SERVICE_BASE_URL.as_str(),
+        token.as_ref().expose_secret()
+    );
+
+    state
+        .email_client
+        .send_email(email, "Verify your email address", &content)
+        .await?;
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct VerifyEmailParams {
+    pub token: Secret<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ResendVerificationEmailRequest {
+    pub email: Secret<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct VerifyEmailResponse {
+    pub message: String,
+}
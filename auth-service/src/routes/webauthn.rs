@@ -0,0 +1,297 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    domain::{AppState, AuthAPIError, Email, Password, UserId},
+    routes::{get_authenticated_user, issue_recovery_codes, reauthenticate, start_session},
+    services::data_stores::{
+        LoginAttemptId, WebAuthnCeremony, WebAuthnChallenge, WebAuthnChallengeStoreError,
+        WebAuthnCredentialStoreError,
+    },
+    utils::{
+        auth::authenticate,
+        client::ClientInfo,
+        webauthn::{
+            creation_options, decode_credential_id, request_options, verify_authentication,
+            verify_registration, AuthenticationCredential, PublicKeyCredentialCreationOptions,
+            PublicKeyCredentialRequestOptions, RegistrationCredential,
+        },
+    },
+};
+
+#[instrument(name = "Start WebAuthn registration", skip_all)]
+pub async fn start_webauthn_registration(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    client: ClientInfo,
+    Json(request): Json<StartWebAuthnRegistrationRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let user_id = authenticate(&jar, &state).await?;
+    let user = get_authenticated_user(&user_id, &state).await?;
+
+    let password =
+        Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    reauthenticate(&user.email, &password, &client, &state).await?;
+
+    let existing_credentials = state
+        .webauthn_credential_store
+        .read()
+        .await
+        .get_credentials(&user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let challenge = WebAuthnChallenge::default();
+
+    state
+        .webauthn_challenge_store
+        .write()
+        .await
+        .add_challenge(&user_id, WebAuthnCeremony::Registration, challenge.clone())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let response = Json(StartWebAuthnRegistrationResponse {
+        public_key: creation_options(&user, &challenge, &existing_credentials),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[instrument(name = "Finish WebAuthn registration", skip_all)]
+pub async fn finish_webauthn_registration(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(credential): Json<RegistrationCredential>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let user_id = authenticate(&jar, &state).await?;
+
+    let challenge = match state
+        .webauthn_challenge_store
+        .write()
+        .await
+        .take_challenge(&user_id, WebAuthnCeremony::Registration)
+        .await
+    {
+        Ok(challenge) => challenge,
+        Err(WebAuthnChallengeStoreError::ChallengeNotFound) => {
+            return Err(AuthAPIError::InvalidCredentials)
+        }
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    let new_credential = verify_registration(&user_id, &challenge, &credential).map_err(|e| {
+        tracing::warn!("rejected WebAuthn registration: {:?}", e);
+        AuthAPIError::IncorrectCredentials
+    })?;
+
+    let mut webauthn_credential_store = state.webauthn_credential_store.write().await;
+
+    let is_first_passkey = webauthn_credential_store
+        .get_credentials(&user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?
+        .is_empty();
+
+    match webauthn_credential_store
+        .add_credential(new_credential)
+        .await
+    {
+        Ok(()) => (),
+        Err(WebAuthnCredentialStoreError::CredentialAlreadyExists) => {
+            return Err(AuthAPIError::InvalidCredentials)
+        }
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    drop(webauthn_credential_store);
+
+    // The first passkey turns on 2FA for the user, so they get recovery codes for it
+    let recovery_codes = match is_first_passkey {
+        true => issue_recovery_codes(&user_id, &state)
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?,
+        false => Vec::new(),
+    };
+
+    let response = Json(FinishWebAuthnRegistrationResponse {
+        message: "Passkey registered".to_owned(),
+        recovery_codes,
+    });
+
+    Ok((StatusCode::CREATED, response))
+}
+
+#[instrument(name = "Verify WebAuthn", skip_all)]
+pub async fn verify_webauthn(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    client: ClientInfo,
+    Json(request): Json<VerifyWebAuthnRequest>,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let email = match Email::parse(request.email) {
+        Ok(email) => email,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+    };
+
+    let login_attempt_id = match LoginAttemptId::parse(request.login_attempt_id) {
+        Ok(login_attempt_id) => login_attempt_id,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+    };
+
+    let credential_id = match decode_credential_id(&request.credential.raw_id) {
+        Ok(credential_id) => credential_id,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+    };
+
+    let user = match state
+        .user_store
+        .read()
+        .await
+        .get_user_by_email(&email)
+        .await
+    {
+        Ok(user) => user,
+        Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
+    };
+
+    let mut two_fa_code_store = state.two_fa_code_store.write().await;
+
+    let code_tuple = match two_fa_code_store.get_code(&user.id).await {
+        Ok(code_tuple) => code_tuple,
+        Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
+    };
+
+    if !code_tuple.0.eq(&login_attempt_id) {
+        return (jar, Err(AuthAPIError::IncorrectCredentials));
+    }
+
+    let challenge = match state
+        .webauthn_challenge_store
+        .write()
+        .await
+        .take_challenge(&user.id, WebAuthnCeremony::Authentication)
+        .await
+    {
+        Ok(challenge) => challenge,
+        Err(WebAuthnChallengeStoreError::ChallengeNotFound) => {
+            return (jar, Err(AuthAPIError::IncorrectCredentials))
+        }
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    };
+
+    let stored_credential = match state
+        .webauthn_credential_store
+        .read()
+        .await
+        .get_credentials(&user.id)
+        .await
+    {
+        Ok(credentials) => credentials
+            .into_iter()
+            .find(|credential| credential.credential_id == credential_id),
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    };
+
+    let stored_credential = match stored_credential {
+        Some(credential) => credential,
+        None => return (jar, Err(AuthAPIError::IncorrectCredentials)),
+    };
+
+    let sign_count =
+        match verify_authentication(&challenge, &request.credential, &stored_credential) {
+            Ok(sign_count) => sign_count,
+            Err(e) => {
+                tracing::warn!("rejected WebAuthn assertion: {:?}", e);
+                return (jar, Err(AuthAPIError::IncorrectCredentials));
+            }
+        };
+
+    match state
+        .webauthn_credential_store
+        .write()
+        .await
+        .update_sign_count(&credential_id, sign_count)
+        .await
+    {
+        Ok(()) => (),
+        Err(WebAuthnCredentialStoreError::SignCountNotIncreased)
+        | Err(WebAuthnCredentialStoreError::CredentialNotFound) => {
+            return (jar, Err(AuthAPIError::IncorrectCredentials))
+        }
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    }
+
+    if let Err(e) = two_fa_code_store.remove_code(&user.id).await {
+        return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+    }
+
+    // Hold on to the jar in case starting the session fails
+    match start_session(&user.id, client, &state, jar.clone()).await {
+        Ok(updated_jar) => (updated_jar, Ok(())),
+        Err(e) => (jar, Err(e)),
+    }
+}
+
+// Issue an authentication challenge for the user's passkeys - called from login once the
+// password has been checked
+#[instrument(name = "Start WebAuthn authentication", skip_all)]
+pub async fn start_webauthn_authentication(
+    user_id: &UserId,
+    state: &AppState,
+) -> Result<PublicKeyCredentialRequestOptions, AuthAPIError> {
+    let credentials = state
+        .webauthn_credential_store
+        .read()
+        .await
+        .get_credentials(user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let challenge = WebAuthnChallenge::default();
+
+    state
+        .webauthn_challenge_store
+        .write()
+        .await
+        .add_challenge(user_id, WebAuthnCeremony::Authentication, challenge.clone())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    Ok(request_options(&challenge, &credentials))
+}
+
+#[derive(Deserialize)]
+pub struct StartWebAuthnRegistrationRequest {
+    pub password: Secret<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct StartWebAuthnRegistrationResponse {
+    #[serde(rename = "publicKey")]
+    pub public_key: PublicKeyCredentialCreationOptions,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct FinishWebAuthnRegistrationResponse {
+    pub message: String,
+    // Only returned when the first passkey is registered
+    #[serde(
+        rename = "recoveryCodes",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct VerifyWebAuthnRequest {
+    pub email: Secret<String>,
+    #[serde(rename = "loginAttemptId")]
+    pub login_attempt_id: Secret<String>,
+    pub credential: AuthenticationCredential,
+}
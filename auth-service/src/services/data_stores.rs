@@ -1,15 +1,59 @@
+pub mod hashmap_confirmation_code_store;
+pub mod hashmap_email_change_store;
+pub mod hashmap_email_verification_token_store;
+pub mod hashmap_login_attempt_store;
+pub mod hashmap_password_reset_token_store;
+pub mod hashmap_recovery_code_store;
+pub mod hashmap_refresh_token_store;
+pub mod hashmap_session_store;
+pub mod hashmap_totp_secret_store;
 pub mod hashmap_two_fa_code_store;
 pub mod hashmap_user_store;
+pub mod hashmap_webauthn_challenge_store;
+pub mod hashmap_webauthn_credential_store;
 pub mod hashset_banned_token_store;
+pub mod postgres_recovery_code_store;
+pub mod postgres_session_store;
+pub mod postgres_totp_secret_store;
 pub mod postgres_user_store;
+pub mod postgres_webauthn_credential_store;
 pub mod redis_banned_token_store;
+pub mod redis_confirmation_code_store;
+pub mod redis_email_change_store;
+pub mod redis_email_verification_token_store;
+pub mod redis_login_attempt_store;
+pub mod redis_password_reset_token_store;
+pub mod redis_refresh_token_store;
 pub mod redis_two_fa_code_store;
+pub mod redis_webauthn_challenge_store;
 pub mod user_stores;
 
+pub use hashmap_confirmation_code_store::*;
+pub use hashmap_email_change_store::*;
+pub use hashmap_email_verification_token_store::*;
+pub use hashmap_login_attempt_store::*;
+pub use hashmap_password_reset_token_store::*;
+pub use hashmap_recovery_code_store::*;
+pub use hashmap_refresh_token_store::*;
+pub use hashmap_session_store::*;
+pub use hashmap_totp_secret_store::*;
 pub use hashmap_two_fa_code_store::*;
 pub use hashmap_user_store::*;
+pub use hashmap_webauthn_challenge_store::*;
+pub use hashmap_webauthn_credential_store::*;
 pub use hashset_banned_token_store::*;
+pub use postgres_recovery_code_store::*;
+pub use postgres_session_store::*;
+pub use postgres_totp_secret_store::*;
 pub use postgres_user_store::*;
+pub use postgres_webauthn_credential_store::*;
 pub use redis_banned_token_store::*;
+pub use redis_confirmation_code_store::*;
+pub use redis_email_change_store::*;
+pub use redis_email_verification_token_store::*;
+pub use redis_login_attempt_store::*;
+pub use redis_password_reset_token_store::*;
+pub use redis_refresh_token_store::*;
 pub use redis_two_fa_code_store::*;
+pub use redis_webauthn_challenge_store::*;
 pub use user_stores::*;
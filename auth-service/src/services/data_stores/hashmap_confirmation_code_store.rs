@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use crate::domain::UserId;
+use crate::services::data_stores::{
+    ConfirmationCodeStore, ConfirmationCodeStoreError, ConfirmationPurpose, TwoFACode,
+};
+use crate::utils::constants::MAX_TWO_FA_ATTEMPTS;
+
+#[derive(Default, Debug)]
+pub struct HashmapConfirmationCodeStore {
+    codes: HashMap<(UserId, ConfirmationPurpose), TwoFACode>,
+    // Wrong codes entered by each user, whichever code they were meant for
+    failed_attempts: HashMap<UserId, u64>,
+}
+
+impl HashmapConfirmationCodeStore {
+    fn is_locked(&self, user_id: &UserId) -> bool {
+        self.failed_attempts
+            .get(user_id)
+            .is_some_and(|failed_attempts| *failed_attempts >= MAX_TWO_FA_ATTEMPTS)
+    }
+}
+
+#[async_trait::async_trait]
+impl ConfirmationCodeStore for HashmapConfirmationCodeStore {
+    async fn add_code(
+        &mut self,
+        user_id: &UserId,
+        purpose: ConfirmationPurpose,
+        code: TwoFACode,
+    ) -> Result<(), ConfirmationCodeStoreError> {
+        self.codes.insert((*user_id, purpose), code);
+        Ok(())
+    }
+
+    async fn get_code(
+        &self,
+        user_id: &UserId,
+        purpose: ConfirmationPurpose,
+    ) -> Result<TwoFACode, ConfirmationCodeStoreError> {
+        if self.is_locked(user_id) {
+            return Err(ConfirmationCodeStoreError::TooManyAttempts);
+        }
+
+        self.codes
+            .get(&(*user_id, purpose))
+            .cloned()
+            .ok_or(ConfirmationCodeStoreError::CodeNotFound)
+    }
+
+    async fn remove_code(
+        &mut self,
+        user_id: &UserId,
+        purpose: ConfirmationPurpose,
+    ) -> Result<(), ConfirmationCodeStoreError> {
+        self.failed_attempts.remove(user_id);
+        match self.codes.remove(&(*user_id, purpose)) {
+            Some(_) => Ok(()),
+            None => Err(ConfirmationCodeStoreError::CodeNotFound),
+        }
+    }
+
+    async fn record_failed_attempt(
+        &mut self,
+        user_id: &UserId,
+    ) -> Result<(), ConfirmationCodeStoreError> {
+        *self.failed_attempts.entry(*user_id).or_default() += 1;
+
+        self.check_attempts(user_id).await
+    }
+
+    async fn check_attempts(&self, user_id: &UserId) -> Result<(), ConfirmationCodeStoreError> {
+        match self.is_locked(user_id) {
+            true => Err(ConfirmationCodeStoreError::TooManyAttempts),
+            false => Ok(()),
+        }
+    }
+
+    async fn remove_codes(&mut self, user_id: &UserId) -> Result<(), ConfirmationCodeStoreError> {
+        self.codes
+            .retain(|(code_user_id, _), _| code_user_id != user_id);
+        self.failed_attempts.remove(user_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_add_and_get_code() {
+        let mut store = HashmapConfirmationCodeStore::default();
+        let user_id = UserId::default();
+        let code = TwoFACode::default();
+
+        let result = store
+            .add_code(&user_id, ConfirmationPurpose::EnableEmail2FA, code.clone())
+            .await;
+        assert_eq!(result, Ok(()));
+
+        let result = store
+            .get_code(&user_id, ConfirmationPurpose::EnableEmail2FA)
+            .await;
+        assert_eq!(result, Ok(code));
+
+        // A code is only good for what it was sent for
+        let result = store
+            .get_code(&user_id, ConfirmationPurpose::DisableEmail2FA)
+            .await;
+        assert_eq!(result, Err(ConfirmationCodeStoreError::CodeNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_remove_code() {
+        let mut store = HashmapConfirmationCodeStore::default();
+        let user_id = UserId::default();
+
+        store
+            .add_code(
+                &user_id,
+                ConfirmationPurpose::EnableEmail2FA,
+                TwoFACode::default(),
+            )
+            .await
+            .unwrap();
+
+        let result = store
+            .remove_code(&user_id, ConfirmationPurpose::EnableEmail2FA)
+            .await;
+        assert_eq!(result, Ok(()));
+
+        let result = store
+            .get_code(&user_id, ConfirmationPurpose::EnableEmail2FA)
+            .await;
+        assert_eq!(result, Err(ConfirmationCodeStoreError::CodeNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_failed_attempts_outlive_new_codes() {
+        let mut store = HashmapConfirmationCodeStore::default();
+        let user_id = UserId::default();
+        let purpose = ConfirmationPurpose::DisableEmail2FA;
+
+        store
+            .add_code(&user_id, purpose, TwoFACode::default())
+            .await
+            .unwrap();
+
+        for _ in 1..MAX_TWO_FA_ATTEMPTS {
+            assert_eq!(store.record_failed_attempt(&user_id).await, Ok(()));
+        }
+
+        // Sending a new code doesn't start the count again
+        store
+            .add_code(&user_id, purpose, TwoFACode::default())
+            .await
+            .unwrap();
+
+        let result = store.record_failed_attempt(&user_id).await;
+        assert_eq!(result, Err(ConfirmationCodeStoreError::TooManyAttempts));
+
+        let result = store.get_code(&user_id, purpose).await;
+        assert_eq!(result, Err(ConfirmationCodeStoreError::TooManyAttempts));
+        let result = store.check_attempts(&user_id).await;
+        assert_eq!(result, Err(ConfirmationCodeStoreError::TooManyAttempts));
+    }
+
+    #[tokio::test]
+    async fn test_remove_codes() {
+        let mut store = HashmapConfirmationCodeStore::default();
+        let user_id = UserId::default();
+
+        for purpose in [
+            ConfirmationPurpose::EnableEmail2FA,
+            ConfirmationPurpose::DisableEmail2FA,
+        ] {
+            store
+                .add_code(&user_id, purpose, TwoFACode::default())
+                .await
+                .unwrap();
+        }
+        let _ = store.record_failed_attempt(&user_id).await;
+
+        let result = store.remove_codes(&user_id).await;
+        assert_eq!(result, Ok(()));
+
+        for purpose in [
+            ConfirmationPurpose::EnableEmail2FA,
+            ConfirmationPurpose::DisableEmail2FA,
+        ] {
+            let result = store.get_code(&user_id, purpose).await;
+            assert_eq!(result, Err(ConfirmationCodeStoreError::CodeNotFound));
+        }
+        assert!(!store.failed_attempts.contains_key(&user_id));
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+
+use secrecy::ExposeSecret;
+
+use crate::{
+    domain::UserId,
+    services::data_stores::{
+        EmailChange, EmailChangeStore, EmailChangeStoreError, EmailChangeToken, PendingEmailChange,
+    },
+};
+
+#[derive(Default, Debug)]
+pub struct HashmapEmailChangeStore {
+    // Keyed by confirm token
+    changes: HashMap<String, EmailChange>,
+    // Cancel tokens mapped to the confirm token of their change
+    cancel_tokens: HashMap<String, String>,
+}
+
+#[async_trait::async_trait]
+impl EmailChangeStore for HashmapEmailChangeStore {
+    async fn add_change(&mut self, change: EmailChange) -> Result<(), EmailChangeStoreError> {
+        let confirm_token = change.confirm_token.as_ref().expose_secret().to_owned();
+        self.cancel_tokens.insert(
+            change.cancel_token.as_ref().expose_secret().to_owned(),
+            confirm_token.clone(),
+        );
+        self.changes.insert(confirm_token, change);
+        Ok(())
+    }
+
+    async fn take_change(
+        &mut self,
+        confirm_token: &EmailChangeToken,
+    ) -> Result<PendingEmailChange, EmailChangeStoreError> {
+        let change = self
+            .changes
+            .remove(confirm_token.as_ref().expose_secret())
+            .ok_or(EmailChangeStoreError::ChangeNotFound)?;
+        self.cancel_tokens
+            .remove(change.cancel_token.as_ref().expose_secret());
+        Ok(change.into())
+    }
+
+    async fn cancel_change(
+        &mut self,
+        cancel_token: &EmailChangeToken,
+    ) -> Result<PendingEmailChange, EmailChangeStoreError> {
+        let confirm_token = self
+            .cancel_tokens
+            .remove(cancel_token.as_ref().expose_secret())
+            .ok_or(EmailChangeStoreError::ChangeNotFound)?;
+        self.changes
+            .remove(&confirm_token)
+            .map(PendingEmailChange::from)
+            .ok_or(EmailChangeStoreError::ChangeNotFound)
+    }
+
+    async fn get_changes(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<PendingEmailChange>, EmailChangeStoreError> {
+        Ok(self
+            .changes
+            .values()
+            .filter(|change| change.user_id == *user_id)
+            .cloned()
+            .map(PendingEmailChange::from)
+            .collect())
+    }
+
+    async fn remove_changes(&mut self, user_id: &UserId) -> Result<(), EmailChangeStoreError> {
+        self.changes.retain(|_, change| change.user_id != *user_id);
+        let changes = &self.changes;
+        self.cancel_tokens
+            .retain(|_, confirm_token| changes.contains_key(confirm_token));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+
+    use super::*;
+    use crate::domain::Email;
+    use crate::test_helpers::get_random_email;
+
+    fn change() -> EmailChange {
+        change_for(UserId::default())
+    }
+
+    fn change_for(user_id: UserId) -> EmailChange {
+        EmailChange::new(
+            user_id,
+            Email::parse(Secret::new(get_random_email())).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_add_and_take_change() {
+        let mut store = HashmapEmailChangeStore::default();
+        let change = change();
+
+        let result = store.add_change(change.clone()).await;
+        assert_eq!(result, Ok(()));
+
+        let result = store.take_change(&change.confirm_token).await;
+        assert_eq!(result, Ok(change.clone().into()));
+
+        // A confirmed change can't be confirmed again, or cancelled
+        let result = store.take_change(&change.confirm_token).await;
+        assert_eq!(result, Err(EmailChangeStoreError::ChangeNotFound));
+        let result = store.cancel_change(&change.cancel_token).await;
+        assert_eq!(result, Err(EmailChangeStoreError::ChangeNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_change() {
+        let mut store = HashmapEmailChangeStore::default();
+        let change = change();
+
+        store.add_change(change.clone()).await.unwrap();
+
+        let result = store.cancel_change(&change.cancel_token).await;
+        assert_eq!(result, Ok(change.clone().into()));
+
+        let result = store.take_change(&change.confirm_token).await;
+        assert_eq!(result, Err(EmailChangeStoreError::ChangeNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_tokens_are_not_interchangeable() {
+        let mut store = HashmapEmailChangeStore::default();
+        let change = change();
+
+        store.add_change(change.clone()).await.unwrap();
+
+        let result = store.take_change(&change.cancel_token).await;
+        assert_eq!(result, Err(EmailChangeStoreError::ChangeNotFound));
+        let result = store.cancel_change(&change.confirm_token).await;
+        assert_eq!(result, Err(EmailChangeStoreError::ChangeNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_get_changes() {
+        let mut store = HashmapEmailChangeStore::default();
+        let user_id = UserId::default();
+        let change = change_for(user_id);
+
+        store.add_change(change.clone()).await.unwrap();
+        store.add_change(change_for(user_id)).await.unwrap();
+        store.add_change(self::change()).await.unwrap();
+
+        // Only the user's own changes are returned
+        let changes = store.get_changes(&user_id).await.unwrap();
+        assert_eq!(changes.len(), 2);
+        assert!(changes.iter().all(|change| change.user_id == user_id));
+
+        // Confirmed changes aren't waiting any more
+        store.take_change(&change.confirm_token).await.unwrap();
+        let changes = store.get_changes(&user_id).await.unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_ne!(changes[0], change.into());
+    }
+
+    #[tokio::test]
+    async fn test_remove_changes() {
+        let mut store = HashmapEmailChangeStore::default();
+        let user_id = UserId::default();
+        let change = change_for(user_id);
+        let other_change = self::change();
+
+        store.add_change(change.clone()).await.unwrap();
+        store.add_change(other_change.clone()).await.unwrap();
+
+        let result = store.remove_changes(&user_id).await;
+        assert_eq!(result, Ok(()));
+
+        assert!(store.get_changes(&user_id).await.unwrap().is_empty());
+        let result = store.cancel_change(&change.cancel_token).await;
+        assert_eq!(result, Err(EmailChangeStoreError::ChangeNotFound));
+
+        // Other users' changes are left alone
+        let result = store.take_change(&other_change.confirm_token).await;
+        assert_eq!(result, Ok(other_change.into()));
+    }
+}
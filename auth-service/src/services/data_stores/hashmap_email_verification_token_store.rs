@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use secrecy::ExposeSecret;
+
+use crate::domain::{Email, UserId};
+use crate::services::data_stores::{
+    EmailVerificationToken, EmailVerificationTokenStore, EmailVerificationTokenStoreError,
+};
+use crate::utils::constants::{VERIFICATION_EMAIL_LIMIT, VERIFICATION_EMAIL_WINDOW_SECONDS};
+
+#[derive(Default, Debug)]
+pub struct HashmapEmailVerificationTokenStore {
+    tokens: HashMap<String, UserId>,
+    // Number of emails sent to each address, and when their current window started
+    emails_sent: HashMap<Email, (u64, Instant)>,
+}
+
+#[async_trait::async_trait]
+impl EmailVerificationTokenStore for HashmapEmailVerificationTokenStore {
+    async fn add_token(
+        &mut self,
+        token: EmailVerificationToken,
+        user_id: UserId,
+    ) -> Result<(), EmailVerificationTokenStoreError> {
+        self.tokens
+            .insert(token.as_ref().expose_secret().to_owned(), user_id);
+        Ok(())
+    }
+
+    async fn take_token(
+        &mut self,
+        token: &EmailVerificationToken,
+    ) -> Result<UserId, EmailVerificationTokenStoreError> {
+        match self.tokens.remove(token.as_ref().expose_secret()) {
+            Some(user_id) => Ok(user_id),
+            None => Err(EmailVerificationTokenStoreError::TokenNotFound),
+        }
+    }
+
+    async fn record_email_sent(
+        &mut self,
+        email: &Email,
+    ) -> Result<(), EmailVerificationTokenStoreError> {
+        let now = Instant::now();
+        let window = Duration::from_secs(VERIFICATION_EMAIL_WINDOW_SECONDS);
+
+        let (count, window_start) = self.emails_sent.entry(email.clone()).or_insert((0, now));
+        if now.duration_since(*window_start) >= window {
+            *count = 0;
+            *window_start = now;
+        }
+
+        if *count >= VERIFICATION_EMAIL_LIMIT {
+            return Err(EmailVerificationTokenStoreError::TooManyEmails);
+        }
+
+        *count += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+
+    use super::*;
+    use crate::test_helpers::get_random_email;
+
+    #[tokio::test]
+    async fn test_add_and_take_token() {
+        let mut store = HashmapEmailVerificationTokenStore::default();
+        let user_id = UserId::default();
+        let token = EmailVerificationToken::default();
+
+        let result = store.add_token(token.clone(), user_id).await;
+        assert_eq!(result, Ok(()));
+
+        let result = store.take_token(&token).await;
+        assert_eq!(result, Ok(user_id));
+
+        // Tokens can only be taken once
+        let result = store.take_token(&token).await;
+        assert_eq!(result, Err(EmailVerificationTokenStoreError::TokenNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_record_email_sent_is_rate_limited() {
+        let mut store = HashmapEmailVerificationTokenStore::default();
+        let email = Email::parse(Secret::new(get_random_email())).unwrap();
+        let other_email = Email::parse(Secret::new(get_random_email())).unwrap();
+
+        for _ in 0..VERIFICATION_EMAIL_LIMIT {
+            assert_eq!(store.record_email_sent(&email).await, Ok(()));
+        }
+
+        let result = store.record_email_sent(&email).await;
+        assert_eq!(result, Err(EmailVerificationTokenStoreError::TooManyEmails));
+
+        // Other users have their own limit
+        assert_eq!(store.record_email_sent(&other_email).await, Ok(()));
+    }
+}
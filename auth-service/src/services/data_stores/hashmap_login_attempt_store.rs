@@ -0,0 +1,285 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use secrecy::ExposeSecret;
+
+use crate::domain::Email;
+use crate::services::data_stores::{
+    LoginAttemptStore, LoginAttemptStoreError, LoginThrottleSettings,
+};
+use crate::utils::constants::LOGIN_FAILURES_TTL_SECONDS;
+
+#[derive(Default, Debug)]
+pub struct HashmapLoginAttemptStore {
+    settings: LoginThrottleSettings,
+    // When each IP address or account made its attempts, oldest first
+    attempts: HashMap<String, VecDeque<Instant>>,
+    // Failed logins in a row for each account, and when the last one was
+    failures: HashMap<Email, (u64, Instant)>,
+    locked_until: HashMap<Email, Instant>,
+}
+
+impl HashmapLoginAttemptStore {
+    pub fn new(settings: LoginThrottleSettings) -> Self {
+        Self {
+            settings,
+            ..Default::default()
+        }
+    }
+
+    // Sliding window log - only attempts that were let through are counted
+    fn record_in_window(
+        &mut self,
+        key: String,
+        limit: u64,
+        window_seconds: u64,
+        now: Instant,
+    ) -> Result<(), LoginAttemptStoreError> {
+        let window = Duration::from_secs(window_seconds);
+        let attempts = self.attempts.entry(key).or_default();
+        while attempts
+            .front()
+            .is_some_and(|attempt| now.duration_since(*attempt) >= window)
+        {
+            attempts.pop_front();
+        }
+
+        if attempts.len() as u64 >= limit {
+            let retry_after = attempts
+                .front()
+                .map_or(window, |oldest| window - now.duration_since(*oldest));
+            return Err(LoginAttemptStoreError::TooManyAttempts {
+                retry_after_seconds: whole_seconds(retry_after),
+            });
+        }
+
+        attempts.push_back(now);
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl LoginAttemptStore for HashmapLoginAttemptStore {
+    async fn record_attempt(
+        &mut self,
+        ip_address: Option<&str>,
+        email: &Email,
+    ) -> Result<(), LoginAttemptStoreError> {
+        let now = Instant::now();
+
+        if let Some(until) = self.locked_until.get(email).filter(|until| **until > now) {
+            return Err(LoginAttemptStoreError::AccountLocked {
+                retry_after_seconds: whole_seconds(*until - now),
+            });
+        }
+
+        if let Some(ip_address) = ip_address {
+            self.record_in_window(
+                format!("ip:{}", ip_address),
+                self.settings.ip_limit,
+                self.settings.ip_window_seconds,
+                now,
+            )?;
+        }
+        self.record_in_window(
+            format!("account:{}", email.as_ref().expose_secret()),
+            self.settings.account_limit,
+            self.settings.account_window_seconds,
+            now,
+        )
+    }
+
+    async fn record_failure(&mut self, email: &Email) -> Result<(), LoginAttemptStoreError> {
+        let now = Instant::now();
+
+        let (count, last_failure) = self.failures.entry(email.clone()).or_insert((0, now));
+        if now.duration_since(*last_failure) >= Duration::from_secs(LOGIN_FAILURES_TTL_SECONDS) {
+            *count = 0;
+        }
+        *count += 1;
+        *last_failure = now;
+
+        if let Some(lockout_seconds) = self.settings.lockout_seconds(*count) {
+            self.locked_until
+                .insert(email.clone(), now + Duration::from_secs(lockout_seconds));
+        }
+        Ok(())
+    }
+
+    async fn clear_failures(&mut self, email: &Email) -> Result<(), LoginAttemptStoreError> {
+        self.failures.remove(email);
+        self.locked_until.remove(email);
+        Ok(())
+    }
+
+    async fn remove_account(&mut self, email: &Email) -> Result<(), LoginAttemptStoreError> {
+        self.attempts
+            .remove(&format!("account:{}", email.as_ref().expose_secret()));
+        self.clear_failures(email).await
+    }
+}
+
+// Retry-After is in whole seconds - round up so clients never retry too early
+fn whole_seconds(duration: Duration) -> u64 {
+    (duration.as_millis().div_ceil(1000) as u64).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+
+    use super::*;
+    use crate::test_helpers::get_random_email;
+
+    const IP_ADDRESS: Option<&str> = Some("127.0.0.1");
+
+    fn email() -> Email {
+        Email::parse(Secret::new(get_random_email())).unwrap()
+    }
+
+    fn settings() -> LoginThrottleSettings {
+        LoginThrottleSettings {
+            ip_limit: 4,
+            ip_window_seconds: 60,
+            account_limit: 2,
+            account_window_seconds: 30,
+            lockout_threshold: 3,
+            lockout_base_seconds: 10,
+            lockout_max_seconds: 25,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record_attempt_limits_each_account() {
+        let mut store = HashmapLoginAttemptStore::new(settings());
+        let email = email();
+
+        for _ in 0..2 {
+            assert_eq!(store.record_attempt(IP_ADDRESS, &email).await, Ok(()));
+        }
+
+        let result = store.record_attempt(IP_ADDRESS, &email).await;
+        assert_eq!(
+            result,
+            Err(LoginAttemptStoreError::TooManyAttempts {
+                retry_after_seconds: 30
+            })
+        );
+
+        // Other accounts are unaffected
+        let result = store.record_attempt(IP_ADDRESS, &self::email()).await;
+        assert_eq!(result, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_record_attempt_limits_each_ip_address() {
+        let mut store = HashmapLoginAttemptStore::new(settings());
+
+        for _ in 0..4 {
+            assert_eq!(store.record_attempt(IP_ADDRESS, &email()).await, Ok(()));
+        }
+
+        let result = store.record_attempt(IP_ADDRESS, &email()).await;
+        assert_eq!(
+            result,
+            Err(LoginAttemptStoreError::TooManyAttempts {
+                retry_after_seconds: 60
+            })
+        );
+
+        // Other IP addresses are unaffected
+        let result = store.record_attempt(Some("10.0.0.1"), &email()).await;
+        assert_eq!(result, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_record_attempt_forgets_attempts_outside_window() {
+        let mut store = HashmapLoginAttemptStore::new(settings());
+        let email = email();
+
+        // Pretend the first attempts were made a window ago
+        let long_ago = Instant::now() - Duration::from_secs(30);
+        store.attempts.insert(
+            format!("account:{}", email.as_ref().expose_secret()),
+            VecDeque::from([long_ago, long_ago]),
+        );
+
+        let result = store.record_attempt(IP_ADDRESS, &email).await;
+        assert_eq!(result, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_record_failure_locks_account_with_back_off() {
+        let mut store = HashmapLoginAttemptStore::new(LoginThrottleSettings {
+            account_limit: 100,
+            ..settings()
+        });
+        let email = email();
+
+        for _ in 0..2 {
+            store.record_failure(&email).await.unwrap();
+        }
+        assert_eq!(store.record_attempt(None, &email).await, Ok(()));
+
+        // The third failure in a row locks the account
+        store.record_failure(&email).await.unwrap();
+        let result = store.record_attempt(None, &email).await;
+        assert_eq!(
+            result,
+            Err(LoginAttemptStoreError::AccountLocked {
+                retry_after_seconds: 10
+            })
+        );
+
+        // Every further failure doubles the lockout, up to the maximum
+        store.record_failure(&email).await.unwrap();
+        let result = store.record_attempt(None, &email).await;
+        assert_eq!(
+            result,
+            Err(LoginAttemptStoreError::AccountLocked {
+                retry_after_seconds: 20
+            })
+        );
+
+        store.record_failure(&email).await.unwrap();
+        let result = store.record_attempt(None, &email).await;
+        assert_eq!(
+            result,
+            Err(LoginAttemptStoreError::AccountLocked {
+                retry_after_seconds: 25
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_clear_failures() {
+        let mut store = HashmapLoginAttemptStore::new(settings());
+        let email = email();
+
+        for _ in 0..2 {
+            store.record_failure(&email).await.unwrap();
+        }
+        store.clear_failures(&email).await.unwrap();
+
+        // The count starts over, so one more failure does not lock the account
+        store.record_failure(&email).await.unwrap();
+        assert_eq!(store.record_attempt(None, &email).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_remove_account() {
+        let mut store = HashmapLoginAttemptStore::new(settings());
+        let email = email();
+
+        for _ in 0..2 {
+            store.record_attempt(None, &email).await.unwrap();
+        }
+        for _ in 0..3 {
+            store.record_failure(&email).await.unwrap();
+        }
+        store.remove_account(&email).await.unwrap();
+
+        // Neither the lockout nor the attempts in the window are held against the address
+        assert_eq!(store.record_attempt(None, &email).await, Ok(()));
+    }
+}
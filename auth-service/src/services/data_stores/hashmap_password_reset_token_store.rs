@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+use secrecy::ExposeSecret;
+
+use crate::domain::UserId;
+use crate::services::data_stores::{
+    PasswordResetToken, PasswordResetTokenStore, PasswordResetTokenStoreError,
+};
+
+#[derive(Default, Debug)]
+pub struct HashmapPasswordResetTokenStore {
+    tokens: HashMap<String, UserId>,
+}
+
+#[async_trait::async_trait]
+impl PasswordResetTokenStore for HashmapPasswordResetTokenStore {
+    async fn add_token(
+        &mut self,
+        token: PasswordResetToken,
+        user_id: UserId,
+    ) -> Result<(), PasswordResetTokenStoreError> {
+        self.tokens
+            .insert(token.as_ref().expose_secret().to_owned(), user_id);
+        Ok(())
+    }
+
+    async fn get_token(
+        &self,
+        token: &PasswordResetToken,
+    ) -> Result<UserId, PasswordResetTokenStoreError> {
+        match self.tokens.get(token.as_ref().expose_secret()) {
+            Some(user_id) => Ok(*user_id),
+            None => Err(PasswordResetTokenStoreError::TokenNotFound),
+        }
+    }
+
+    async fn take_token(
+        &mut self,
+        token: &PasswordResetToken,
+    ) -> Result<UserId, PasswordResetTokenStoreError> {
+        match self.tokens.remove(token.as_ref().expose_secret()) {
+            Some(user_id) => Ok(user_id),
+            None => Err(PasswordResetTokenStoreError::TokenNotFound),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_add_and_take_token() {
+        let mut store = HashmapPasswordResetTokenStore::default();
+        let user_id = UserId::default();
+        let token = PasswordResetToken::default();
+
+        let result = store.add_token(token.clone(), user_id).await;
+        assert_eq!(result, Ok(()));
+
+        let result = store.take_token(&token).await;
+        assert_eq!(result, Ok(user_id));
+    }
+
+    #[tokio::test]
+    async fn test_token_can_only_be_taken_once() {
+        let mut store = HashmapPasswordResetTokenStore::default();
+        let user_id = UserId::default();
+        let token = PasswordResetToken::default();
+
+        store.add_token(token.clone(), user_id).await.unwrap();
+        assert!(store.take_token(&token).await.is_ok());
+
+        let result = store.take_token(&token).await;
+        assert_eq!(result, Err(PasswordResetTokenStoreError::TokenNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_get_token_does_not_use_it() {
+        let mut store = HashmapPasswordResetTokenStore::default();
+        let user_id = UserId::default();
+        let token = PasswordResetToken::default();
+
+        store.add_token(token.clone(), user_id).await.unwrap();
+        assert_eq!(store.get_token(&token).await, Ok(user_id));
+        assert_eq!(store.take_token(&token).await, Ok(user_id));
+        assert_eq!(
+            store.get_token(&token).await,
+            Err(PasswordResetTokenStoreError::TokenNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_take_token_not_found() {
+        let mut store = HashmapPasswordResetTokenStore::default();
+
+        let result = store.take_token(&PasswordResetToken::default()).await;
+        assert_eq!(result, Err(PasswordResetTokenStoreError::TokenNotFound));
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use crate::domain::UserId;
+use crate::services::data_stores::{RecoveryCode, RecoveryCodeStore, RecoveryCodeStoreError};
+
+#[derive(Default, Debug)]
+pub struct HashmapRecoveryCodeStore {
+    codes: HashMap<UserId, Vec<RecoveryCode>>,
+}
+
+#[async_trait::async_trait]
+impl RecoveryCodeStore for HashmapRecoveryCodeStore {
+    async fn replace_codes(
+        &mut self,
+        user_id: &UserId,
+        codes: Vec<RecoveryCode>,
+    ) -> Result<(), RecoveryCodeStoreError> {
+        self.codes.insert(*user_id, codes);
+        Ok(())
+    }
+
+    async fn use_code(
+        &mut self,
+        user_id: &UserId,
+        code: &RecoveryCode,
+    ) -> Result<(), RecoveryCodeStoreError> {
+        let codes = self
+            .codes
+            .get_mut(user_id)
+            .ok_or(RecoveryCodeStoreError::CodeNotFound)?;
+
+        let index = codes
+            .iter()
+            .position(|stored| stored.eq(code))
+            .ok_or(RecoveryCodeStoreError::CodeNotFound)?;
+
+        codes.remove(index);
+        Ok(())
+    }
+
+    async fn count_codes(&self, user_id: &UserId) -> Result<usize, RecoveryCodeStoreError> {
+        Ok(self.codes.get(user_id).map_or(0, Vec::len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_use_code_burns_code() {
+        let mut store = HashmapRecoveryCodeStore::default();
+        let user_id = UserId::default();
+        let codes: Vec<RecoveryCode> = (0..3).map(|_| RecoveryCode::default()).collect();
+
+        store.replace_codes(&user_id, codes.clone()).await.unwrap();
+        assert_eq!(store.count_codes(&user_id).await, Ok(3));
+
+        assert_eq!(store.use_code(&user_id, &codes[1]).await, Ok(()));
+        assert_eq!(store.count_codes(&user_id).await, Ok(2));
+
+        let result = store.use_code(&user_id, &codes[1]).await;
+        assert_eq!(result, Err(RecoveryCodeStoreError::CodeNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_replace_codes_invalidates_old_codes() {
+        let mut store = HashmapRecoveryCodeStore::default();
+        let user_id = UserId::default();
+        let old_code = RecoveryCode::default();
+
+        store
+            .replace_codes(&user_id, vec![old_code.clone()])
+            .await
+            .unwrap();
+        store
+            .replace_codes(&user_id, vec![RecoveryCode::default()])
+            .await
+            .unwrap();
+
+        let result = store.use_code(&user_id, &old_code).await;
+        assert_eq!(result, Err(RecoveryCodeStoreError::CodeNotFound));
+        assert_eq!(store.count_codes(&user_id).await, Ok(1));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_user_has_no_codes() {
+        let mut store = HashmapRecoveryCodeStore::default();
+        let user_id = UserId::default();
+
+        assert_eq!(store.count_codes(&user_id).await, Ok(0));
+        let result = store.use_code(&user_id, &RecoveryCode::default()).await;
+        assert_eq!(result, Err(RecoveryCodeStoreError::CodeNotFound));
+    }
+
+    #[test]
+    fn test_parse_recovery_code_normalizes_input() {
+        let code = RecoveryCode::parse(Secret::new(" K7MZQ4HX2P ".to_owned())).unwrap();
+        assert_eq!(
+            code,
+            RecoveryCode::parse(Secret::new("k7mzq-4hx2p".to_owned())).unwrap()
+        );
+
+        for invalid in ["k7mzq-4hx2", "k7mzq-4hx2p1", "k7mzq-4hx0p", "123456"] {
+            assert!(RecoveryCode::parse(Secret::new(invalid.to_owned())).is_err());
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::{HashMap, HashSet};
+
+use secrecy::ExposeSecret;
+
+use crate::domain::UserId;
+use crate::services::data_stores::{
+    RefreshToken, RefreshTokenRecord, RefreshTokenStore, RefreshTokenStoreError,
+};
+
+#[derive(Default, Debug)]
+pub struct HashmapRefreshTokenStore {
+    tokens: HashMap<String, RefreshTokenRecord>,
+    revoked_families: HashSet<String>,
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenStore for HashmapRefreshTokenStore {
+    async fn add_token(
+        &mut self,
+        token: RefreshToken,
+        record: RefreshTokenRecord,
+    ) -> Result<(), RefreshTokenStoreError> {
+        self.tokens
+            .insert(token.as_ref().expose_secret().to_owned(), record);
+        Ok(())
+    }
+
+    async fn get_token(
+        &self,
+        token: &RefreshToken,
+    ) -> Result<RefreshTokenRecord, RefreshTokenStoreError> {
+        match self.tokens.get(token.as_ref().expose_secret()) {
+            Some(record) => Ok(record.clone()),
+            None => Err(RefreshTokenStoreError::TokenNotFound),
+        }
+    }
+
+    async fn mark_token_used(
+        &mut self,
+        token: &RefreshToken,
+    ) -> Result<(), RefreshTokenStoreError> {
+        match self.tokens.get_mut(token.as_ref().expose_secret()) {
+            Some(record) => {
+                record.used = true;
+                Ok(())
+            }
+            None => Err(RefreshTokenStoreError::TokenNotFound),
+        }
+    }
+
+    async fn revoke_family(&mut self, family_id: &str) -> Result<(), RefreshTokenStoreError> {
+        self.tokens
+            .retain(|_, record| record.family_id != family_id);
+        self.revoked_families.insert(family_id.to_owned());
+        Ok(())
+    }
+
+    async fn is_family_revoked(&self, family_id: &str) -> Result<bool, RefreshTokenStoreError> {
+        Ok(self.revoked_families.contains(family_id))
+    }
+
+    async fn revoke_all_families_for_user(
+        &mut self,
+        user_id: &UserId,
+    ) -> Result<(), RefreshTokenStoreError> {
+        let family_ids: HashSet<String> = self
+            .tokens
+            .values()
+            .filter(|record| &record.user_id == user_id)
+            .map(|record| record.family_id.clone())
+            .collect();
+
+        for family_id in family_ids {
+            self.revoke_family(&family_id).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::data_stores::SessionId;
+
+    fn record() -> RefreshTokenRecord {
+        RefreshTokenRecord::new(UserId::default(), &SessionId::default())
+    }
+
+    #[tokio::test]
+    async fn test_add_and_get_token() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let token = RefreshToken::default();
+        let record = record();
+
+        let result = store.add_token(token.clone(), record.clone()).await;
+        assert_eq!(result, Ok(()));
+
+        let result = store.get_token(&token).await;
+        assert_eq!(result, Ok(record));
+    }
+
+    #[tokio::test]
+    async fn test_get_token_not_found() {
+        let store = HashmapRefreshTokenStore::default();
+
+        let result = store.get_token(&RefreshToken::default()).await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_mark_token_used() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let token = RefreshToken::default();
+
+        store.add_token(token.clone(), record()).await.unwrap();
+
+        let result = store.mark_token_used(&token).await;
+        assert_eq!(result, Ok(()));
+        assert!(store.get_token(&token).await.unwrap().used);
+
+        // Marking an unknown token fails
+        let result = store.mark_token_used(&RefreshToken::default()).await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_revoke_family() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let first_token = RefreshToken::default();
+        let second_token = RefreshToken::default();
+        let other_token = RefreshToken::default();
+        let record = record();
+
+        store
+            .add_token(first_token.clone(), record.clone())
+            .await
+            .unwrap();
+        store
+            .add_token(second_token.clone(), record.rotate())
+            .await
+            .unwrap();
+        store
+            .add_token(other_token.clone(), self::record())
+            .await
+            .unwrap();
+
+        let result = store.revoke_family(&record.family_id).await;
+        assert_eq!(result, Ok(()));
+
+        // All tokens in the family are gone, other families are untouched
+        assert!(store.get_token(&first_token).await.is_err());
+        assert!(store.get_token(&second_token).await.is_err());
+        assert!(store.get_token(&other_token).await.is_ok());
+
+        assert_eq!(store.is_family_revoked(&record.family_id).await, Ok(true));
+        let other_family = store.get_token(&other_token).await.unwrap().family_id;
+        assert_eq!(store.is_family_revoked(&other_family).await, Ok(false));
+    }
+
+    #[tokio::test]
+    async fn test_revoke_all_families_for_user() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let first_login = record();
+        let second_login = RefreshTokenRecord::new(first_login.user_id, &SessionId::default());
+        let other_user = record();
+
+        let tokens = [
+            RefreshToken::default(),
+            RefreshToken::default(),
+            RefreshToken::default(),
+        ];
+        store
+            .add_token(tokens[0].clone(), first_login.clone())
+            .await
+            .unwrap();
+        store
+            .add_token(tokens[1].clone(), second_login.clone())
+            .await
+            .unwrap();
+        store
+            .add_token(tokens[2].clone(), other_user.clone())
+            .await
+            .unwrap();
+
+        let result = store
+            .revoke_all_families_for_user(&first_login.user_id)
+            .await;
+        assert_eq!(result, Ok(()));
+
+        // Every login of the user is revoked, other users are untouched
+        assert_eq!(
+            store.is_family_revoked(&first_login.family_id).await,
+            Ok(true)
+        );
+        assert_eq!(
+            store.is_family_revoked(&second_login.family_id).await,
+            Ok(true)
+        );
+        assert_eq!(
+            store.is_family_revoked(&other_user.family_id).await,
+            Ok(false)
+        );
+        assert!(store.get_token(&tokens[2]).await.is_ok());
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+
+use crate::domain::UserId;
+use crate::services::data_stores::{Session, SessionId, SessionStore, SessionStoreError, TokenId};
+
+#[derive(Default, Debug)]
+pub struct HashmapSessionStore {
+    sessions: HashMap<SessionId, Session>,
+}
+
+#[async_trait::async_trait]
+impl SessionStore for HashmapSessionStore {
+    async fn add_session(&mut self, session: Session) -> Result<(), SessionStoreError> {
+        if self.sessions.contains_key(&session.id) {
+            return Err(SessionStoreError::SessionAlreadyExists);
+        }
+        self.sessions.insert(session.id.clone(), session);
+        Ok(())
+    }
+
+    async fn get_session(&self, id: &SessionId) -> Result<Session, SessionStoreError> {
+        self.sessions
+            .get(id)
+            .cloned()
+            .ok_or(SessionStoreError::SessionNotFound)
+    }
+
+    async fn get_sessions(&self, user_id: &UserId) -> Result<Vec<Session>, SessionStoreError> {
+        let mut sessions: Vec<Session> = self
+            .sessions
+            .values()
+            .filter(|session| session.user_id.eq(user_id))
+            .cloned()
+            .collect();
+        sessions.sort_by_key(|session| session.created_at);
+        Ok(sessions)
+    }
+
+    async fn touch_session(
+        &mut self,
+        id: &SessionId,
+        token_id: &TokenId,
+    ) -> Result<(), SessionStoreError> {
+        let session = self
+            .sessions
+            .get_mut(id)
+            .ok_or(SessionStoreError::SessionNotFound)?;
+        session.token_id = token_id.clone();
+        session.last_seen = Utc::now();
+        Ok(())
+    }
+
+    async fn remove_session(&mut self, id: &SessionId) -> Result<(), SessionStoreError> {
+        self.sessions.remove(id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn session(user_id: &UserId) -> Session {
+        let now = Utc::now();
+        Session {
+            id: SessionId::default(),
+            user_id: *user_id,
+            token_id: TokenId::default(),
+            created_at: now,
+            last_seen: now,
+            ip_address: Some("127.0.0.1".to_owned()),
+            user_agent: Some("test".to_owned()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_and_get_session() {
+        let mut store = HashmapSessionStore::default();
+        let user_id = UserId::default();
+        let session = session(&user_id);
+
+        assert_eq!(store.add_session(session.clone()).await, Ok(()));
+        assert_eq!(store.get_session(&session.id).await, Ok(session.clone()));
+
+        // Session ids are unique
+        assert_eq!(
+            store.add_session(session).await,
+            Err(SessionStoreError::SessionAlreadyExists)
+        );
+
+        assert_eq!(
+            store.get_session(&SessionId::default()).await,
+            Err(SessionStoreError::SessionNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_sessions_for_user_oldest_first() {
+        let mut store = HashmapSessionStore::default();
+        let user_id = UserId::default();
+        let other_user_id = UserId::default();
+
+        let newer = session(&user_id);
+        let older = Session {
+            created_at: newer.created_at - Duration::hours(1),
+            ..session(&user_id)
+        };
+        store.add_session(newer.clone()).await.unwrap();
+        store.add_session(older.clone()).await.unwrap();
+        store.add_session(session(&other_user_id)).await.unwrap();
+
+        assert_eq!(store.get_sessions(&user_id).await, Ok(vec![older, newer]));
+    }
+
+    #[tokio::test]
+    async fn test_touch_session() {
+        let mut store = HashmapSessionStore::default();
+        let user_id = UserId::default();
+        let session = Session {
+            last_seen: Utc::now() - Duration::hours(1),
+            ..session(&user_id)
+        };
+        store.add_session(session.clone()).await.unwrap();
+
+        let token_id = TokenId::default();
+        assert_eq!(store.touch_session(&session.id, &token_id).await, Ok(()));
+
+        let touched = store.get_session(&session.id).await.unwrap();
+        assert_eq!(touched.token_id, token_id);
+        assert!(touched.last_seen > session.last_seen);
+        assert_eq!(touched.created_at, session.created_at);
+
+        assert_eq!(
+            store
+                .touch_session(&SessionId::default(), &TokenId::default())
+                .await,
+            Err(SessionStoreError::SessionNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_remove_session() {
+        let mut store = HashmapSessionStore::default();
+        let user_id = UserId::default();
+        let session = session(&user_id);
+        store.add_session(session.clone()).await.unwrap();
+
+        assert_eq!(store.remove_session(&session.id).await, Ok(()));
+        assert_eq!(
+            store.get_session(&session.id).await,
+            Err(SessionStoreError::SessionNotFound)
+        );
+
+        // Removing a session that is already gone is not an error
+        assert_eq!(store.remove_session(&session.id).await, Ok(()));
+    }
+}
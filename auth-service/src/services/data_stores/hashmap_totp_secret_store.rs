@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use crate::domain::UserId;
+use crate::services::data_stores::{
+    TotpSecret, TotpSecretRecord, TotpSecretStore, TotpSecretStoreError,
+};
+
+#[derive(Default, Debug)]
+pub struct HashmapTotpSecretStore {
+    secrets: HashMap<UserId, TotpSecretRecord>,
+}
+
+#[async_trait::async_trait]
+impl TotpSecretStore for HashmapTotpSecretStore {
+    async fn add_secret(
+        &mut self,
+        user_id: &UserId,
+        secret: TotpSecret,
+    ) -> Result<(), TotpSecretStoreError> {
+        let record = TotpSecretRecord {
+            secret,
+            confirmed: false,
+            last_used_step: None,
+        };
+        self.secrets.insert(*user_id, record);
+        Ok(())
+    }
+
+    async fn get_secret(&self, user_id: &UserId) -> Result<TotpSecretRecord, TotpSecretStoreError> {
+        match self.secrets.get(user_id) {
+            Some(record) => Ok(record.clone()),
+            None => Err(TotpSecretStoreError::SecretNotFound),
+        }
+    }
+
+    async fn confirm_secret(&mut self, user_id: &UserId) -> Result<(), TotpSecretStoreError> {
+        match self.secrets.get_mut(user_id) {
+            Some(record) => {
+                record.confirmed = true;
+                Ok(())
+            }
+            None => Err(TotpSecretStoreError::SecretNotFound),
+        }
+    }
+
+    async fn record_used_step(
+        &mut self,
+        user_id: &UserId,
+        step: u64,
+    ) -> Result<(), TotpSecretStoreError> {
+        let record = self
+            .secrets
+            .get_mut(user_id)
+            .ok_or(TotpSecretStoreError::SecretNotFound)?;
+
+        if record.last_used_step.is_some_and(|last| last >= step) {
+            return Err(TotpSecretStoreError::CodeAlreadyUsed);
+        }
+
+        record.last_used_step = Some(step);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_add_and_get_secret() {
+        let mut store = HashmapTotpSecretStore::default();
+        let user_id = UserId::default();
+        let secret = TotpSecret::default();
+
+        let result = store.add_secret(&user_id, secret.clone()).await;
+        assert_eq!(result, Ok(()));
+
+        let record = store.get_secret(&user_id).await.unwrap();
+        assert_eq!(record.secret, secret);
+        assert!(!record.confirmed);
+        assert_eq!(record.last_used_step, None);
+
+        let other_user_id = UserId::default();
+        let result = store.get_secret(&other_user_id).await;
+        assert_eq!(result, Err(TotpSecretStoreError::SecretNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_confirm_secret() {
+        let mut store = HashmapTotpSecretStore::default();
+        let user_id = UserId::default();
+
+        store
+            .add_secret(&user_id, TotpSecret::default())
+            .await
+            .unwrap();
+
+        let result = store.confirm_secret(&user_id).await;
+        assert_eq!(result, Ok(()));
+        assert!(store.get_secret(&user_id).await.unwrap().confirmed);
+
+        // Re-enrolling replaces the secret with a new, unconfirmed one
+        store
+            .add_secret(&user_id, TotpSecret::default())
+            .await
+            .unwrap();
+        assert!(!store.get_secret(&user_id).await.unwrap().confirmed);
+    }
+
+    #[tokio::test]
+    async fn test_record_used_step() {
+        let mut store = HashmapTotpSecretStore::default();
+        let user_id = UserId::default();
+
+        store
+            .add_secret(&user_id, TotpSecret::default())
+            .await
+            .unwrap();
+
+        assert_eq!(store.record_used_step(&user_id, 100).await, Ok(()));
+
+        // The same or an earlier step can't be used again
+        for step in [100, 99] {
+            let result = store.record_used_step(&user_id, step).await;
+            assert_eq!(result, Err(TotpSecretStoreError::CodeAlreadyUsed));
+        }
+
+        assert_eq!(store.record_used_step(&user_id, 101).await, Ok(()));
+    }
+}
@@ -1,13 +1,16 @@
 use std::collections::HashMap;
 
-use crate::domain::Email;
+use crate::domain::UserId;
 use crate::services::data_stores::{
     LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError,
 };
+use crate::utils::constants::MAX_TWO_FA_ATTEMPTS;
 
 #[derive(Default, Debug)]
 pub struct HashmapTwoFACodeStore {
-    codes: HashMap<Email, (LoginAttemptId, TwoFACode)>,
+    codes: HashMap<UserId, (LoginAttemptId, TwoFACode)>,
+    // Wrong codes entered for each user's current login attempt
+    failed_attempts: HashMap<UserId, u64>,
 }
 
 // implement TwoFACodeStore for HashmapTwoFACodeStore
@@ -15,84 +18,98 @@ pub struct HashmapTwoFACodeStore {
 impl TwoFACodeStore for HashmapTwoFACodeStore {
     async fn add_code(
         &mut self,
-        email: Email,
+        user_id: UserId,
         login_attempt_id: LoginAttemptId,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError> {
-        self.codes.insert(email, (login_attempt_id, code));
+        self.failed_attempts.remove(&user_id);
+        self.codes.insert(user_id, (login_attempt_id, code));
         Ok(())
     }
 
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
-        match self.codes.remove(email) {
+    async fn remove_code(&mut self, user_id: &UserId) -> Result<(), TwoFACodeStoreError> {
+        self.failed_attempts.remove(user_id);
+        match self.codes.remove(user_id) {
             Some(_) => Ok(()),
             None => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
         }
     }
     async fn get_code(
         &self,
-        email: &Email,
+        user_id: &UserId,
     ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
-        match self.codes.get(email) {
+        match self.codes.get(user_id) {
             Some((login_attempt_id, code)) => Ok((login_attempt_id.clone(), code.clone())),
             None => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
         }
     }
+
+    async fn record_failed_attempt(&mut self, user_id: &UserId) -> Result<(), TwoFACodeStoreError> {
+        if !self.codes.contains_key(user_id) {
+            return Err(TwoFACodeStoreError::LoginAttemptIdNotFound);
+        }
+
+        let failed_attempts = self.failed_attempts.entry(*user_id).or_default();
+        *failed_attempts += 1;
+
+        if *failed_attempts >= MAX_TWO_FA_ATTEMPTS {
+            self.remove_code(user_id).await?;
+            return Err(TwoFACodeStoreError::TooManyAttempts);
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use secrecy::Secret;
-
     use super::*;
-    use crate::test_helpers::get_random_email;
 
     #[tokio::test]
     async fn test_add_code() {
         let mut store = HashmapTwoFACodeStore::default();
-        let email = Email::parse(Secret::new(get_random_email())).unwrap();
+        let user_id = UserId::default();
         let login_attempt_id = LoginAttemptId::default();
         let code = TwoFACode::default();
 
         let result = store
-            .add_code(email.clone(), login_attempt_id.clone(), code.clone())
+            .add_code(user_id, login_attempt_id.clone(), code.clone())
             .await;
 
         assert!(result.is_ok());
-        assert_eq!(store.codes.get(&email), Some(&(login_attempt_id, code)));
+        assert_eq!(store.codes.get(&user_id), Some(&(login_attempt_id, code)));
     }
 
     #[tokio::test]
     async fn test_add_and_remove_code() {
         let mut store = HashmapTwoFACodeStore::default();
-        let email = Email::parse(Secret::new(get_random_email())).unwrap();
+        let user_id = UserId::default();
         let login_attempt_id = LoginAttemptId::default();
         let code = TwoFACode::default();
 
         store
             .codes
-            .insert(email.clone(), (login_attempt_id.clone(), code.clone()));
+            .insert(user_id, (login_attempt_id.clone(), code.clone()));
 
-        let result = store.remove_code(&email).await;
+        let result = store.remove_code(&user_id).await;
 
         assert!(result.is_ok());
-        assert_eq!(store.codes.get(&email), None);
+        assert_eq!(store.codes.get(&user_id), None);
     }
 
     #[tokio::test]
     async fn test_add_and_get_code() {
         let mut store = HashmapTwoFACodeStore::default();
-        let email = Email::parse(Secret::new(get_random_email())).unwrap();
+        let user_id = UserId::default();
         let login_attempt_id = LoginAttemptId::default();
         let code = TwoFACode::default();
 
         let result = store
-            .add_code(email.clone(), login_attempt_id.clone(), code.clone())
+            .add_code(user_id, login_attempt_id.clone(), code.clone())
             .await;
 
         assert!(result.is_ok());
         assert_eq!(
-            store.get_code(&email).await.unwrap(),
+            store.get_code(&user_id).await.unwrap(),
             (login_attempt_id, code)
         );
     }
@@ -100,15 +117,15 @@ mod tests {
     #[tokio::test]
     async fn test_get_code() {
         let mut store = HashmapTwoFACodeStore::default();
-        let email = Email::parse(Secret::new(get_random_email())).unwrap();
+        let user_id = UserId::default();
         let login_attempt_id = LoginAttemptId::default();
         let code = TwoFACode::default();
 
         store
             .codes
-            .insert(email.clone(), (login_attempt_id.clone(), code.clone()));
+            .insert(user_id, (login_attempt_id.clone(), code.clone()));
 
-        let result = store.get_code(&email).await;
+        let result = store.get_code(&user_id).await;
 
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), (login_attempt_id, code));
@@ -117,8 +134,8 @@ mod tests {
     #[tokio::test]
     async fn test_get_code_not_found() {
         let store = HashmapTwoFACodeStore::default();
-        let email = Email::parse(Secret::new(get_random_email())).unwrap();
-        let result = store.get_code(&email).await;
+        let user_id = UserId::default();
+        let result = store.get_code(&user_id).await;
 
         assert!(result.is_err());
         assert_eq!(
@@ -126,4 +143,56 @@ mod tests {
             TwoFACodeStoreError::LoginAttemptIdNotFound
         );
     }
+
+    #[tokio::test]
+    async fn test_record_failed_attempt_removes_code_after_too_many() {
+        let mut store = HashmapTwoFACodeStore::default();
+        let user_id = UserId::default();
+
+        store
+            .add_code(user_id, LoginAttemptId::default(), TwoFACode::default())
+            .await
+            .unwrap();
+
+        for _ in 1..MAX_TWO_FA_ATTEMPTS {
+            assert_eq!(store.record_failed_attempt(&user_id).await, Ok(()));
+        }
+
+        let result = store.record_failed_attempt(&user_id).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::TooManyAttempts));
+        assert_eq!(
+            store.get_code(&user_id).await,
+            Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_code_resets_failed_attempts() {
+        let mut store = HashmapTwoFACodeStore::default();
+        let user_id = UserId::default();
+
+        store
+            .add_code(user_id, LoginAttemptId::default(), TwoFACode::default())
+            .await
+            .unwrap();
+        for _ in 1..MAX_TWO_FA_ATTEMPTS {
+            store.record_failed_attempt(&user_id).await.unwrap();
+        }
+
+        // A new login attempt gets a fresh allowance
+        store
+            .add_code(user_id, LoginAttemptId::default(), TwoFACode::default())
+            .await
+            .unwrap();
+        assert_eq!(store.record_failed_attempt(&user_id).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_record_failed_attempt_not_found() {
+        let mut store = HashmapTwoFACodeStore::default();
+        let user_id = UserId::default();
+
+        let result = store.record_failed_attempt(&user_id).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+    }
 }
@@ -1,14 +1,22 @@
 use std::collections::HashMap;
 
-use crate::domain::{Email, Password, User};
+use chrono::Utc;
+
+use crate::domain::{Email, Password, User, UserId};
 use crate::services::{UserStore, UserStoreError};
 
 // Create a new struct called `HashmapUserStore` containing a `users` field
-// which stores a `HashMap` of `Email` String's mapped to `User` objects.
+// which stores a `HashMap` of `UserId`s mapped to `User` objects.
 // Derive the `Default` trait for `HashmapUserStore`.
 #[derive(Default, Debug)]
 pub struct HashmapUserStore {
-    users: HashMap<Email, User>,
+    users: HashMap<UserId, User>,
+}
+
+impl HashmapUserStore {
+    fn find_by_email(&self, email: &Email) -> Option<&User> {
+        self.users.values().find(|user| user.email == *email)
+    }
 }
 
 #[async_trait::async_trait]
@@ -16,21 +24,28 @@ impl UserStore for HashmapUserStore {
     async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
         // Return `UserStoreError::UserAlreadyExists` if the user already exists,
         // otherwise insert the user into the hashmap and return `Ok(())`.
-        if self.users.contains_key(&user.email) {
+        if self.users.contains_key(&user.id) || self.find_by_email(&user.email).is_some() {
             return Err(UserStoreError::UserAlreadyExists);
         }
-        self.users.insert(user.email.clone(), user);
+        self.users.insert(user.id, user);
         Ok(())
     }
 
     // Implement a public method called `get_user`, which takes an
-    // immutable reference to self and an email string slice as arguments.
+    // immutable reference to self and a user id as arguments.
     // This function should return a `Result` type containing either a
     // `User` object or a `UserStoreError`.
     // Return `UserStoreError::UserNotFound` if the user can not be found.
-    async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
+    async fn get_user(&self, user_id: &UserId) -> Result<User, UserStoreError> {
         // get the user from the hashmap
-        match self.users.get(email) {
+        match self.users.get(user_id) {
+            Some(user) => Ok(user.clone()),
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn get_user_by_email(&self, email: &Email) -> Result<User, UserStoreError> {
+        match self.find_by_email(email) {
             Some(user) => Ok(user.clone()),
             None => Err(UserStoreError::UserNotFound),
         }
@@ -47,7 +62,7 @@ impl UserStore for HashmapUserStore {
         email: &Email,
         password: &Password,
     ) -> Result<(), UserStoreError> {
-        match self.users.get(email) {
+        match self.find_by_email(email) {
             Some(user) => {
                 if user.password.eq(password) {
                     Ok(())
@@ -58,6 +73,69 @@ impl UserStore for HashmapUserStore {
             None => Err(UserStoreError::UserNotFound),
         }
     }
+
+    async fn update_password(
+        &mut self,
+        user_id: &UserId,
+        password: Password,
+    ) -> Result<(), UserStoreError> {
+        match self.users.get_mut(user_id) {
+            Some(user) => {
+                user.password = password;
+                user.updated_at = Utc::now();
+                Ok(())
+            }
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn mark_email_verified(&mut self, user_id: &UserId) -> Result<(), UserStoreError> {
+        match self.users.get_mut(user_id) {
+            Some(user) => {
+                user.verified = true;
+                user.updated_at = Utc::now();
+                Ok(())
+            }
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn change_email(
+        &mut self,
+        user_id: &UserId,
+        new_email: &Email,
+    ) -> Result<(), UserStoreError> {
+        if self.find_by_email(new_email).is_some() {
+            return Err(UserStoreError::UserAlreadyExists);
+        }
+        match self.users.get_mut(user_id) {
+            Some(user) => {
+                user.email = new_email.clone();
+                user.updated_at = Utc::now();
+                Ok(())
+            }
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn update_user(&mut self, user: User) -> Result<(), UserStoreError> {
+        match self.users.get_mut(&user.id) {
+            Some(stored_user) => {
+                stored_user.requires_2fa = user.requires_2fa;
+                stored_user.verified = user.verified;
+                stored_user.updated_at = Utc::now();
+                Ok(())
+            }
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn delete_user(&mut self, user_id: &UserId) -> Result<(), UserStoreError> {
+        match self.users.remove(user_id) {
+            Some(_) => Ok(()),
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
 }
 
 // unit tests for `HashmapUserStore` implementation
@@ -72,18 +150,24 @@ mod tests {
     #[tokio::test]
     async fn test_add_user() {
         let mut store = HashmapUserStore::default();
-        let user = User {
-            email: Email::parse(Secret::new(get_random_email())).unwrap(),
-            password: Password::parse("password123".to_owned().into()).unwrap(),
-            requires_2fa: false,
-        };
+        let user = User::new(
+            Email::parse(Secret::new(get_random_email())).unwrap(),
+            Password::parse("password123".to_owned().into()).unwrap(),
+            false,
+        );
 
         // Add user for the first time
         let result = store.add_user(user.clone()).await;
         assert_eq!(result, Ok(()));
 
         // Try to add the same user again
-        let result = store.add_user(user).await;
+        let result = store.add_user(user.clone()).await;
+        assert_eq!(result, Err(UserStoreError::UserAlreadyExists));
+
+        // Try to add another user with the same email
+        let result = store
+            .add_user(User::new(user.email, user.password, false))
+            .await;
         assert_eq!(result, Err(UserStoreError::UserAlreadyExists));
     }
 
@@ -91,20 +175,24 @@ mod tests {
     async fn test_get_user() {
         let mut store = HashmapUserStore::default();
         let email = Email::parse(Secret::new(get_random_email())).unwrap();
-        let user = User {
-            email: email.clone(),
-            password: Password::parse("password123".to_owned().into()).unwrap(),
-            requires_2fa: false,
-        };
-
-        // Get existing user
-        store.users.insert(email.clone(), user.clone());
-        let result = store.get_user(&email).await;
+        let user = User::new(
+            email.clone(),
+            Password::parse("password123".to_owned().into()).unwrap(),
+            false,
+        );
+
+        // Get existing user, by id or by email
+        store.users.insert(user.id, user.clone());
+        let result = store.get_user(&user.id).await;
+        assert_eq!(result, Ok(user.clone()));
+        let result = store.get_user_by_email(&email).await;
         assert_eq!(result, Ok(user.clone()));
 
         // Get non-existing user
+        let result = store.get_user(&UserId::default()).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
         let result = store
-            .get_user(&Email::parse(Secret::new(get_random_email())).unwrap())
+            .get_user_by_email(&Email::parse(Secret::new(get_random_email())).unwrap())
             .await;
         assert_eq!(result, Err(UserStoreError::UserNotFound));
     }
@@ -114,14 +202,10 @@ mod tests {
         let mut store = HashmapUserStore::default();
         let email = Email::parse(Secret::new(get_random_email())).unwrap();
         let password = Password::parse("password123".to_owned().into()).unwrap();
-        let user = User {
-            email: email.clone(),
-            password: password.clone(),
-            requires_2fa: false,
-        };
+        let user = User::new(email.clone(), password.clone(), false);
 
         // add a new user for the tests
-        store.users.insert(email.clone(), user.clone());
+        store.users.insert(user.id, user.clone());
 
         // Validate existing user with correct credentials
         let result = store.validate_user(&email, &password).await;
@@ -141,4 +225,160 @@ mod tests {
             .await;
         assert_eq!(result, Err(UserStoreError::UserNotFound));
     }
+
+    #[tokio::test]
+    async fn test_update_password() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new(get_random_email())).unwrap();
+        let old_password = Password::parse("password123".to_owned().into()).unwrap();
+        let new_password = Password::parse("newpassword123".to_owned().into()).unwrap();
+        let user = User::new(email.clone(), old_password.clone(), false);
+
+        store.users.insert(user.id, user.clone());
+
+        // Update existing user's password
+        let result = store.update_password(&user.id, new_password.clone()).await;
+        assert_eq!(result, Ok(()));
+        assert_eq!(store.validate_user(&email, &new_password).await, Ok(()));
+        assert_eq!(
+            store.validate_user(&email, &old_password).await,
+            Err(UserStoreError::IncorrectCredentials)
+        );
+
+        // Update non-existing user's password
+        let result = store
+            .update_password(&UserId::default(), new_password)
+            .await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_mark_email_verified() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new(get_random_email())).unwrap();
+        let password = Password::parse("password123".to_owned().into()).unwrap();
+        let user = User::new(email, password, false);
+
+        store.add_user(user.clone()).await.unwrap();
+        assert!(!store.get_user(&user.id).await.unwrap().verified);
+
+        // Verify existing user
+        let result = store.mark_email_verified(&user.id).await;
+        assert_eq!(result, Ok(()));
+        assert!(store.get_user(&user.id).await.unwrap().verified);
+
+        // Verify non-existing user
+        let result = store.mark_email_verified(&UserId::default()).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_change_email() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new(get_random_email())).unwrap();
+        let new_email = Email::parse(Secret::new(get_random_email())).unwrap();
+        let taken_email = Email::parse(Secret::new(get_random_email())).unwrap();
+        let password = Password::parse("password123".to_owned().into()).unwrap();
+
+        let user = User::new(email.clone(), password.clone(), true);
+        store.add_user(user.clone()).await.unwrap();
+        store
+            .add_user(User::new(taken_email.clone(), password.clone(), true))
+            .await
+            .unwrap();
+
+        // Another account's address can't be taken
+        let result = store.change_email(&user.id, &taken_email).await;
+        assert_eq!(result, Err(UserStoreError::UserAlreadyExists));
+
+        // The user keeps their id, and can only be found by their new address
+        let result = store.change_email(&user.id, &new_email).await;
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            store.get_user_by_email(&email).await,
+            Err(UserStoreError::UserNotFound)
+        );
+        let changed = store.get_user_by_email(&new_email).await.unwrap();
+        assert_eq!(changed.id, user.id);
+        assert_eq!(changed.email, new_email);
+        assert!(changed.requires_2fa);
+        assert_eq!(store.validate_user(&new_email, &password).await, Ok(()));
+
+        // Change a non-existing user's address
+        let result = store
+            .change_email(
+                &UserId::default(),
+                &Email::parse(Secret::new(get_random_email())).unwrap(),
+            )
+            .await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_update_user() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new(get_random_email())).unwrap();
+        let password = Password::parse("password123".to_owned().into()).unwrap();
+        let user = User::new(email.clone(), password.clone(), false);
+
+        store.add_user(user.clone()).await.unwrap();
+
+        // Update existing user
+        let result = store
+            .update_user(User {
+                requires_2fa: true,
+                ..user.clone()
+            })
+            .await;
+        assert_eq!(result, Ok(()));
+        assert!(store.get_user(&user.id).await.unwrap().requires_2fa);
+
+        // The email address and password aren't changed this way
+        let new_email = Email::parse(Secret::new(get_random_email())).unwrap();
+        let new_password = Password::parse("newpassword123".to_owned().into()).unwrap();
+        let result = store
+            .update_user(User {
+                email: new_email,
+                password: new_password,
+                ..user.clone()
+            })
+            .await;
+        assert_eq!(result, Ok(()));
+        assert_eq!(store.get_user(&user.id).await.unwrap().email, email);
+        assert_eq!(store.validate_user(&email, &password).await, Ok(()));
+
+        // Update non-existing user
+        let result = store.update_user(User::new(email, password, true)).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_delete_user() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new(get_random_email())).unwrap();
+        let password = Password::parse("password123".to_owned().into()).unwrap();
+        let user = User::new(email.clone(), password.clone(), false);
+
+        store.add_user(user.clone()).await.unwrap();
+
+        // Delete existing user
+        let result = store.delete_user(&user.id).await;
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            store.get_user(&user.id).await,
+            Err(UserStoreError::UserNotFound)
+        );
+        assert_eq!(
+            store.get_user_by_email(&email).await,
+            Err(UserStoreError::UserNotFound)
+        );
+
+        // The address can be used for a new account
+        let result = store.add_user(User::new(email, password, false)).await;
+        assert_eq!(result, Ok(()));
+
+        // Delete non-existing user
+        let result = store.delete_user(&user.id).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
 }
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+
+use crate::domain::UserId;
+use crate::services::data_stores::{
+    WebAuthnCeremony, WebAuthnChallenge, WebAuthnChallengeStore, WebAuthnChallengeStoreError,
+};
+
+#[derive(Default, Debug)]
+pub struct HashmapWebAuthnChallengeStore {
+    challenges: HashMap<(UserId, WebAuthnCeremony), WebAuthnChallenge>,
+}
+
+#[async_trait::async_trait]
+impl WebAuthnChallengeStore for HashmapWebAuthnChallengeStore {
+    async fn add_challenge(
+        &mut self,
+        user_id: &UserId,
+        ceremony: WebAuthnCeremony,
+        challenge: WebAuthnChallenge,
+    ) -> Result<(), WebAuthnChallengeStoreError> {
+        self.challenges.insert((*user_id, ceremony), challenge);
+        Ok(())
+    }
+
+    async fn take_challenge(
+        &mut self,
+        user_id: &UserId,
+        ceremony: WebAuthnCeremony,
+    ) -> Result<WebAuthnChallenge, WebAuthnChallengeStoreError> {
+        self.challenges
+            .remove(&(*user_id, ceremony))
+            .ok_or(WebAuthnChallengeStoreError::ChallengeNotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_add_and_take_challenge() {
+        let mut store = HashmapWebAuthnChallengeStore::default();
+        let user_id = UserId::default();
+        let challenge = WebAuthnChallenge::default();
+
+        store
+            .add_challenge(&user_id, WebAuthnCeremony::Registration, challenge.clone())
+            .await
+            .unwrap();
+
+        // Challenges for the other ceremony are kept separately
+        let result = store
+            .take_challenge(&user_id, WebAuthnCeremony::Authentication)
+            .await;
+        assert_eq!(result, Err(WebAuthnChallengeStoreError::ChallengeNotFound));
+
+        let result = store
+            .take_challenge(&user_id, WebAuthnCeremony::Registration)
+            .await;
+        assert_eq!(result, Ok(challenge));
+
+        let result = store
+            .take_challenge(&user_id, WebAuthnCeremony::Registration)
+            .await;
+        assert_eq!(result, Err(WebAuthnChallengeStoreError::ChallengeNotFound));
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+
+use crate::domain::UserId;
+use crate::services::data_stores::{
+    WebAuthnCredential, WebAuthnCredentialStore, WebAuthnCredentialStoreError,
+};
+
+#[derive(Default, Debug)]
+pub struct HashmapWebAuthnCredentialStore {
+    credentials: HashMap<Vec<u8>, WebAuthnCredential>,
+}
+
+#[async_trait::async_trait]
+impl WebAuthnCredentialStore for HashmapWebAuthnCredentialStore {
+    async fn add_credential(
+        &mut self,
+        credential: WebAuthnCredential,
+    ) -> Result<(), WebAuthnCredentialStoreError> {
+        if self.credentials.contains_key(&credential.credential_id) {
+            return Err(WebAuthnCredentialStoreError::CredentialAlreadyExists);
+        }
+        self.credentials
+            .insert(credential.credential_id.clone(), credential);
+        Ok(())
+    }
+
+    async fn get_credentials(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<WebAuthnCredential>, WebAuthnCredentialStoreError> {
+        Ok(self
+            .credentials
+            .values()
+            .filter(|credential| credential.user_id.eq(user_id))
+            .cloned()
+            .collect())
+    }
+
+    async fn update_sign_count(
+        &mut self,
+        credential_id: &[u8],
+        sign_count: u32,
+    ) -> Result<(), WebAuthnCredentialStoreError> {
+        let credential = self
+            .credentials
+            .get_mut(credential_id)
+            .ok_or(WebAuthnCredentialStoreError::CredentialNotFound)?;
+
+        if !credential.is_sign_count_valid(sign_count) {
+            return Err(WebAuthnCredentialStoreError::SignCountNotIncreased);
+        }
+
+        credential.sign_count = sign_count;
+        credential.last_used_at = Some(Utc::now());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credential(user_id: &UserId, sign_count: u32) -> WebAuthnCredential {
+        WebAuthnCredential {
+            credential_id: rand::random::<[u8; 16]>().to_vec(),
+            user_id: *user_id,
+            public_key: vec![4; 65],
+            sign_count,
+            created_at: Utc::now(),
+            last_used_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_and_get_credentials() {
+        let mut store = HashmapWebAuthnCredentialStore::default();
+        let user_id = UserId::default();
+        let other_user_id = UserId::default();
+        let credential = credential(&user_id, 0);
+
+        let result = store.add_credential(credential.clone()).await;
+        assert_eq!(result, Ok(()));
+
+        let result = store.add_credential(credential.clone()).await;
+        assert_eq!(
+            result,
+            Err(WebAuthnCredentialStoreError::CredentialAlreadyExists)
+        );
+
+        assert_eq!(store.get_credentials(&user_id).await, Ok(vec![credential]));
+        assert_eq!(store.get_credentials(&other_user_id).await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn test_update_sign_count_must_increase() {
+        let mut store = HashmapWebAuthnCredentialStore::default();
+        let user_id = UserId::default();
+        let credential = credential(&user_id, 5);
+        store.add_credential(credential.clone()).await.unwrap();
+
+        for sign_count in [5, 4, 0] {
+            let result = store
+                .update_sign_count(&credential.credential_id, sign_count)
+                .await;
+            assert_eq!(
+                result,
+                Err(WebAuthnCredentialStoreError::SignCountNotIncreased)
+            );
+        }
+
+        let result = store.update_sign_count(&credential.credential_id, 6).await;
+        assert_eq!(result, Ok(()));
+
+        let result = store.update_sign_count(b"unknown", 7).await;
+        assert_eq!(
+            result,
+            Err(WebAuthnCredentialStoreError::CredentialNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_sign_count_allows_authenticators_without_counter() {
+        let mut store = HashmapWebAuthnCredentialStore::default();
+        let user_id = UserId::default();
+        let credential = credential(&user_id, 0);
+        store.add_credential(credential.clone()).await.unwrap();
+
+        let result = store.update_sign_count(&credential.credential_id, 0).await;
+        assert_eq!(result, Ok(()));
+    }
+}
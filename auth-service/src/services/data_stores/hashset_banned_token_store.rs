@@ -1,23 +1,39 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
-use secrecy::{ExposeSecret, Secret};
-
-use crate::services::data_stores::{BannedTokenStore, BannedTokenStoreError};
+use crate::domain::UserId;
+use crate::services::data_stores::{BannedTokenStore, BannedTokenStoreError, TokenId};
 
 #[derive(Default, Debug)]
 pub struct HashsetBannedTokenStore {
-    banned_tokens: HashSet<String>,
+    banned_tokens: HashSet<TokenId>,
+    token_generations: HashMap<UserId, u64>,
 }
 
 #[async_trait::async_trait]
 impl BannedTokenStore for HashsetBannedTokenStore {
-    async fn ban_token(&mut self, token: Secret<String>) -> Result<(), BannedTokenStoreError> {
-        self.banned_tokens.insert(token.expose_secret().to_owned());
+    async fn ban_token(&mut self, token_id: &TokenId) -> Result<(), BannedTokenStoreError> {
+        self.banned_tokens.insert(token_id.clone());
+        Ok(())
+    }
+
+    async fn is_token_banned(&self, token_id: &TokenId) -> Result<bool, BannedTokenStoreError> {
+        Ok(self.banned_tokens.contains(token_id))
+    }
+
+    async fn ban_all_tokens_for_user(
+        &mut self,
+        user_id: &UserId,
+    ) -> Result<(), BannedTokenStoreError> {
+        *self.token_generations.entry(*user_id).or_default() += 1;
         Ok(())
     }
 
-    async fn is_token_banned(&self, token: &Secret<String>) -> Result<bool, BannedTokenStoreError> {
-        Ok(self.banned_tokens.contains(token.expose_secret()))
+    async fn get_token_generation(&self, user_id: &UserId) -> Result<u64, BannedTokenStoreError> {
+        Ok(self
+            .token_generations
+            .get(user_id)
+            .copied()
+            .unwrap_or_default())
     }
 }
 
@@ -28,77 +44,99 @@ mod tests {
     #[tokio::test]
     async fn test_ban_token() {
         let mut store = HashsetBannedTokenStore::default();
-        let token = Secret::new("test_token_123".to_string());
+        let token_id = TokenId::default();
 
-        let result = store.ban_token(token.clone()).await;
+        let result = store.ban_token(&token_id).await;
         assert_eq!(result, Ok(()));
 
         // Verify the token was actually stored
-        assert!(store.banned_tokens.contains(token.expose_secret()));
+        assert!(store.banned_tokens.contains(&token_id));
     }
 
     #[tokio::test]
     async fn test_is_token_banned() {
         let mut store = HashsetBannedTokenStore::default();
-        let banned_token = Secret::new("banned_token_456".to_owned());
-        let clean_token = Secret::new("clean_token_789".to_owned());
+        let banned_token_id = TokenId::default();
+        let clean_token_id = TokenId::default();
 
         // Ban a token
-        store
-            .banned_tokens
-            .insert(banned_token.expose_secret().to_owned());
+        store.banned_tokens.insert(banned_token_id.clone());
 
         // Check banned token
-        let result = store.is_token_banned(&banned_token).await;
+        let result = store.is_token_banned(&banned_token_id).await;
         assert_eq!(result, Ok(true));
 
         // Check clean token
-        let result = store.is_token_banned(&clean_token).await;
+        let result = store.is_token_banned(&clean_token_id).await;
         assert_eq!(result, Ok(false));
     }
 
     #[tokio::test]
     async fn test_ban_multiple_tokens() {
         let mut store = HashsetBannedTokenStore::default();
-        let tokens = vec![
-            Secret::new("token1".to_owned()),
-            Secret::new("token2".to_owned()),
-            Secret::new("token3".to_owned()),
-        ];
+        let token_ids = vec![TokenId::default(), TokenId::default(), TokenId::default()];
 
         // Ban multiple tokens
-        for token in &tokens {
-            let result = store.ban_token(token.clone()).await;
+        for token_id in &token_ids {
+            let result = store.ban_token(token_id).await;
             assert_eq!(result, Ok(()));
         }
 
         // Verify all tokens are banned
-        for token in &tokens {
-            let result = store.is_token_banned(token).await;
+        for token_id in &token_ids {
+            let result = store.is_token_banned(token_id).await;
             assert_eq!(result, Ok(true));
         }
 
         // Verify clean token is not banned
-        let clean_token = Secret::new("clean_token".to_owned());
-        let result = store.is_token_banned(&clean_token).await;
+        let result = store.is_token_banned(&TokenId::default()).await;
         assert_eq!(result, Ok(false));
     }
 
     #[tokio::test]
     async fn test_ban_duplicate_token() {
         let mut store = HashsetBannedTokenStore::default();
-        let token = Secret::new("duplicate_token".to_owned());
+        let token_id = TokenId::default();
 
         // Ban token twice
-        let result1 = store.ban_token(token.clone()).await;
-        let result2 = store.ban_token(token.clone()).await;
+        let result1 = store.ban_token(&token_id).await;
+        let result2 = store.ban_token(&token_id).await;
 
         assert_eq!(result1, Ok(()));
         assert_eq!(result2, Ok(()));
 
         // Should still be banned only once (HashSet behavior)
         assert_eq!(store.banned_tokens.len(), 1);
-        let result = store.is_token_banned(&token).await;
+        let result = store.is_token_banned(&token_id).await;
         assert_eq!(result, Ok(true));
     }
+
+    #[tokio::test]
+    async fn test_ban_all_tokens_for_user() {
+        let mut store = HashsetBannedTokenStore::default();
+        let user_id = UserId::default();
+        let other_user_id = UserId::default();
+
+        // Users start at generation 0
+        assert_eq!(store.get_token_generation(&user_id).await, Ok(0));
+
+        // Each ban bumps the user's generation
+        assert_eq!(store.ban_all_tokens_for_user(&user_id).await, Ok(()));
+        assert_eq!(store.get_token_generation(&user_id).await, Ok(1));
+        assert_eq!(store.ban_all_tokens_for_user(&user_id).await, Ok(()));
+        assert_eq!(store.get_token_generation(&user_id).await, Ok(2));
+
+        // Other users are unaffected
+        assert_eq!(store.get_token_generation(&other_user_id).await, Ok(0));
+    }
+
+    #[test]
+    fn test_token_id_parse() {
+        let token_id = TokenId::default();
+        assert_eq!(
+            TokenId::parse(token_id.as_ref().to_owned()).unwrap(),
+            token_id
+        );
+        assert!(TokenId::parse("not-a-uuid".to_owned()).is_err());
+    }
 }
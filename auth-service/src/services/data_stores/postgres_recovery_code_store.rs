@@ -0,0 +1,124 @@
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+
+use crate::{
+    domain::UserId,
+    services::data_stores::{
+        postgres_user_store::{compute_password_hash, verify_password_hash},
+        RecoveryCode, RecoveryCodeStore, RecoveryCodeStoreError,
+    },
+    utils::constants::PASSWORD_HASH_PARAMS,
+};
+
+#[derive(Debug, Clone)]
+pub struct PostgresRecoveryCodeStore {
+    pool: PgPool,
+}
+
+impl PostgresRecoveryCodeStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl RecoveryCodeStore for PostgresRecoveryCodeStore {
+    #[tracing::instrument(name = "Replacing recovery codes in PostgreSQL", skip_all)]
+    async fn replace_codes(
+        &mut self,
+        user_id: &UserId,
+        codes: Vec<RecoveryCode>,
+    ) -> Result<(), RecoveryCodeStoreError> {
+        // Only hashes are stored, the same way as passwords
+        let mut code_hashes = Vec::with_capacity(codes.len());
+        for code in codes {
+            code_hashes.push(
+                compute_password_hash(code.as_ref().to_owned(), PASSWORD_HASH_PARAMS.clone(), None)
+                    .await?,
+            );
+        }
+
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| RecoveryCodeStoreError::UnexpectedError(e.into()))?;
+
+        sqlx::query!(
+            r#"DELETE FROM recovery_codes WHERE user_id = $1"#,
+            user_id.as_ref()
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| RecoveryCodeStoreError::UnexpectedError(e.into()))?;
+
+        for code_hash in code_hashes {
+            sqlx::query!(
+                r#"INSERT INTO recovery_codes (user_id, code_hash) VALUES ($1, $2)"#,
+                user_id.as_ref(),
+                code_hash.expose_secret()
+            )
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| RecoveryCodeStoreError::UnexpectedError(e.into()))?;
+        }
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| RecoveryCodeStoreError::UnexpectedError(e.into()))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Using recovery code in PostgreSQL", skip_all)]
+    async fn use_code(
+        &mut self,
+        user_id: &UserId,
+        code: &RecoveryCode,
+    ) -> Result<(), RecoveryCodeStoreError> {
+        let rows = sqlx::query!(
+            r#"SELECT id, code_hash FROM recovery_codes WHERE user_id = $1"#,
+            user_id.as_ref()
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| RecoveryCodeStoreError::UnexpectedError(e.into()))?;
+
+        for row in rows {
+            let is_match =
+                verify_password_hash(Secret::new(row.code_hash), code.as_ref().to_owned(), None)
+                    .await
+                    .is_ok();
+            if !is_match {
+                continue;
+            }
+
+            // If a concurrent request deleted the code first, it has already been used
+            let result = sqlx::query!(r#"DELETE FROM recovery_codes WHERE id = $1"#, row.id)
+                .execute(&self.pool)
+                .await
+                .map_err(|e| RecoveryCodeStoreError::UnexpectedError(e.into()))?;
+
+            return match result.rows_affected() {
+                0 => Err(RecoveryCodeStoreError::CodeNotFound),
+                _ => Ok(()),
+            };
+        }
+
+        Err(RecoveryCodeStoreError::CodeNotFound)
+    }
+
+    #[tracing::instrument(name = "Counting recovery codes in PostgreSQL", skip_all)]
+    async fn count_codes(&self, user_id: &UserId) -> Result<usize, RecoveryCodeStoreError> {
+        let count = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM recovery_codes WHERE user_id = $1"#,
+            user_id.as_ref()
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| RecoveryCodeStoreError::UnexpectedError(e.into()))?;
+
+        usize::try_from(count).map_err(|e| RecoveryCodeStoreError::UnexpectedError(e.into()))
+    }
+}
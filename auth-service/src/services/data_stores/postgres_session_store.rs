@@ -0,0 +1,135 @@
+use sqlx::PgPool;
+
+use crate::{
+    domain::UserId,
+    services::data_stores::{Session, SessionId, SessionStore, SessionStoreError, TokenId},
+};
+
+#[derive(Debug, Clone)]
+pub struct PostgresSessionStore {
+    pool: PgPool,
+}
+
+impl PostgresSessionStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for PostgresSessionStore {
+    #[tracing::instrument(name = "Adding session to PostgreSQL", skip_all)]
+    async fn add_session(&mut self, session: Session) -> Result<(), SessionStoreError> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO sessions (id, user_id, token_id, created_at, last_seen, ip_address, user_agent)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            session.id.as_ref(),
+            session.user_id.as_ref(),
+            session.token_id.as_ref(),
+            session.created_at,
+            session.last_seen,
+            session.ip_address,
+            session.user_agent
+        )
+        .execute(&self.pool)
+        .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(sqlx::Error::Database(db_err)) if db_err.code() == Some("23505".into()) => {
+                // 23505 is the PostgreSQL error code for unique_violation
+                Err(SessionStoreError::SessionAlreadyExists)
+            }
+            Err(e) => Err(SessionStoreError::UnexpectedError(e.into())),
+        }
+    }
+
+    #[tracing::instrument(name = "Retrieving session from PostgreSQL", skip_all)]
+    async fn get_session(&self, id: &SessionId) -> Result<Session, SessionStoreError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT id, user_id, token_id, created_at, last_seen, ip_address, user_agent
+            FROM sessions WHERE id = $1
+            "#,
+            id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| SessionStoreError::UnexpectedError(e.into()))?
+        .ok_or(SessionStoreError::SessionNotFound)?;
+
+        Ok(Session {
+            id: SessionId::parse(row.id)?,
+            user_id: UserId::from(row.user_id),
+            token_id: TokenId::parse(row.token_id)?,
+            created_at: row.created_at,
+            last_seen: row.last_seen,
+            ip_address: row.ip_address,
+            user_agent: row.user_agent,
+        })
+    }
+
+    #[tracing::instrument(name = "Retrieving sessions from PostgreSQL", skip_all)]
+    async fn get_sessions(&self, user_id: &UserId) -> Result<Vec<Session>, SessionStoreError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, user_id, token_id, created_at, last_seen, ip_address, user_agent
+            FROM sessions WHERE user_id = $1 ORDER BY created_at
+            "#,
+            user_id.as_ref()
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| SessionStoreError::UnexpectedError(e.into()))?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(Session {
+                    id: SessionId::parse(row.id)?,
+                    user_id: UserId::from(row.user_id),
+                    token_id: TokenId::parse(row.token_id)?,
+                    created_at: row.created_at,
+                    last_seen: row.last_seen,
+                    ip_address: row.ip_address,
+                    user_agent: row.user_agent,
+                })
+            })
+            .collect()
+    }
+
+    #[tracing::instrument(name = "Touching session in PostgreSQL", skip_all)]
+    async fn touch_session(
+        &mut self,
+        id: &SessionId,
+        token_id: &TokenId,
+    ) -> Result<(), SessionStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE sessions SET token_id = $2, last_seen = now()
+            WHERE id = $1
+            "#,
+            id.as_ref(),
+            token_id.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| SessionStoreError::UnexpectedError(e.into()))?;
+
+        match result.rows_affected() {
+            0 => Err(SessionStoreError::SessionNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Removing session from PostgreSQL", skip_all)]
+    async fn remove_session(&mut self, id: &SessionId) -> Result<(), SessionStoreError> {
+        sqlx::query!(r#"DELETE FROM sessions WHERE id = $1"#, id.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| SessionStoreError::UnexpectedError(e.into()))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use color_eyre::eyre::{eyre, Context, Result};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+use crate::{
+    domain::UserId,
+    services::data_stores::{TotpSecret, TotpSecretRecord, TotpSecretStore, TotpSecretStoreError},
+    utils::constants::TOTP_ENCRYPTION_KEY,
+};
+
+#[derive(Debug, Clone)]
+pub struct PostgresTotpSecretStore {
+    pool: PgPool,
+}
+
+impl PostgresTotpSecretStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    // Re-encrypt a secret that is still bound to the user's email address. Only a secret that
+    // hasn't changed in the meantime is replaced, so a concurrent re-enrollment wins.
+    async fn upgrade_secret(
+        &self,
+        user_id: &UserId,
+        old_encrypted_secret: &str,
+        secret: &TotpSecret,
+    ) -> Result<()> {
+        let encrypted_secret = encrypt_secret(user_id, secret)?;
+
+        sqlx::query!(
+            r#"UPDATE totp_secrets SET encrypted_secret = $2 WHERE user_id = $1 AND encrypted_secret = $3"#,
+            user_id.as_ref(),
+            encrypted_secret,
+            old_encrypted_secret
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("failed to store re-encrypted TOTP secret")?;
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl TotpSecretStore for PostgresTotpSecretStore {
+    #[tracing::instrument(name = "Adding TOTP secret to PostgreSQL", skip_all)]
+    async fn add_secret(
+        &mut self,
+        user_id: &UserId,
+        secret: TotpSecret,
+    ) -> Result<(), TotpSecretStoreError> {
+        let encrypted_secret = encrypt_secret(user_id, &secret)?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO totp_secrets (user_id, encrypted_secret) VALUES ($1, $2)
+            ON CONFLICT (user_id) DO UPDATE
+            SET encrypted_secret = EXCLUDED.encrypted_secret, confirmed = false,
+                last_used_step = NULL, updated_at = now()
+            "#,
+            user_id.as_ref(),
+            encrypted_secret
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| TotpSecretStoreError::UnexpectedError(e.into()))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Retrieving TOTP secret from PostgreSQL", skip_all)]
+    async fn get_secret(&self, user_id: &UserId) -> Result<TotpSecretRecord, TotpSecretStoreError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT t.encrypted_secret, t.confirmed, t.last_used_step, u.email
+            FROM totp_secrets t JOIN users u ON u.id = t.user_id WHERE t.user_id = $1
+            "#,
+            user_id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| TotpSecretStoreError::UnexpectedError(e.into()))?
+        .ok_or(TotpSecretStoreError::SecretNotFound)?;
+
+        let last_used_step = row
+            .last_used_step
+            .map(u64::try_from)
+            .transpose()
+            .wrap_err("failed to cast last_used_step to u64")?;
+
+        // Secrets stored before users had ids are bound to their email address instead
+        let secret = match decrypt_secret(user_id, &row.encrypted_secret) {
+            Ok(secret) => secret,
+            Err(e) => {
+                let secret = decrypt_legacy_secret(&row.email, &row.encrypted_secret)
+                    .map_err(|_| TotpSecretStoreError::UnexpectedError(e))?;
+                if let Err(e) = self
+                    .upgrade_secret(user_id, &row.encrypted_secret, &secret)
+                    .await
+                {
+                    tracing::warn!("failed to re-encrypt TOTP secret: {:?}", e);
+                }
+                secret
+            }
+        };
+
+        Ok(TotpSecretRecord {
+            secret,
+            confirmed: row.confirmed,
+            last_used_step,
+        })
+    }
+
+    #[tracing::instrument(name = "Confirming TOTP secret in PostgreSQL", skip_all)]
+    async fn confirm_secret(&mut self, user_id: &UserId) -> Result<(), TotpSecretStoreError> {
+        let result = sqlx::query!(
+            r#"UPDATE totp_secrets SET confirmed = true, updated_at = now() WHERE user_id = $1"#,
+            user_id.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| TotpSecretStoreError::UnexpectedError(e.into()))?;
+
+        match result.rows_affected() {
+            0 => Err(TotpSecretStoreError::SecretNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Recording used TOTP step in PostgreSQL", skip_all)]
+    async fn record_used_step(
+        &mut self,
+        user_id: &UserId,
+        step: u64,
+    ) -> Result<(), TotpSecretStoreError> {
+        let step = i64::try_from(step).wrap_err("failed to cast step to i64")?;
+
+        // The comparison happens in the UPDATE itself, so two concurrent logins with the same
+        // code can't both succeed
+        let result = sqlx::query!(
+            r#"
+            UPDATE totp_secrets SET last_used_step = $2, updated_at = now()
+            WHERE user_id = $1 AND (last_used_step IS NULL OR last_used_step < $2)
+            "#,
+            user_id.as_ref(),
+            step
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| TotpSecretStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() > 0 {
+            return Ok(());
+        }
+
+        // Nothing was updated - either there is no secret or the step has already been used
+        self.get_secret(user_id).await?;
+        Err(TotpSecretStoreError::CodeAlreadyUsed)
+    }
+}
+
+// Secrets are encrypted with AES-256-GCM, using the user's id as associated data so a
+// ciphertext can't be moved to another user's row
+#[tracing::instrument(name = "Encrypting TOTP secret", skip_all)]
+fn encrypt_secret(user_id: &UserId, secret: &TotpSecret) -> Result<String> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let payload = Payload {
+        msg: secret.as_ref().expose_secret().as_bytes(),
+        aad: user_id.as_ref().as_bytes(),
+    };
+
+    let ciphertext = cipher()
+        .encrypt(&nonce, payload)
+        .map_err(|_| eyre!("failed to encrypt TOTP secret"))?;
+
+    let mut data = nonce.to_vec();
+    data.extend_from_slice(&ciphertext);
+    Ok(BASE64.encode(data))
+}
+
+#[tracing::instrument(name = "Decrypting TOTP secret", skip_all)]
+fn decrypt_secret(user_id: &UserId, encrypted_secret: &str) -> Result<TotpSecret> {
+    decrypt_with_aad(user_id.as_ref().as_bytes(), encrypted_secret)
+}
+
+// Secrets stored before users had ids used their email address as associated data
+#[tracing::instrument(name = "Decrypting legacy TOTP secret", skip_all)]
+fn decrypt_legacy_secret(email: &str, encrypted_secret: &str) -> Result<TotpSecret> {
+    decrypt_with_aad(email.as_bytes(), encrypted_secret)
+}
+
+fn decrypt_with_aad(aad: &[u8], encrypted_secret: &str) -> Result<TotpSecret> {
+    let data = BASE64
+        .decode(encrypted_secret)
+        .wrap_err("failed to decode TOTP secret")?;
+
+    // AES-GCM nonces are 96 bits
+    if data.len() < 12 {
+        return Err(eyre!("encrypted TOTP secret is too short"));
+    }
+    let (nonce, ciphertext) = data.split_at(12);
+    let payload = Payload {
+        msg: ciphertext,
+        aad,
+    };
+
+    let plaintext = cipher()
+        .decrypt(Nonce::from_slice(nonce), payload)
+        .map_err(|_| eyre!("failed to decrypt TOTP secret"))?;
+    let secret = String::from_utf8(plaintext).wrap_err("decrypted TOTP secret is not UTF-8")?;
+
+    TotpSecret::parse(Secret::new(secret))
+}
+
+fn cipher() -> Aes256Gcm {
+    let key = Sha256::digest(TOTP_ENCRYPTION_KEY.expose_secret().as_bytes());
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+}
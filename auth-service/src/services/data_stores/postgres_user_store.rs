@@ -1,26 +1,140 @@
-use color_eyre::eyre::{Context, Result};
+use color_eyre::eyre::{eyre, Context, Result};
 
 use argon2::{
     password_hash::rand_core::OsRng, password_hash::SaltString, Algorithm, Argon2, Params,
     PasswordHash, PasswordHasher, PasswordVerifier, Version,
 };
 
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
 use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
+use tokio::sync::OnceCell;
 
 use crate::{
-    domain::{Email, Password, User},
+    domain::{Email, Password, User, UserId},
     services::data_stores::{UserStore, UserStoreError},
+    utils::{
+        password_hashing::{
+            needs_rehash, verify_legacy_password_hash, PasswordHashFormat, PasswordPeppers,
+        },
+        user_import::{ImportedUser, UserImportSummary},
+    },
 };
 
 #[derive(Debug, Clone)]
 pub struct PostgresUserStore {
     pool: PgPool,
+    // New passwords are hashed with these, and older hashes are upgraded to them on login
+    hash_params: Params,
+    // New passwords are hashed with the active pepper, and older hashes are upgraded to it on login
+    peppers: PasswordPeppers,
+    // A hash of no one's password, made with the same parameters as real ones
+    dummy_password_hash: Arc<OnceCell<Secret<String>>>,
 }
 
 impl PostgresUserStore {
-    pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+    pub fn new(pool: PgPool, hash_params: Params, peppers: PasswordPeppers) -> Self {
+        Self {
+            pool,
+            hash_params,
+            peppers,
+            dummy_password_hash: Arc::new(OnceCell::new()),
+        }
+    }
+
+    async fn dummy_password_hash(&self) -> Result<Secret<String>> {
+        self.dummy_password_hash
+            .get_or_try_init(|| {
+                compute_password_hash(
+                    Secret::new("dummy password".to_owned()),
+                    self.hash_params.clone(),
+                    self.active_pepper(),
+                )
+            })
+            .await
+            .cloned()
+    }
+
+    fn active_pepper(&self) -> Option<Secret<String>> {
+        self.peppers.active().map(|(_, pepper)| pepper.clone())
+    }
+
+    // Hash a new password, returning the ID of the pepper it was hashed with to store alongside it
+    async fn hash_password(&self, password: &Password) -> Result<(Secret<String>, Option<String>)> {
+        let password_hash = compute_password_hash(
+            password.as_ref().to_owned(),
+            self.hash_params.clone(),
+            self.active_pepper(),
+        )
+        .await?;
+
+        Ok((password_hash, self.peppers.active_id().map(str::to_owned)))
+    }
+
+    // Add users with password hashes from another system, as it is being migrated from. Their
+    // hashes are kept as they are until each user next logs in, and their email addresses are
+    // treated as verified. Users whose email address already has an account are skipped.
+    #[tracing::instrument(name = "Importing users into PostgreSQL", skip_all)]
+    pub async fn import_users(
+        &mut self,
+        users: &[ImportedUser],
+    ) -> Result<UserImportSummary, UserStoreError> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        let mut imported = 0;
+        for user in users {
+            let result = sqlx::query!(
+                r#"INSERT INTO users (email, password_hash, requires_2fa, verified) VALUES ($1, $2, $3, true) ON CONFLICT (email) DO NOTHING"#,
+                user.email.as_ref().expose_secret(),
+                user.password_hash.expose_secret(),
+                user.requires_2fa
+            )
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+            imported += result.rows_affected();
+        }
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        Ok(UserImportSummary {
+            imported,
+            skipped: users.len() as u64 - imported,
+        })
+    }
+
+    // Replace a hash made in an older format or with outdated parameters or pepper, now that the
+    // password is known. Only a hash that hasn't changed in the meantime is replaced, so a
+    // concurrent password change wins.
+    async fn rehash_password(
+        &self,
+        email: &Email,
+        old_password_hash: &Secret<String>,
+        password: &Password,
+    ) -> Result<()> {
+        let (password_hash, pepper_id) = self.hash_password(password).await?;
+
+        sqlx::query!(
+            r#"UPDATE users SET password_hash = $2, password_pepper_id = $3 WHERE email = $1 AND password_hash = $4"#,
+            email.as_ref().expose_secret(),
+            password_hash.expose_secret(),
+            pepper_id,
+            old_password_hash.expose_secret()
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("failed to store rehashed password")?;
+
+        Ok(())
     }
 }
 
@@ -31,14 +145,20 @@ impl UserStore for PostgresUserStore {
 
     #[tracing::instrument(name = "Adding user to PostgreSQL", skip_all)]
     async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
-        let password_hash = compute_password_hash(user.password.as_ref().to_owned())
+        let (password_hash, pepper_id) = self
+            .hash_password(&user.password)
             .await
             .map_err(UserStoreError::UnexpectedError)?;
         let result = sqlx::query!(
-            r#"INSERT INTO users (email, password_hash, requires_2fa) VALUES ($1, $2, $3)"#,
+            r#"INSERT INTO users (id, email, password_hash, password_pepper_id, requires_2fa, verified, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"#,
+            user.id.as_ref(),
             user.email.as_ref().expose_secret(),
             &password_hash.expose_secret(),
-            user.requires_2fa
+            pepper_id,
+            user.requires_2fa,
+            user.verified,
+            user.created_at,
+            user.updated_at
         )
         .execute(&self.pool)
         .await;
@@ -54,22 +174,47 @@ impl UserStore for PostgresUserStore {
     }
 
     #[tracing::instrument(name = "Retrieving user from PostgreSQL", skip_all)]
-    async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
+    async fn get_user(&self, user_id: &UserId) -> Result<User, UserStoreError> {
+        sqlx::query!(
+            r#"SELECT id, email, password_hash, requires_2fa, verified, created_at, updated_at FROM users WHERE id = $1"#,
+            user_id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .map(|row| {
+            parse_user(
+                row.id,
+                row.email,
+                row.password_hash,
+                row.requires_2fa,
+                row.verified,
+                row.created_at,
+                row.updated_at,
+            )
+        })
+        .ok_or(UserStoreError::UserNotFound)?
+    }
+
+    #[tracing::instrument(name = "Retrieving user by email from PostgreSQL", skip_all)]
+    async fn get_user_by_email(&self, email: &Email) -> Result<User, UserStoreError> {
         sqlx::query!(
-            r#"SELECT email, password_hash, requires_2fa FROM users WHERE email = $1"#,
+            r#"SELECT id, email, password_hash, requires_2fa, verified, created_at, updated_at FROM users WHERE email = $1"#,
             email.as_ref().expose_secret()
         )
         .fetch_optional(&self.pool)
         .await
         .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
         .map(|row| {
-            Ok(User {
-                email: Email::parse(Secret::new(row.email))
-                    .map_err(UserStoreError::UnexpectedError)?,
-                password: Password::parse(Secret::new(row.password_hash))
-                    .map_err(UserStoreError::UnexpectedError)?,
-                requires_2fa: row.requires_2fa,
-            })
+            parse_user(
+                row.id,
+                row.email,
+                row.password_hash,
+                row.requires_2fa,
+                row.verified,
+                row.created_at,
+                row.updated_at,
+            )
         })
         .ok_or(UserStoreError::UserNotFound)?
     }
@@ -80,54 +225,217 @@ impl UserStore for PostgresUserStore {
         email: &Email,
         password: &Password,
     ) -> Result<(), UserStoreError> {
-        let user = self.get_user(email).await?;
+        let row = sqlx::query!(
+            r#"SELECT password_hash, password_pepper_id FROM users WHERE email = $1"#,
+            email.as_ref().expose_secret()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
 
-        verify_password_hash(
-            user.password.as_ref().to_owned(),
-            password.as_ref().to_owned(),
+        let Some(row) = row else {
+            // Check the password anyway, so unknown users take as long to turn away as known ones
+            let dummy_password_hash = self
+                .dummy_password_hash()
+                .await
+                .map_err(UserStoreError::UnexpectedError)?;
+            let _ = verify_password_hash(
+                dummy_password_hash,
+                password.as_ref().to_owned(),
+                self.active_pepper(),
+            )
+            .await;
+            return Err(UserStoreError::UserNotFound);
+        };
+        let password_hash = Secret::new(row.password_hash);
+
+        // A pepper that has been removed from the config can't be checked, so is an error rather
+        // than a wrong password
+        let pepper = match &row.password_pepper_id {
+            Some(pepper_id) => Some(
+                self.peppers
+                    .get(pepper_id)
+                    .cloned()
+                    .ok_or_else(|| eyre!("password pepper {} is not configured", pepper_id))?,
+            ),
+            None => None,
+        };
+
+        verify_password_hash(password_hash.clone(), password.as_ref().to_owned(), pepper)
+            .await
+            .map_err(|_| UserStoreError::IncorrectCredentials)?;
+
+        // The user can log in whether or not their hash is brought up to date
+        if needs_rehash(&password_hash, &self.hash_params)
+            || row.password_pepper_id.as_deref() != self.peppers.active_id()
+        {
+            if let Err(e) = self.rehash_password(email, &password_hash, password).await {
+                tracing::warn!("failed to rehash password: {:?}", e);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Updating user password in PostgreSQL", skip_all)]
+    async fn update_password(
+        &mut self,
+        user_id: &UserId,
+        password: Password,
+    ) -> Result<(), UserStoreError> {
+        let (password_hash, pepper_id) = self
+            .hash_password(&password)
+            .await
+            .map_err(UserStoreError::UnexpectedError)?;
+
+        let result = sqlx::query!(
+            r#"UPDATE users SET password_hash = $2, password_pepper_id = $3, updated_at = now() WHERE id = $1"#,
+            user_id.as_ref(),
+            &password_hash.expose_secret(),
+            pepper_id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Marking user email as verified in PostgreSQL", skip_all)]
+    async fn mark_email_verified(&mut self, user_id: &UserId) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"UPDATE users SET verified = true, updated_at = now() WHERE id = $1"#,
+            user_id.as_ref()
         )
+        .execute(&self.pool)
         .await
-        .map_err(|_| UserStoreError::IncorrectCredentials)
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Changing user email in PostgreSQL", skip_all)]
+    async fn change_email(
+        &mut self,
+        user_id: &UserId,
+        new_email: &Email,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"UPDATE users SET email = $2, updated_at = now() WHERE id = $1"#,
+            user_id.as_ref(),
+            new_email.as_ref().expose_secret()
+        )
+        .execute(&self.pool)
+        .await;
+
+        match result {
+            Ok(result) if result.rows_affected() == 0 => Err(UserStoreError::UserNotFound),
+            Ok(_) => Ok(()),
+            Err(sqlx::Error::Database(db_err)) if db_err.code() == Some("23505".into()) => {
+                Err(UserStoreError::UserAlreadyExists)
+            }
+            Err(e) => Err(UserStoreError::UnexpectedError(e.into())),
+        }
+    }
+
+    #[tracing::instrument(name = "Updating user in PostgreSQL", skip_all)]
+    async fn update_user(&mut self, user: User) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users SET requires_2fa = $2, verified = $3, updated_at = now()
+            WHERE id = $1
+            "#,
+            user.id.as_ref(),
+            user.requires_2fa,
+            user.verified
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    // Everything else stored with the user in the database is deleted along with them
+    #[tracing::instrument(name = "Deleting user from PostgreSQL", skip_all)]
+    async fn delete_user(&mut self, user_id: &UserId) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(r#"DELETE FROM users WHERE id = $1"#, user_id.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        match result.rows_affected() {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
     }
 }
 
-// Helper function to verify if a given password matches an expected hash
-// Hashing is a CPU-intensive operation. To avoid blocking
-// other async tasks, update this function to perform hashing on a
-// separate thread pool using tokio::task::spawn_blocking. Note that you
-// will need to update the input parameters to be String types instead of &str
+fn parse_user(
+    id: uuid::Uuid,
+    email: String,
+    password_hash: String,
+    requires_2fa: bool,
+    verified: bool,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+) -> Result<User, UserStoreError> {
+    Ok(User {
+        id: UserId::from(id),
+        email: Email::parse(Secret::new(email)).map_err(UserStoreError::UnexpectedError)?,
+        password: Password::parse(Secret::new(password_hash))
+            .map_err(UserStoreError::UnexpectedError)?,
+        requires_2fa,
+        verified,
+        created_at,
+        updated_at,
+    })
+}
+
+// Hashes made with a pepper can only be verified with the same pepper, and hashes imported from
+// older systems are checked in their own format
 #[tracing::instrument(name = "Verify password hash", skip_all)]
-async fn verify_password_hash(
+pub(crate) async fn verify_password_hash(
     expected_password_hash: Secret<String>,
     password_candidate: Secret<String>,
+    pepper: Option<Secret<String>>,
 ) -> Result<()> {
     let current_span: tracing::Span = tracing::Span::current();
 
     let result = tokio::task::spawn_blocking(move || {
         current_span.in_scope(|| {
-            let password_candidate = password_candidate.expose_secret().as_bytes();
+            let expected_password_hash = expected_password_hash.expose_secret();
+            match PasswordHashFormat::detect(expected_password_hash) {
+                Some(PasswordHashFormat::Argon2) => (),
+                Some(_) => {
+                    return verify_legacy_password_hash(
+                        expected_password_hash,
+                        password_candidate.expose_secret(),
+                    )
+                }
+                None => return Err(eyre!("unknown password hash format")),
+            }
 
+            let password_candidate = password_candidate.expose_secret().as_bytes();
             let expected_password_hash: PasswordHash<'_> =
-                PasswordHash::new(expected_password_hash.expose_secret())?;
-
-            tracing::debug!(
-                ?expected_password_hash.hash,
-                ?password_candidate,
-                "debugging inside spawn_blocking"
-            );
+                PasswordHash::new(expected_password_hash)?;
 
-            tracing::debug!(?expected_password_hash, "parsed expected hash");
-            tracing::debug!(?password_candidate, "password candidate");
-
-            Argon2::default()
+            argon2_with_pepper(pepper.as_ref(), Params::default())?
                 .verify_password(password_candidate, &expected_password_hash)
                 .wrap_err("failed to verify password hash")
         })
     })
     .await;
 
-    tracing::debug!(?result, "result from spawn_blocking");
-
     result?
 }
 
@@ -137,20 +445,20 @@ async fn verify_password_hash(
 // separate thread pool using tokio::task::spawn_blocking. Note that you
 // will need to update the input parameters to be String types instead of &str
 #[tracing::instrument(name = "Computing password hash", skip_all)]
-async fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>> {
+pub(crate) async fn compute_password_hash(
+    password: Secret<String>,
+    params: Params,
+    pepper: Option<Secret<String>>,
+) -> Result<Secret<String>> {
     let current_span: tracing::Span = tracing::Span::current();
 
     let result = tokio::task::spawn_blocking(move || {
         current_span.in_scope(|| {
             let mut rng = OsRng;
             let salt: SaltString = SaltString::generate(&mut rng);
-            let password_hash = Argon2::new(
-                Algorithm::Argon2id,
-                Version::V0x13,
-                Params::new(15000, 2, 1, None)?,
-            )
-            .hash_password(password.expose_secret().as_bytes(), &salt)?
-            .to_string();
+            let password_hash = argon2_with_pepper(pepper.as_ref(), params)?
+                .hash_password(password.expose_secret().as_bytes(), &salt)?
+                .to_string();
 
             Ok(Secret::new(password_hash))
         })
@@ -159,3 +467,17 @@ async fn compute_password_hash(password: Secret<String>) -> Result<Secret<String
 
     result?
 }
+
+// Argon2id with the pepper as its secret. When verifying, the parameters are taken from the hash.
+fn argon2_with_pepper(pepper: Option<&Secret<String>>, params: Params) -> Result<Argon2<'_>> {
+    match pepper {
+        Some(pepper) => Argon2::new_with_secret(
+            pepper.expose_secret().as_bytes(),
+            Algorithm::Argon2id,
+            Version::V0x13,
+            params,
+        )
+        .map_err(|e| eyre!("invalid password pepper: {}", e)),
+        None => Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params)),
+    }
+}
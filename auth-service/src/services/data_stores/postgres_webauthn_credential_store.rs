@@ -0,0 +1,126 @@
+use color_eyre::eyre::Context;
+use sqlx::PgPool;
+
+use crate::{
+    domain::UserId,
+    services::data_stores::{
+        WebAuthnCredential, WebAuthnCredentialStore, WebAuthnCredentialStoreError,
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct PostgresWebAuthnCredentialStore {
+    pool: PgPool,
+}
+
+impl PostgresWebAuthnCredentialStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl WebAuthnCredentialStore for PostgresWebAuthnCredentialStore {
+    #[tracing::instrument(name = "Adding WebAuthn credential to PostgreSQL", skip_all)]
+    async fn add_credential(
+        &mut self,
+        credential: WebAuthnCredential,
+    ) -> Result<(), WebAuthnCredentialStoreError> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO webauthn_credentials
+                (credential_id, user_id, public_key, sign_count, created_at, last_used_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            &credential.credential_id,
+            credential.user_id.as_ref(),
+            &credential.public_key,
+            i64::from(credential.sign_count),
+            credential.created_at,
+            credential.last_used_at
+        )
+        .execute(&self.pool)
+        .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(sqlx::Error::Database(db_err)) if db_err.code() == Some("23505".into()) => {
+                // 23505 is the PostgreSQL error code for unique_violation
+                Err(WebAuthnCredentialStoreError::CredentialAlreadyExists)
+            }
+            Err(e) => Err(WebAuthnCredentialStoreError::UnexpectedError(e.into())),
+        }
+    }
+
+    #[tracing::instrument(name = "Retrieving WebAuthn credentials from PostgreSQL", skip_all)]
+    async fn get_credentials(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<WebAuthnCredential>, WebAuthnCredentialStoreError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT credential_id, user_id, public_key, sign_count, created_at, last_used_at
+            FROM webauthn_credentials WHERE user_id = $1 ORDER BY created_at
+            "#,
+            user_id.as_ref()
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| WebAuthnCredentialStoreError::UnexpectedError(e.into()))?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(WebAuthnCredential {
+                    credential_id: row.credential_id,
+                    user_id: UserId::from(row.user_id),
+                    public_key: row.public_key,
+                    sign_count: u32::try_from(row.sign_count)
+                        .wrap_err("failed to cast sign_count to u32")?,
+                    created_at: row.created_at,
+                    last_used_at: row.last_used_at,
+                })
+            })
+            .collect()
+    }
+
+    #[tracing::instrument(name = "Updating WebAuthn signature counter in PostgreSQL", skip_all)]
+    async fn update_sign_count(
+        &mut self,
+        credential_id: &[u8],
+        sign_count: u32,
+    ) -> Result<(), WebAuthnCredentialStoreError> {
+        let sign_count = i64::from(sign_count);
+
+        // The comparison happens in the UPDATE itself, so two concurrent assertions with the
+        // same counter can't both succeed
+        let result = sqlx::query!(
+            r#"
+            UPDATE webauthn_credentials SET sign_count = $2, last_used_at = now()
+            WHERE credential_id = $1 AND (sign_count < $2 OR (sign_count = 0 AND $2 = 0))
+            "#,
+            credential_id,
+            sign_count
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| WebAuthnCredentialStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() > 0 {
+            return Ok(());
+        }
+
+        // Nothing was updated - either the credential doesn't exist or the counter went backwards
+        let exists = sqlx::query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM webauthn_credentials WHERE credential_id = $1) AS "exists!""#,
+            credential_id
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| WebAuthnCredentialStoreError::UnexpectedError(e.into()))?;
+
+        match exists {
+            true => Err(WebAuthnCredentialStoreError::SignCountNotIncreased),
+            false => Err(WebAuthnCredentialStoreError::CredentialNotFound),
+        }
+    }
+}
@@ -2,13 +2,13 @@ use std::sync::Arc;
 
 use color_eyre::eyre::{Result, WrapErr};
 use redis::{Commands, Connection};
-use secrecy::{ExposeSecret, Secret};
 use tokio::sync::RwLock;
 use tracing::instrument;
 
 use crate::{
-    services::{BannedTokenStore, BannedTokenStoreError},
-    utils::auth::TOKEN_TTL_SECONDS,
+    domain::UserId,
+    services::{BannedTokenStore, BannedTokenStoreError, TokenId},
+    utils::{auth::TOKEN_TTL_SECONDS, constants::JWT_LEEWAY_SECONDS},
 };
 
 #[derive(Clone)]
@@ -33,16 +33,18 @@ impl std::fmt::Debug for RedisBannedTokenStore {
 
 #[async_trait::async_trait]
 impl BannedTokenStore for RedisBannedTokenStore {
-    #[instrument(name = "ban_token", skip(self, token), fields(token = %token.expose_secret()))]
-    async fn ban_token(&mut self, token: Secret<String>) -> Result<(), BannedTokenStoreError> {
-        let token_key = get_key(token.expose_secret());
+    #[instrument(name = "ban_token", skip(self, token_id), fields(jti = %token_id.as_ref()))]
+    async fn ban_token(&mut self, token_id: &TokenId) -> Result<(), BannedTokenStoreError> {
+        let token_key = get_key(token_id);
 
         let value = true;
 
+        // Keep the ban until the token would be rejected anyway, including the leeway on `exp`
         let ttl: u64 = TOKEN_TTL_SECONDS
             .try_into()
             .wrap_err("failed to cast TOKEN_TTL_SECONDS to u64")
             .map_err(BannedTokenStoreError::UnexpectedError)?;
+        let ttl = ttl + *JWT_LEEWAY_SECONDS;
 
         let _: () = self
             .conn
@@ -55,9 +57,9 @@ impl BannedTokenStore for RedisBannedTokenStore {
         Ok(())
     }
 
-    #[instrument(name = "ban_token", skip(self, token), fields(token = %token.expose_secret()))]
-    async fn is_token_banned(&self, token: &Secret<String>) -> Result<bool, BannedTokenStoreError> {
-        let token_key = get_key(token.expose_secret());
+    #[instrument(name = "is_token_banned", skip(self, token_id), fields(jti = %token_id.as_ref()))]
+    async fn is_token_banned(&self, token_id: &TokenId) -> Result<bool, BannedTokenStoreError> {
+        let token_key = get_key(token_id);
 
         let is_banned: bool = self
             .conn
@@ -69,12 +71,46 @@ impl BannedTokenStore for RedisBannedTokenStore {
 
         Ok(is_banned)
     }
+
+    #[instrument(name = "ban_all_tokens_for_user", skip_all)]
+    async fn ban_all_tokens_for_user(
+        &mut self,
+        user_id: &UserId,
+    ) -> Result<(), BannedTokenStoreError> {
+        // No expiry here - the generation has to outlive every token issued before it
+        let _: u64 = self
+            .conn
+            .write()
+            .await
+            .incr(get_generation_key(user_id), 1)
+            .wrap_err("failed to increment token generation in Redis")
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[instrument(name = "get_token_generation", skip_all)]
+    async fn get_token_generation(&self, user_id: &UserId) -> Result<u64, BannedTokenStoreError> {
+        let generation: Option<u64> = self
+            .conn
+            .write()
+            .await
+            .get(get_generation_key(user_id))
+            .wrap_err("failed to get token generation from Redis")
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(generation.unwrap_or_default())
+    }
 }
 
 // We are using a key prefix to prevent collisions and organize data!
 const BANNED_TOKEN_KEY_PREFIX: &str = "banned_token:";
+const TOKEN_GENERATION_KEY_PREFIX: &str = "token_generation:";
+
+fn get_generation_key(user_id: &UserId) -> String {
+    format!("{}{}", TOKEN_GENERATION_KEY_PREFIX, user_id)
+}
 
-#[instrument(name = "get_key", skip(token), fields(token = %token))]
-fn get_key(token: &str) -> String {
-    format!("{}{}", BANNED_TOKEN_KEY_PREFIX, token)
+fn get_key(token_id: &TokenId) -> String {
+    format!("{}{}", BANNED_TOKEN_KEY_PREFIX, token_id.as_ref())
 }
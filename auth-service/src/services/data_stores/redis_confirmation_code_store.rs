@@ -0,0 +1,167 @@
+use std::sync::Arc;
+
+use color_eyre::eyre::Context;
+use redis::{Commands, Connection};
+use secrecy::{ExposeSecret, Secret};
+use tokio::sync::RwLock;
+use tracing::instrument;
+
+use crate::{
+    domain::UserId,
+    services::{ConfirmationCodeStore, ConfirmationCodeStoreError, ConfirmationPurpose, TwoFACode},
+    utils::constants::MAX_TWO_FA_ATTEMPTS,
+};
+
+#[derive(Clone)]
+pub struct RedisConfirmationCodeStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl std::fmt::Debug for RedisConfirmationCodeStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisConfirmationCodeStore")
+            .field("conn", &"<redis connection>")
+            .finish()
+    }
+}
+
+impl RedisConfirmationCodeStore {
+    #[instrument(name = "new_redis_confirmation_code_store", skip(conn))]
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl ConfirmationCodeStore for RedisConfirmationCodeStore {
+    #[instrument(name = "add_confirmation_code", skip(self, user_id, code), fields(user_id = %user_id))]
+    async fn add_code(
+        &mut self,
+        user_id: &UserId,
+        purpose: ConfirmationPurpose,
+        code: TwoFACode,
+    ) -> Result<(), ConfirmationCodeStoreError> {
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .set_ex(
+                get_key(user_id, purpose),
+                code.as_ref().expose_secret(),
+                TEN_MINUTES_IN_SECONDS,
+            )
+            .wrap_err("failed to set confirmation code in Redis")
+            .map_err(ConfirmationCodeStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[instrument(name = "get_confirmation_code", skip(self, user_id), fields(user_id = %user_id))]
+    async fn get_code(
+        &self,
+        user_id: &UserId,
+        purpose: ConfirmationPurpose,
+    ) -> Result<TwoFACode, ConfirmationCodeStoreError> {
+        self.check_attempts(user_id).await?;
+
+        let code: Option<String> = self
+            .conn
+            .write()
+            .await
+            .get(get_key(user_id, purpose))
+            .wrap_err("failed to get confirmation code from Redis")
+            .map_err(ConfirmationCodeStoreError::UnexpectedError)?;
+        let code = code.ok_or(ConfirmationCodeStoreError::CodeNotFound)?;
+
+        TwoFACode::parse(Secret::new(code)).map_err(ConfirmationCodeStoreError::UnexpectedError)
+    }
+
+    #[instrument(name = "remove_confirmation_code", skip(self, user_id), fields(user_id = %user_id))]
+    async fn remove_code(
+        &mut self,
+        user_id: &UserId,
+        purpose: ConfirmationPurpose,
+    ) -> Result<(), ConfirmationCodeStoreError> {
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .del(&[get_key(user_id, purpose), get_failed_attempts_key(user_id)])
+            .wrap_err("failed to delete confirmation code from Redis")
+            .map_err(ConfirmationCodeStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[instrument(name = "record_failed_confirmation_attempt", skip(self, user_id), fields(user_id = %user_id))]
+    async fn record_failed_attempt(
+        &mut self,
+        user_id: &UserId,
+    ) -> Result<(), ConfirmationCodeStoreError> {
+        let key = get_failed_attempts_key(user_id);
+        let (failed_attempts,): (u64,) = redis::pipe()
+            .atomic()
+            .incr(&key, 1)
+            .expire(&key, TEN_MINUTES_IN_SECONDS as i64)
+            .ignore()
+            .query(&mut *self.conn.write().await)
+            .wrap_err("failed to increment failed confirmation attempts in Redis")
+            .map_err(ConfirmationCodeStoreError::UnexpectedError)?;
+
+        match failed_attempts >= MAX_TWO_FA_ATTEMPTS {
+            true => Err(ConfirmationCodeStoreError::TooManyAttempts),
+            false => Ok(()),
+        }
+    }
+
+    #[instrument(name = "check_confirmation_attempts", skip(self, user_id), fields(user_id = %user_id))]
+    async fn check_attempts(&self, user_id: &UserId) -> Result<(), ConfirmationCodeStoreError> {
+        let failed_attempts: Option<u64> = self
+            .conn
+            .write()
+            .await
+            .get(get_failed_attempts_key(user_id))
+            .wrap_err("failed to get failed confirmation attempts from Redis")
+            .map_err(ConfirmationCodeStoreError::UnexpectedError)?;
+
+        match failed_attempts.is_some_and(|failed_attempts| failed_attempts >= MAX_TWO_FA_ATTEMPTS)
+        {
+            true => Err(ConfirmationCodeStoreError::TooManyAttempts),
+            false => Ok(()),
+        }
+    }
+
+    #[instrument(name = "remove_confirmation_codes", skip(self, user_id), fields(user_id = %user_id))]
+    async fn remove_codes(&mut self, user_id: &UserId) -> Result<(), ConfirmationCodeStoreError> {
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .del(&[
+                get_key(user_id, ConfirmationPurpose::EnableEmail2FA),
+                get_key(user_id, ConfirmationPurpose::DisableEmail2FA),
+                get_failed_attempts_key(user_id),
+            ])
+            .wrap_err("failed to delete confirmation codes from Redis")
+            .map_err(ConfirmationCodeStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
+
+const TEN_MINUTES_IN_SECONDS: u64 = 600;
+const CONFIRMATION_CODE_PREFIX: &str = "confirmation_code:";
+const CONFIRMATION_FAILED_ATTEMPTS_PREFIX: &str = "confirmation_failed_attempts:";
+
+fn get_key(user_id: &UserId, purpose: ConfirmationPurpose) -> String {
+    format!(
+        "{}{}:{}",
+        CONFIRMATION_CODE_PREFIX,
+        purpose.as_ref(),
+        user_id
+    )
+}
+
+fn get_failed_attempts_key(user_id: &UserId) -> String {
+    format!("{}{}", CONFIRMATION_FAILED_ATTEMPTS_PREFIX, user_id)
+}
@@ -0,0 +1,258 @@
+use std::sync::Arc;
+
+use color_eyre::eyre::Context;
+use redis::{Commands, Connection};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use tracing::instrument;
+
+use crate::{
+    domain::{Email, UserId},
+    services::{
+        hash_token, EmailChange, EmailChangeStore, EmailChangeStoreError, EmailChangeToken,
+        PendingEmailChange,
+    },
+    utils::constants::EMAIL_CHANGE_TOKEN_TTL_SECONDS,
+};
+
+#[derive(Clone)]
+pub struct RedisEmailChangeStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl std::fmt::Debug for RedisEmailChangeStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisEmailChangeStore")
+            .field("conn", &"<redis connection>")
+            .finish()
+    }
+}
+
+impl RedisEmailChangeStore {
+    #[instrument(name = "new_redis_email_change_store", skip(conn))]
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailChangeStore for RedisEmailChangeStore {
+    #[instrument(name = "add_email_change", skip_all)]
+    async fn add_change(&mut self, change: EmailChange) -> Result<(), EmailChangeStoreError> {
+        let confirm_hash = hash_token(change.confirm_token.as_ref());
+        let cancel_hash = hash_token(change.cancel_token.as_ref());
+
+        let data = StoredChange {
+            user_id: change.user_id.to_string(),
+            new_email: change.new_email.as_ref().expose_secret().to_owned(),
+            cancel_hash: cancel_hash.clone(),
+        };
+        let serialized_data = serde_json::to_string(&data)
+            .wrap_err("failed to serialize email change")
+            .map_err(EmailChangeStoreError::UnexpectedError)?;
+
+        let mut conn = self.conn.write().await;
+
+        let _: () = conn
+            .set_ex(
+                get_change_key(&confirm_hash),
+                serialized_data,
+                EMAIL_CHANGE_TOKEN_TTL_SECONDS,
+            )
+            .wrap_err("failed to set email change in Redis")
+            .map_err(EmailChangeStoreError::UnexpectedError)?;
+
+        // The cancel token points at the change, so either token can remove it
+        let _: () = conn
+            .set_ex(
+                get_cancel_key(&cancel_hash),
+                &confirm_hash,
+                EMAIL_CHANGE_TOKEN_TTL_SECONDS,
+            )
+            .wrap_err("failed to set email change cancel token in Redis")
+            .map_err(EmailChangeStoreError::UnexpectedError)?;
+
+        // The user's changes are listed by confirm token, so they can be found without either
+        let user_key = get_user_key(&change.user_id);
+        let _: () = conn
+            .sadd(&user_key, &confirm_hash)
+            .wrap_err("failed to add email change to user's changes in Redis")
+            .map_err(EmailChangeStoreError::UnexpectedError)?;
+        let _: () = conn
+            .expire(&user_key, EMAIL_CHANGE_TOKEN_TTL_SECONDS as i64)
+            .wrap_err("failed to set expiry of user's email changes in Redis")
+            .map_err(EmailChangeStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[instrument(name = "take_email_change", skip_all)]
+    async fn take_change(
+        &mut self,
+        confirm_token: &EmailChangeToken,
+    ) -> Result<PendingEmailChange, EmailChangeStoreError> {
+        let confirm_hash = hash_token(confirm_token.as_ref());
+        let mut conn = self.conn.write().await;
+
+        // GETDEL reads and removes the change atomically, so it can only be confirmed once
+        let value: Option<String> = conn
+            .get_del(get_change_key(&confirm_hash))
+            .wrap_err("failed to take email change from Redis")
+            .map_err(EmailChangeStoreError::UnexpectedError)?;
+        let data = parse_change(&value.ok_or(EmailChangeStoreError::ChangeNotFound)?)?;
+
+        let _: () = conn
+            .del(get_cancel_key(&data.cancel_hash))
+            .wrap_err("failed to delete email change cancel token from Redis")
+            .map_err(EmailChangeStoreError::UnexpectedError)?;
+
+        remove_from_user(&mut conn, data, &confirm_hash)
+    }
+
+    #[instrument(name = "cancel_email_change", skip_all)]
+    async fn cancel_change(
+        &mut self,
+        cancel_token: &EmailChangeToken,
+    ) -> Result<PendingEmailChange, EmailChangeStoreError> {
+        let mut conn = self.conn.write().await;
+
+        let confirm_hash: Option<String> = conn
+            .get_del(get_cancel_key(&hash_token(cancel_token.as_ref())))
+            .wrap_err("failed to take email change cancel token from Redis")
+            .map_err(EmailChangeStoreError::UnexpectedError)?;
+        let confirm_hash = confirm_hash.ok_or(EmailChangeStoreError::ChangeNotFound)?;
+
+        let value: Option<String> = conn
+            .get_del(get_change_key(&confirm_hash))
+            .wrap_err("failed to take email change from Redis")
+            .map_err(EmailChangeStoreError::UnexpectedError)?;
+        let data = parse_change(&value.ok_or(EmailChangeStoreError::ChangeNotFound)?)?;
+
+        remove_from_user(&mut conn, data, &confirm_hash)
+    }
+
+    #[instrument(name = "get_email_changes", skip_all)]
+    async fn get_changes(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<PendingEmailChange>, EmailChangeStoreError> {
+        let mut conn = self.conn.write().await;
+
+        let user_key = get_user_key(user_id);
+        let confirm_hashes: Vec<String> = conn
+            .smembers(&user_key)
+            .wrap_err("failed to get user's email changes from Redis")
+            .map_err(EmailChangeStoreError::UnexpectedError)?;
+
+        let mut changes = Vec::with_capacity(confirm_hashes.len());
+        for confirm_hash in confirm_hashes {
+            let value: Option<String> = conn
+                .get(get_change_key(&confirm_hash))
+                .wrap_err("failed to get email change from Redis")
+                .map_err(EmailChangeStoreError::UnexpectedError)?;
+
+            match value {
+                Some(value) => changes.push(parse_change(&value)?.try_into()?),
+                // Changes expire on their own, leaving their token behind in the user's list
+                None => {
+                    let _: () = conn
+                        .srem(&user_key, &confirm_hash)
+                        .wrap_err("failed to remove email change from user's changes in Redis")
+                        .map_err(EmailChangeStoreError::UnexpectedError)?;
+                }
+            }
+        }
+
+        Ok(changes)
+    }
+
+    #[instrument(name = "remove_email_changes", skip_all)]
+    async fn remove_changes(&mut self, user_id: &UserId) -> Result<(), EmailChangeStoreError> {
+        let mut conn = self.conn.write().await;
+
+        let user_key = get_user_key(user_id);
+        let confirm_hashes: Vec<String> = conn
+            .smembers(&user_key)
+            .wrap_err("failed to get user's email changes from Redis")
+            .map_err(EmailChangeStoreError::UnexpectedError)?;
+
+        let mut keys = vec![user_key];
+        for confirm_hash in confirm_hashes {
+            let value: Option<String> = conn
+                .get(get_change_key(&confirm_hash))
+                .wrap_err("failed to get email change from Redis")
+                .map_err(EmailChangeStoreError::UnexpectedError)?;
+            if let Some(value) = value {
+                keys.push(get_cancel_key(&parse_change(&value)?.cancel_hash));
+            }
+            keys.push(get_change_key(&confirm_hash));
+        }
+
+        let _: () = conn
+            .del(keys)
+            .wrap_err("failed to delete email changes from Redis")
+            .map_err(EmailChangeStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredChange {
+    user_id: String,
+    new_email: String,
+    cancel_hash: String,
+}
+
+impl TryFrom<StoredChange> for PendingEmailChange {
+    type Error = EmailChangeStoreError;
+
+    fn try_from(data: StoredChange) -> Result<Self, Self::Error> {
+        Ok(PendingEmailChange {
+            user_id: UserId::parse(&data.user_id)
+                .map_err(EmailChangeStoreError::UnexpectedError)?,
+            new_email: Email::parse(Secret::new(data.new_email))
+                .map_err(EmailChangeStoreError::UnexpectedError)?,
+        })
+    }
+}
+
+fn parse_change(value: &str) -> Result<StoredChange, EmailChangeStoreError> {
+    serde_json::from_str(value)
+        .wrap_err("failed to deserialize email change")
+        .map_err(EmailChangeStoreError::UnexpectedError)
+}
+
+// Once a change is confirmed or cancelled it's no longer one of the user's pending changes
+fn remove_from_user(
+    conn: &mut Connection,
+    data: StoredChange,
+    confirm_hash: &str,
+) -> Result<PendingEmailChange, EmailChangeStoreError> {
+    let change = PendingEmailChange::try_from(data)?;
+
+    let _: () = conn
+        .srem(get_user_key(&change.user_id), confirm_hash)
+        .wrap_err("failed to remove email change from user's changes in Redis")
+        .map_err(EmailChangeStoreError::UnexpectedError)?;
+
+    Ok(change)
+}
+
+// Changes are kept by the hashes of their tokens
+const EMAIL_CHANGE_KEY_PREFIX: &str = "email_change:";
+const EMAIL_CHANGE_CANCEL_KEY_PREFIX: &str = "email_change_cancel:";
+const EMAIL_CHANGE_USER_KEY_PREFIX: &str = "email_change_user:";
+
+fn get_change_key(confirm_hash: &str) -> String {
+    format!("{}{}", EMAIL_CHANGE_KEY_PREFIX, confirm_hash)
+}
+
+fn get_cancel_key(cancel_hash: &str) -> String {
+    format!("{}{}", EMAIL_CHANGE_CANCEL_KEY_PREFIX, cancel_hash)
+}
+
+fn get_user_key(user_id: &UserId) -> String {
+    format!("{}{}", EMAIL_CHANGE_USER_KEY_PREFIX, user_id)
+}
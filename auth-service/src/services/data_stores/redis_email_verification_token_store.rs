@@ -0,0 +1,129 @@
+use std::sync::Arc;
+
+use color_eyre::eyre::Context;
+use redis::{Commands, Connection};
+use secrecy::ExposeSecret;
+use tokio::sync::RwLock;
+use tracing::instrument;
+
+use crate::{
+    domain::{Email, UserId},
+    services::{
+        hash_token, EmailVerificationToken, EmailVerificationTokenStore,
+        EmailVerificationTokenStoreError,
+    },
+    utils::constants::{
+        EMAIL_VERIFICATION_TOKEN_TTL_SECONDS, VERIFICATION_EMAIL_LIMIT,
+        VERIFICATION_EMAIL_WINDOW_SECONDS,
+    },
+};
+
+#[derive(Clone)]
+pub struct RedisEmailVerificationTokenStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl std::fmt::Debug for RedisEmailVerificationTokenStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisEmailVerificationTokenStore")
+            .field("conn", &"<redis connection>")
+            .finish()
+    }
+}
+
+impl RedisEmailVerificationTokenStore {
+    #[instrument(name = "new_redis_email_verification_token_store", skip(conn))]
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailVerificationTokenStore for RedisEmailVerificationTokenStore {
+    #[instrument(name = "add_email_verification_token", skip_all)]
+    async fn add_token(
+        &mut self,
+        token: EmailVerificationToken,
+        user_id: UserId,
+    ) -> Result<(), EmailVerificationTokenStoreError> {
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .set_ex(
+                get_token_key(&token),
+                user_id.to_string(),
+                EMAIL_VERIFICATION_TOKEN_TTL_SECONDS,
+            )
+            .wrap_err("failed to set email verification token in Redis")
+            .map_err(EmailVerificationTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[instrument(name = "take_email_verification_token", skip_all)]
+    async fn take_token(
+        &mut self,
+        token: &EmailVerificationToken,
+    ) -> Result<UserId, EmailVerificationTokenStoreError> {
+        let user_id: Option<String> = self
+            .conn
+            .write()
+            .await
+            .get_del(get_token_key(token))
+            .wrap_err("failed to take email verification token from Redis")
+            .map_err(EmailVerificationTokenStoreError::UnexpectedError)?;
+
+        let user_id = user_id.ok_or(EmailVerificationTokenStoreError::TokenNotFound)?;
+
+        UserId::parse(&user_id).map_err(EmailVerificationTokenStoreError::UnexpectedError)
+    }
+
+    #[instrument(name = "record_verification_email_sent", skip_all)]
+    async fn record_email_sent(
+        &mut self,
+        email: &Email,
+    ) -> Result<(), EmailVerificationTokenStoreError> {
+        let key = get_emails_sent_key(email);
+        let mut conn = self.conn.write().await;
+
+        // Fixed window counter - the first email in a window starts the expiry clock
+        let count: u64 = conn
+            .incr(&key, 1)
+            .wrap_err("failed to increment verification email count in Redis")
+            .map_err(EmailVerificationTokenStoreError::UnexpectedError)?;
+
+        if count == 1 {
+            let _: () = conn
+                .expire(&key, VERIFICATION_EMAIL_WINDOW_SECONDS as i64)
+                .wrap_err("failed to set expiry of verification email count in Redis")
+                .map_err(EmailVerificationTokenStoreError::UnexpectedError)?;
+        }
+
+        if count > VERIFICATION_EMAIL_LIMIT {
+            return Err(EmailVerificationTokenStoreError::TooManyEmails);
+        }
+
+        Ok(())
+    }
+}
+
+// We are using key prefixes to prevent collisions and organize data!
+const EMAIL_VERIFICATION_TOKEN_KEY_PREFIX: &str = "email_verification_token:";
+const VERIFICATION_EMAILS_SENT_KEY_PREFIX: &str = "verification_emails_sent:";
+
+fn get_token_key(token: &EmailVerificationToken) -> String {
+    format!(
+        "{}{}",
+        EMAIL_VERIFICATION_TOKEN_KEY_PREFIX,
+        hash_token(token.as_ref())
+    )
+}
+
+fn get_emails_sent_key(email: &Email) -> String {
+    format!(
+        "{}{}",
+        VERIFICATION_EMAILS_SENT_KEY_PREFIX,
+        email.as_ref().expose_secret()
+    )
+}
@@ -0,0 +1,202 @@
+use std::sync::Arc;
+
+use chrono::Utc;
+use color_eyre::eyre::Context;
+use redis::{Commands, Connection};
+use secrecy::ExposeSecret;
+use tokio::sync::RwLock;
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::{
+    domain::Email,
+    services::{LoginAttemptStore, LoginAttemptStoreError, LoginThrottleSettings},
+    utils::constants::LOGIN_FAILURES_TTL_SECONDS,
+};
+
+#[derive(Clone)]
+pub struct RedisLoginAttemptStore {
+    conn: Arc<RwLock<Connection>>,
+    settings: LoginThrottleSettings,
+}
+
+impl std::fmt::Debug for RedisLoginAttemptStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisLoginAttemptStore")
+            .field("conn", &"<redis connection>")
+            .field("settings", &self.settings)
+            .finish()
+    }
+}
+
+impl RedisLoginAttemptStore {
+    #[instrument(name = "new_redis_login_attempt_store", skip(conn))]
+    pub fn new(conn: Arc<RwLock<Connection>>, settings: LoginThrottleSettings) -> Self {
+        Self { conn, settings }
+    }
+}
+
+#[async_trait::async_trait]
+impl LoginAttemptStore for RedisLoginAttemptStore {
+    #[instrument(name = "record_login_attempt", skip_all)]
+    async fn record_attempt(
+        &mut self,
+        ip_address: Option<&str>,
+        email: &Email,
+    ) -> Result<(), LoginAttemptStoreError> {
+        let mut conn = self.conn.write().await;
+
+        let locked_for: i64 = conn
+            .ttl(get_lockout_key(email))
+            .wrap_err("failed to get account lockout from Redis")
+            .map_err(LoginAttemptStoreError::UnexpectedError)?;
+        if locked_for > 0 {
+            return Err(LoginAttemptStoreError::AccountLocked {
+                retry_after_seconds: locked_for as u64,
+            });
+        }
+
+        if let Some(ip_address) = ip_address {
+            record_in_window(
+                &mut conn,
+                &format!("{}{}", LOGIN_ATTEMPTS_IP_KEY_PREFIX, ip_address),
+                self.settings.ip_limit,
+                self.settings.ip_window_seconds,
+            )?;
+        }
+        record_in_window(
+            &mut conn,
+            &get_account_attempts_key(email),
+            self.settings.account_limit,
+            self.settings.account_window_seconds,
+        )
+    }
+
+    #[instrument(name = "record_login_failure", skip_all)]
+    async fn record_failure(&mut self, email: &Email) -> Result<(), LoginAttemptStoreError> {
+        let key = get_failures_key(email);
+        let mut conn = self.conn.write().await;
+
+        let (count,): (u64,) = redis::pipe()
+            .atomic()
+            .incr(&key, 1)
+            .expire(&key, LOGIN_FAILURES_TTL_SECONDS as i64)
+            .ignore()
+            .query(&mut *conn)
+            .wrap_err("failed to increment login failure count in Redis")
+            .map_err(LoginAttemptStoreError::UnexpectedError)?;
+
+        if let Some(lockout_seconds) = self.settings.lockout_seconds(count) {
+            let _: () = conn
+                .set_ex(get_lockout_key(email), count, lockout_seconds)
+                .wrap_err("failed to set account lockout in Redis")
+                .map_err(LoginAttemptStoreError::UnexpectedError)?;
+        }
+
+        Ok(())
+    }
+
+    #[instrument(name = "clear_login_failures", skip_all)]
+    async fn clear_failures(&mut self, email: &Email) -> Result<(), LoginAttemptStoreError> {
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .del(&[get_failures_key(email), get_lockout_key(email)])
+            .wrap_err("failed to clear login failures in Redis")
+            .map_err(LoginAttemptStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[instrument(name = "remove_login_attempts", skip_all)]
+    async fn remove_account(&mut self, email: &Email) -> Result<(), LoginAttemptStoreError> {
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .del(&[
+                get_account_attempts_key(email),
+                get_failures_key(email),
+                get_lockout_key(email),
+            ])
+            .wrap_err("failed to remove login attempts from Redis")
+            .map_err(LoginAttemptStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
+
+// Sliding window log - each attempt that is let through is a member of a sorted set, scored by
+// when it was made in milliseconds
+fn record_in_window(
+    conn: &mut Connection,
+    key: &str,
+    limit: u64,
+    window_seconds: u64,
+) -> Result<(), LoginAttemptStoreError> {
+    let now = Utc::now().timestamp_millis();
+    let window = (window_seconds * 1000) as i64;
+    let attempt = Uuid::new_v4().to_string();
+
+    let (count, oldest): (u64, Vec<(String, i64)>) = redis::pipe()
+        .atomic()
+        .zrembyscore(key, "-inf", now - window)
+        .ignore()
+        .zadd(key, &attempt, now)
+        .ignore()
+        .zcard(key)
+        .zrange_withscores(key, 0, 0)
+        .expire(key, window_seconds as i64)
+        .ignore()
+        .query(conn)
+        .wrap_err("failed to record login attempt in Redis")
+        .map_err(LoginAttemptStoreError::UnexpectedError)?;
+
+    if count > limit {
+        // Attempts that were turned away do not count
+        let _: () = conn
+            .zrem(key, &attempt)
+            .wrap_err("failed to remove login attempt from Redis")
+            .map_err(LoginAttemptStoreError::UnexpectedError)?;
+
+        let retry_after = oldest
+            .first()
+            .map_or(window, |(_, oldest)| oldest + window - now);
+        return Err(LoginAttemptStoreError::TooManyAttempts {
+            retry_after_seconds: (retry_after.max(1) as u64).div_ceil(1000),
+        });
+    }
+
+    Ok(())
+}
+
+// We are using key prefixes to prevent collisions and organize data!
+const LOGIN_ATTEMPTS_IP_KEY_PREFIX: &str = "login_attempts_ip:";
+const LOGIN_ATTEMPTS_ACCOUNT_KEY_PREFIX: &str = "login_attempts_account:";
+const LOGIN_FAILURES_KEY_PREFIX: &str = "login_failures:";
+const LOGIN_LOCKOUT_KEY_PREFIX: &str = "login_lockout:";
+
+fn get_account_attempts_key(email: &Email) -> String {
+    format!(
+        "{}{}",
+        LOGIN_ATTEMPTS_ACCOUNT_KEY_PREFIX,
+        email.as_ref().expose_secret()
+    )
+}
+
+fn get_failures_key(email: &Email) -> String {
+    format!(
+        "{}{}",
+        LOGIN_FAILURES_KEY_PREFIX,
+        email.as_ref().expose_secret()
+    )
+}
+
+fn get_lockout_key(email: &Email) -> String {
+    format!(
+        "{}{}",
+        LOGIN_LOCKOUT_KEY_PREFIX,
+        email.as_ref().expose_secret()
+    )
+}
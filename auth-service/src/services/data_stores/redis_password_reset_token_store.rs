@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use color_eyre::eyre::Context;
+use redis::{Commands, Connection};
+use tokio::sync::RwLock;
+use tracing::instrument;
+
+use crate::{
+    domain::UserId,
+    services::{
+        hash_token, PasswordResetToken, PasswordResetTokenStore, PasswordResetTokenStoreError,
+    },
+    utils::constants::PASSWORD_RESET_TOKEN_TTL_SECONDS,
+};
+
+#[derive(Clone)]
+pub struct RedisPasswordResetTokenStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl std::fmt::Debug for RedisPasswordResetTokenStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisPasswordResetTokenStore")
+            .field("conn", &"<redis connection>")
+            .finish()
+    }
+}
+
+impl RedisPasswordResetTokenStore {
+    #[instrument(name = "new_redis_password_reset_token_store", skip(conn))]
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl PasswordResetTokenStore for RedisPasswordResetTokenStore {
+    #[instrument(name = "add_password_reset_token", skip_all)]
+    async fn add_token(
+        &mut self,
+        token: PasswordResetToken,
+        user_id: UserId,
+    ) -> Result<(), PasswordResetTokenStoreError> {
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .set_ex(
+                get_key(&token),
+                user_id.to_string(),
+                PASSWORD_RESET_TOKEN_TTL_SECONDS,
+            )
+            .wrap_err("failed to set password reset token in Redis")
+            .map_err(PasswordResetTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[instrument(name = "get_password_reset_token", skip_all)]
+    async fn get_token(
+        &self,
+        token: &PasswordResetToken,
+    ) -> Result<UserId, PasswordResetTokenStoreError> {
+        let user_id: Option<String> = self
+            .conn
+            .write()
+            .await
+            .get(get_key(token))
+            .wrap_err("failed to get password reset token from Redis")
+            .map_err(PasswordResetTokenStoreError::UnexpectedError)?;
+
+        let user_id = user_id.ok_or(PasswordResetTokenStoreError::TokenNotFound)?;
+
+        UserId::parse(&user_id).map_err(PasswordResetTokenStoreError::UnexpectedError)
+    }
+
+    #[instrument(name = "take_password_reset_token", skip_all)]
+    async fn take_token(
+        &mut self,
+        token: &PasswordResetToken,
+    ) -> Result<UserId, PasswordResetTokenStoreError> {
+        // GETDEL reads and removes the token atomically, so it can only be redeemed once
+        let user_id: Option<String> = self
+            .conn
+            .write()
+            .await
+            .get_del(get_key(token))
+            .wrap_err("failed to take password reset token from Redis")
+            .map_err(PasswordResetTokenStoreError::UnexpectedError)?;
+
+        let user_id = user_id.ok_or(PasswordResetTokenStoreError::TokenNotFound)?;
+
+        UserId::parse(&user_id).map_err(PasswordResetTokenStoreError::UnexpectedError)
+    }
+}
+
+const PASSWORD_RESET_TOKEN_KEY_PREFIX: &str = "password_reset_token:";
+
+fn get_key(token: &PasswordResetToken) -> String {
+    format!(
+        "{}{}",
+        PASSWORD_RESET_TOKEN_KEY_PREFIX,
+        hash_token(token.as_ref())
+    )
+}
@@ -0,0 +1,229 @@
+use std::sync::Arc;
+
+use color_eyre::eyre::Context;
+use redis::{Commands, Connection, SetExpiry, SetOptions};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use tracing::instrument;
+
+use crate::{
+    domain::UserId,
+    services::{
+        hash_token, RefreshToken, RefreshTokenRecord, RefreshTokenStore, RefreshTokenStoreError,
+    },
+    utils::auth::REFRESH_TOKEN_TTL_SECONDS,
+};
+
+#[derive(Clone)]
+pub struct RedisRefreshTokenStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl std::fmt::Debug for RedisRefreshTokenStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisRefreshTokenStore")
+            .field("conn", &"<redis connection>")
+            .finish()
+    }
+}
+
+impl RedisRefreshTokenStore {
+    #[instrument(name = "new_redis_refresh_token_store", skip(conn))]
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenStore for RedisRefreshTokenStore {
+    #[instrument(name = "add_refresh_token", skip_all)]
+    async fn add_token(
+        &mut self,
+        token: RefreshToken,
+        record: RefreshTokenRecord,
+    ) -> Result<(), RefreshTokenStoreError> {
+        let key = get_token_key(&token);
+        let families_key = get_user_families_key(&record.user_id);
+
+        let data = StoredRecord {
+            user_id: Some(record.user_id.to_string()),
+            family_id: record.family_id,
+            used: record.used,
+        };
+        let serialized_data = serde_json::to_string(&data)
+            .wrap_err("failed to serialize refresh token record")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        let ttl = ttl()?;
+        let mut conn = self.conn.write().await;
+
+        let _: () = conn
+            .set_ex(&key, serialized_data, ttl)
+            .wrap_err("failed to set refresh token in Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        // Track the user's families so they can all be revoked at once
+        let _: () = conn
+            .sadd(&families_key, &data.family_id)
+            .wrap_err("failed to add refresh token family in Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        let _: () = conn
+            .expire(&families_key, REFRESH_TOKEN_TTL_SECONDS)
+            .wrap_err("failed to set expiry of refresh token families in Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[instrument(name = "get_refresh_token", skip_all)]
+    async fn get_token(
+        &self,
+        token: &RefreshToken,
+    ) -> Result<RefreshTokenRecord, RefreshTokenStoreError> {
+        let key = get_token_key(token);
+
+        let value: Option<String> = self
+            .conn
+            .write()
+            .await
+            .get(&key)
+            .wrap_err("failed to get refresh token from Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        let value = value.ok_or(RefreshTokenStoreError::TokenNotFound)?;
+
+        let data: StoredRecord = serde_json::from_str(&value)
+            .wrap_err("failed to deserialize refresh token record")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        // Tokens from before users had ids have to be replaced by logging in again
+        let user_id = data.user_id.ok_or(RefreshTokenStoreError::TokenNotFound)?;
+
+        Ok(RefreshTokenRecord {
+            user_id: UserId::parse(&user_id).map_err(RefreshTokenStoreError::UnexpectedError)?,
+            family_id: data.family_id,
+            used: data.used,
+        })
+    }
+
+    #[instrument(name = "mark_refresh_token_used", skip_all)]
+    async fn mark_token_used(
+        &mut self,
+        token: &RefreshToken,
+    ) -> Result<(), RefreshTokenStoreError> {
+        let mut record = self.get_token(token).await?;
+        record.used = true;
+
+        let data = StoredRecord {
+            user_id: Some(record.user_id.to_string()),
+            family_id: record.family_id,
+            used: record.used,
+        };
+        let serialized_data = serde_json::to_string(&data)
+            .wrap_err("failed to serialize refresh token record")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        // Keep the original expiry - a used token only needs to live as long as its family could
+        let options = SetOptions::default().with_expiration(SetExpiry::KEEPTTL);
+
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .set_options(get_token_key(token), serialized_data, options)
+            .wrap_err("failed to update refresh token in Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[instrument(name = "revoke_refresh_token_family", skip_all)]
+    async fn revoke_family(&mut self, family_id: &str) -> Result<(), RefreshTokenStoreError> {
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .set_ex(get_family_key(family_id), true, ttl()?)
+            .wrap_err("failed to revoke refresh token family in Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[instrument(name = "is_refresh_token_family_revoked", skip_all)]
+    async fn is_family_revoked(&self, family_id: &str) -> Result<bool, RefreshTokenStoreError> {
+        let is_revoked: bool = self
+            .conn
+            .write()
+            .await
+            .exists(get_family_key(family_id))
+            .wrap_err("failed to check if refresh token family is revoked in Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        Ok(is_revoked)
+    }
+
+    #[instrument(name = "revoke_all_refresh_token_families_for_user", skip_all)]
+    async fn revoke_all_families_for_user(
+        &mut self,
+        user_id: &UserId,
+    ) -> Result<(), RefreshTokenStoreError> {
+        let families_key = get_user_families_key(user_id);
+
+        let family_ids: Vec<String> = self
+            .conn
+            .write()
+            .await
+            .smembers(&families_key)
+            .wrap_err("failed to get refresh token families from Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        for family_id in family_ids {
+            self.revoke_family(&family_id).await?;
+        }
+
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .del(&families_key)
+            .wrap_err("failed to delete refresh token families from Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredRecord {
+    // Missing from tokens issued before users had ids, which named their email instead
+    #[serde(default)]
+    user_id: Option<String>,
+    family_id: String,
+    used: bool,
+}
+
+// We are using key prefixes to prevent collisions and organize data!
+const REFRESH_TOKEN_KEY_PREFIX: &str = "refresh_token:";
+const REVOKED_FAMILY_KEY_PREFIX: &str = "revoked_refresh_token_family:";
+const USER_FAMILIES_KEY_PREFIX: &str = "refresh_token_families:";
+
+fn ttl() -> Result<u64, RefreshTokenStoreError> {
+    REFRESH_TOKEN_TTL_SECONDS
+        .try_into()
+        .wrap_err("failed to cast REFRESH_TOKEN_TTL_SECONDS to u64")
+        .map_err(RefreshTokenStoreError::UnexpectedError)
+}
+
+fn get_token_key(token: &RefreshToken) -> String {
+    format!("{}{}", REFRESH_TOKEN_KEY_PREFIX, hash_token(token.as_ref()))
+}
+
+fn get_family_key(family_id: &str) -> String {
+    format!("{}{}", REVOKED_FAMILY_KEY_PREFIX, family_id)
+}
+
+fn get_user_families_key(user_id: &UserId) -> String {
+    format!("{}{}", USER_FAMILIES_KEY_PREFIX, user_id)
+}
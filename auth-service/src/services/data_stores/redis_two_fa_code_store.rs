@@ -8,8 +8,9 @@ use tokio::sync::RwLock;
 use tracing::instrument;
 
 use crate::{
-    domain::Email,
+    domain::UserId,
     services::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
+    utils::constants::MAX_TWO_FA_ATTEMPTS,
 };
 
 #[derive(Clone)]
@@ -34,14 +35,14 @@ impl RedisTwoFACodeStore {
 
 #[async_trait::async_trait]
 impl TwoFACodeStore for RedisTwoFACodeStore {
-    #[instrument(name = "add_two_fa_code", skip(self, email, login_attempt_id, code), fields(email = %email.as_ref().expose_secret()))]
+    #[instrument(name = "add_two_fa_code", skip(self, user_id, login_attempt_id, code), fields(user_id = %user_id))]
     async fn add_code(
         &mut self,
-        email: Email,
+        user_id: UserId,
         login_attempt_id: LoginAttemptId,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError> {
-        let key = get_key(&email);
+        let key = get_key(&user_id);
 
         let data = TwoFATuple(
             login_attempt_id.as_ref().expose_secret().to_owned(),
@@ -51,38 +52,41 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
             .wrap_err("failed to serialize 2FA tuple")
             .map_err(TwoFACodeStoreError::UnexpectedError)?;
 
-        let _: () = self
-            .conn
-            .write()
-            .await
+        // A new login attempt starts with no wrong codes against it
+        let _: () = redis::pipe()
+            .atomic()
             .set_ex(&key, serialized_data, TEN_MINUTES_IN_SECONDS)
+            .ignore()
+            .del(get_failed_attempts_key(&user_id))
+            .ignore()
+            .query(&mut *self.conn.write().await)
             .wrap_err("failed to set 2FA code in Redis")
             .map_err(TwoFACodeStoreError::UnexpectedError)?;
 
         Ok(())
     }
 
-    #[instrument(name = "remove_two_fa_code", skip(self, email), fields(email = %email.as_ref().expose_secret()))]
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
-        let key = get_key(email);
+    #[instrument(name = "remove_two_fa_code", skip(self, user_id), fields(user_id = %user_id))]
+    async fn remove_code(&mut self, user_id: &UserId) -> Result<(), TwoFACodeStoreError> {
+        let key = get_key(user_id);
 
         let _: () = self
             .conn
             .write()
             .await
-            .del(&key)
+            .del(&[key, get_failed_attempts_key(user_id)])
             .wrap_err("failed to delete 2FA code from Redis")
             .map_err(TwoFACodeStoreError::UnexpectedError)?;
 
         Ok(())
     }
 
-    #[instrument(name = "get_two_fa_code", skip(self, email), fields(email = %email.as_ref().expose_secret()))]
+    #[instrument(name = "get_two_fa_code", skip(self, user_id), fields(user_id = %user_id))]
     async fn get_code(
         &self,
-        email: &Email,
+        user_id: &UserId,
     ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
-        let key = get_key(email);
+        let key = get_key(user_id);
 
         match self.conn.write().await.get::<_, String>(&key) {
             Ok(value) => {
@@ -101,6 +105,39 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
             Err(_) => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
         }
     }
+
+    #[instrument(name = "record_failed_two_fa_attempt", skip(self, user_id), fields(user_id = %user_id))]
+    async fn record_failed_attempt(&mut self, user_id: &UserId) -> Result<(), TwoFACodeStoreError> {
+        let mut conn = self.conn.write().await;
+
+        let exists: bool = conn
+            .exists(get_key(user_id))
+            .wrap_err("failed to check for 2FA code in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        if !exists {
+            return Err(TwoFACodeStoreError::LoginAttemptIdNotFound);
+        }
+
+        let key = get_failed_attempts_key(user_id);
+        let (failed_attempts,): (u64,) = redis::pipe()
+            .atomic()
+            .incr(&key, 1)
+            .expire(&key, TEN_MINUTES_IN_SECONDS as i64)
+            .ignore()
+            .query(&mut *conn)
+            .wrap_err("failed to increment failed 2FA attempts in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        if failed_attempts >= MAX_TWO_FA_ATTEMPTS {
+            let _: () = conn
+                .del(&[get_key(user_id), key])
+                .wrap_err("failed to delete 2FA code from Redis")
+                .map_err(TwoFACodeStoreError::UnexpectedError)?;
+            return Err(TwoFACodeStoreError::TooManyAttempts);
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -108,8 +145,14 @@ struct TwoFATuple(pub String, pub String);
 
 const TEN_MINUTES_IN_SECONDS: u64 = 600;
 const TWO_FA_CODE_PREFIX: &str = "two_fa_code:";
+const TWO_FA_FAILED_ATTEMPTS_PREFIX: &str = "two_fa_failed_attempts:";
+
+#[instrument(name = "get_key", skip(user_id))]
+fn get_key(user_id: &UserId) -> String {
+    format!("{}{}", TWO_FA_CODE_PREFIX, user_id)
+}
 
-#[instrument(name = "get_key", skip(email))]
-fn get_key(email: &Email) -> String {
-    format!("{}{}", TWO_FA_CODE_PREFIX, email.as_ref().expose_secret())
+#[instrument(name = "get_failed_attempts_key", skip(user_id))]
+fn get_failed_attempts_key(user_id: &UserId) -> String {
+    format!("{}{}", TWO_FA_FAILED_ATTEMPTS_PREFIX, user_id)
 }
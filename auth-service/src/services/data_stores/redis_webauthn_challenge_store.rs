@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use color_eyre::eyre::Context;
+use redis::{Commands, Connection};
+use secrecy::{ExposeSecret, Secret};
+use tokio::sync::RwLock;
+use tracing::instrument;
+
+use crate::{
+    domain::UserId,
+    services::{
+        WebAuthnCeremony, WebAuthnChallenge, WebAuthnChallengeStore, WebAuthnChallengeStoreError,
+    },
+    utils::constants::WEBAUTHN_CHALLENGE_TTL_SECONDS,
+};
+
+#[derive(Clone)]
+pub struct RedisWebAuthnChallengeStore {
+    conn: Arc<RwLock<Connection>>,
+}
+
+impl std::fmt::Debug for RedisWebAuthnChallengeStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisWebAuthnChallengeStore")
+            .field("conn", &"<redis connection>")
+            .finish()
+    }
+}
+
+impl RedisWebAuthnChallengeStore {
+    #[instrument(name = "new_redis_webauthn_challenge_store", skip(conn))]
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl WebAuthnChallengeStore for RedisWebAuthnChallengeStore {
+    #[instrument(name = "add_webauthn_challenge", skip_all)]
+    async fn add_challenge(
+        &mut self,
+        user_id: &UserId,
+        ceremony: WebAuthnCeremony,
+        challenge: WebAuthnChallenge,
+    ) -> Result<(), WebAuthnChallengeStoreError> {
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .set_ex(
+                get_key(user_id, ceremony),
+                challenge.as_ref().expose_secret(),
+                WEBAUTHN_CHALLENGE_TTL_SECONDS,
+            )
+            .wrap_err("failed to set WebAuthn challenge in Redis")
+            .map_err(WebAuthnChallengeStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[instrument(name = "take_webauthn_challenge", skip_all)]
+    async fn take_challenge(
+        &mut self,
+        user_id: &UserId,
+        ceremony: WebAuthnCeremony,
+    ) -> Result<WebAuthnChallenge, WebAuthnChallengeStoreError> {
+        // GETDEL reads and removes the challenge atomically, so it can only be answered once
+        let challenge: Option<String> = self
+            .conn
+            .write()
+            .await
+            .get_del(get_key(user_id, ceremony))
+            .wrap_err("failed to take WebAuthn challenge from Redis")
+            .map_err(WebAuthnChallengeStoreError::UnexpectedError)?;
+
+        let challenge = challenge.ok_or(WebAuthnChallengeStoreError::ChallengeNotFound)?;
+
+        WebAuthnChallenge::parse(Secret::new(challenge))
+            .map_err(WebAuthnChallengeStoreError::UnexpectedError)
+    }
+}
+
+const WEBAUTHN_CHALLENGE_KEY_PREFIX: &str = "webauthn_challenge:";
+
+fn get_key(user_id: &UserId, ceremony: WebAuthnCeremony) -> String {
+    format!(
+        "{}{}:{}",
+        WEBAUTHN_CHALLENGE_KEY_PREFIX,
+        ceremony.as_str(),
+        user_id
+    )
+}
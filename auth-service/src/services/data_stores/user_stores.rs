@@ -1,14 +1,41 @@
-use crate::domain::{Email, Password, User};
+use crate::domain::{Email, Password, User, UserId};
+use crate::utils::constants::{
+    DEFAULT_LOGIN_ACCOUNT_LIMIT, DEFAULT_LOGIN_ACCOUNT_WINDOW_SECONDS, DEFAULT_LOGIN_IP_LIMIT,
+    DEFAULT_LOGIN_IP_WINDOW_SECONDS, DEFAULT_LOGIN_LOCKOUT_BASE_SECONDS,
+    DEFAULT_LOGIN_LOCKOUT_MAX_SECONDS, DEFAULT_LOGIN_LOCKOUT_THRESHOLD,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL, Engine};
+use chrono::{DateTime, Utc};
 use color_eyre::eyre::{eyre, Context, Report, Result};
 use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
 use thiserror::Error;
 
 #[async_trait::async_trait]
 pub trait UserStore: std::fmt::Debug {
     async fn add_user(&mut self, user: User) -> Result<(), UserStoreError>;
-    async fn get_user(&self, email: &Email) -> Result<User, UserStoreError>;
+    async fn get_user(&self, user_id: &UserId) -> Result<User, UserStoreError>;
+    // Users log in with their email address, so they can also be looked up by it
+    async fn get_user_by_email(&self, email: &Email) -> Result<User, UserStoreError>;
     async fn validate_user(&self, email: &Email, password: &Password)
         -> Result<(), UserStoreError>;
+    async fn update_password(
+        &mut self,
+        user_id: &UserId,
+        password: Password,
+    ) -> Result<(), UserStoreError>;
+    async fn mark_email_verified(&mut self, user_id: &UserId) -> Result<(), UserStoreError>;
+    // Fails with `UserAlreadyExists` if the new address already has an account
+    async fn change_email(
+        &mut self,
+        user_id: &UserId,
+        new_email: &Email,
+    ) -> Result<(), UserStoreError>;
+    // Save changes to the user's settings. Their email address and password are changed with
+    // `change_email` and `update_password` instead, so they are left as they are.
+    async fn update_user(&mut self, user: User) -> Result<(), UserStoreError>;
+    async fn delete_user(&mut self, user_id: &UserId) -> Result<(), UserStoreError>;
 }
 
 #[derive(Debug, Error)]
@@ -54,8 +81,16 @@ impl PartialEq for UserStoreError {
 
 #[async_trait::async_trait]
 pub trait BannedTokenStore: std::fmt::Debug {
-    async fn ban_token(&mut self, token: Secret<String>) -> Result<(), BannedTokenStoreError>;
-    async fn is_token_banned(&self, token: &Secret<String>) -> Result<bool, BannedTokenStoreError>;
+    // Tokens are banned by their `jti` claim
+    async fn ban_token(&mut self, token_id: &TokenId) -> Result<(), BannedTokenStoreError>;
+    async fn is_token_banned(&self, token_id: &TokenId) -> Result<bool, BannedTokenStoreError>;
+    // Every auth token carries the user's token generation at the time it was issued.
+    // Bumping the generation bans all tokens issued to the user before that point.
+    async fn ban_all_tokens_for_user(
+        &mut self,
+        user_id: &UserId,
+    ) -> Result<(), BannedTokenStoreError>;
+    async fn get_token_generation(&self, user_id: &UserId) -> Result<u64, BannedTokenStoreError>;
 }
 
 #[derive(Debug, Error)]
@@ -79,20 +114,46 @@ impl PartialEq for BannedTokenStoreError {
     }
 }
 
+// The unique id of an auth token, carried in its `jti` claim
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TokenId(String);
+
+impl TokenId {
+    pub fn parse(id: String) -> Result<Self> {
+        let parsed_id = uuid::Uuid::parse_str(&id).wrap_err("Invalid token id")?;
+        Ok(Self(parsed_id.to_string()))
+    }
+}
+
+impl Default for TokenId {
+    fn default() -> Self {
+        Self(uuid::Uuid::new_v4().to_string())
+    }
+}
+
+impl AsRef<str> for TokenId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
 // This trait represents the interface all concrete 2FA code stores should implement
 #[async_trait::async_trait]
 pub trait TwoFACodeStore: std::fmt::Debug {
     async fn add_code(
         &mut self,
-        email: Email,
+        user_id: UserId,
         login_attempt_id: LoginAttemptId,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError>;
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError>;
+    async fn remove_code(&mut self, user_id: &UserId) -> Result<(), TwoFACodeStoreError>;
     async fn get_code(
         &self,
-        email: &Email,
+        user_id: &UserId,
     ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError>;
+    // Count a wrong code for the user's current login attempt. Once there have been too many,
+    // the code is removed and `TooManyAttempts` is returned, so the user has to log in again.
+    async fn record_failed_attempt(&mut self, user_id: &UserId) -> Result<(), TwoFACodeStoreError>;
 }
 
 #[derive(Debug, Error)]
@@ -101,6 +162,8 @@ pub enum TwoFACodeStoreError {
     LoginAttemptIdNotFound,
     #[error("2FA code not found")]
     TwoFACodeNotFound,
+    #[error("Too many 2FA attempts")]
+    TooManyAttempts,
     #[error("Unexpected error")]
     UnexpectedError(#[source] Report),
 }
@@ -122,6 +185,7 @@ impl From<TwoFACodeStoreError> for String {
         match err {
             TwoFACodeStoreError::LoginAttemptIdNotFound => "Login attempt ID not found".to_owned(),
             TwoFACodeStoreError::TwoFACodeNotFound => "2FA code not found".to_owned(),
+            TwoFACodeStoreError::TooManyAttempts => "Too many 2FA attempts".to_owned(),
             TwoFACodeStoreError::UnexpectedError(e) => format!("Unexpected error: {}", e),
         }
     }
@@ -133,11 +197,1039 @@ impl PartialEq for TwoFACodeStoreError {
             (self, other),
             (Self::LoginAttemptIdNotFound, Self::LoginAttemptIdNotFound)
                 | (Self::TwoFACodeNotFound, Self::TwoFACodeNotFound)
+                | (Self::TooManyAttempts, Self::TooManyAttempts)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// Codes emailed to a logged in user to confirm a change to their account. They are kept apart
+// from the codes sent at login, so sending one doesn't replace a login waiting on its code and
+// neither can be used in place of the other. Wrong authenticator app codes at enrollment count
+// towards the same limit.
+#[async_trait::async_trait]
+pub trait ConfirmationCodeStore: std::fmt::Debug {
+    // Replaces any code already sent to the user for the same purpose
+    async fn add_code(
+        &mut self,
+        user_id: &UserId,
+        purpose: ConfirmationPurpose,
+        code: TwoFACode,
+    ) -> Result<(), ConfirmationCodeStoreError>;
+    // Fails with `TooManyAttempts` while the user has too many wrong codes against them
+    async fn get_code(
+        &self,
+        user_id: &UserId,
+        purpose: ConfirmationPurpose,
+    ) -> Result<TwoFACode, ConfirmationCodeStoreError>;
+    async fn remove_code(
+        &mut self,
+        user_id: &UserId,
+        purpose: ConfirmationPurpose,
+    ) -> Result<(), ConfirmationCodeStoreError>;
+    // Count a wrong code against the user. The count isn't reset when a new code is sent, so
+    // asking for another code doesn't buy more guesses. Once there have been too many,
+    // `TooManyAttempts` is returned until they expire.
+    async fn record_failed_attempt(
+        &mut self,
+        user_id: &UserId,
+    ) -> Result<(), ConfirmationCodeStoreError>;
+    // Fails with `TooManyAttempts` like `get_code`, for codes that aren't kept here
+    async fn check_attempts(&self, user_id: &UserId) -> Result<(), ConfirmationCodeStoreError>;
+    // Remove every code sent to the user and their count of wrong ones - called when their
+    // account is deleted
+    async fn remove_codes(&mut self, user_id: &UserId) -> Result<(), ConfirmationCodeStoreError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConfirmationPurpose {
+    EnableEmail2FA,
+    DisableEmail2FA,
+}
+
+impl AsRef<str> for ConfirmationPurpose {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::EnableEmail2FA => "enable_email_2fa",
+            Self::DisableEmail2FA => "disable_email_2fa",
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ConfirmationCodeStoreError {
+    #[error("Confirmation code not found")]
+    CodeNotFound,
+    #[error("Too many confirmation attempts")]
+    TooManyAttempts,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl From<ErrReport> for ConfirmationCodeStoreError {
+    fn from(err: ErrReport) -> Self {
+        ConfirmationCodeStoreError::UnexpectedError(err)
+    }
+}
+
+impl PartialEq for ConfirmationCodeStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::CodeNotFound, Self::CodeNotFound)
+                | (Self::TooManyAttempts, Self::TooManyAttempts)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// This trait represents the interface all concrete refresh token stores should implement
+#[async_trait::async_trait]
+pub trait RefreshTokenStore: std::fmt::Debug {
+    async fn add_token(
+        &mut self,
+        token: RefreshToken,
+        record: RefreshTokenRecord,
+    ) -> Result<(), RefreshTokenStoreError>;
+    async fn get_token(
+        &self,
+        token: &RefreshToken,
+    ) -> Result<RefreshTokenRecord, RefreshTokenStoreError>;
+    async fn mark_token_used(&mut self, token: &RefreshToken)
+        -> Result<(), RefreshTokenStoreError>;
+    async fn revoke_family(&mut self, family_id: &str) -> Result<(), RefreshTokenStoreError>;
+    async fn is_family_revoked(&self, family_id: &str) -> Result<bool, RefreshTokenStoreError>;
+    async fn revoke_all_families_for_user(
+        &mut self,
+        user_id: &UserId,
+    ) -> Result<(), RefreshTokenStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum RefreshTokenStoreError {
+    #[error("Refresh token not found")]
+    TokenNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl From<ErrReport> for RefreshTokenStoreError {
+    fn from(err: ErrReport) -> Self {
+        RefreshTokenStoreError::UnexpectedError(err)
+    }
+}
+
+impl PartialEq for RefreshTokenStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::TokenNotFound, Self::TokenNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// Everything the server knows about an issued refresh token. Tokens rotated from the
+// same login share a `family_id`, so reuse of a rotated token can revoke all of them. The
+// family id is also the id of the login's `Session`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RefreshTokenRecord {
+    pub user_id: UserId,
+    pub family_id: String,
+    pub used: bool,
+}
+
+impl RefreshTokenRecord {
+    // Start a new token family for a session - called when a user logs in
+    pub fn new(user_id: UserId, session_id: &SessionId) -> Self {
+        Self {
+            user_id,
+            family_id: session_id.as_ref().to_owned(),
+            used: false,
+        }
+    }
+
+    // Continue an existing token family - called when a refresh token is rotated
+    pub fn rotate(&self) -> Self {
+        Self {
+            user_id: self.user_id,
+            family_id: self.family_id.clone(),
+            used: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RefreshToken(Secret<String>);
+
+impl RefreshToken {
+    pub fn parse(token: Secret<String>) -> Result<Self> {
+        if is_random_token(token.expose_secret()) {
+            Ok(Self(token))
+        } else {
+            Err(eyre!("Invalid refresh token"))
+        }
+    }
+}
+
+impl PartialEq for RefreshToken {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.expose_secret() == other.0.expose_secret()
+    }
+}
+
+impl Default for RefreshToken {
+    fn default() -> Self {
+        Self(generate_random_token())
+    }
+}
+
+impl AsRef<Secret<String>> for RefreshToken {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+// This trait represents the interface all concrete session stores should implement
+#[async_trait::async_trait]
+pub trait SessionStore: std::fmt::Debug {
+    async fn add_session(&mut self, session: Session) -> Result<(), SessionStoreError>;
+    async fn get_session(&self, id: &SessionId) -> Result<Session, SessionStoreError>;
+    // Oldest first
+    async fn get_sessions(&self, user_id: &UserId) -> Result<Vec<Session>, SessionStoreError>;
+    // Record the auth token most recently issued to the session - called when it is refreshed
+    async fn touch_session(
+        &mut self,
+        id: &SessionId,
+        token_id: &TokenId,
+    ) -> Result<(), SessionStoreError>;
+    async fn remove_session(&mut self, id: &SessionId) -> Result<(), SessionStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum SessionStoreError {
+    #[error("Session already exists")]
+    SessionAlreadyExists,
+    #[error("Session not found")]
+    SessionNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl From<ErrReport> for SessionStoreError {
+    fn from(err: ErrReport) -> Self {
+        SessionStoreError::UnexpectedError(err)
+    }
+}
+
+impl PartialEq for SessionStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::SessionAlreadyExists, Self::SessionAlreadyExists)
+                | (Self::SessionNotFound, Self::SessionNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// A login on one device. A session lasts as long as its refresh token family, whose id it
+// shares, and every auth token issued to it names it in its `sid` claim.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub id: SessionId,
+    pub user_id: UserId,
+    // The `jti` of the auth token most recently issued to the session
+    pub token_id: TokenId,
+    pub created_at: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl Session {
+    // Start a session for a user who has just logged in
+    pub fn new(user_id: UserId, ip_address: Option<String>, user_agent: Option<String>) -> Self {
+        let now = Utc::now();
+        Self {
+            id: SessionId::default(),
+            user_id,
+            token_id: TokenId::default(),
+            created_at: now,
+            last_seen: now,
+            ip_address,
+            user_agent,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SessionId(String);
+
+impl SessionId {
+    pub fn parse(id: String) -> Result<Self> {
+        let parsed_id = uuid::Uuid::parse_str(&id).wrap_err("Invalid session id")?;
+        Ok(Self(parsed_id.to_string()))
+    }
+}
+
+impl Default for SessionId {
+    fn default() -> Self {
+        Self(uuid::Uuid::new_v4().to_string())
+    }
+}
+
+impl AsRef<str> for SessionId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// This trait represents the interface all concrete login attempt stores should implement
+#[async_trait::async_trait]
+pub trait LoginAttemptStore: std::fmt::Debug {
+    // Count a login attempt from `ip_address` for `email`, unless the account is locked out or
+    // either has made too many attempts within its sliding window
+    async fn record_attempt(
+        &mut self,
+        ip_address: Option<&str>,
+        email: &Email,
+    ) -> Result<(), LoginAttemptStoreError>;
+    // Count a failed login, locking the account out once there have been too many in a row
+    async fn record_failure(&mut self, email: &Email) -> Result<(), LoginAttemptStoreError>;
+    // Reset the count of failed logins - called after a successful login
+    async fn clear_failures(&mut self, email: &Email) -> Result<(), LoginAttemptStoreError>;
+    // Forget the account's attempts, failures and lockout - called when it is deleted. Attempts
+    // from IP addresses are kept, as they aren't tied to the account.
+    async fn remove_account(&mut self, email: &Email) -> Result<(), LoginAttemptStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum LoginAttemptStoreError {
+    #[error("Too many login attempts")]
+    TooManyAttempts { retry_after_seconds: u64 },
+    #[error("Account locked")]
+    AccountLocked { retry_after_seconds: u64 },
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl From<ErrReport> for LoginAttemptStoreError {
+    fn from(err: ErrReport) -> Self {
+        LoginAttemptStoreError::UnexpectedError(err)
+    }
+}
+
+impl PartialEq for LoginAttemptStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                Self::TooManyAttempts {
+                    retry_after_seconds: a,
+                },
+                Self::TooManyAttempts {
+                    retry_after_seconds: b,
+                },
+            )
+            | (
+                Self::AccountLocked {
+                    retry_after_seconds: a,
+                },
+                Self::AccountLocked {
+                    retry_after_seconds: b,
+                },
+            ) => a == b,
+            (Self::UnexpectedError(_), Self::UnexpectedError(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+// How many logins are allowed, and how accounts are locked out after repeated failures
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoginThrottleSettings {
+    // Attempts allowed from one IP address within its sliding window
+    pub ip_limit: u64,
+    pub ip_window_seconds: u64,
+    // Attempts allowed for one account within its sliding window
+    pub account_limit: u64,
+    pub account_window_seconds: u64,
+    // Failed logins in a row before the account is locked out. The lockout starts at
+    // `lockout_base_seconds` and doubles with every further failure, up to
+    // `lockout_max_seconds`.
+    pub lockout_threshold: u64,
+    pub lockout_base_seconds: u64,
+    pub lockout_max_seconds: u64,
+}
+
+impl LoginThrottleSettings {
+    // How long to lock an account out for after `failures` failed logins in a row, if at all
+    pub fn lockout_seconds(&self, failures: u64) -> Option<u64> {
+        if failures < self.lockout_threshold {
+            return None;
+        }
+        let doublings = u32::try_from(failures - self.lockout_threshold).unwrap_or(u32::MAX);
+        let lockout = self
+            .lockout_base_seconds
+            .saturating_mul(2u64.saturating_pow(doublings));
+        Some(lockout.min(self.lockout_max_seconds))
+    }
+}
+
+impl Default for LoginThrottleSettings {
+    fn default() -> Self {
+        Self {
+            ip_limit: DEFAULT_LOGIN_IP_LIMIT,
+            ip_window_seconds: DEFAULT_LOGIN_IP_WINDOW_SECONDS,
+            account_limit: DEFAULT_LOGIN_ACCOUNT_LIMIT,
+            account_window_seconds: DEFAULT_LOGIN_ACCOUNT_WINDOW_SECONDS,
+            lockout_threshold: DEFAULT_LOGIN_LOCKOUT_THRESHOLD,
+            lockout_base_seconds: DEFAULT_LOGIN_LOCKOUT_BASE_SECONDS,
+            lockout_max_seconds: DEFAULT_LOGIN_LOCKOUT_MAX_SECONDS,
+        }
+    }
+}
+
+// This trait represents the interface all concrete password reset token stores should implement
+#[async_trait::async_trait]
+pub trait PasswordResetTokenStore: std::fmt::Debug {
+    async fn add_token(
+        &mut self,
+        token: PasswordResetToken,
+        user_id: UserId,
+    ) -> Result<(), PasswordResetTokenStoreError>;
+    // Look a token up without using it, e.g. to check a new password before it is redeemed
+    async fn get_token(
+        &self,
+        token: &PasswordResetToken,
+    ) -> Result<UserId, PasswordResetTokenStoreError>;
+    // Reset tokens are single-use, so looking one up also removes it
+    async fn take_token(
+        &mut self,
+        token: &PasswordResetToken,
+    ) -> Result<UserId, PasswordResetTokenStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum PasswordResetTokenStoreError {
+    #[error("Password reset token not found")]
+    TokenNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl From<ErrReport> for PasswordResetTokenStoreError {
+    fn from(err: ErrReport) -> Self {
+        PasswordResetTokenStoreError::UnexpectedError(err)
+    }
+}
+
+impl PartialEq for PasswordResetTokenStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::TokenNotFound, Self::TokenNotFound)
                 | (Self::UnexpectedError(_), Self::UnexpectedError(_))
         )
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct PasswordResetToken(Secret<String>);
+
+impl PasswordResetToken {
+    pub fn parse(token: Secret<String>) -> Result<Self> {
+        if is_random_token(token.expose_secret()) {
+            Ok(Self(token))
+        } else {
+            Err(eyre!("Invalid password reset token"))
+        }
+    }
+}
+
+impl PartialEq for PasswordResetToken {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.expose_secret() == other.0.expose_secret()
+    }
+}
+
+impl Default for PasswordResetToken {
+    fn default() -> Self {
+        Self(generate_random_token())
+    }
+}
+
+impl AsRef<Secret<String>> for PasswordResetToken {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+// This trait represents the interface all concrete email verification token stores should implement
+#[async_trait::async_trait]
+pub trait EmailVerificationTokenStore: std::fmt::Debug {
+    async fn add_token(
+        &mut self,
+        token: EmailVerificationToken,
+        user_id: UserId,
+    ) -> Result<(), EmailVerificationTokenStoreError>;
+    // Verification tokens are single-use, so looking one up also removes it
+    async fn take_token(
+        &mut self,
+        token: &EmailVerificationToken,
+    ) -> Result<UserId, EmailVerificationTokenStoreError>;
+    // Record that a verification email is about to be sent, failing with `TooManyEmails` once
+    // the user has reached the limit for the current window
+    async fn record_email_sent(
+        &mut self,
+        email: &Email,
+    ) -> Result<(), EmailVerificationTokenStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum EmailVerificationTokenStoreError {
+    #[error("Email verification token not found")]
+    TokenNotFound,
+    #[error("Too many verification emails sent")]
+    TooManyEmails,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl From<ErrReport> for EmailVerificationTokenStoreError {
+    fn from(err: ErrReport) -> Self {
+        EmailVerificationTokenStoreError::UnexpectedError(err)
+    }
+}
+
+impl PartialEq for EmailVerificationTokenStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::TokenNotFound, Self::TokenNotFound)
+                | (Self::TooManyEmails, Self::TooManyEmails)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EmailVerificationToken(Secret<String>);
+
+impl EmailVerificationToken {
+    pub fn parse(token: Secret<String>) -> Result<Self> {
+        if is_random_token(token.expose_secret()) {
+            Ok(Self(token))
+        } else {
+            Err(eyre!("Invalid email verification token"))
+        }
+    }
+}
+
+impl PartialEq for EmailVerificationToken {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.expose_secret() == other.0.expose_secret()
+    }
+}
+
+impl Default for EmailVerificationToken {
+    fn default() -> Self {
+        Self(generate_random_token())
+    }
+}
+
+impl AsRef<Secret<String>> for EmailVerificationToken {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+// This trait represents the interface all concrete email change stores should implement
+#[async_trait::async_trait]
+pub trait EmailChangeStore: std::fmt::Debug {
+    async fn add_change(&mut self, change: EmailChange) -> Result<(), EmailChangeStoreError>;
+    // Changes are confirmed once, so looking one up by its confirm token also removes it
+    async fn take_change(
+        &mut self,
+        confirm_token: &EmailChangeToken,
+    ) -> Result<PendingEmailChange, EmailChangeStoreError>;
+    // Remove a change by its cancel token, so it can no longer be confirmed
+    async fn cancel_change(
+        &mut self,
+        cancel_token: &EmailChangeToken,
+    ) -> Result<PendingEmailChange, EmailChangeStoreError>;
+    // Every change the user has asked for that is still waiting to be confirmed
+    async fn get_changes(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<PendingEmailChange>, EmailChangeStoreError>;
+    // Remove all of the user's pending changes, so none can be confirmed or cancelled
+    async fn remove_changes(&mut self, user_id: &UserId) -> Result<(), EmailChangeStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum EmailChangeStoreError {
+    #[error("Email change not found")]
+    ChangeNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl From<ErrReport> for EmailChangeStoreError {
+    fn from(err: ErrReport) -> Self {
+        EmailChangeStoreError::UnexpectedError(err)
+    }
+}
+
+impl PartialEq for EmailChangeStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::ChangeNotFound, Self::ChangeNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// A change of email address waiting to be confirmed from the new address. The old address is
+// sent the cancel token, so its owner can stop a change they didn't ask for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmailChange {
+    pub user_id: UserId,
+    pub new_email: Email,
+    pub confirm_token: EmailChangeToken,
+    pub cancel_token: EmailChangeToken,
+}
+
+impl EmailChange {
+    pub fn new(user_id: UserId, new_email: Email) -> Self {
+        Self {
+            user_id,
+            new_email,
+            confirm_token: EmailChangeToken::default(),
+            cancel_token: EmailChangeToken::default(),
+        }
+    }
+}
+
+// A change as it is read back from the store, which doesn't keep the tokens themselves
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingEmailChange {
+    pub user_id: UserId,
+    pub new_email: Email,
+}
+
+impl From<EmailChange> for PendingEmailChange {
+    fn from(change: EmailChange) -> Self {
+        Self {
+            user_id: change.user_id,
+            new_email: change.new_email,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EmailChangeToken(Secret<String>);
+
+impl EmailChangeToken {
+    pub fn parse(token: Secret<String>) -> Result<Self> {
+        if is_random_token(token.expose_secret()) {
+            Ok(Self(token))
+        } else {
+            Err(eyre!("Invalid email change token"))
+        }
+    }
+}
+
+impl PartialEq for EmailChangeToken {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.expose_secret() == other.0.expose_secret()
+    }
+}
+
+impl Default for EmailChangeToken {
+    fn default() -> Self {
+        Self(generate_random_token())
+    }
+}
+
+impl AsRef<Secret<String>> for EmailChangeToken {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+// This trait represents the interface all concrete TOTP secret stores should implement
+#[async_trait::async_trait]
+pub trait TotpSecretStore: std::fmt::Debug {
+    // Adds an unconfirmed secret, replacing any previous one for the user
+    async fn add_secret(
+        &mut self,
+        user_id: &UserId,
+        secret: TotpSecret,
+    ) -> Result<(), TotpSecretStoreError>;
+    async fn get_secret(&self, user_id: &UserId) -> Result<TotpSecretRecord, TotpSecretStoreError>;
+    async fn confirm_secret(&mut self, user_id: &UserId) -> Result<(), TotpSecretStoreError>;
+    // Records the time step of an accepted code, failing with `CodeAlreadyUsed` unless it is
+    // later than the last recorded step
+    async fn record_used_step(
+        &mut self,
+        user_id: &UserId,
+        step: u64,
+    ) -> Result<(), TotpSecretStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum TotpSecretStoreError {
+    #[error("TOTP secret not found")]
+    SecretNotFound,
+    #[error("TOTP code already used")]
+    CodeAlreadyUsed,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl From<ErrReport> for TotpSecretStoreError {
+    fn from(err: ErrReport) -> Self {
+        TotpSecretStoreError::UnexpectedError(err)
+    }
+}
+
+impl PartialEq for TotpSecretStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::SecretNotFound, Self::SecretNotFound)
+                | (Self::CodeAlreadyUsed, Self::CodeAlreadyUsed)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TotpSecretRecord {
+    pub secret: TotpSecret,
+    // Codes are only accepted at login once the user has proven their authenticator works
+    pub confirmed: bool,
+    pub last_used_step: Option<u64>,
+}
+
+// A base32 encoded shared secret, as used in `otpauth://` URIs
+#[derive(Debug, Clone)]
+pub struct TotpSecret(Secret<String>);
+
+impl TotpSecret {
+    pub fn parse(secret: Secret<String>) -> Result<Self> {
+        let bytes = totp_rs::Secret::Encoded(secret.expose_secret().to_owned())
+            .to_bytes()
+            .map_err(|_| eyre!("Invalid TOTP secret"))?;
+        // RFC 4226 requires at least 128 bits of shared secret
+        if bytes.len() < 16 {
+            return Err(eyre!("Invalid TOTP secret"));
+        }
+        Ok(Self(secret))
+    }
+}
+
+impl PartialEq for TotpSecret {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.expose_secret() == other.0.expose_secret()
+    }
+}
+
+impl Default for TotpSecret {
+    fn default() -> Self {
+        let secret = totp_rs::Secret::generate_secret().to_encoded().to_string();
+        Self(Secret::new(secret))
+    }
+}
+
+impl AsRef<Secret<String>> for TotpSecret {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+#[async_trait::async_trait]
+pub trait RecoveryCodeStore: std::fmt::Debug {
+    // Replaces any existing codes, so a regenerated set invalidates the old one
+    async fn replace_codes(
+        &mut self,
+        user_id: &UserId,
+        codes: Vec<RecoveryCode>,
+    ) -> Result<(), RecoveryCodeStoreError>;
+    // Codes are single use - a matching code is removed as it is accepted
+    async fn use_code(
+        &mut self,
+        user_id: &UserId,
+        code: &RecoveryCode,
+    ) -> Result<(), RecoveryCodeStoreError>;
+    async fn count_codes(&self, user_id: &UserId) -> Result<usize, RecoveryCodeStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum RecoveryCodeStoreError {
+    #[error("Recovery code not found")]
+    CodeNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl From<ErrReport> for RecoveryCodeStoreError {
+    fn from(err: ErrReport) -> Self {
+        RecoveryCodeStoreError::UnexpectedError(err)
+    }
+}
+
+impl PartialEq for RecoveryCodeStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::CodeNotFound, Self::CodeNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// Recovery codes leave out characters that are easily confused when written down
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+// A single use code formatted as two groups of five characters, e.g. `k7mzq-4hx2p`
+#[derive(Debug, Clone)]
+pub struct RecoveryCode(Secret<String>);
+
+impl RecoveryCode {
+    pub fn parse(code: Secret<String>) -> Result<Self> {
+        // Accept codes with or without the separator and in any case, as users type them in
+        let chars: String = code
+            .expose_secret()
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '-')
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+
+        if chars.len() != RECOVERY_CODE_LENGTH
+            || !chars.bytes().all(|c| RECOVERY_CODE_ALPHABET.contains(&c))
+        {
+            return Err(eyre!("Invalid recovery code"));
+        }
+
+        let (first, second) = chars.split_at(RECOVERY_CODE_LENGTH / 2);
+        Ok(Self(Secret::new(format!("{}-{}", first, second))))
+    }
+}
+
+// Constant time, like `TwoFACode`
+impl PartialEq for RecoveryCode {
+    fn eq(&self, other: &Self) -> bool {
+        self.0
+            .expose_secret()
+            .as_bytes()
+            .ct_eq(other.0.expose_secret().as_bytes())
+            .into()
+    }
+}
+
+impl Default for RecoveryCode {
+    fn default() -> Self {
+        let chars: String = (0..RECOVERY_CODE_LENGTH)
+            .map(|_| {
+                let index = rand::random_range(0..RECOVERY_CODE_ALPHABET.len());
+                char::from(RECOVERY_CODE_ALPHABET[index])
+            })
+            .collect();
+        Self::parse(Secret::new(chars)).expect("generated recovery code should be valid")
+    }
+}
+
+impl AsRef<Secret<String>> for RecoveryCode {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+#[async_trait::async_trait]
+pub trait WebAuthnCredentialStore: std::fmt::Debug {
+    async fn add_credential(
+        &mut self,
+        credential: WebAuthnCredential,
+    ) -> Result<(), WebAuthnCredentialStoreError>;
+    async fn get_credentials(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<WebAuthnCredential>, WebAuthnCredentialStoreError>;
+    // Authenticators increase their signature counter on every assertion. A counter that
+    // doesn't increase suggests the credential has been cloned, so the update is rejected.
+    async fn update_sign_count(
+        &mut self,
+        credential_id: &[u8],
+        sign_count: u32,
+    ) -> Result<(), WebAuthnCredentialStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum WebAuthnCredentialStoreError {
+    #[error("Credential already exists")]
+    CredentialAlreadyExists,
+    #[error("Credential not found")]
+    CredentialNotFound,
+    #[error("Signature counter did not increase")]
+    SignCountNotIncreased,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl From<ErrReport> for WebAuthnCredentialStoreError {
+    fn from(err: ErrReport) -> Self {
+        WebAuthnCredentialStoreError::UnexpectedError(err)
+    }
+}
+
+impl PartialEq for WebAuthnCredentialStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::CredentialAlreadyExists, Self::CredentialAlreadyExists)
+                | (Self::CredentialNotFound, Self::CredentialNotFound)
+                | (Self::SignCountNotIncreased, Self::SignCountNotIncreased)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// A registered passkey. Only ES256 (ECDSA P-256) credentials are supported, so the public key
+// is kept as an uncompressed SEC1 point.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebAuthnCredential {
+    pub credential_id: Vec<u8>,
+    pub user_id: UserId,
+    pub public_key: Vec<u8>,
+    pub sign_count: u32,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl WebAuthnCredential {
+    // Both counters being zero means the authenticator doesn't implement a counter at all
+    pub fn is_sign_count_valid(&self, sign_count: u32) -> bool {
+        sign_count > self.sign_count || (sign_count == 0 && self.sign_count == 0)
+    }
+}
+
+#[async_trait::async_trait]
+pub trait WebAuthnChallengeStore: std::fmt::Debug {
+    async fn add_challenge(
+        &mut self,
+        user_id: &UserId,
+        ceremony: WebAuthnCeremony,
+        challenge: WebAuthnChallenge,
+    ) -> Result<(), WebAuthnChallengeStoreError>;
+    // Challenges are single use - taking one removes it
+    async fn take_challenge(
+        &mut self,
+        user_id: &UserId,
+        ceremony: WebAuthnCeremony,
+    ) -> Result<WebAuthnChallenge, WebAuthnChallengeStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum WebAuthnChallengeStoreError {
+    #[error("Challenge not found")]
+    ChallengeNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl From<ErrReport> for WebAuthnChallengeStoreError {
+    fn from(err: ErrReport) -> Self {
+        WebAuthnChallengeStoreError::UnexpectedError(err)
+    }
+}
+
+impl PartialEq for WebAuthnChallengeStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::ChallengeNotFound, Self::ChallengeNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// Registering a passkey and logging in with one use separate challenges, so starting one
+// doesn't cancel the other
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WebAuthnCeremony {
+    Registration,
+    Authentication,
+}
+
+impl WebAuthnCeremony {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebAuthnCeremony::Registration => "registration",
+            WebAuthnCeremony::Authentication => "authentication",
+        }
+    }
+}
+
+// 32 random bytes, base64url encoded without padding as WebAuthn clients expect
+#[derive(Debug, Clone)]
+pub struct WebAuthnChallenge(Secret<String>);
+
+impl WebAuthnChallenge {
+    pub fn parse(challenge: Secret<String>) -> Result<Self> {
+        let bytes = BASE64_URL
+            .decode(challenge.expose_secret())
+            .wrap_err("Invalid WebAuthn challenge")?;
+        if bytes.len() != 32 {
+            return Err(eyre!("Invalid WebAuthn challenge"));
+        }
+        Ok(Self(challenge))
+    }
+}
+
+impl PartialEq for WebAuthnChallenge {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.expose_secret() == other.0.expose_secret()
+    }
+}
+
+impl Default for WebAuthnChallenge {
+    fn default() -> Self {
+        Self(Secret::new(BASE64_URL.encode(rand::random::<[u8; 32]>())))
+    }
+}
+
+impl AsRef<Secret<String>> for WebAuthnChallenge {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+// Opaque tokens handed out to clients are 32 random bytes, hex encoded
+fn generate_random_token() -> Secret<String> {
+    let token: String = rand::random::<[u8; 32]>()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    Secret::new(token)
+}
+
+fn is_random_token(token: &str) -> bool {
+    token.len() == 64 && token.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+// Stores that outlive a request keep a SHA-256 of each token rather than the token itself, so a
+// copy of their data holds nothing that can be redeemed
+pub fn hash_token(token: &Secret<String>) -> String {
+    Sha256::digest(token.expose_secret().as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
 #[derive(Debug, Clone)]
 pub struct LoginAttemptId(Secret<String>);
 
@@ -149,9 +1241,14 @@ impl LoginAttemptId {
     }
 }
 
+// Compared in constant time, so response times can't be used to guess an ID a byte at a time
 impl PartialEq for LoginAttemptId {
     fn eq(&self, other: &Self) -> bool {
-        self.0.expose_secret() == other.0.expose_secret()
+        self.0
+            .expose_secret()
+            .as_bytes()
+            .ct_eq(other.0.expose_secret().as_bytes())
+            .into()
     }
 }
 
@@ -185,9 +1282,14 @@ impl TwoFACode {
     }
 }
 
+// Constant time, like `LoginAttemptId`
 impl PartialEq for TwoFACode {
     fn eq(&self, other: &Self) -> bool {
-        self.0.expose_secret() == other.0.expose_secret()
+        self.0
+            .expose_secret()
+            .as_bytes()
+            .ct_eq(other.0.expose_secret().as_bytes())
+            .into()
     }
 }
 
@@ -209,3 +1311,63 @@ impl AsRef<Secret<String>> for TwoFACode {
         &self.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::capture_logs;
+
+    #[test]
+    fn two_fa_codes_are_compared_by_value() {
+        let code = TwoFACode::parse(Secret::new("123456".to_owned())).unwrap();
+
+        assert_eq!(
+            code,
+            TwoFACode::parse(Secret::new("123456".to_owned())).unwrap()
+        );
+        assert_ne!(
+            code,
+            TwoFACode::parse(Secret::new("123457".to_owned())).unwrap()
+        );
+    }
+
+    #[test]
+    fn tokens_are_hashed_consistently() {
+        let token = RefreshToken::default();
+        let hash = hash_token(token.as_ref());
+
+        assert_eq!(hash, hash_token(token.as_ref()));
+        assert_ne!(hash, token.as_ref().expose_secret().as_str());
+        assert_ne!(hash, hash_token(RefreshToken::default().as_ref()));
+        assert!(is_random_token(&hash));
+    }
+
+    #[test]
+    fn login_attempt_ids_are_compared_by_value() {
+        let id = LoginAttemptId::default();
+
+        assert_eq!(id, id.clone());
+        assert_ne!(id, LoginAttemptId::default());
+    }
+
+    #[test]
+    fn two_fa_secrets_are_not_logged() {
+        let code = TwoFACode::default();
+        let login_attempt_id = LoginAttemptId::default();
+        let secrets = [
+            code.as_ref().expose_secret().to_owned(),
+            login_attempt_id.as_ref().expose_secret().to_owned(),
+        ];
+
+        let debug = format!("{:?} {:?}", code, login_attempt_id);
+        let logs = capture_logs(|| {
+            tracing::info!(?code, ?login_attempt_id, "started login attempt");
+        });
+        assert!(logs.contains("started login attempt"));
+
+        for secret in secrets {
+            assert!(!debug.contains(&secret));
+            assert!(!logs.contains(&secret));
+        }
+    }
+}
@@ -1,5 +1,38 @@
+use std::sync::{Arc, Mutex};
+
 use uuid::Uuid;
 
 pub fn get_random_email() -> String {
     format!("{}@example.com", Uuid::new_v4())
 }
+
+// Run `f` and return everything it logged, at every level, as formatted text
+pub fn capture_logs(f: impl FnOnce()) -> String {
+    let logs = Arc::new(Mutex::new(Vec::new()));
+    let writer = {
+        let logs = logs.clone();
+        move || LogWriter(logs.clone())
+    };
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::TRACE)
+        .with_writer(writer)
+        .finish();
+
+    tracing::subscriber::with_default(subscriber, f);
+
+    let logs = logs.lock().unwrap();
+    String::from_utf8_lossy(&logs).into_owned()
+}
+
+struct LogWriter(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
@@ -1,7 +1,21 @@
 pub mod auth;
+pub mod client;
 pub mod constants;
+pub mod jwt_keys;
+pub mod password_hashing;
+pub mod password_strength;
+pub mod totp;
 pub mod tracing;
+pub mod user_import;
+pub mod webauthn;
 
 pub use auth::*;
+pub use client::*;
 pub use constants::*;
+pub use jwt_keys::*;
+pub use password_hashing::*;
+pub use password_strength::*;
+pub use totp::*;
 pub use tracing::*;
+pub use user_import::*;
+pub use webauthn::*;
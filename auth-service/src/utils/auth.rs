@@ -1,23 +1,43 @@
-use axum_extra::extract::cookie::{Cookie, SameSite};
+use axum_extra::extract::{
+    cookie::{Cookie, SameSite},
+    CookieJar,
+};
 use chrono::Utc;
 use color_eyre::eyre::{eyre, Context, ContextCompat, Result};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Validation};
+use jsonwebtoken::{decode, decode_header, encode, Validation};
 use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
+use std::sync::{PoisonError, RwLockReadGuard};
 use thiserror::Error;
 
-use crate::domain::{BannedTokenStoreType, Email};
-use crate::services::data_stores::ErrReport;
+use crate::domain::{AppState, AuthAPIError, BannedTokenStoreType, RefreshTokenStoreType, UserId};
+use crate::services::data_stores::{
+    ErrReport, RefreshToken, RefreshTokenRecord, RefreshTokenStoreError, Session, SessionId,
+    SessionStoreError, TokenId,
+};
 
-use super::constants::{JWT_COOKIE_NAME, JWT_SECRET};
+use super::constants::{
+    load_jwt_key_ring, JWT_AUDIENCE, JWT_COOKIE_NAME, JWT_ISSUER, JWT_KEY_RING, JWT_LEEWAY_SECONDS,
+    REFRESH_COOKIE_NAME,
+};
+use super::jwt_keys::JwtKeyRing;
 
 // This is definitely NOT a good secret. We will update it soon!
 // const JWT_SECRET: &str = "secret";
 
-// Create cookie with a new JWT auth token - called from login route handler
+// Create cookie with a new JWT auth token for a session, identified by the session's current
+// `token_id` - called from login and refresh route handlers
 #[tracing::instrument(name = "Generate auth cookie", skip_all)]
-pub fn generate_auth_cookie(email: &Email) -> Result<Cookie<'static>> {
-    let token = generate_auth_token(email)?;
+pub async fn generate_auth_cookie(
+    session: &Session,
+    banned_token_store: BannedTokenStoreType,
+) -> Result<Cookie<'static>> {
+    let generation = banned_token_store
+        .read()
+        .await
+        .get_token_generation(&session.user_id)
+        .await?;
+    let token = generate_auth_token(session, generation)?;
     Ok(create_auth_cookie(token))
 }
 
@@ -33,6 +53,43 @@ fn create_auth_cookie(token: Secret<String>) -> Cookie<'static> {
     cookie
 }
 
+// Create cookie with a new refresh token and persist it - called from login, verify_2fa and
+// refresh route handlers
+#[tracing::instrument(name = "Generate refresh cookie", skip_all)]
+pub async fn generate_refresh_cookie(
+    record: RefreshTokenRecord,
+    refresh_token_store: RefreshTokenStoreType,
+) -> Result<Cookie<'static>> {
+    let token = RefreshToken::default();
+
+    refresh_token_store
+        .write()
+        .await
+        .add_token(token.clone(), record)
+        .await
+        .wrap_err("failed to store refresh token")?;
+
+    Ok(create_refresh_cookie(token.as_ref().to_owned()))
+}
+
+// Create refresh cookie and set the value to the passed-in token string
+#[tracing::instrument(name = "Create refresh cookie", skip_all)]
+fn create_refresh_cookie(token: Secret<String>) -> Cookie<'static> {
+    Cookie::build((REFRESH_COOKIE_NAME, token.expose_secret().to_owned()))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .build()
+}
+
+// Remove the auth and refresh cookies - called from route handlers that log the user out. The
+// removal cookies need the same path as the originals, or they won't replace them when sent
+// from a nested route like `/logout/all`.
+pub fn remove_auth_cookies(jar: CookieJar) -> CookieJar {
+    jar.remove(Cookie::build(JWT_COOKIE_NAME).path("/"))
+        .remove(Cookie::build(REFRESH_COOKIE_NAME).path("/"))
+}
+
 #[derive(Debug, Error)]
 pub enum GenerateTokenError {
     TokenError(jsonwebtoken::errors::Error),
@@ -57,14 +114,19 @@ impl From<ErrReport> for GenerateTokenError {
 // This value determines how long the JWT auth token is valid for
 pub const TOKEN_TTL_SECONDS: i64 = 600; // 10 minutes
 
+// This value determines how long an unused refresh token is valid for
+pub const REFRESH_TOKEN_TTL_SECONDS: i64 = 60 * 60 * 24 * 14; // 14 days
+
 // Create JWT auth token
 #[tracing::instrument(name = "Generate auth token", skip_all)]
-fn generate_auth_token(email: &Email) -> Result<Secret<String>> {
+fn generate_auth_token(session: &Session, generation: u64) -> Result<Secret<String>> {
     let delta = chrono::Duration::try_seconds(TOKEN_TTL_SECONDS)
         .wrap_err("failed to create 10 minute time delta")?;
 
+    let now = Utc::now();
+
     // Create JWT expiration time
-    let exp = Utc::now()
+    let exp = now
         .checked_add_signed(delta)
         .ok_or(eyre!("failed to add 10 minutes to current time"))?
         .timestamp();
@@ -75,21 +137,109 @@ fn generate_auth_token(email: &Email) -> Result<Secret<String>> {
         exp
     ))?;
 
-    let sub = email.as_ref().expose_secret().to_owned();
+    let iat: usize = now
+        .timestamp()
+        .try_into()
+        .wrap_err("failed to cast current time to usize")?;
 
-    let claims = Claims { sub, exp };
+    // The subject is the user's id rather than their email address, which can change and
+    // shouldn't be readable by anyone holding the token
+    let sub = session.user_id.to_string();
+
+    let claims = Claims {
+        iss: JWT_ISSUER.to_owned(),
+        aud: JWT_AUDIENCE.to_owned(),
+        sub,
+        exp,
+        iat,
+        nbf: iat,
+        jti: session.token_id.as_ref().to_owned(),
+        sid: session.id.as_ref().to_owned(),
+        generation,
+    };
 
     create_token(&claims)
 }
 
-// Check if JWT auth token is valid by decoding it using the JWT secret
+// Check if JWT auth token is valid: that it decodes, hasn't been banned, and the session it was
+// issued to is still going. Ending a session from `DELETE /sessions/{id}` or `/logout` rejects
+// every token issued to it, not just the latest.
 #[tracing::instrument(name = "Validate token", skip_all)]
-pub async fn validate_token(
+pub async fn validate_token(token: &Secret<String>, state: &AppState) -> Result<Claims> {
+    let claims = decode_token(token, state.banned_token_store.clone()).await?;
+
+    let session_id = SessionId::parse(claims.sid.clone()).wrap_err("invalid token session id")?;
+    match state
+        .session_store
+        .read()
+        .await
+        .get_session(&session_id)
+        .await
+    {
+        Ok(session) if session.user_id.to_string() == claims.sub => (),
+        Ok(_) => return Err(eyre!("token session belongs to another user")),
+        Err(SessionStoreError::SessionNotFound) => return Err(eyre!("token session has ended")),
+        Err(e) => return Err(eyre!(e)),
+    }
+
+    // A session's refresh tokens are revoked as it ends, so its tokens are refused even before
+    // its record is removed
+    let is_revoked = state
+        .refresh_token_store
+        .read()
+        .await
+        .is_family_revoked(&claims.sid)
+        .await
+        .wrap_err("failed to check token session")?;
+    if is_revoked {
+        return Err(eyre!("token session has been revoked"));
+    }
+
+    Ok(claims)
+}
+
+// Decode a JWT auth token using the JWT signing key, and check it hasn't been banned on its own
+// or along with every other token issued to the user
+#[tracing::instrument(name = "Decode token", skip_all)]
+pub async fn decode_token(
     token: &Secret<String>,
     banned_token_store: BannedTokenStoreType,
 ) -> Result<Claims> {
+    // Find the key the token was signed with - it may have been rotated out of the ring
+    let header = decode_header(token.expose_secret()).wrap_err("failed to decode token header")?;
+    let key = jwt_key_ring()
+        .find_key(header.kid.as_deref())
+        .inspect_err(|e| tracing::warn!("rejected token: {}", e))
+        .wrap_err("failed to find token signing key")?;
+
+    let claims = decode::<Claims>(
+        token.expose_secret(),
+        key.decoding_key(),
+        &token_validation(key.validation()),
+    )
+    .map(|data| data.claims)
+    .wrap_err(eyre!("failed to decode token"))?;
+
+    // `exp` and `nbf` are checked when decoding, but nothing checks `iat`
+    let now: usize = Utc::now()
+        .timestamp()
+        .try_into()
+        .wrap_err("failed to cast current time to usize")?;
+    let leeway: usize = (*JWT_LEEWAY_SECONDS)
+        .try_into()
+        .wrap_err("failed to cast leeway to usize")?;
+    if claims.iat > now + leeway {
+        return Err(eyre!("token was issued in the future"));
+    }
+
     // Check if the token is in the banned token store
-    match banned_token_store.read().await.is_token_banned(token).await {
+    let token_id = TokenId::parse(claims.jti.clone()).wrap_err("invalid token id")?;
+    match banned_token_store
+        .read()
+        .await
+        .is_token_banned(&token_id)
+        .await
+    {
         Ok(value) => {
             if value {
                 return Err(eyre!("token is banned"));
@@ -98,31 +248,148 @@ pub async fn validate_token(
         Err(e) => return Err(eyre!(e)),
     }
 
-    decode::<Claims>(
-        token.expose_secret(),
-        &DecodingKey::from_secret(JWT_SECRET.expose_secret().as_bytes()),
-        &Validation::default(),
-    )
-    .map(|data| data.claims)
-    .wrap_err(eyre!("failed to decode token"))
+    // Check if the token was issued before all of the user's tokens were banned
+    let user_id = UserId::parse(&claims.sub).wrap_err("invalid token subject")?;
+    let generation = banned_token_store
+        .read()
+        .await
+        .get_token_generation(&user_id)
+        .await?;
+    if claims.generation < generation {
+        return Err(eyre!("token has been revoked"));
+    }
+
+    Ok(claims)
+}
+
+// Validate the JWT cookie sent with a request and return the logged in user's id - called
+// from route handlers that require the user to be logged in
+#[tracing::instrument(name = "Authenticate", skip_all)]
+pub async fn authenticate(jar: &CookieJar, state: &AppState) -> Result<UserId, AuthAPIError> {
+    let claims = authenticate_claims(jar, state).await?;
+
+    UserId::parse(&claims.sub).map_err(|_| AuthAPIError::InvalidToken)
+}
+
+// Validate the JWT cookie sent with a request and return its claims - called from route
+// handlers that also need to know which session the request was made from
+#[tracing::instrument(name = "Authenticate claims", skip_all)]
+pub async fn authenticate_claims(
+    jar: &CookieJar,
+    state: &AppState,
+) -> Result<Claims, AuthAPIError> {
+    let cookie = jar.get(JWT_COOKIE_NAME).ok_or(AuthAPIError::MissingToken)?;
+    let token = Secret::new(cookie.value().to_owned());
+
+    validate_token(&token, state)
+        .await
+        .map_err(|_| AuthAPIError::InvalidToken)
 }
 
-// Create JWT auth token by encoding claims using the JWT secret
+// Invalidate every auth and refresh token issued to a user - called after their password
+// has been reset, and when they sign out everywhere
+#[tracing::instrument(name = "Revoke all sessions", skip_all)]
+pub async fn revoke_all_sessions(
+    user_id: &UserId,
+    banned_token_store: BannedTokenStoreType,
+    refresh_token_store: RefreshTokenStoreType,
+) -> Result<()> {
+    banned_token_store
+        .write()
+        .await
+        .ban_all_tokens_for_user(user_id)
+        .await
+        .wrap_err("failed to ban auth tokens")?;
+
+    refresh_token_store
+        .write()
+        .await
+        .revoke_all_families_for_user(user_id)
+        .await
+        .wrap_err("failed to revoke refresh tokens")?;
+
+    Ok(())
+}
+
+// Revoke a refresh token, and every token rotated from it, returning whether there was
+// anything to revoke - called from the revoke route handler
+#[tracing::instrument(name = "Revoke refresh token", skip_all)]
+pub async fn revoke_refresh_token(
+    token: Secret<String>,
+    refresh_token_store: RefreshTokenStoreType,
+) -> Result<bool> {
+    // A malformed or unknown refresh token has nothing left to revoke
+    let token = match RefreshToken::parse(token) {
+        Ok(token) => token,
+        Err(_) => return Ok(false),
+    };
+
+    let mut refresh_token_store = refresh_token_store.write().await;
+
+    let record = match refresh_token_store.get_token(&token).await {
+        Ok(record) => record,
+        Err(RefreshTokenStoreError::TokenNotFound) => return Ok(false),
+        Err(e) => return Err(e).wrap_err("failed to get refresh token"),
+    };
+
+    refresh_token_store
+        .revoke_family(&record.family_id)
+        .await
+        .wrap_err("failed to revoke refresh token")?;
+
+    Ok(true)
+}
+
+// Require every registered claim we issue, and check them against this service's configuration
+fn token_validation(mut validation: Validation) -> Validation {
+    validation.set_issuer(&[JWT_ISSUER.as_str()]);
+    validation.set_audience(&[JWT_AUDIENCE.as_str()]);
+    validation.set_required_spec_claims(&["iss", "aud", "sub", "exp", "nbf", "iat", "jti"]);
+    validation.validate_nbf = true;
+    validation.leeway = *JWT_LEEWAY_SECONDS;
+    validation
+}
+
+// The JWT signing keys currently in use
+pub fn jwt_key_ring() -> RwLockReadGuard<'static, JwtKeyRing> {
+    // The lock is only held to read or swap the ring, so a poisoned lock still holds a valid ring
+    JWT_KEY_RING.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Replace the JWT signing keys with the ones currently configured, so keys can be promoted and
+// retired without a restart. The current keys are kept if the new ones fail to load.
+#[tracing::instrument(name = "Reload JWT key ring", skip_all)]
+pub fn reload_jwt_key_ring() -> Result<()> {
+    let key_ring = load_jwt_key_ring().wrap_err("failed to load JWT key ring")?;
+    *JWT_KEY_RING.write().unwrap_or_else(PoisonError::into_inner) = key_ring;
+    Ok(())
+}
+
+// Create JWT auth token by encoding claims using the active JWT signing key
 #[tracing::instrument(name = "Create token", skip_all)]
 fn create_token(claims: &Claims) -> Result<Secret<String>> {
-    encode(
-        &jsonwebtoken::Header::default(),
-        &claims,
-        &EncodingKey::from_secret(JWT_SECRET.expose_secret().as_bytes()),
-    )
-    .map(Secret::new)
-    .wrap_err(eyre!("failed to encode token"))
+    let key = jwt_key_ring().active_key();
+    encode(&key.header(), &claims, key.encoding_key())
+        .map(Secret::new)
+        .wrap_err(eyre!("failed to encode token"))
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
+    pub iss: String,
+    pub aud: String,
+    // The user's id - see `UserId`
     pub sub: String,
     pub exp: usize,
+    pub iat: usize,
+    pub nbf: usize,
+    // Identifies the token, so it can be banned on its own - see `BannedTokenStore`
+    pub jti: String,
+    // The session the token was issued to - see `Session`
+    pub sid: String,
+    // The user's token generation when the token was issued - see `revoke_all_sessions`
+    #[serde(rename = "gen")]
+    pub generation: u64,
 }
 
 #[cfg(test)]
@@ -131,14 +398,19 @@ mod tests {
     use std::sync::Arc;
     use tokio::sync::RwLock;
 
-    use crate::services::{BannedTokenStore, HashsetBannedTokenStore};
+    use crate::services::{
+        BannedTokenStore, HashmapRefreshTokenStore, HashsetBannedTokenStore, RefreshTokenStore,
+    };
 
     use super::*;
 
     #[tokio::test]
     async fn test_generate_auth_cookie() {
-        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
-        let cookie = generate_auth_cookie(&email).unwrap();
+        let user_id = UserId::default();
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        let cookie = generate_auth_cookie(&Session::new(user_id, None, None), banned_token_store)
+            .await
+            .unwrap();
         assert_eq!(cookie.name(), JWT_COOKIE_NAME);
         assert_eq!(cookie.value().split('.').count(), 3);
         assert_eq!(cookie.path(), Some("/"));
@@ -157,20 +429,45 @@ mod tests {
         assert_eq!(cookie.same_site(), Some(SameSite::Lax));
     }
 
+    #[tokio::test]
+    async fn test_generate_refresh_cookie() {
+        let user_id = UserId::default();
+        let record = RefreshTokenRecord::new(user_id, &SessionId::default());
+        let refresh_token_store = Arc::new(RwLock::new(HashmapRefreshTokenStore::default()));
+
+        let cookie = generate_refresh_cookie(record.clone(), refresh_token_store.clone())
+            .await
+            .unwrap();
+        assert_eq!(cookie.name(), REFRESH_COOKIE_NAME);
+        assert_eq!(cookie.path(), Some("/"));
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.same_site(), Some(SameSite::Lax));
+
+        // The token in the cookie is persisted against the record
+        let token = RefreshToken::parse(Secret::new(cookie.value().to_owned())).unwrap();
+        let stored = refresh_token_store
+            .read()
+            .await
+            .get_token(&token)
+            .await
+            .unwrap();
+        assert_eq!(stored, record);
+    }
+
     #[tokio::test]
     async fn test_generate_auth_token() {
-        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
-        let result = generate_auth_token(&email).unwrap();
+        let user_id = UserId::default();
+        let result = generate_auth_token(&Session::new(user_id, None, None), 0).unwrap();
         assert_eq!(result.expose_secret().split('.').count(), 3);
     }
 
     #[tokio::test]
     async fn test_validate_token_with_valid_token() {
-        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
-        let token = generate_auth_token(&email).unwrap();
+        let user_id = UserId::default();
+        let token = generate_auth_token(&Session::new(user_id, None, None), 0).unwrap();
         let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
-        let result = validate_token(&token, banned_token_store).await.unwrap();
-        assert_eq!(result.sub, "test@example.com");
+        let result = decode_token(&token, banned_token_store).await.unwrap();
+        assert_eq!(result.sub, user_id.to_string());
 
         let exp = Utc::now()
             .checked_add_signed(chrono::Duration::try_minutes(9).expect("valid duration"))
@@ -184,18 +481,178 @@ mod tests {
     async fn test_validate_token_with_invalid_token() {
         let token = Secret::new("invalid_token".to_owned());
         let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
-        let result = validate_token(&token, banned_token_store).await;
+        let result = decode_token(&token, banned_token_store).await;
         assert!(result.is_err());
     }
 
     #[tokio::test]
     async fn test_validate_token_with_banned_token() {
-        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
-        let token = generate_auth_token(&email).unwrap();
-        let mut hs = HashsetBannedTokenStore::default();
-        hs.ban_token(token.clone()).await.unwrap();
-        let banned_token_store = Arc::new(RwLock::new(hs));
-        let result = validate_token(&token, banned_token_store).await;
+        let user_id = UserId::default();
+        let token = generate_auth_token(&Session::new(user_id, None, None), 0).unwrap();
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        let claims = decode_token(&token, banned_token_store.clone())
+            .await
+            .unwrap();
+
+        // Bans are keyed on the token's `jti`
+        let token_id = TokenId::parse(claims.jti).unwrap();
+        banned_token_store
+            .write()
+            .await
+            .ban_token(&token_id)
+            .await
+            .unwrap();
+        let result = decode_token(&token, banned_token_store.clone()).await;
         assert!(result.is_err());
+
+        // Other tokens for the same user are unaffected
+        let other_token = generate_auth_token(&Session::new(user_id, None, None), 0).unwrap();
+        let result = decode_token(&other_token, banned_token_store).await;
+        assert!(result.is_ok());
+    }
+
+    fn valid_claims() -> Claims {
+        let now = Utc::now().timestamp() as usize;
+        Claims {
+            iss: JWT_ISSUER.to_owned(),
+            aud: JWT_AUDIENCE.to_owned(),
+            sub: UserId::default().to_string(),
+            exp: now + 600,
+            iat: now,
+            nbf: now,
+            jti: TokenId::default().as_ref().to_owned(),
+            sid: SessionId::default().as_ref().to_owned(),
+            generation: 0,
+        }
+    }
+
+    async fn validate_claims(claims: Claims) -> Result<Claims> {
+        let token = create_token(&claims).unwrap();
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        decode_token(&token, banned_token_store).await
+    }
+
+    #[tokio::test]
+    async fn test_generated_token_has_registered_claims() {
+        let user_id = UserId::default();
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+
+        let session = Session::new(user_id, None, None);
+        let first = generate_auth_token(&session, 0).unwrap();
+        let first = decode_token(&first, banned_token_store.clone())
+            .await
+            .unwrap();
+        assert_eq!(first.jti, session.token_id.as_ref());
+        assert_eq!(first.sid, session.id.as_ref());
+        assert_eq!(first.iss, *JWT_ISSUER);
+        assert_eq!(first.aud, *JWT_AUDIENCE);
+        assert_eq!(first.nbf, first.iat);
+        assert_eq!(first.exp, first.iat + TOKEN_TTL_SECONDS as usize);
+
+        // Every session's token gets its own id
+        let second = generate_auth_token(&Session::new(user_id, None, None), 0).unwrap();
+        let second = decode_token(&second, banned_token_store).await.unwrap();
+        assert_ne!(first.jti, second.jti);
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_checks_issuer_and_audience() {
+        assert!(validate_claims(valid_claims()).await.is_ok());
+
+        let claims = Claims {
+            iss: "https://elsewhere.example.com".to_owned(),
+            ..valid_claims()
+        };
+        assert!(validate_claims(claims).await.is_err());
+
+        let claims = Claims {
+            aud: "some-other-service".to_owned(),
+            ..valid_claims()
+        };
+        assert!(validate_claims(claims).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_checks_times_with_leeway() {
+        let now = Utc::now().timestamp() as usize;
+        let leeway = *JWT_LEEWAY_SECONDS as usize;
+
+        // Within the leeway either side
+        let claims = Claims {
+            exp: now - leeway / 2,
+            nbf: now + leeway / 2,
+            iat: now + leeway / 2,
+            ..valid_claims()
+        };
+        assert!(validate_claims(claims).await.is_ok());
+
+        let claims = Claims {
+            exp: now - leeway - 10,
+            ..valid_claims()
+        };
+        assert!(validate_claims(claims).await.is_err());
+
+        let claims = Claims {
+            nbf: now + leeway + 10,
+            ..valid_claims()
+        };
+        assert!(validate_claims(claims).await.is_err());
+
+        let claims = Claims {
+            iat: now + leeway + 10,
+            ..valid_claims()
+        };
+        assert!(validate_claims(claims).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_requires_token_id() {
+        let claims = Claims {
+            jti: "not-a-uuid".to_owned(),
+            ..valid_claims()
+        };
+        assert!(validate_claims(claims).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_requires_user_id_subject() {
+        // Tokens issued before users had ids named them by email address
+        let claims = Claims {
+            sub: "test@example.com".to_owned(),
+            ..valid_claims()
+        };
+        assert!(validate_claims(claims).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_after_all_tokens_banned() {
+        let user_id = UserId::default();
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        let refresh_token_store = Arc::new(RwLock::new(HashmapRefreshTokenStore::default()));
+
+        let old_token = generate_auth_token(&Session::new(user_id, None, None), 0).unwrap();
+
+        revoke_all_sessions(
+            &user_id,
+            banned_token_store.clone(),
+            refresh_token_store.clone(),
+        )
+        .await
+        .unwrap();
+
+        // Tokens issued before the ban are rejected...
+        let result = decode_token(&old_token, banned_token_store.clone()).await;
+        assert!(result.is_err());
+
+        // ...while tokens issued afterwards are accepted
+        let cookie = generate_auth_cookie(
+            &Session::new(user_id, None, None),
+            banned_token_store.clone(),
+        )
+        .await
+        .unwrap();
+        let new_token = Secret::new(cookie.value().to_owned());
+        let result = decode_token(&new_token, banned_token_store).await.unwrap();
+        assert_eq!(result.generation, 1);
     }
 }
@@ -1,14 +1,39 @@
+use color_eyre::eyre::Result;
 use dotenvy::dotenv;
 use lazy_static::lazy_static;
 use secrecy::Secret;
-use std::env as std_env;
+use std::{
+    collections::HashMap,
+    env as std_env,
+    sync::{Arc, RwLock},
+};
+
+use super::jwt_keys::{JwtKeyRing, JwtSigningKey};
+use super::password_hashing::PasswordPeppers;
+use crate::domain::{BreachedPasswords, PasswordPolicySettings, StandardPasswordPolicy};
+use crate::services::data_stores::LoginThrottleSettings;
 
 // Define a lazily evaluated static. lazy_static is needed because std_env::var is not a const function.
 lazy_static! {
     pub static ref JWT_SECRET: Secret<String> = set_token();
+    pub static ref JWT_KEY_RING: RwLock<JwtKeyRing> = set_jwt_key_ring();
+    pub static ref JWT_ISSUER: String = set_jwt_issuer();
+    pub static ref JWT_AUDIENCE: String = set_jwt_audience();
+    pub static ref JWT_LEEWAY_SECONDS: u64 = set_jwt_leeway_seconds();
+    pub static ref INTROSPECTION_CLIENTS: HashMap<String, Secret<String>> =
+        set_introspection_clients();
+    pub static ref ENUMERATION_SAFE_MODE: bool = set_enumeration_safe_mode();
+    pub static ref LOGIN_THROTTLE_SETTINGS: LoginThrottleSettings = set_login_throttle_settings();
+    pub static ref PASSWORD_POLICY: Arc<StandardPasswordPolicy> = set_password_policy();
+    pub static ref PASSWORD_HASH_PARAMS: argon2::Params = set_password_hash_params();
+    pub static ref PASSWORD_PEPPERS: PasswordPeppers = set_password_peppers();
     pub static ref DATABASE_URL: Secret<String> = set_dburl();
     pub static ref REDIS_HOST_NAME: String = set_redis_host();
     pub static ref POSTMARK_AUTH_TOKEN: Secret<String> = set_postmark_auth_token();
+    pub static ref AUTH_SERVICE_BASE_URL: String = set_auth_service_base_url();
+    pub static ref TOTP_ENCRYPTION_KEY: Secret<String> = set_totp_encryption_key();
+    pub static ref WEBAUTHN_ORIGIN: String = set_webauthn_origin();
+    pub static ref WEBAUTHN_RP_ID: String = set_webauthn_rp_id();
 }
 
 fn set_token() -> Secret<String> {
@@ -27,6 +52,199 @@ fn set_token() -> Secret<String> {
     Secret::new(secret)
 }
 
+fn set_jwt_key_ring() -> RwLock<JwtKeyRing> {
+    let key_ring =
+        load_jwt_key_ring().unwrap_or_else(|e| panic!("JWT signing keys must be valid: {:?}", e));
+    RwLock::new(key_ring)
+}
+
+// Load the key ring in JWT_KEY_RING_PATH, or a ring of just the key in JWT_SIGNING_KEY_PATH,
+// otherwise fall back to HS256 with JWT_SECRET. Also called to reload the keys - see
+// `reload_jwt_key_ring`.
+pub fn load_jwt_key_ring() -> Result<JwtKeyRing> {
+    dotenv().ok(); // Load environment variables
+    let non_empty_var = |name| std_env::var(name).ok().filter(|value| !value.is_empty());
+
+    if let Some(path) = non_empty_var(env::JWT_KEY_RING_PATH_ENV_VAR) {
+        return JwtKeyRing::from_manifest_file(path);
+    }
+
+    let active = match non_empty_var(env::JWT_SIGNING_KEY_PATH_ENV_VAR) {
+        Some(path) => JwtSigningKey::from_pem_file(path, non_empty_var(env::JWT_KEY_ID_ENV_VAR))?,
+        None => JwtSigningKey::from_secret(&JWT_SECRET),
+    };
+    Ok(JwtKeyRing::new(active))
+}
+
+// Tokens are issued by this service's public URL unless configured otherwise
+fn set_jwt_issuer() -> String {
+    dotenv().ok();
+    std_env::var(env::JWT_ISSUER_ENV_VAR)
+        .ok()
+        .filter(|issuer| !issuer.is_empty())
+        .unwrap_or_else(|| AUTH_SERVICE_BASE_URL.to_owned())
+}
+
+fn set_jwt_audience() -> String {
+    dotenv().ok();
+    std_env::var(env::JWT_AUDIENCE_ENV_VAR)
+        .ok()
+        .filter(|audience| !audience.is_empty())
+        .unwrap_or(DEFAULT_JWT_AUDIENCE.to_owned())
+}
+
+// How far out `exp`, `nbf` and `iat` may be, to allow for clock skew between services
+fn set_jwt_leeway_seconds() -> u64 {
+    dotenv().ok();
+    match std_env::var(env::JWT_LEEWAY_SECONDS_ENV_VAR) {
+        Ok(leeway) if !leeway.is_empty() => leeway
+            .parse()
+            .expect("JWT_LEEWAY_SECONDS must be a number of seconds."),
+        _ => DEFAULT_JWT_LEEWAY_SECONDS,
+    }
+}
+
+// The clients allowed to call `/introspect`, as comma separated `client_id:client_secret` pairs.
+// With none configured, every introspection request is rejected.
+fn set_introspection_clients() -> HashMap<String, Secret<String>> {
+    dotenv().ok();
+    let clients = std_env::var(env::INTROSPECTION_CLIENTS_ENV_VAR).unwrap_or_default();
+    clients
+        .split(',')
+        .map(str::trim)
+        .filter(|client| !client.is_empty())
+        .map(|client| match client.split_once(':') {
+            Some((id, secret)) if !id.is_empty() && !secret.is_empty() => {
+                (id.to_owned(), Secret::new(secret.to_owned()))
+            }
+            _ => panic!("INTROSPECTION_CLIENTS must be a list of client_id:client_secret pairs."),
+        })
+        .collect()
+}
+
+// Off by default, so clients can tell an unknown user from a wrong password
+fn set_enumeration_safe_mode() -> bool {
+    dotenv().ok();
+    match std_env::var(env::ENUMERATION_SAFE_MODE_ENV_VAR) {
+        Ok(enabled) if !enabled.is_empty() => enabled
+            .parse()
+            .expect("ENUMERATION_SAFE_MODE must be true or false."),
+        _ => false,
+    }
+}
+
+// Every login threshold can be overridden on its own, the rest keep their defaults
+fn set_login_throttle_settings() -> LoginThrottleSettings {
+    dotenv().ok();
+    let var = number_var;
+    let defaults = LoginThrottleSettings::default();
+
+    LoginThrottleSettings {
+        ip_limit: var(env::LOGIN_IP_LIMIT_ENV_VAR, defaults.ip_limit),
+        ip_window_seconds: var(
+            env::LOGIN_IP_WINDOW_SECONDS_ENV_VAR,
+            defaults.ip_window_seconds,
+        ),
+        account_limit: var(env::LOGIN_ACCOUNT_LIMIT_ENV_VAR, defaults.account_limit),
+        account_window_seconds: var(
+            env::LOGIN_ACCOUNT_WINDOW_SECONDS_ENV_VAR,
+            defaults.account_window_seconds,
+        ),
+        lockout_threshold: var(
+            env::LOGIN_LOCKOUT_THRESHOLD_ENV_VAR,
+            defaults.lockout_threshold,
+        ),
+        lockout_base_seconds: var(
+            env::LOGIN_LOCKOUT_BASE_SECONDS_ENV_VAR,
+            defaults.lockout_base_seconds,
+        ),
+        lockout_max_seconds: var(
+            env::LOGIN_LOCKOUT_MAX_SECONDS_ENV_VAR,
+            defaults.lockout_max_seconds,
+        ),
+    }
+}
+
+// Like the login thresholds, each password rule can be overridden on its own. The breached
+// passwords are read once, here, so a missing or malformed file stops the service at startup.
+fn set_password_policy() -> Arc<StandardPasswordPolicy> {
+    dotenv().ok();
+    let defaults = PasswordPolicySettings::default();
+    let settings = PasswordPolicySettings {
+        min_length: number_var(env::PASSWORD_MIN_LENGTH_ENV_VAR, defaults.min_length),
+        max_length: number_var(env::PASSWORD_MAX_LENGTH_ENV_VAR, defaults.max_length),
+        min_strength: number_var(env::PASSWORD_MIN_STRENGTH_ENV_VAR, defaults.min_strength),
+    };
+    if settings.min_strength > 4 {
+        panic!("PASSWORD_MIN_STRENGTH must be from 0 to 4.");
+    }
+    if settings.min_length > settings.max_length {
+        panic!("PASSWORD_MIN_LENGTH must not be more than PASSWORD_MAX_LENGTH.");
+    }
+    if settings.max_length > PASSWORD_LENGTH_LIMIT {
+        panic!(
+            "PASSWORD_MAX_LENGTH must not be more than {}.",
+            PASSWORD_LENGTH_LIMIT
+        );
+    }
+
+    let breached_passwords = std_env::var(env::BREACHED_PASSWORDS_PATH_ENV_VAR)
+        .ok()
+        .filter(|path| !path.is_empty())
+        .map(|path| {
+            BreachedPasswords::from_file(path)
+                .unwrap_or_else(|e| panic!("BREACHED_PASSWORDS_PATH must be valid: {:?}", e))
+        });
+
+    Arc::new(StandardPasswordPolicy::new(settings, breached_passwords))
+}
+
+// Hashes made with other parameters are replaced when their users next log in
+fn set_password_hash_params() -> argon2::Params {
+    dotenv().ok();
+    argon2::Params::new(
+        number_var(env::ARGON2_MEMORY_KIB_ENV_VAR, DEFAULT_ARGON2_MEMORY_KIB),
+        number_var(env::ARGON2_ITERATIONS_ENV_VAR, DEFAULT_ARGON2_ITERATIONS),
+        number_var(env::ARGON2_PARALLELISM_ENV_VAR, DEFAULT_ARGON2_PARALLELISM),
+        None,
+    )
+    .unwrap_or_else(|e| panic!("Argon2 parameters must be valid: {}", e))
+}
+
+// Peppers are listed in PASSWORD_PEPPERS as comma separated `id:secret` pairs, like introspection
+// clients. New hashes use the one named by PASSWORD_PEPPER_ID, the rest are kept so hashes made
+// with them can still be checked until they are upgraded.
+fn set_password_peppers() -> PasswordPeppers {
+    dotenv().ok();
+    let peppers = std_env::var(env::PASSWORD_PEPPERS_ENV_VAR).unwrap_or_default();
+    let peppers = peppers
+        .split(',')
+        .map(str::trim)
+        .filter(|pepper| !pepper.is_empty())
+        .map(|pepper| match pepper.split_once(':') {
+            Some((id, secret)) if !id.is_empty() && !secret.is_empty() => {
+                (id.to_owned(), Secret::new(secret.to_owned()))
+            }
+            _ => panic!("PASSWORD_PEPPERS must be a list of id:secret pairs."),
+        })
+        .collect();
+    let active_id = std_env::var(env::PASSWORD_PEPPER_ID_ENV_VAR)
+        .ok()
+        .filter(|id| !id.is_empty());
+
+    PasswordPeppers::new(active_id, peppers)
+        .unwrap_or_else(|e| panic!("PASSWORD_PEPPER_ID must be in PASSWORD_PEPPERS: {}", e))
+}
+
+fn number_var<T: std::str::FromStr>(name: &str, default: T) -> T {
+    match std_env::var(name) {
+        Ok(value) if !value.is_empty() => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} must be a whole number.", name)),
+        _ => default,
+    }
+}
+
 fn set_dburl() -> Secret<String> {
     dotenv().ok(); // Load environment variables
     let dburl = std_env::var(env::DATABASE_URL_ENV_VAR).unwrap_or_else(|_| {
@@ -55,11 +273,107 @@ fn set_postmark_auth_token() -> Secret<String> {
     )
 }
 
+// The default only suits tests and local development - see `require_auth_service_base_url`
+fn set_auth_service_base_url() -> String {
+    dotenv().ok();
+    std_env::var(env::AUTH_SERVICE_BASE_URL_ENV_VAR)
+        .ok()
+        .filter(|url| !url.is_empty())
+        .map(|url| url.trim_end_matches('/').to_owned())
+        .unwrap_or(DEFAULT_AUTH_SERVICE_BASE_URL.to_owned())
+}
+
+// Called at startup. Emailed links, passkeys and the JWT issuer all name the service's public
+// URL, so a deployment that didn't set it would quietly send everyone to localhost.
+pub fn require_auth_service_base_url() {
+    dotenv().ok();
+    if std_env::var(env::AUTH_SERVICE_BASE_URL_ENV_VAR)
+        .unwrap_or_default()
+        .is_empty()
+    {
+        panic!("AUTH_SERVICE_BASE_URL must be set.");
+    }
+    lazy_static::initialize(&JWT_ISSUER);
+    lazy_static::initialize(&WEBAUTHN_ORIGIN);
+    lazy_static::initialize(&WEBAUTHN_RP_ID);
+}
+
+fn set_totp_encryption_key() -> Secret<String> {
+    dotenv().ok(); // Load environment variables
+    let key = std_env::var(env::TOTP_ENCRYPTION_KEY_ENV_VAR).unwrap_or_else(|_| {
+        // For tests, provide a default test key
+        if cfg!(test) {
+            "test_totp_key_for_unit_tests_only".to_string()
+        } else {
+            panic!("TOTP_ENCRYPTION_KEY must be set.");
+        }
+    });
+    if key.is_empty() {
+        panic!("TOTP_ENCRYPTION_KEY must not be empty.");
+    }
+    Secret::new(key)
+}
+
+// Passkeys are bound to the site the auth service is served from, unless the pages that use
+// them are served from somewhere else
+fn set_webauthn_origin() -> String {
+    dotenv().ok();
+    std_env::var(env::WEBAUTHN_ORIGIN_ENV_VAR)
+        .ok()
+        .filter(|origin| !origin.is_empty())
+        .unwrap_or_else(|| auth_service_url().origin().ascii_serialization())
+}
+
+fn set_webauthn_rp_id() -> String {
+    dotenv().ok();
+    std_env::var(env::WEBAUTHN_RP_ID_ENV_VAR)
+        .ok()
+        .filter(|rp_id| !rp_id.is_empty())
+        .unwrap_or_else(|| {
+            auth_service_url()
+                .host_str()
+                .expect("AUTH_SERVICE_BASE_URL must have a host.")
+                .to_owned()
+        })
+}
+
+fn auth_service_url() -> url::Url {
+    url::Url::parse(&AUTH_SERVICE_BASE_URL).expect("AUTH_SERVICE_BASE_URL must be a valid URL.")
+}
+
 pub mod env {
     pub const JWT_SECRET_ENV_VAR: &str = "JWT_SECRET";
+    pub const JWT_SIGNING_KEY_PATH_ENV_VAR: &str = "JWT_SIGNING_KEY_PATH";
+    pub const JWT_KEY_ID_ENV_VAR: &str = "JWT_KEY_ID";
+    pub const JWT_KEY_RING_PATH_ENV_VAR: &str = "JWT_KEY_RING_PATH";
+    pub const JWT_ISSUER_ENV_VAR: &str = "JWT_ISSUER";
+    pub const JWT_AUDIENCE_ENV_VAR: &str = "JWT_AUDIENCE";
+    pub const JWT_LEEWAY_SECONDS_ENV_VAR: &str = "JWT_LEEWAY_SECONDS";
+    pub const INTROSPECTION_CLIENTS_ENV_VAR: &str = "INTROSPECTION_CLIENTS";
+    pub const ENUMERATION_SAFE_MODE_ENV_VAR: &str = "ENUMERATION_SAFE_MODE";
+    pub const LOGIN_IP_LIMIT_ENV_VAR: &str = "LOGIN_IP_LIMIT";
+    pub const LOGIN_IP_WINDOW_SECONDS_ENV_VAR: &str = "LOGIN_IP_WINDOW_SECONDS";
+    pub const LOGIN_ACCOUNT_LIMIT_ENV_VAR: &str = "LOGIN_ACCOUNT_LIMIT";
+    pub const LOGIN_ACCOUNT_WINDOW_SECONDS_ENV_VAR: &str = "LOGIN_ACCOUNT_WINDOW_SECONDS";
+    pub const LOGIN_LOCKOUT_THRESHOLD_ENV_VAR: &str = "LOGIN_LOCKOUT_THRESHOLD";
+    pub const LOGIN_LOCKOUT_BASE_SECONDS_ENV_VAR: &str = "LOGIN_LOCKOUT_BASE_SECONDS";
+    pub const LOGIN_LOCKOUT_MAX_SECONDS_ENV_VAR: &str = "LOGIN_LOCKOUT_MAX_SECONDS";
+    pub const PASSWORD_MIN_LENGTH_ENV_VAR: &str = "PASSWORD_MIN_LENGTH";
+    pub const PASSWORD_MAX_LENGTH_ENV_VAR: &str = "PASSWORD_MAX_LENGTH";
+    pub const PASSWORD_MIN_STRENGTH_ENV_VAR: &str = "PASSWORD_MIN_STRENGTH";
+    pub const BREACHED_PASSWORDS_PATH_ENV_VAR: &str = "BREACHED_PASSWORDS_PATH";
+    pub const ARGON2_MEMORY_KIB_ENV_VAR: &str = "ARGON2_MEMORY_KIB";
+    pub const ARGON2_ITERATIONS_ENV_VAR: &str = "ARGON2_ITERATIONS";
+    pub const ARGON2_PARALLELISM_ENV_VAR: &str = "ARGON2_PARALLELISM";
+    pub const PASSWORD_PEPPERS_ENV_VAR: &str = "PASSWORD_PEPPERS";
+    pub const PASSWORD_PEPPER_ID_ENV_VAR: &str = "PASSWORD_PEPPER_ID";
     pub const DATABASE_URL_ENV_VAR: &str = "DATABASE_URL";
     pub const REDIS_HOST_NAME_ENV_VAR: &str = "REDIS_HOST_NAME";
     pub const POSTMARK_AUTH_TOKEN_ENV_VAR: &str = "POSTMARK_AUTH_TOKEN";
+    pub const AUTH_SERVICE_BASE_URL_ENV_VAR: &str = "AUTH_SERVICE_BASE_URL";
+    pub const TOTP_ENCRYPTION_KEY_ENV_VAR: &str = "TOTP_ENCRYPTION_KEY";
+    pub const WEBAUTHN_ORIGIN_ENV_VAR: &str = "WEBAUTHN_ORIGIN";
+    pub const WEBAUTHN_RP_ID_ENV_VAR: &str = "WEBAUTHN_RP_ID";
 }
 
 pub mod prod {
@@ -85,4 +399,39 @@ pub mod test {
 }
 
 pub const JWT_COOKIE_NAME: &str = "jwt";
+pub const REFRESH_COOKIE_NAME: &str = "refresh_token";
 pub const DEFAULT_REDIS_HOSTNAME: &str = "127.0.0.1";
+pub const DEFAULT_AUTH_SERVICE_BASE_URL: &str = "http://localhost:3000";
+pub const DEFAULT_JWT_AUDIENCE: &str = "app-service";
+pub const DEFAULT_JWT_LEEWAY_SECONDS: u64 = 60;
+// Auth tokens grant full access to the user's account
+pub const AUTH_TOKEN_SCOPE: &str = "account";
+pub const DEFAULT_LOGIN_IP_LIMIT: u64 = 20; // per IP address, per window
+pub const DEFAULT_LOGIN_IP_WINDOW_SECONDS: u64 = 300; // 5 minutes
+pub const DEFAULT_LOGIN_ACCOUNT_LIMIT: u64 = 10; // per account, per window
+pub const DEFAULT_LOGIN_ACCOUNT_WINDOW_SECONDS: u64 = 300; // 5 minutes
+pub const DEFAULT_LOGIN_LOCKOUT_THRESHOLD: u64 = 5; // failed logins in a row
+pub const DEFAULT_LOGIN_LOCKOUT_BASE_SECONDS: u64 = 60; // 1 minute
+pub const DEFAULT_LOGIN_LOCKOUT_MAX_SECONDS: u64 = 3600; // 1 hour
+
+// Failed logins stop counting towards a lockout once there has been none for this long
+pub const LOGIN_FAILURES_TTL_SECONDS: u64 = 86400; // 24 hours
+pub const MAX_TWO_FA_ATTEMPTS: u64 = 5; // wrong codes per login attempt
+pub const DEFAULT_PASSWORD_MIN_LENGTH: usize = 8;
+pub const DEFAULT_PASSWORD_MAX_LENGTH: usize = 128;
+pub const PASSWORD_LENGTH_LIMIT: usize = 1024; // whatever PASSWORD_MAX_LENGTH is, so hashing stays cheap
+pub const DEFAULT_PASSWORD_MIN_STRENGTH: u8 = 2; // from 0 to 4
+pub const DEFAULT_ARGON2_MEMORY_KIB: u32 = 19456; // 19 MiB, the OWASP minimum
+pub const DEFAULT_ARGON2_ITERATIONS: u32 = 2;
+pub const DEFAULT_ARGON2_PARALLELISM: u32 = 1;
+pub const PASSWORD_RESET_TOKEN_TTL_SECONDS: u64 = 1800; // 30 minutes
+pub const EMAIL_VERIFICATION_TOKEN_TTL_SECONDS: u64 = 86400; // 24 hours
+pub const EMAIL_CHANGE_TOKEN_TTL_SECONDS: u64 = 86400; // 24 hours
+pub const VERIFICATION_EMAIL_LIMIT: u64 = 5; // per user, per window
+pub const VERIFICATION_EMAIL_WINDOW_SECONDS: u64 = 3600; // 1 hour
+pub const TOTP_ISSUER: &str = "auth-service";
+pub const TOTP_STEP_SECONDS: u64 = 30;
+pub const TOTP_DIGITS: usize = 6;
+pub const RECOVERY_CODE_COUNT: usize = 10;
+pub const WEBAUTHN_RP_NAME: &str = "auth-service";
+pub const WEBAUTHN_CHALLENGE_TTL_SECONDS: u64 = 300; // 5 minutes
@@ -0,0 +1,478 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL, Engine};
+use color_eyre::eyre::{eyre, Context, Result};
+use jsonwebtoken::{
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+    Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use ring::signature::{Ed25519KeyPair, KeyPair, RsaKeyPair, RsaPublicKeyComponents};
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use thiserror::Error;
+
+// The key auth tokens are signed and verified with. Asymmetric keys are identified by a `kid`
+// in the token header and published in the JWKS, so other services can verify tokens without
+// knowing anything secret.
+pub struct JwtSigningKey {
+    algorithm: Algorithm,
+    kid: Option<String>,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    jwk: Option<Jwk>,
+}
+
+impl std::fmt::Debug for JwtSigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JwtSigningKey")
+            .field("algorithm", &self.algorithm)
+            .field("kid", &self.kid)
+            .finish_non_exhaustive()
+    }
+}
+
+impl JwtSigningKey {
+    // HS256 with the shared `JWT_SECRET` - used when no signing key file is configured
+    pub fn from_secret(secret: &Secret<String>) -> Self {
+        let secret = secret.expose_secret().as_bytes();
+        Self {
+            algorithm: Algorithm::HS256,
+            kid: None,
+            encoding_key: EncodingKey::from_secret(secret),
+            decoding_key: DecodingKey::from_secret(secret),
+            jwk: None,
+        }
+    }
+
+    // Load an RSA (PKCS#1 or PKCS#8) or Ed25519 (PKCS#8) private key. Without an explicit `kid`
+    // the key's RFC 7638 thumbprint is used, so it stays the same across restarts.
+    pub fn from_pem(pem: &[u8], kid: Option<String>) -> Result<Self> {
+        let parsed = pem::parse(pem).wrap_err("failed to parse signing key PEM")?;
+
+        let (algorithm, encoding_key, decoding_key, algorithm_parameters) = match parsed.tag() {
+            "RSA PRIVATE KEY" => rsa_key(pem, RsaKeyPair::from_der(parsed.contents()))?,
+            "PRIVATE KEY" => match RsaKeyPair::from_pkcs8(parsed.contents()) {
+                Ok(key_pair) => rsa_key(pem, Ok(key_pair))?,
+                Err(_) => ed25519_key(pem, parsed.contents())?,
+            },
+            tag => return Err(eyre!("unsupported signing key PEM type: {}", tag)),
+        };
+
+        let kid = match kid {
+            Some(kid) => kid,
+            None => thumbprint(&algorithm_parameters),
+        };
+
+        let jwk = Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(key_algorithm(algorithm)),
+                key_id: Some(kid.clone()),
+                ..Default::default()
+            },
+            algorithm: algorithm_parameters,
+        };
+
+        Ok(Self {
+            algorithm,
+            kid: Some(kid),
+            encoding_key,
+            decoding_key,
+            jwk: Some(jwk),
+        })
+    }
+
+    pub fn from_pem_file(path: impl AsRef<Path>, kid: Option<String>) -> Result<Self> {
+        let path = path.as_ref();
+        let pem = std::fs::read(path).wrap_err(format!(
+            "failed to read signing key from {}",
+            path.display()
+        ))?;
+        Self::from_pem(&pem, kid)
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn kid(&self) -> Option<&str> {
+        self.kid.as_deref()
+    }
+
+    pub fn encoding_key(&self) -> &EncodingKey {
+        &self.encoding_key
+    }
+
+    pub fn decoding_key(&self) -> &DecodingKey {
+        &self.decoding_key
+    }
+
+    // The header for tokens signed with this key
+    pub fn header(&self) -> Header {
+        Header {
+            kid: self.kid.clone(),
+            ..Header::new(self.algorithm)
+        }
+    }
+
+    // Only accept tokens signed with this key's algorithm
+    pub fn validation(&self) -> Validation {
+        Validation::new(self.algorithm)
+    }
+
+    // The public keys to publish at `/.well-known/jwks.json` - empty for a shared secret
+    pub fn jwk_set(&self) -> JwkSet {
+        JwkSet {
+            keys: self.jwk.iter().cloned().collect(),
+        }
+    }
+}
+
+// The keys tokens are signed and verified with. New tokens are signed with the active key, while
+// tokens signed with any key in the ring are accepted, so a new key can be made active without
+// invalidating live sessions. Keys are rotated by editing the key ring file and reloading it -
+// an old key has to stay in the file until the last token it signed has expired, which is
+// `TOKEN_TTL_SECONDS` plus `JWT_LEEWAY_SECONDS` after it stopped being active. Refresh tokens
+// aren't signed, so they outlive any rotation.
+#[derive(Debug)]
+pub struct JwtKeyRing {
+    active: Arc<JwtSigningKey>,
+    verification_keys: Vec<Arc<JwtSigningKey>>,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum JwtKeyRingError {
+    #[error("Unknown signing key id: {0}")]
+    UnknownKeyId(String),
+    #[error("Token has no signing key id")]
+    MissingKeyId,
+    #[error("Duplicate signing key id: {0}")]
+    DuplicateKeyId(String),
+}
+
+// A key ring file lists every key by `kid` and names the active one, e.g.
+// `{ "active": "2026-10", "keys": { "2026-10": "2026-10.pem", "2026-04": "2026-04.pem" } }`.
+// Relative paths are resolved against the file's directory.
+#[derive(Debug, Deserialize)]
+struct KeyRingManifest {
+    active: String,
+    keys: BTreeMap<String, PathBuf>,
+}
+
+impl JwtKeyRing {
+    pub fn new(active: JwtSigningKey) -> Self {
+        Self {
+            active: Arc::new(active),
+            verification_keys: Vec::new(),
+        }
+    }
+
+    pub fn from_manifest_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let manifest = std::fs::read(path)
+            .wrap_err(format!("failed to read key ring from {}", path.display()))?;
+        let manifest: KeyRingManifest =
+            serde_json::from_slice(&manifest).wrap_err("failed to parse key ring")?;
+        let directory = path.parent().unwrap_or(Path::new("."));
+
+        let mut keys = BTreeMap::new();
+        for (kid, key_path) in manifest.keys {
+            let key = JwtSigningKey::from_pem_file(directory.join(key_path), Some(kid.clone()))
+                .wrap_err(format!("failed to load signing key {}", kid))?;
+            keys.insert(kid, key);
+        }
+
+        let active = keys
+            .remove(&manifest.active)
+            .ok_or(JwtKeyRingError::UnknownKeyId(manifest.active))?;
+
+        let mut key_ring = Self::new(active);
+        for key in keys.into_values() {
+            key_ring.add_verification_key(key)?;
+        }
+        Ok(key_ring)
+    }
+
+    // The key new tokens are signed with
+    pub fn active_key(&self) -> Arc<JwtSigningKey> {
+        self.active.clone()
+    }
+
+    // Add a key that tokens are accepted from, but not signed with
+    pub fn add_verification_key(&mut self, key: JwtSigningKey) -> Result<(), JwtKeyRingError> {
+        let kid = key.kid().ok_or(JwtKeyRingError::MissingKeyId)?;
+        if self.find_key(Some(kid)).is_ok() {
+            return Err(JwtKeyRingError::DuplicateKeyId(kid.to_owned()));
+        }
+        self.verification_keys.push(Arc::new(key));
+        Ok(())
+    }
+
+    // Find the key a token was signed with from the `kid` in its header
+    pub fn find_key(&self, kid: Option<&str>) -> Result<Arc<JwtSigningKey>, JwtKeyRingError> {
+        if self.active.kid() == kid {
+            return Ok(self.active.clone());
+        }
+
+        let kid = kid.ok_or(JwtKeyRingError::MissingKeyId)?;
+        self.verification_keys
+            .iter()
+            .find(|key| key.kid() == Some(kid))
+            .cloned()
+            .ok_or(JwtKeyRingError::UnknownKeyId(kid.to_owned()))
+    }
+
+    // The public keys of every key in the ring, active key first
+    pub fn jwk_set(&self) -> JwkSet {
+        JwkSet {
+            keys: std::iter::once(&self.active)
+                .chain(&self.verification_keys)
+                .flat_map(|key| key.jwk_set().keys)
+                .collect(),
+        }
+    }
+}
+
+type LoadedKey = (Algorithm, EncodingKey, DecodingKey, AlgorithmParameters);
+
+fn rsa_key(
+    pem: &[u8],
+    key_pair: Result<RsaKeyPair, ring::error::KeyRejected>,
+) -> Result<LoadedKey> {
+    let key_pair = key_pair.map_err(|e| eyre!("invalid RSA signing key: {}", e))?;
+    let public_key: RsaPublicKeyComponents<Vec<u8>> = key_pair.public().into();
+
+    Ok((
+        Algorithm::RS256,
+        EncodingKey::from_rsa_pem(pem).wrap_err("invalid RSA signing key")?,
+        DecodingKey::from_rsa_raw_components(&public_key.n, &public_key.e),
+        AlgorithmParameters::RSA(RSAKeyParameters {
+            key_type: RSAKeyType::RSA,
+            n: BASE64_URL.encode(&public_key.n),
+            e: BASE64_URL.encode(&public_key.e),
+        }),
+    ))
+}
+
+fn ed25519_key(pem: &[u8], der: &[u8]) -> Result<LoadedKey> {
+    let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(der)
+        .map_err(|_| eyre!("signing key is neither an RSA nor an Ed25519 key"))?;
+    let x = BASE64_URL.encode(key_pair.public_key().as_ref());
+
+    Ok((
+        Algorithm::EdDSA,
+        EncodingKey::from_ed_pem(pem).wrap_err("invalid Ed25519 signing key")?,
+        DecodingKey::from_ed_components(&x).wrap_err("invalid Ed25519 public key")?,
+        AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+            key_type: OctetKeyPairType::OctetKeyPair,
+            curve: EllipticCurve::Ed25519,
+            x,
+        }),
+    ))
+}
+
+fn key_algorithm(algorithm: Algorithm) -> KeyAlgorithm {
+    match algorithm {
+        Algorithm::EdDSA => KeyAlgorithm::EdDSA,
+        _ => KeyAlgorithm::RS256,
+    }
+}
+
+// RFC 7638 JWK thumbprint: the SHA-256 of the required public members in lexicographic order
+fn thumbprint(parameters: &AlgorithmParameters) -> String {
+    let canonical = match parameters {
+        AlgorithmParameters::RSA(rsa) => {
+            format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, rsa.e, rsa.n)
+        }
+        AlgorithmParameters::OctetKeyPair(okp) => {
+            format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, okp.x)
+        }
+        _ => unreachable!("only RSA and Ed25519 signing keys are loaded"),
+    };
+    BASE64_URL.encode(Sha256::digest(canonical.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::{decode, decode_header, encode};
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    const RSA_PEM: &[u8] = include_bytes!("../../tests/fixtures/jwt_rsa.pem");
+    const RSA_PKCS1_PEM: &[u8] = include_bytes!("../../tests/fixtures/jwt_rsa_pkcs1.pem");
+    const ED25519_PEM: &[u8] = include_bytes!("../../tests/fixtures/jwt_ed25519.pem");
+    const EC_PEM: &[u8] = include_bytes!("../../tests/fixtures/jwt_ec.pem");
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct TestClaims {
+        sub: String,
+        exp: usize,
+    }
+
+    fn claims() -> TestClaims {
+        TestClaims {
+            sub: "test@example.com".to_owned(),
+            exp: 4_102_444_800, // 2100-01-01
+        }
+    }
+
+    // Sign with the key, then verify through its published JWK like a downstream service would
+    fn round_trip(key: &JwtSigningKey) {
+        let token = encode(&key.header(), &claims(), key.encoding_key()).unwrap();
+
+        let header = decode_header(&token).unwrap();
+        assert_eq!(header.alg, key.algorithm());
+        let kid = header.kid.unwrap();
+        assert_eq!(Some(kid.as_str()), key.kid());
+
+        let jwks = key.jwk_set();
+        let jwk = jwks.find(&kid).unwrap();
+        let decoding_key = DecodingKey::from_jwk(jwk).unwrap();
+        let decoded = decode::<TestClaims>(&token, &decoding_key, &key.validation()).unwrap();
+        assert_eq!(decoded.claims, claims());
+    }
+
+    #[test]
+    fn test_rsa_key_signs_rs256() {
+        let key = JwtSigningKey::from_pem(RSA_PEM, None).unwrap();
+        assert_eq!(key.algorithm(), Algorithm::RS256);
+        round_trip(&key);
+    }
+
+    #[test]
+    fn test_ed25519_key_signs_eddsa() {
+        let key = JwtSigningKey::from_pem(ED25519_PEM, None).unwrap();
+        assert_eq!(key.algorithm(), Algorithm::EdDSA);
+        round_trip(&key);
+    }
+
+    #[test]
+    fn test_kid_is_thumbprint_of_public_key() {
+        let pkcs8 = JwtSigningKey::from_pem(RSA_PEM, None).unwrap();
+        let pkcs1 = JwtSigningKey::from_pem(RSA_PKCS1_PEM, None).unwrap();
+        assert_eq!(pkcs8.kid(), pkcs1.kid());
+
+        let ed25519 = JwtSigningKey::from_pem(ED25519_PEM, None).unwrap();
+        assert_ne!(pkcs8.kid(), ed25519.kid());
+
+        let named = JwtSigningKey::from_pem(RSA_PEM, Some("2026-10".to_owned())).unwrap();
+        assert_eq!(named.kid(), Some("2026-10"));
+        assert_eq!(named.header().kid.as_deref(), Some("2026-10"));
+    }
+
+    #[test]
+    fn test_jwk_set_only_has_public_key() {
+        let key = JwtSigningKey::from_pem(RSA_PEM, None).unwrap();
+        let jwks = serde_json::to_value(key.jwk_set()).unwrap();
+        let jwk = &jwks["keys"][0];
+        assert_eq!(jwk["kty"], "RSA");
+        assert_eq!(jwk["alg"], "RS256");
+        assert_eq!(jwk["use"], "sig");
+        assert!(jwk.get("d").is_none());
+
+        let secret = JwtSigningKey::from_secret(&Secret::new("secret".to_owned()));
+        assert!(secret.jwk_set().keys.is_empty());
+        assert_eq!(secret.header().kid, None);
+    }
+
+    #[test]
+    fn test_token_from_another_key_is_rejected() {
+        let rsa = JwtSigningKey::from_pem(RSA_PEM, None).unwrap();
+        let ed25519 = JwtSigningKey::from_pem(ED25519_PEM, None).unwrap();
+        let token = encode(&rsa.header(), &claims(), rsa.encoding_key()).unwrap();
+        assert!(
+            decode::<TestClaims>(&token, ed25519.decoding_key(), &ed25519.validation()).is_err()
+        );
+    }
+
+    fn key(pem: &[u8], kid: &str) -> JwtSigningKey {
+        JwtSigningKey::from_pem(pem, Some(kid.to_owned())).unwrap()
+    }
+
+    fn sign(key: &JwtSigningKey) -> String {
+        encode(&key.header(), &claims(), key.encoding_key()).unwrap()
+    }
+
+    fn verify(key_ring: &JwtKeyRing, token: &str) -> Result<TestClaims> {
+        let header = decode_header(token)?;
+        let key = key_ring.find_key(header.kid.as_deref())?;
+        Ok(decode::<TestClaims>(token, key.decoding_key(), &key.validation())?.claims)
+    }
+
+    #[test]
+    fn test_verification_key_still_verifies() {
+        let mut key_ring = JwtKeyRing::new(key(RSA_PEM, "new"));
+        let old_key = key(ED25519_PEM, "old");
+        let old_token = sign(&old_key);
+        key_ring.add_verification_key(old_key).unwrap();
+        let new_token = sign(&key_ring.active_key());
+
+        assert_eq!(verify(&key_ring, &old_token).unwrap(), claims());
+        assert_eq!(verify(&key_ring, &new_token).unwrap(), claims());
+
+        // Both keys are published, the active one first
+        let kids: Vec<_> = key_ring
+            .jwk_set()
+            .keys
+            .into_iter()
+            .map(|jwk| jwk.common.key_id.unwrap())
+            .collect();
+        assert_eq!(kids, ["new", "old"]);
+    }
+
+    #[test]
+    fn test_unknown_and_missing_kids_are_rejected() {
+        let mut key_ring = JwtKeyRing::new(key(ED25519_PEM, "current"));
+        assert_eq!(
+            key_ring.find_key(Some("other")).unwrap_err(),
+            JwtKeyRingError::UnknownKeyId("other".to_owned())
+        );
+        assert_eq!(
+            key_ring.find_key(None).unwrap_err(),
+            JwtKeyRingError::MissingKeyId
+        );
+        assert_eq!(
+            key_ring.add_verification_key(key(RSA_PEM, "current")),
+            Err(JwtKeyRingError::DuplicateKeyId("current".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_key_ring_from_manifest_file() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let key_ring = JwtKeyRing::from_manifest_file(fixtures.join("jwt_key_ring.json")).unwrap();
+        assert_eq!(key_ring.active_key().kid(), Some("2026-10"));
+        assert_eq!(key_ring.active_key().algorithm(), Algorithm::EdDSA);
+        assert_eq!(
+            key_ring.find_key(Some("2026-04")).unwrap().algorithm(),
+            Algorithm::RS256
+        );
+
+        // The active key has to be one of the listed keys
+        let manifest = std::env::temp_dir().join(format!("{}.json", uuid::Uuid::new_v4()));
+        let rsa_path = fixtures.join("jwt_rsa.pem");
+        std::fs::write(
+            &manifest,
+            serde_json::json!({ "active": "missing", "keys": { "2026-04": rsa_path } }).to_string(),
+        )
+        .unwrap();
+        let result = JwtKeyRing::from_manifest_file(&manifest);
+        std::fs::remove_file(&manifest).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_unsupported_keys_are_rejected() {
+        assert!(JwtSigningKey::from_pem(EC_PEM, None).is_err());
+        assert!(JwtSigningKey::from_pem(b"not a pem", None).is_err());
+        assert!(JwtSigningKey::from_pem_file("does/not/exist.pem", None).is_err());
+    }
+}
@@ -0,0 +1,328 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use argon2::{
+    password_hash::{rand_core::OsRng, SaltString},
+    Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use color_eyre::eyre::{eyre, Context, Result};
+use pbkdf2::Pbkdf2;
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+
+// The least memory and iterations worth using for Argon2id, as recommended by OWASP
+const MIN_ARGON2_MEMORY_KIB: u32 = 19456; // 19 MiB
+const MIN_ARGON2_ITERATIONS: u32 = 2;
+const MAX_ARGON2_MEMORY_KIB: u32 = 4 * 1024 * 1024; // 4 GiB
+
+// Secrets mixed into password hashes, as Argon2's secret parameter, so a leaked database can't be
+// cracked without them as well. Each hash is stored with the ID of the pepper it was made with, so
+// peppers can be rotated: new hashes use the active pepper, older ones are checked with theirs and
+// upgraded on login. With no active pepper, new hashes are only salted.
+#[derive(Clone, Default)]
+pub struct PasswordPeppers {
+    active_id: Option<String>,
+    peppers: HashMap<String, Secret<String>>,
+}
+
+impl std::fmt::Debug for PasswordPeppers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PasswordPeppers")
+            .field("active_id", &self.active_id)
+            .field("ids", &self.peppers.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl PasswordPeppers {
+    pub fn new(
+        active_id: Option<String>,
+        peppers: HashMap<String, Secret<String>>,
+    ) -> Result<Self> {
+        if let Some(active_id) = &active_id {
+            if !peppers.contains_key(active_id) {
+                return Err(eyre!("no pepper with the active ID {}", active_id));
+            }
+        }
+        Ok(Self { active_id, peppers })
+    }
+
+    // The ID and secret new hashes are made with, if any
+    pub fn active(&self) -> Option<(&str, &Secret<String>)> {
+        let active_id = self.active_id.as_deref()?;
+        Some((active_id, &self.peppers[active_id]))
+    }
+
+    pub fn active_id(&self) -> Option<&str> {
+        self.active_id.as_deref()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Secret<String>> {
+        self.peppers.get(id)
+    }
+}
+
+// The formats password hashes can be in. Only Argon2 hashes are made here - the others are
+// imported from older systems, and replaced with Argon2id when their users next log in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PasswordHashFormat {
+    Argon2,
+    Bcrypt,
+    // `$pbkdf2-sha256$i=...,l=...$salt$hash` PHC strings
+    Pbkdf2Sha256,
+    // `pbkdf2_sha256$iterations$salt$hash`, as made by Django
+    DjangoPbkdf2Sha256,
+}
+
+impl PasswordHashFormat {
+    pub fn detect(password_hash: &str) -> Option<Self> {
+        if password_hash.starts_with("$argon2") {
+            Some(Self::Argon2)
+        } else if ["$2a$", "$2b$", "$2x$", "$2y$"]
+            .iter()
+            .any(|prefix| password_hash.starts_with(prefix))
+        {
+            Some(Self::Bcrypt)
+        } else if password_hash.starts_with("$pbkdf2-sha256$") {
+            Some(Self::Pbkdf2Sha256)
+        } else if password_hash.starts_with("pbkdf2_sha256$") {
+            Some(Self::DjangoPbkdf2Sha256)
+        } else {
+            None
+        }
+    }
+}
+
+// Check a password against a hash imported from an older system. These were never peppered.
+pub fn verify_legacy_password_hash(password_hash: &str, password_candidate: &str) -> Result<()> {
+    let matches = match PasswordHashFormat::detect(password_hash) {
+        Some(PasswordHashFormat::Bcrypt) => bcrypt::verify(password_candidate, password_hash)
+            .wrap_err("failed to verify bcrypt hash")?,
+        Some(PasswordHashFormat::Pbkdf2Sha256) => Pbkdf2
+            .verify_password(
+                password_candidate.as_bytes(),
+                &PasswordHash::new(password_hash)?,
+            )
+            .is_ok(),
+        Some(PasswordHashFormat::DjangoPbkdf2Sha256) => {
+            verify_django_pbkdf2_sha256(password_hash, password_candidate)?
+        }
+        Some(PasswordHashFormat::Argon2) | None => {
+            return Err(eyre!("not a legacy password hash"));
+        }
+    };
+
+    match matches {
+        true => Ok(()),
+        false => Err(eyre!("failed to verify password hash")),
+    }
+}
+
+fn verify_django_pbkdf2_sha256(password_hash: &str, password_candidate: &str) -> Result<bool> {
+    let [_, iterations, salt, expected] = password_hash.splitn(4, '$').collect::<Vec<_>>()[..]
+    else {
+        return Err(eyre!("invalid PBKDF2-SHA256 hash"));
+    };
+    let iterations: u32 = iterations
+        .parse()
+        .wrap_err("invalid PBKDF2-SHA256 iterations")?;
+    let expected = STANDARD
+        .decode(expected)
+        .wrap_err("invalid PBKDF2-SHA256 hash")?;
+
+    let mut derived = vec![0; expected.len()];
+    pbkdf2::pbkdf2_hmac::<Sha256>(
+        password_candidate.as_bytes(),
+        salt.as_bytes(),
+        iterations,
+        &mut derived,
+    );
+
+    Ok(derived.ct_eq(&expected).into())
+}
+
+// Whether a hash was made with anything other than Argon2id and the given parameters, so should
+// be replaced the next time the password is known
+pub fn needs_rehash(password_hash: &Secret<String>, params: &Params) -> bool {
+    let Ok(password_hash) = PasswordHash::new(password_hash.expose_secret()) else {
+        return true;
+    };
+    if password_hash.algorithm != Algorithm::Argon2id.ident()
+        || password_hash.version != Some(Version::V0x13.into())
+    {
+        return true;
+    }
+
+    match Params::try_from(&password_hash) {
+        Ok(hash_params) => {
+            hash_params.m_cost() != params.m_cost()
+                || hash_params.t_cost() != params.t_cost()
+                || hash_params.p_cost() != params.p_cost()
+        }
+        Err(_) => true,
+    }
+}
+
+// Find the strongest Argon2id parameters that hash a password in about `target` on this host.
+// Memory is the better defence against cracking on GPUs, so it is doubled for as long as hashing
+// stays within the target, then iterations are added. Returns the parameters and how long they
+// took - the OWASP minimum is returned even if it takes longer than the target.
+pub fn recommend_argon2_params(target: Duration, parallelism: u32) -> Result<(Params, Duration)> {
+    let params = Params::new(
+        MIN_ARGON2_MEMORY_KIB,
+        MIN_ARGON2_ITERATIONS,
+        parallelism,
+        None,
+    )?;
+    let mut best = (params.clone(), time_hash(&params)?);
+
+    while best.1 < target && best.0.m_cost() < MAX_ARGON2_MEMORY_KIB {
+        let params = Params::new(best.0.m_cost() * 2, best.0.t_cost(), parallelism, None)?;
+        let elapsed = time_hash(&params)?;
+        if elapsed > target {
+            break;
+        }
+        best = (params, elapsed);
+    }
+
+    while best.1 < target {
+        let params = Params::new(best.0.m_cost(), best.0.t_cost() + 1, parallelism, None)?;
+        let elapsed = time_hash(&params)?;
+        if elapsed > target {
+            break;
+        }
+        best = (params, elapsed);
+    }
+
+    Ok(best)
+}
+
+// The fastest of a few runs, so other work on the host doesn't skew the result as much
+fn time_hash(params: &Params) -> Result<Duration> {
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone());
+    let salt = SaltString::generate(&mut OsRng);
+    let mut fastest = Duration::MAX;
+
+    for _ in 0..3 {
+        let start = Instant::now();
+        argon2.hash_password(b"benchmark password", &salt)?;
+        fastest = fastest.min(start.elapsed());
+    }
+
+    Ok(fastest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(params: Params) -> Secret<String> {
+        let salt = SaltString::generate(&mut OsRng);
+        let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password(b"password123", &salt)
+            .unwrap();
+        Secret::new(password_hash.to_string())
+    }
+
+    #[test]
+    fn hashes_with_current_params_are_kept() {
+        let params = Params::new(8192, 2, 1, None).unwrap();
+        assert!(!needs_rehash(&hash(params.clone()), &params));
+    }
+
+    #[test]
+    fn hashes_with_outdated_params_need_rehashing() {
+        let params = Params::new(8192, 2, 1, None).unwrap();
+
+        for outdated in [
+            Params::new(4096, 2, 1, None).unwrap(),
+            Params::new(8192, 1, 1, None).unwrap(),
+            Params::new(8192, 2, 2, None).unwrap(),
+        ] {
+            assert!(needs_rehash(&hash(outdated), &params));
+        }
+    }
+
+    #[test]
+    fn other_algorithms_need_rehashing() {
+        let params = Params::new(8192, 2, 1, None).unwrap();
+        let salt = SaltString::generate(&mut OsRng);
+        let argon2i = Argon2::new(Algorithm::Argon2i, Version::V0x13, params.clone())
+            .hash_password(b"password123", &salt)
+            .unwrap()
+            .to_string();
+
+        assert!(needs_rehash(&Secret::new(argon2i), &params));
+        assert!(needs_rehash(&Secret::new("not a hash".to_owned()), &params));
+    }
+
+    #[test]
+    fn legacy_hashes_are_verified() {
+        let bcrypt_hash = bcrypt::hash("password123", 4).unwrap();
+        let pbkdf2_hash = Pbkdf2
+            .hash_password_customized(
+                b"password123",
+                None,
+                None,
+                pbkdf2::Params {
+                    rounds: 1000,
+                    output_length: 32,
+                },
+                &SaltString::generate(&mut OsRng),
+            )
+            .unwrap()
+            .to_string();
+        let django_hash = "pbkdf2_sha256$1000$seasalt$DKtn4wN1JA5g5IiTPMBbOfQEYX4cfOdbEPpqC26lBfU=";
+
+        for (password_hash, format) in [
+            (bcrypt_hash.as_str(), PasswordHashFormat::Bcrypt),
+            (pbkdf2_hash.as_str(), PasswordHashFormat::Pbkdf2Sha256),
+            (django_hash, PasswordHashFormat::DjangoPbkdf2Sha256),
+        ] {
+            assert_eq!(PasswordHashFormat::detect(password_hash), Some(format));
+            assert!(verify_legacy_password_hash(password_hash, "password123").is_ok());
+            assert!(verify_legacy_password_hash(password_hash, "password1234").is_err());
+            assert!(needs_rehash(
+                &Secret::new(password_hash.to_owned()),
+                &Params::default()
+            ));
+        }
+    }
+
+    #[test]
+    fn unknown_hash_formats_are_not_verified() {
+        assert_eq!(
+            PasswordHashFormat::detect("5f4dcc3b5aa765d61d8327deb882cf99"),
+            None
+        );
+        assert!(
+            verify_legacy_password_hash("5f4dcc3b5aa765d61d8327deb882cf99", "password").is_err()
+        );
+    }
+
+    #[test]
+    fn active_pepper_must_exist() {
+        let peppers = HashMap::from([("2026-10".to_owned(), Secret::new("pepper".to_owned()))]);
+
+        let active = PasswordPeppers::new(Some("2026-10".to_owned()), peppers.clone()).unwrap();
+        assert_eq!(active.active().map(|(id, _)| id), Some("2026-10"));
+
+        let inactive = PasswordPeppers::new(None, peppers.clone()).unwrap();
+        assert!(inactive.active().is_none());
+        assert!(inactive.get("2026-10").is_some());
+
+        assert!(PasswordPeppers::new(Some("2026-04".to_owned()), peppers).is_err());
+    }
+
+    #[test]
+    fn recommends_the_minimum_for_an_unreachable_target() {
+        let (params, _) = recommend_argon2_params(Duration::ZERO, 1).unwrap();
+        assert_eq!(params.m_cost(), MIN_ARGON2_MEMORY_KIB);
+        assert_eq!(params.t_cost(), MIN_ARGON2_ITERATIONS);
+        assert_eq!(params.p_cost(), 1);
+    }
+}
@@ -0,0 +1,227 @@
+// A rough estimate of how many guesses it would take to crack a password, scored from 0 (too
+// guessable) to 4 (very unguessable) on the same scale as zxcvbn. Like zxcvbn, dictionary words,
+// repeats, sequences and keyboard runs count for much less than random characters.
+
+// Roughly how many guesses an attacker would try before a word from a password list
+const DICTIONARY_LOG10_GUESSES: f64 = 3.0;
+
+// The most common passwords and words found in them, lowercased
+#[rustfmt::skip]
+const COMMON_WORDS: &[&str] = &[
+    "password", "qwerty", "letmein", "welcome", "admin", "administrator", "login",
+    "dragon", "monkey", "football", "baseball", "soccer", "hockey", "iloveyou", "sunshine",
+    "princess", "master", "shadow", "superman", "batman", "trustno1", "hello", "freedom",
+    "whatever", "starwars", "secret", "summer", "winter", "spring", "autumn", "love", "charlie",
+    "michael", "jessica", "ashley", "jordan", "hunter", "ranger", "buster", "thomas", "robert",
+    "daniel", "andrew", "joshua", "matthew", "jennifer", "pepper", "cheese", "cookie", "computer",
+    "internet", "changeme", "default", "access", "flower", "killer", "mustang", "maggie", "ginger",
+    "banana", "orange", "purple", "silver", "golden", "diamond", "tigger", "cowboy", "angel",
+    "blink", "test", "guest", "user", "root", "pass", "abc123", "qazwsx", "zaq1",
+];
+
+// Characters that are next to each other on a keyboard or in a common sequence
+const SEQUENCES: &[&str] = &[
+    "abcdefghijklmnopqrstuvwxyz",
+    "0123456789",
+    "qwertyuiop",
+    "asdfghjkl",
+    "zxcvbnm",
+    "!@#$%^&*()",
+];
+
+pub fn strength_score(password: &str, user_inputs: &[&str]) -> u8 {
+    match log10_guesses(password, user_inputs) {
+        guesses if guesses < 3.0 => 0,
+        guesses if guesses < 6.0 => 1,
+        guesses if guesses < 8.0 => 2,
+        guesses if guesses < 10.0 => 3,
+        _ => 4,
+    }
+}
+
+fn log10_guesses(password: &str, user_inputs: &[&str]) -> f64 {
+    let chars: Vec<char> = password.chars().collect();
+    let cardinality = cardinality(&chars);
+    estimate(&chars, cardinality, user_inputs)
+}
+
+// Take out the longest dictionary word and estimate what is left either side of it, unless
+// guessing it all one character at a time would be quicker
+fn estimate(chars: &[char], cardinality: f64, user_inputs: &[&str]) -> f64 {
+    if chars.is_empty() {
+        return 0.0;
+    }
+    let brute_force = brute_force(chars, cardinality);
+
+    let normalized: Vec<char> = chars.iter().map(|c| unleet(*c)).collect();
+    let words = COMMON_WORDS
+        .iter()
+        .copied()
+        .chain(user_inputs.iter().copied())
+        .filter(|word| word.chars().count() >= 3);
+
+    let longest = words
+        .filter_map(|word| {
+            let word: Vec<char> = word.chars().map(unleet).collect();
+            find(&normalized, &word).map(|start| (start, word.len()))
+        })
+        .max_by_key(|(_, len)| *len);
+
+    match longest {
+        Some((start, len)) => {
+            let word = &chars[start..start + len];
+            // Capitals and substituted characters make a word a little harder to guess
+            let variations = if word.iter().any(|c| !c.is_ascii_lowercase()) {
+                0.5
+            } else {
+                0.0
+            };
+            let dictionary = estimate(&chars[..start], cardinality, user_inputs)
+                + DICTIONARY_LOG10_GUESSES
+                + variations
+                + estimate(&chars[start + len..], cardinality, user_inputs);
+            dictionary.min(brute_force)
+        }
+        None => brute_force,
+    }
+}
+
+// Runs of repeated or sequential characters cost about as much as their first character,
+// everything else has to be guessed one character at a time
+fn brute_force(chars: &[char], cardinality: f64) -> f64 {
+    let mut guesses = 0.0;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let run = run_length(&chars[i..]);
+        if run >= 3 {
+            guesses += cardinality.log10() + (run as f64).log10();
+            i += run;
+        } else {
+            guesses += cardinality.log10();
+            i += 1;
+        }
+    }
+
+    guesses
+}
+
+fn run_length(chars: &[char]) -> usize {
+    let lower: Vec<char> = chars.iter().map(|c| c.to_ascii_lowercase()).collect();
+
+    let repeated = lower.iter().take_while(|c| **c == lower[0]).count();
+    let sequential = SEQUENCES
+        .iter()
+        .map(|sequence| {
+            let forward: Vec<char> = sequence.chars().collect();
+            let backward: Vec<char> = sequence.chars().rev().collect();
+            sequence_length(&lower, &forward).max(sequence_length(&lower, &backward))
+        })
+        .max()
+        .unwrap_or(0);
+
+    repeated.max(sequential)
+}
+
+fn sequence_length(chars: &[char], sequence: &[char]) -> usize {
+    let Some(start) = sequence.iter().position(|c| *c == chars[0]) else {
+        return 0;
+    };
+    chars
+        .iter()
+        .zip(&sequence[start..])
+        .take_while(|(c, expected)| c == expected)
+        .count()
+}
+
+// How many characters each position could be, going by the kinds of character used
+fn cardinality(chars: &[char]) -> f64 {
+    let mut cardinality = 0.0;
+    if chars.iter().any(|c| c.is_ascii_lowercase()) {
+        cardinality += 26.0;
+    }
+    if chars.iter().any(|c| c.is_ascii_uppercase()) {
+        cardinality += 26.0;
+    }
+    if chars.iter().any(|c| c.is_ascii_digit()) {
+        cardinality += 10.0;
+    }
+    if chars.iter().any(|c| c.is_ascii_punctuation() || *c == ' ') {
+        cardinality += 33.0;
+    }
+    if chars.iter().any(|c| !c.is_ascii()) {
+        cardinality += 100.0;
+    }
+    f64::max(cardinality, 10.0)
+}
+
+fn unleet(c: char) -> char {
+    match c.to_ascii_lowercase() {
+        '4' | '@' => 'a',
+        '3' => 'e',
+        '1' | '!' => 'i',
+        '0' => 'o',
+        '5' | '$' => 's',
+        '7' => 't',
+        c => c,
+    }
+}
+
+fn find(haystack: &[char], needle: &[char]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn common_passwords_are_weak() {
+        for password in [
+            "password",
+            "password123",
+            "P@ssw0rd!",
+            "qwerty123",
+            "letmein",
+        ] {
+            assert!(
+                strength_score(password, &[]) <= 1,
+                "{} scored {}",
+                password,
+                strength_score(password, &[])
+            );
+        }
+    }
+
+    #[test]
+    fn repeats_and_sequences_are_weak() {
+        for password in [
+            "aaaaaaaaaa",
+            "12345678",
+            "abcdefghij",
+            "qwertyuiop",
+            "9876543210",
+        ] {
+            assert_eq!(strength_score(password, &[]), 0, "{}", password);
+        }
+    }
+
+    #[test]
+    fn user_inputs_are_weak() {
+        let password = "jane.doe1990";
+        assert!(strength_score(password, &["jane.doe1990"]) < strength_score(password, &[]));
+    }
+
+    #[test]
+    fn long_random_passwords_are_strong() {
+        for password in [
+            "correct horse battery staple",
+            "x7#Qm9!vLp2@",
+            "Zq8vN3kTw5Rb",
+        ] {
+            assert_eq!(strength_score(password, &[]), 4, "{}", password);
+        }
+    }
+}
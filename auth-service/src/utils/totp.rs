@@ -0,0 +1,168 @@
+use chrono::Utc;
+use color_eyre::eyre::{eyre, Context, Result};
+use secrecy::ExposeSecret;
+use subtle::ConstantTimeEq;
+use totp_rs::{Algorithm, TOTP};
+
+use crate::domain::{Email, TotpSecretStoreType, UserId};
+use crate::services::data_stores::{TotpSecret, TotpSecretStoreError, TwoFACode};
+
+use super::constants::{TOTP_DIGITS, TOTP_ISSUER, TOTP_STEP_SECONDS};
+
+// Build the `otpauth://` URI authenticator apps use to enroll a secret, usually via a QR code
+pub fn totp_uri(secret: &TotpSecret, email: &Email) -> Result<String> {
+    Ok(totp(secret, email.as_ref().expose_secret().to_owned())?.get_url())
+}
+
+// Generate the code for a secret at the given unix time
+pub fn generate_totp_code(secret: &TotpSecret, time: u64) -> Result<String> {
+    Ok(totp(secret, String::new())?.generate(time))
+}
+
+// Find the time step a code was generated for, allowing one step of clock drift either way
+pub fn find_totp_step(secret: &TotpSecret, code: &TwoFACode, time: u64) -> Result<Option<u64>> {
+    let totp = totp(secret, String::new())?;
+    let current_step = time / TOTP_STEP_SECONDS;
+
+    let step = (current_step.saturating_sub(1)..=current_step + 1).find(|step| {
+        totp.generate(step * TOTP_STEP_SECONDS)
+            .as_bytes()
+            .ct_eq(code.as_ref().expose_secret().as_bytes())
+            .into()
+    });
+
+    Ok(step)
+}
+
+// Check a code against the user's secret, rejecting codes for a time step that has already
+// been used so an intercepted code can't be replayed
+#[tracing::instrument(name = "Check TOTP code", skip_all)]
+pub async fn check_totp_code(
+    user_id: &UserId,
+    secret: &TotpSecret,
+    code: &TwoFACode,
+    totp_secret_store: TotpSecretStoreType,
+) -> Result<bool> {
+    let now: u64 = Utc::now()
+        .timestamp()
+        .try_into()
+        .wrap_err("failed to cast current time to u64")?;
+
+    let step = match find_totp_step(secret, code, now)? {
+        Some(step) => step,
+        None => return Ok(false),
+    };
+
+    match totp_secret_store
+        .write()
+        .await
+        .record_used_step(user_id, step)
+        .await
+    {
+        Ok(()) => Ok(true),
+        Err(TotpSecretStoreError::CodeAlreadyUsed) => Ok(false),
+        Err(e) => Err(eyre!(e)),
+    }
+}
+
+// The account name only labels the secret in authenticator apps - codes don't depend on it
+fn totp(secret: &TotpSecret, account_name: String) -> Result<TOTP> {
+    let secret_bytes = totp_rs::Secret::Encoded(secret.as_ref().expose_secret().to_owned())
+        .to_bytes()
+        .map_err(|_| eyre!("failed to decode TOTP secret"))?;
+
+    TOTP::new(
+        Algorithm::SHA1,
+        TOTP_DIGITS,
+        1,
+        TOTP_STEP_SECONDS,
+        secret_bytes,
+        Some(TOTP_ISSUER.to_owned()),
+        account_name,
+    )
+    .wrap_err("failed to create TOTP")
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    use crate::services::{HashmapTotpSecretStore, TotpSecretStore};
+
+    use super::*;
+
+    fn email() -> Email {
+        Email::parse(Secret::new("test@example.com".to_owned())).unwrap()
+    }
+
+    fn code(code: String) -> TwoFACode {
+        TwoFACode::parse(Secret::new(code)).unwrap()
+    }
+
+    #[test]
+    fn test_totp_uri() {
+        let secret = TotpSecret::default();
+        let uri = totp_uri(&secret, &email()).unwrap();
+
+        assert!(uri.starts_with("otpauth://totp/"));
+        assert!(uri.contains(&format!("secret={}", secret.as_ref().expose_secret())));
+        assert!(uri.contains(&format!("issuer={}", TOTP_ISSUER)));
+    }
+
+    #[test]
+    fn test_generate_totp_code_matches_rfc_6238() {
+        // Test vector from RFC 6238 appendix B, truncated to 6 digits
+        let secret =
+            TotpSecret::parse(Secret::new("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ".to_owned())).unwrap();
+
+        let result = generate_totp_code(&secret, 59).unwrap();
+        assert_eq!(result, "287082");
+    }
+
+    #[test]
+    fn test_find_totp_step_allows_one_step_of_drift() {
+        let secret = TotpSecret::default();
+        let time = 1_000_000 * TOTP_STEP_SECONDS;
+        let step = time / TOTP_STEP_SECONDS;
+
+        for offset in [-1i64, 0, 1] {
+            let code_time = (time as i64 + offset * TOTP_STEP_SECONDS as i64) as u64;
+            let code = code(generate_totp_code(&secret, code_time).unwrap());
+
+            let result = find_totp_step(&secret, &code, time).unwrap();
+            assert_eq!(result, Some((step as i64 + offset) as u64));
+        }
+
+        for offset in [-2i64, 2] {
+            let code_time = (time as i64 + offset * TOTP_STEP_SECONDS as i64) as u64;
+            let code = code(generate_totp_code(&secret, code_time).unwrap());
+
+            let result = find_totp_step(&secret, &code, time).unwrap();
+            assert_eq!(result, None);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_totp_code_rejects_replay() {
+        let secret = TotpSecret::default();
+        let user_id = UserId::default();
+        let store = Arc::new(RwLock::new(HashmapTotpSecretStore::default()));
+        store
+            .write()
+            .await
+            .add_secret(&user_id, secret.clone())
+            .await
+            .unwrap();
+
+        let now = Utc::now().timestamp() as u64;
+        let code = code(generate_totp_code(&secret, now).unwrap());
+
+        let result = check_totp_code(&user_id, &secret, &code, store.clone()).await;
+        assert!(result.unwrap());
+
+        let result = check_totp_code(&user_id, &secret, &code, store).await;
+        assert!(!result.unwrap());
+    }
+}
@@ -0,0 +1,167 @@
+use std::path::Path;
+
+use color_eyre::eyre::{eyre, Context, Result};
+use secrecy::Secret;
+use serde::Deserialize;
+
+use crate::domain::Email;
+
+use super::password_hashing::PasswordHashFormat;
+
+// A user from another system, with their password hash as it was stored there
+#[derive(Debug, Clone)]
+pub struct ImportedUser {
+    pub email: Email,
+    pub password_hash: Secret<String>,
+    pub requires_2fa: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UserImportSummary {
+    pub imported: u64,
+    // Users whose email address already has an account
+    pub skipped: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UserImportFormat {
+    // With an `email,hash,requires_2fa` header
+    Csv,
+    // One `{"email": ..., "hash": ..., "requires_2fa": ...}` object per line
+    Jsonl,
+}
+
+impl UserImportFormat {
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("csv") => Ok(Self::Csv),
+            Some("jsonl" | "ndjson") => Ok(Self::Jsonl),
+            _ => Err(eyre!("{:?} is not a .csv or .jsonl file", path)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct UserRecord {
+    email: String,
+    hash: String,
+    requires_2fa: bool,
+}
+
+// Read every user in an import, failing on the first invalid one so nothing is half imported
+pub fn parse_user_import(contents: &str, format: UserImportFormat) -> Result<Vec<ImportedUser>> {
+    let records: Vec<(usize, UserRecord)> = match format {
+        UserImportFormat::Csv => csv::Reader::from_reader(contents.as_bytes())
+            .into_deserialize()
+            .enumerate()
+            // The header is line 1
+            .map(|(i, record)| {
+                let line = i + 2;
+                record
+                    .map(|record| (line, record))
+                    .wrap_err_with(|| format!("invalid user on line {}", line))
+            })
+            .collect::<Result<_>>()?,
+        UserImportFormat::Jsonl => contents
+            .lines()
+            .enumerate()
+            .filter(|(_, record)| !record.trim().is_empty())
+            .map(|(i, record)| {
+                let line = i + 1;
+                serde_json::from_str(record)
+                    .map(|record| (line, record))
+                    .wrap_err_with(|| format!("invalid user on line {}", line))
+            })
+            .collect::<Result<_>>()?,
+    };
+
+    records
+        .into_iter()
+        .map(|(line, record)| {
+            let email = Email::parse(Secret::new(record.email))
+                .wrap_err_with(|| format!("invalid email on line {}", line))?;
+            if PasswordHashFormat::detect(&record.hash).is_none() {
+                return Err(eyre!("unsupported password hash on line {}", line));
+            }
+
+            Ok(ImportedUser {
+                email,
+                password_hash: Secret::new(record.hash),
+                requires_2fa: record.requires_2fa,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::ExposeSecret;
+
+    use super::*;
+
+    const BCRYPT_HASH: &str = "$2b$04$W8bVjZWyuXJj1ZPbmyS3MeyBlqTXaMX4Ot8A2T5mVZO/t2t.uzDDK";
+
+    #[test]
+    fn csv_users_are_parsed() {
+        let contents = format!(
+            "email,hash,requires_2fa\nuser@example.com,{},true\nother@example.com,{},false\n",
+            BCRYPT_HASH, BCRYPT_HASH
+        );
+
+        let users = parse_user_import(&contents, UserImportFormat::Csv).unwrap();
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[0].email.as_ref().expose_secret(), "user@example.com");
+        assert_eq!(users[0].password_hash.expose_secret(), BCRYPT_HASH);
+        assert!(users[0].requires_2fa);
+        assert!(!users[1].requires_2fa);
+    }
+
+    #[test]
+    fn jsonl_users_are_parsed() {
+        let contents = format!(
+            "{}\n\n{}\n",
+            serde_json::json!({ "email": "user@example.com", "hash": BCRYPT_HASH, "requires_2fa": true }),
+            serde_json::json!({ "email": "other@example.com", "hash": BCRYPT_HASH, "requires_2fa": false }),
+        );
+
+        let users = parse_user_import(&contents, UserImportFormat::Jsonl).unwrap();
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[1].email.as_ref().expose_secret(), "other@example.com");
+        assert!(!users[1].requires_2fa);
+    }
+
+    #[test]
+    fn invalid_users_are_rejected_with_their_line() {
+        let test_cases = [
+            format!(
+                "email,hash,requires_2fa\nnot-an-email,{},true\n",
+                BCRYPT_HASH
+            ),
+            "email,hash,requires_2fa\nuser@example.com,5f4dcc3b5aa765d61d8327deb882cf99,true\n"
+                .to_owned(),
+            format!(
+                "email,hash,requires_2fa\nuser@example.com,{},maybe\n",
+                BCRYPT_HASH
+            ),
+        ];
+
+        for contents in test_cases {
+            let error = parse_user_import(&contents, UserImportFormat::Csv).unwrap_err();
+            assert!(error.to_string().contains("line 2"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn format_is_chosen_by_extension() {
+        assert_eq!(
+            UserImportFormat::from_path("users.csv").unwrap(),
+            UserImportFormat::Csv
+        );
+        assert_eq!(
+            UserImportFormat::from_path("users.jsonl").unwrap(),
+            UserImportFormat::Jsonl
+        );
+        assert!(UserImportFormat::from_path("users.txt").is_err());
+    }
+}
@@ -0,0 +1,539 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL, Engine};
+use chrono::Utc;
+use ciborium::Value;
+use color_eyre::eyre::{eyre, Context, Result};
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::domain::{User, UserId};
+use crate::services::data_stores::{WebAuthnChallenge, WebAuthnCredential};
+
+use super::constants::{
+    WEBAUTHN_CHALLENGE_TTL_SECONDS, WEBAUTHN_ORIGIN, WEBAUTHN_RP_ID, WEBAUTHN_RP_NAME,
+};
+
+// COSE algorithm identifier for ECDSA with P-256 and SHA-256, the only one we accept
+const COSE_ALG_ES256: i64 = -7;
+const PUBLIC_KEY_CREDENTIAL_TYPE: &str = "public-key";
+
+// Authenticator data flags
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+// The options passed to `navigator.credentials.create()` to register a passkey
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicKeyCredentialCreationOptions {
+    pub challenge: String,
+    pub rp: RelyingParty,
+    pub user: UserEntity,
+    pub pub_key_cred_params: Vec<CredentialParameters>,
+    pub timeout: u64,
+    pub attestation: String,
+    pub exclude_credentials: Vec<CredentialDescriptor>,
+}
+
+// The options passed to `navigator.credentials.get()` to log in with a passkey
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicKeyCredentialRequestOptions {
+    pub challenge: String,
+    pub rp_id: String,
+    pub timeout: u64,
+    pub allow_credentials: Vec<CredentialDescriptor>,
+    pub user_verification: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RelyingParty {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UserEntity {
+    pub id: String,
+    pub name: String,
+    pub display_name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CredentialParameters {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub alg: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CredentialDescriptor {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub id: String,
+}
+
+// The JSON form of the `PublicKeyCredential` returned by `navigator.credentials.create()`,
+// with binary fields base64url encoded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistrationCredential {
+    pub id: String,
+    pub raw_id: String,
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub response: AttestationResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub attestation_object: String,
+}
+
+// The JSON form of the `PublicKeyCredential` returned by `navigator.credentials.get()`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticationCredential {
+    pub id: String,
+    pub raw_id: String,
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub response: AssertionResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+    #[serde(default)]
+    pub user_handle: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    type_: String,
+    challenge: String,
+    origin: String,
+}
+
+struct AuthenticatorData {
+    rp_id_hash: Vec<u8>,
+    flags: u8,
+    sign_count: u32,
+    attested_credential: Option<AttestedCredential>,
+}
+
+struct AttestedCredential {
+    credential_id: Vec<u8>,
+    public_key: Vec<u8>,
+}
+
+pub fn creation_options(
+    user: &User,
+    challenge: &WebAuthnChallenge,
+    existing_credentials: &[WebAuthnCredential],
+) -> PublicKeyCredentialCreationOptions {
+    let email = user.email.as_ref().expose_secret();
+
+    PublicKeyCredentialCreationOptions {
+        challenge: challenge.as_ref().expose_secret().to_owned(),
+        rp: RelyingParty {
+            id: WEBAUTHN_RP_ID.to_owned(),
+            name: WEBAUTHN_RP_NAME.to_owned(),
+        },
+        user: UserEntity {
+            // The user handle is stored on the authenticator, so it shouldn't contain the email
+            id: BASE64_URL.encode(user.id.as_ref().as_bytes()),
+            name: email.to_owned(),
+            display_name: email.to_owned(),
+        },
+        pub_key_cred_params: vec![CredentialParameters {
+            type_: PUBLIC_KEY_CREDENTIAL_TYPE.to_owned(),
+            alg: COSE_ALG_ES256,
+        }],
+        timeout: WEBAUTHN_CHALLENGE_TTL_SECONDS * 1000,
+        // We don't check which make of authenticator is being used
+        attestation: "none".to_owned(),
+        exclude_credentials: credential_descriptors(existing_credentials),
+    }
+}
+
+pub fn request_options(
+    challenge: &WebAuthnChallenge,
+    credentials: &[WebAuthnCredential],
+) -> PublicKeyCredentialRequestOptions {
+    PublicKeyCredentialRequestOptions {
+        challenge: challenge.as_ref().expose_secret().to_owned(),
+        rp_id: WEBAUTHN_RP_ID.to_owned(),
+        timeout: WEBAUTHN_CHALLENGE_TTL_SECONDS * 1000,
+        allow_credentials: credential_descriptors(credentials),
+        // The password has already been checked, so user presence is enough for a second factor
+        user_verification: "preferred".to_owned(),
+    }
+}
+
+// Check the response to a registration challenge and extract the new credential from it
+#[tracing::instrument(name = "Verify WebAuthn registration", skip_all)]
+pub fn verify_registration(
+    user_id: &UserId,
+    challenge: &WebAuthnChallenge,
+    credential: &RegistrationCredential,
+) -> Result<WebAuthnCredential> {
+    check_credential_type(&credential.type_)?;
+
+    let client_data_json = decode(&credential.response.client_data_json)?;
+    check_client_data(&client_data_json, "webauthn.create", challenge)?;
+
+    let attestation_object = decode(&credential.response.attestation_object)?;
+    let auth_data = parse_attestation_object(&attestation_object)?;
+    let auth_data = parse_authenticator_data(&auth_data)?;
+    check_authenticator_data(&auth_data)?;
+
+    let attested_credential = auth_data
+        .attested_credential
+        .ok_or_else(|| eyre!("authenticator data has no attested credential"))?;
+
+    if attested_credential.credential_id != decode(&credential.raw_id)? {
+        return Err(eyre!("credential ID doesn't match the attested credential"));
+    }
+
+    Ok(WebAuthnCredential {
+        credential_id: attested_credential.credential_id,
+        user_id: *user_id,
+        public_key: attested_credential.public_key,
+        sign_count: auth_data.sign_count,
+        created_at: Utc::now(),
+        last_used_at: None,
+    })
+}
+
+// Check the response to an authentication challenge against the stored credential, returning
+// the authenticator's new signature counter
+#[tracing::instrument(name = "Verify WebAuthn assertion", skip_all)]
+pub fn verify_authentication(
+    challenge: &WebAuthnChallenge,
+    credential: &AuthenticationCredential,
+    stored_credential: &WebAuthnCredential,
+) -> Result<u32> {
+    check_credential_type(&credential.type_)?;
+
+    let client_data_json = decode(&credential.response.client_data_json)?;
+    check_client_data(&client_data_json, "webauthn.get", challenge)?;
+
+    let raw_auth_data = decode(&credential.response.authenticator_data)?;
+    let auth_data = parse_authenticator_data(&raw_auth_data)?;
+    check_authenticator_data(&auth_data)?;
+
+    // The signature covers the authenticator data followed by the hash of the client data
+    let mut signed_data = raw_auth_data;
+    signed_data.extend_from_slice(&Sha256::digest(&client_data_json));
+
+    let verifying_key = VerifyingKey::from_sec1_bytes(&stored_credential.public_key)
+        .map_err(|_| eyre!("stored WebAuthn public key is invalid"))?;
+    let signature = Signature::from_der(&decode(&credential.response.signature)?)
+        .map_err(|_| eyre!("WebAuthn signature is malformed"))?;
+    verifying_key
+        .verify(&signed_data, &signature)
+        .map_err(|_| eyre!("WebAuthn signature is invalid"))?;
+
+    if !stored_credential.is_sign_count_valid(auth_data.sign_count) {
+        return Err(eyre!("WebAuthn signature counter did not increase"));
+    }
+
+    Ok(auth_data.sign_count)
+}
+
+pub fn decode_credential_id(id: &str) -> Result<Vec<u8>> {
+    decode(id)
+}
+
+fn credential_descriptors(credentials: &[WebAuthnCredential]) -> Vec<CredentialDescriptor> {
+    credentials
+        .iter()
+        .map(|credential| CredentialDescriptor {
+            type_: PUBLIC_KEY_CREDENTIAL_TYPE.to_owned(),
+            id: BASE64_URL.encode(&credential.credential_id),
+        })
+        .collect()
+}
+
+fn decode(value: &str) -> Result<Vec<u8>> {
+    BASE64_URL
+        .decode(value)
+        .wrap_err("failed to decode base64url value")
+}
+
+fn check_credential_type(type_: &str) -> Result<()> {
+    match type_ == PUBLIC_KEY_CREDENTIAL_TYPE {
+        true => Ok(()),
+        false => Err(eyre!("unexpected credential type {}", type_)),
+    }
+}
+
+fn check_client_data(
+    client_data_json: &[u8],
+    expected_type: &str,
+    challenge: &WebAuthnChallenge,
+) -> Result<()> {
+    let client_data: ClientData =
+        serde_json::from_slice(client_data_json).wrap_err("failed to parse client data")?;
+
+    if client_data.type_ != expected_type {
+        return Err(eyre!("unexpected client data type {}", client_data.type_));
+    }
+    if client_data.challenge != *challenge.as_ref().expose_secret() {
+        return Err(eyre!("client data challenge doesn't match"));
+    }
+    // Checking the origin is what makes passkeys phishing resistant
+    if client_data.origin != *WEBAUTHN_ORIGIN {
+        return Err(eyre!("unexpected origin {}", client_data.origin));
+    }
+
+    Ok(())
+}
+
+fn check_authenticator_data(auth_data: &AuthenticatorData) -> Result<()> {
+    if auth_data.rp_id_hash.as_slice() != Sha256::digest(WEBAUTHN_RP_ID.as_bytes()).as_slice() {
+        return Err(eyre!("authenticator data is for another relying party"));
+    }
+    if auth_data.flags & FLAG_USER_PRESENT == 0 {
+        return Err(eyre!("user was not present"));
+    }
+    Ok(())
+}
+
+// The attestation object is a CBOR map of `fmt`, `attStmt` and `authData`. With attestation
+// set to "none" only the authenticator data is used.
+fn parse_attestation_object(attestation_object: &[u8]) -> Result<Vec<u8>> {
+    let value: Value =
+        ciborium::from_reader(attestation_object).wrap_err("failed to parse attestation object")?;
+
+    value
+        .as_map()
+        .and_then(|map| {
+            map.iter()
+                .find(|(key, _)| key.as_text() == Some("authData"))
+                .and_then(|(_, value)| value.as_bytes())
+        })
+        .cloned()
+        .ok_or_else(|| eyre!("attestation object has no authenticator data"))
+}
+
+// Authenticator data layout: 32 byte RP ID hash, 1 byte of flags, 4 byte big endian signature
+// counter, then the attested credential data if the AT flag is set
+fn parse_authenticator_data(data: &[u8]) -> Result<AuthenticatorData> {
+    if data.len() < 37 {
+        return Err(eyre!("authenticator data is too short"));
+    }
+
+    let flags = data[32];
+    let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+    let attested_credential = match flags & FLAG_ATTESTED_CREDENTIAL_DATA {
+        0 => None,
+        _ => Some(parse_attested_credential(&data[37..])?),
+    };
+
+    Ok(AuthenticatorData {
+        rp_id_hash: data[..32].to_vec(),
+        flags,
+        sign_count,
+        attested_credential,
+    })
+}
+
+// Attested credential data layout: 16 byte AAGUID, 2 byte big endian credential ID length, the
+// credential ID, then the COSE encoded public key
+fn parse_attested_credential(data: &[u8]) -> Result<AttestedCredential> {
+    if data.len() < 18 {
+        return Err(eyre!("attested credential data is too short"));
+    }
+
+    let id_length = usize::from(u16::from_be_bytes([data[16], data[17]]));
+    let credential_id = data
+        .get(18..18 + id_length)
+        .ok_or_else(|| eyre!("attested credential data is too short"))?
+        .to_vec();
+
+    let cose_key: Value = ciborium::from_reader(&data[18 + id_length..])
+        .wrap_err("failed to parse credential public key")?;
+
+    Ok(AttestedCredential {
+        credential_id,
+        public_key: parse_cose_es256_key(&cose_key)?,
+    })
+}
+
+// Convert a COSE EC2 key to an uncompressed SEC1 point, checking it is an ES256 key on P-256
+fn parse_cose_es256_key(cose_key: &Value) -> Result<Vec<u8>> {
+    let map = cose_key
+        .as_map()
+        .ok_or_else(|| eyre!("credential public key is not a map"))?;
+
+    let get = |label: i64| {
+        map.iter()
+            .find(|(key, _)| key.as_integer() == Some(label.into()))
+            .map(|(_, value)| value)
+    };
+    let get_integer = |label: i64| get(label).and_then(Value::as_integer).map(i128::from);
+
+    // kty 2 is EC2, crv 1 is P-256
+    if get_integer(1) != Some(2)
+        || get_integer(3) != Some(COSE_ALG_ES256.into())
+        || get_integer(-1) != Some(1)
+    {
+        return Err(eyre!("only ES256 credentials are supported"));
+    }
+
+    let x = get(-2).and_then(Value::as_bytes);
+    let y = get(-3).and_then(Value::as_bytes);
+    let (x, y) = match (x, y) {
+        (Some(x), Some(y)) if x.len() == 32 && y.len() == 32 => (x, y),
+        _ => return Err(eyre!("credential public key has invalid coordinates")),
+    };
+
+    let mut public_key = vec![0x04];
+    public_key.extend_from_slice(x);
+    public_key.extend_from_slice(y);
+
+    // Make sure the point is actually on the curve before storing it
+    VerifyingKey::from_sec1_bytes(&public_key)
+        .map_err(|_| eyre!("credential public key is not a valid P-256 point"))?;
+
+    Ok(public_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use p256::ecdsa::{signature::Signer, SigningKey};
+
+    use super::*;
+
+    fn auth_data(flags: u8, sign_count: u32) -> Vec<u8> {
+        let mut data = Sha256::digest(WEBAUTHN_RP_ID.as_bytes()).to_vec();
+        data.push(flags);
+        data.extend_from_slice(&sign_count.to_be_bytes());
+        data
+    }
+
+    fn assertion(
+        signing_key: &SigningKey,
+        challenge: &WebAuthnChallenge,
+        origin: &str,
+        sign_count: u32,
+    ) -> AuthenticationCredential {
+        let client_data_json = serde_json::json!({
+            "type": "webauthn.get",
+            "challenge": challenge.as_ref().expose_secret(),
+            "origin": origin,
+        })
+        .to_string();
+        let auth_data = auth_data(FLAG_USER_PRESENT, sign_count);
+
+        let mut signed_data = auth_data.clone();
+        signed_data.extend_from_slice(&Sha256::digest(client_data_json.as_bytes()));
+        let signature: Signature = signing_key.sign(&signed_data);
+
+        AuthenticationCredential {
+            id: BASE64_URL.encode(b"credential"),
+            raw_id: BASE64_URL.encode(b"credential"),
+            type_: PUBLIC_KEY_CREDENTIAL_TYPE.to_owned(),
+            response: AssertionResponse {
+                client_data_json: BASE64_URL.encode(client_data_json),
+                authenticator_data: BASE64_URL.encode(auth_data),
+                signature: BASE64_URL.encode(signature.to_der()),
+                user_handle: None,
+            },
+        }
+    }
+
+    fn stored_credential(signing_key: &SigningKey, sign_count: u32) -> WebAuthnCredential {
+        WebAuthnCredential {
+            credential_id: b"credential".to_vec(),
+            user_id: UserId::default(),
+            public_key: signing_key
+                .verifying_key()
+                .to_encoded_point(false)
+                .as_bytes()
+                .to_vec(),
+            sign_count,
+            created_at: Utc::now(),
+            last_used_at: None,
+        }
+    }
+
+    #[test]
+    fn test_verify_authentication() {
+        let signing_key = SigningKey::random(&mut p256::elliptic_curve::rand_core::OsRng);
+        let challenge = WebAuthnChallenge::default();
+        let credential = assertion(&signing_key, &challenge, &WEBAUTHN_ORIGIN, 2);
+
+        let result =
+            verify_authentication(&challenge, &credential, &stored_credential(&signing_key, 1));
+        assert_eq!(result.unwrap(), 2);
+
+        // The counter has to go up
+        let result =
+            verify_authentication(&challenge, &credential, &stored_credential(&signing_key, 2));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_verify_authentication_rejects_wrong_challenge_origin_or_key() {
+        let signing_key = SigningKey::random(&mut p256::elliptic_curve::rand_core::OsRng);
+        let other_key = SigningKey::random(&mut p256::elliptic_curve::rand_core::OsRng);
+        let challenge = WebAuthnChallenge::default();
+        let stored = stored_credential(&signing_key, 0);
+
+        let credential = assertion(
+            &signing_key,
+            &WebAuthnChallenge::default(),
+            &WEBAUTHN_ORIGIN,
+            1,
+        );
+        assert!(verify_authentication(&challenge, &credential, &stored).is_err());
+
+        let credential = assertion(&signing_key, &challenge, "https://evil.example.com", 1);
+        assert!(verify_authentication(&challenge, &credential, &stored).is_err());
+
+        let credential = assertion(&other_key, &challenge, &WEBAUTHN_ORIGIN, 1);
+        assert!(verify_authentication(&challenge, &credential, &stored).is_err());
+    }
+
+    #[test]
+    fn test_parse_cose_key_only_accepts_es256() {
+        let signing_key = SigningKey::random(&mut p256::elliptic_curve::rand_core::OsRng);
+        let point = signing_key.verifying_key().to_encoded_point(false);
+        let cose_key = |alg: i64| {
+            Value::Map(vec![
+                (Value::from(1), Value::from(2)),
+                (Value::from(3), Value::from(alg)),
+                (Value::from(-1), Value::from(1)),
+                (Value::from(-2), Value::from(point.x().unwrap().to_vec())),
+                (Value::from(-3), Value::from(point.y().unwrap().to_vec())),
+            ])
+        };
+
+        let result = parse_cose_es256_key(&cose_key(COSE_ALG_ES256)).unwrap();
+        assert_eq!(result, point.as_bytes());
+
+        // RS256
+        assert!(parse_cose_es256_key(&cose_key(-257)).is_err());
+    }
+
+    #[test]
+    fn test_parse_authenticator_data_rejects_short_data() {
+        assert!(parse_authenticator_data(&[0; 36]).is_err());
+
+        // The AT flag is set but there is no attested credential data
+        assert!(parse_authenticator_data(&auth_data(FLAG_ATTESTED_CREDENTIAL_DATA, 0)).is_err());
+    }
+}
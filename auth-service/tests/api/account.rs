@@ -0,0 +1,677 @@
+use auth_service::{
+    domain::PasswordPolicyViolation,
+    routes::{
+        AccountExportResponse, ChangeEmailResponse, ChangePasswordResponse, DeleteAccountResponse,
+        ListSessionsResponse, TwoFactorAuthResponse,
+    },
+    utils::constants::{JWT_COOKIE_NAME, REFRESH_COOKIE_NAME},
+    ErrorResponse,
+};
+use reqwest::Url;
+use secrecy::ExposeSecret;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{configure_redis, get_random_email, TestApp};
+
+fn find_cookie(response: &reqwest::Response, name: &str) -> String {
+    response
+        .cookies()
+        .find(|cookie| cookie.name() == name)
+        .map(|cookie| cookie.value().to_owned())
+        .expect("No cookie found")
+}
+
+fn set_cookie(app: &TestApp, name: &str, value: &str) {
+    app.cookie_jar.add_cookie_str(
+        &format!("{}={}; HttpOnly; SameSite=Lax; Path=/", name, value),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+}
+
+// Find the token in the most recent link to `path` emailed to `email`
+async fn get_token_from_email(app: &TestApp, email: &str, path: &str) -> Option<String> {
+    let requests = app
+        .email_server
+        .received_requests()
+        .await
+        .expect("Request recording is disabled");
+
+    requests.iter().rev().find_map(|request| {
+        let body: serde_json::Value = request.body_json().ok()?;
+        if body["To"].as_str()? != email {
+            return None;
+        }
+        let text = body["TextBody"].as_str()?;
+        let token = text.split(&format!("{}?token=", path)).nth(1)?;
+        token.split_whitespace().next().map(str::to_owned)
+    })
+}
+
+// Emails that must be delivered, like confirmation links and 2FA codes, fail the request if the
+// email server rejects them
+async fn accept_emails(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+async fn request_email_change(app: &TestApp, new_email: &str) -> reqwest::Response {
+    accept_emails(app).await;
+
+    app.post_account_email(&serde_json::json!({
+        "password": "password123",
+        "newEmail": new_email,
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn should_change_password_and_revoke_other_sessions() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    let response = app.signup_and_login(&random_email, "password123").await;
+    let other_auth_token = find_cookie(&response, JWT_COOKIE_NAME);
+    let other_refresh_token = find_cookie(&response, REFRESH_COOKIE_NAME);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_account_password(&serde_json::json!({
+            "currentPassword": "password123",
+            "newPassword": "Zq8vN3kTw5Rb",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(!find_cookie(&response, JWT_COOKIE_NAME).is_empty());
+    assert_eq!(
+        response
+            .json::<ChangePasswordResponse>()
+            .await
+            .expect("Could not deserialize response body to ChangePasswordResponse")
+            .message,
+        "Password has been changed"
+    );
+
+    // The caller stays logged in, in a session of its own
+    let response = app.get_sessions().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The other session is signed out
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": other_auth_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    set_cookie(&app, REFRESH_COOKIE_NAME, &other_refresh_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Only the new password works from now on
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": random_email,
+            "password": "Zq8vN3kTw5Rb",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // And the user is told about the change
+    let requests = app.email_server.received_requests().await.unwrap();
+    assert!(requests
+        .iter()
+        .filter_map(|request| request.body_json::<serde_json::Value>().ok())
+        .any(|body| body["To"] == random_email.as_str()
+            && body["Subject"] == "Your password was changed"));
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_current_password_is_incorrect() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    app.signup_and_login(&random_email, "password123").await;
+
+    let response = app
+        .post_account_password(&serde_json::json!({
+            "currentPassword": "wrongpassword",
+            "newPassword": "Zq8vN3kTw5Rb",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Incorrect credentials"
+    );
+
+    // Nothing changed, and the session is still good
+    let response = app.get_sessions().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": random_email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_400_with_reasons_if_new_password_does_not_meet_policy() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    app.signup_and_login(&random_email, "password123").await;
+
+    // The local part of the email address is a UUID, long enough to count
+    let local_part = random_email.split('@').next().unwrap();
+    let response = app
+        .post_account_password(&serde_json::json!({
+            "currentPassword": "password123",
+            "newPassword": format!("{}!", local_part),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let body = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    assert_eq!(body.error, "Password does not meet requirements");
+    assert_eq!(body.reasons, vec![PasswordPolicyViolation::ContainsEmail]);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_account_password(&serde_json::json!({
+            "currentPassword": "password123",
+            "newPassword": "Zq8vN3kTw5Rb",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_change_email_once_confirmed_from_new_address() {
+    let mut app = TestApp::new().await;
+    let old_email = get_random_email();
+    let new_email = get_random_email();
+
+    app.signup_and_login(&old_email, "password123").await;
+    let session_id = app
+        .get_sessions()
+        .await
+        .json::<ListSessionsResponse>()
+        .await
+        .unwrap()
+        .sessions[0]
+        .id
+        .clone();
+
+    let response = request_email_change(&app, &new_email).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response
+            .json::<ChangeEmailResponse>()
+            .await
+            .expect("Could not deserialize response body to ChangeEmailResponse")
+            .message,
+        "A confirmation link has been sent to the new email address"
+    );
+
+    // The old address is told, and can cancel
+    assert!(
+        get_token_from_email(&app, &old_email, "/account/email/cancel")
+            .await
+            .is_some()
+    );
+    let token = get_token_from_email(&app, &new_email, "/account/email/confirm")
+        .await
+        .expect("No confirmation email sent");
+
+    let response = app.get_account_email_confirm(&token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The link can only be used once
+    let response = app.get_account_email_confirm(&token).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Tokens name the user by id, so the session carries on without having to log in again
+    let sessions = app
+        .get_sessions()
+        .await
+        .json::<ListSessionsResponse>()
+        .await
+        .unwrap()
+        .sessions;
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].id, session_id);
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let refresh_token = find_cookie(&response, REFRESH_COOKIE_NAME);
+
+    // Signing out everywhere still reaches the carried over session
+    let response = app.post_logout_all().await;
+    assert_eq!(response.status().as_u16(), 200);
+    set_cookie(&app, REFRESH_COOKIE_NAME, &refresh_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Only the new address can log in
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": old_email,
+            "password": "password123",
+        }))
+        .await;
+    assert_ne!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": new_email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_not_change_email_once_cancelled_from_old_address() {
+    let mut app = TestApp::new().await;
+    let old_email = get_random_email();
+    let new_email = get_random_email();
+
+    app.signup_and_login(&old_email, "password123").await;
+
+    let response = request_email_change(&app, &new_email).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let cancel_token = get_token_from_email(&app, &old_email, "/account/email/cancel")
+        .await
+        .expect("No email change notice sent");
+    let confirm_token = get_token_from_email(&app, &new_email, "/account/email/confirm")
+        .await
+        .expect("No confirmation email sent");
+
+    let response = app.get_account_email_cancel(&cancel_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.get_account_email_confirm(&confirm_token).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Nothing changed
+    let response = app.get_sessions().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": old_email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_move_pending_2fa_login_to_new_email() {
+    let mut app = TestApp::new().await;
+    let old_email = get_random_email();
+    let new_email = get_random_email();
+    accept_emails(&app).await;
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": old_email,
+            "password": "password123",
+            "requires2FA": true,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    app.verify_email(&old_email).await;
+
+    let login_body = serde_json::json!({
+        "email": old_email,
+        "password": "password123",
+    });
+
+    // Log in once to be able to ask for the change, and start a second login
+    let mut login_attempt_ids = Vec::new();
+    for _ in 0..2 {
+        let response = app.post_login(&login_body).await;
+        assert_eq!(response.status().as_u16(), 206);
+        login_attempt_ids.push(
+            response
+                .json::<TwoFactorAuthResponse>()
+                .await
+                .expect("Could not deserialize response body to TwoFactorAuthResponse")
+                .login_attempt_id,
+        );
+
+        if login_attempt_ids.len() == 1 {
+            let (_, code) = app
+                .two_fa_code_store
+                .read()
+                .await
+                .get_code(&app.get_user_id(&old_email).await)
+                .await
+                .unwrap();
+            let response = app
+                .post_verify_2fa(&serde_json::json!({
+                    "email": old_email,
+                    "loginAttemptId": login_attempt_ids[0],
+                    "2FACode": code.as_ref().expose_secret(),
+                }))
+                .await;
+            assert_eq!(response.status().as_u16(), 200);
+        }
+    }
+    let (_, code) = app
+        .two_fa_code_store
+        .read()
+        .await
+        .get_code(&app.get_user_id(&old_email).await)
+        .await
+        .unwrap();
+
+    let response = request_email_change(&app, &new_email).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let token = get_token_from_email(&app, &new_email, "/account/email/confirm")
+        .await
+        .expect("No confirmation email sent");
+    let response = app.get_account_email_confirm(&token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The second login is finished with the new address
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": new_email,
+            "loginAttemptId": login_attempt_ids[1],
+            "2FACode": code.as_ref().expose_secret(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_409_if_new_email_has_an_account() {
+    let mut app = TestApp::with_app_state(|state| state.enumeration_safe = false).await;
+    let taken_email = get_random_email();
+
+    app.signup_and_login(&taken_email, "password123").await;
+    app.signup_and_login(&get_random_email(), "password123")
+        .await;
+
+    let response = request_email_change(&app, &taken_email).await;
+    assert_eq!(response.status().as_u16(), 409);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_not_send_confirmation_to_taken_email_in_enumeration_safe_mode() {
+    let mut app = TestApp::with_app_state(|state| state.enumeration_safe = true).await;
+    let taken_email = get_random_email();
+
+    app.signup_and_login(&taken_email, "password123").await;
+    app.signup_and_login(&get_random_email(), "password123")
+        .await;
+
+    let response = request_email_change(&app, &taken_email).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(
+        get_token_from_email(&app, &taken_email, "/account/email/confirm")
+            .await
+            .is_none()
+    );
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_password_is_incorrect_for_email_change() {
+    let mut app = TestApp::new().await;
+    let new_email = get_random_email();
+
+    app.signup_and_login(&get_random_email(), "password123")
+        .await;
+
+    let response = app
+        .post_account_email(&serde_json::json!({
+            "password": "wrongpassword",
+            "newEmail": new_email,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(
+        get_token_from_email(&app, &new_email, "/account/email/confirm")
+            .await
+            .is_none()
+    );
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_delete_account_and_revoke_tokens() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    let response = app.signup_and_login(&random_email, "password123").await;
+    let refresh_token = find_cookie(&response, REFRESH_COOKIE_NAME);
+    let user_id = app.get_user_id(&random_email).await;
+
+    let response = app
+        .delete_account(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response
+            .json::<DeleteAccountResponse>()
+            .await
+            .expect("Could not deserialize response body to DeleteAccountResponse")
+            .message,
+        "Account has been deleted"
+    );
+
+    // Everything stored with the user in the database went with them
+    let sessions: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sessions WHERE user_id = $1")
+        .bind(user_id.as_ref())
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(sessions, 0);
+
+    // Tokens issued before the deletion no longer work
+    set_cookie(&app, REFRESH_COOKIE_NAME, &refresh_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": random_email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    // The address is free to sign up with again
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": random_email,
+            "password": "password123",
+            "requires2FA": false,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_forget_pending_changes_and_codes_after_account_deleted() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+    let new_email = get_random_email();
+
+    app.signup_and_login(&random_email, "password123").await;
+    let user_id = app.get_user_id(&random_email).await;
+
+    let response = request_email_change(&app, &new_email).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let confirm_token = get_token_from_email(&app, &new_email, "/account/email/confirm")
+        .await
+        .expect("No confirmation email sent");
+
+    let response = app.post_email_2fa_enable().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let mut conn = configure_redis();
+    let keys = [
+        format!("confirmation_code:enable_email_2fa:{}", user_id),
+        format!("email_change_user:{}", user_id),
+    ];
+    for key in &keys {
+        let exists: bool = redis::Commands::exists(&mut conn, key).unwrap();
+        assert!(exists, "{}", key);
+    }
+
+    let response = app
+        .delete_account(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    for key in &keys {
+        let exists: bool = redis::Commands::exists(&mut conn, key).unwrap();
+        assert!(!exists, "{}", key);
+    }
+
+    // The address the user was moving to isn't kept, and the change can't be confirmed
+    let response = app.get_account_email_confirm(&confirm_token).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_reject_auth_token_after_account_deleted() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    let response = app.signup_and_login(&random_email, "password123").await;
+    let token = find_cookie(&response, JWT_COOKIE_NAME);
+
+    let response = app
+        .delete_account(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_password_is_incorrect_for_deletion() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    app.signup_and_login(&random_email, "password123").await;
+
+    let response = app
+        .delete_account(&serde_json::json!({ "password": "wrongpassword" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The account is still there, and the session still works
+    let response = app.get_sessions().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_export_everything_stored_about_the_user() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    app.signup_and_login(&random_email, "password123").await;
+
+    let new_email = get_random_email();
+    let response = request_email_change(&app, &new_email).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.get_account_export().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let export = response
+        .json::<AccountExportResponse>()
+        .await
+        .expect("Could not deserialize response body to AccountExportResponse");
+    assert_eq!(
+        export.user.id,
+        app.get_user_id(&random_email).await.to_string()
+    );
+    assert_eq!(export.user.email, random_email);
+    assert!(export.user.verified);
+    assert!(!export.user.requires_2fa);
+    assert!(!export.user.created_at.is_empty());
+    assert!(!export.user.updated_at.is_empty());
+    assert_eq!(export.user.pending_email_changes.len(), 1);
+    assert_eq!(export.user.pending_email_changes[0].new_email, new_email);
+    assert!(!export.two_factor.authenticator_app);
+    assert_eq!(export.two_factor.recovery_codes_remaining, 0);
+    assert!(export.two_factor.passkeys.is_empty());
+    assert_eq!(export.sessions.len(), 1);
+    assert!(export.sessions[0].current);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing_for_export() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_account_export().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    TestApp::cleanup(&mut app).await;
+}
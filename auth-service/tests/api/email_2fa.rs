@@ -0,0 +1,366 @@
+use auth_service::{
+    routes::{Email2FAResponse, RecoveryCodesResponse, TwoFAMethod, TwoFactorAuthResponse},
+    services::data_stores::ConfirmationPurpose,
+    utils::constants::{MAX_TWO_FA_ATTEMPTS, RECOVERY_CODE_COUNT, VERIFICATION_EMAIL_LIMIT},
+    ErrorResponse,
+};
+use secrecy::ExposeSecret;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Confirmation codes are emailed, so the email server has to accept them
+async fn accept_emails(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+async fn get_emailed_code(app: &TestApp, email: &str, purpose: ConfirmationPurpose) -> String {
+    let user_id = app.get_user_id(email).await;
+    let code = app
+        .confirmation_code_store
+        .read()
+        .await
+        .get_code(&user_id, purpose)
+        .await
+        .expect("No confirmation code sent");
+
+    code.as_ref().expose_secret().to_owned()
+}
+
+// A code that isn't the one that was sent
+fn wrong_code(code: &str) -> String {
+    match code {
+        "000000" => "111111".to_owned(),
+        _ => "000000".to_owned(),
+    }
+}
+
+async fn login(app: &TestApp, email: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "email": email,
+        "password": "password123",
+    }))
+    .await
+}
+
+// Turn on emailed 2FA for the logged in user, returning the confirmation response
+async fn enable_2fa(app: &TestApp, email: &str) -> reqwest::Response {
+    let response = app.post_email_2fa_enable().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let code = get_emailed_code(app, email, ConfirmationPurpose::EnableEmail2FA).await;
+    app.post_email_2fa_enable_confirm(&serde_json::json!({ "2FACode": code }))
+        .await
+}
+
+#[tokio::test]
+async fn should_enable_2fa_once_confirmed() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    app.signup_and_login(&random_email, "password123").await;
+    accept_emails(&app).await;
+
+    let response = app.post_email_2fa_enable().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Nothing changes until the code is confirmed
+    let response = login(&app, &random_email).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = enable_2fa(&app, &random_email).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response
+        .json::<Email2FAResponse>()
+        .await
+        .expect("Could not deserialize response body to Email2FAResponse");
+    assert_eq!(body.message, "2FA has been turned on");
+    assert_eq!(body.recovery_codes.len(), RECOVERY_CODE_COUNT);
+
+    let response = login(&app, &random_email).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let body = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+    assert_eq!(body.two_fa_method, TwoFAMethod::Email);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_confirmation_code_is_incorrect() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    app.signup_and_login(&random_email, "password123").await;
+    accept_emails(&app).await;
+
+    let response = app.post_email_2fa_enable().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let code = get_emailed_code(&app, &random_email, ConfirmationPurpose::EnableEmail2FA).await;
+
+    let response = app
+        .post_email_2fa_enable_confirm(&serde_json::json!({ "2FACode": wrong_code(&code) }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = login(&app, &random_email).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_429_after_too_many_incorrect_confirmation_codes() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    app.signup_and_login(&random_email, "password123").await;
+    accept_emails(&app).await;
+
+    let response = app.post_email_2fa_enable().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let code = get_emailed_code(&app, &random_email, ConfirmationPurpose::EnableEmail2FA).await;
+    let wrong_code = wrong_code(&code);
+
+    for _ in 1..MAX_TWO_FA_ATTEMPTS {
+        let response = app
+            .post_email_2fa_enable_confirm(&serde_json::json!({ "2FACode": wrong_code }))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+    let response = app
+        .post_email_2fa_enable_confirm(&serde_json::json!({ "2FACode": wrong_code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    // Even the right code is turned away until the lockout is over
+    let response = app
+        .post_email_2fa_enable_confirm(&serde_json::json!({ "2FACode": code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_not_reset_attempt_limit_when_code_is_resent() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    app.signup_and_login(&random_email, "password123").await;
+    accept_emails(&app).await;
+
+    let response = app.post_email_2fa_enable().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let code = get_emailed_code(&app, &random_email, ConfirmationPurpose::EnableEmail2FA).await;
+    let first_wrong_code = wrong_code(&code);
+
+    for _ in 1..MAX_TWO_FA_ATTEMPTS {
+        let response = app
+            .post_email_2fa_enable_confirm(&serde_json::json!({ "2FACode": first_wrong_code }))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    let response = app.post_email_2fa_enable().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let code = get_emailed_code(&app, &random_email, ConfirmationPurpose::EnableEmail2FA).await;
+
+    let response = app
+        .post_email_2fa_enable_confirm(&serde_json::json!({ "2FACode": wrong_code(&code) }))
+        .await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    let response = app
+        .post_email_2fa_enable_confirm(&serde_json::json!({ "2FACode": code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_429_if_too_many_codes_are_requested() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    app.signup_and_login(&random_email, "password123").await;
+    accept_emails(&app).await;
+
+    // The verification email sent at signup counts towards the same limit
+    for _ in 1..VERIFICATION_EMAIL_LIMIT {
+        let response = app.post_email_2fa_enable().await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let response = app.post_email_2fa_enable().await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_409_if_2fa_already_enabled() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    app.signup_and_login(&random_email, "password123").await;
+    accept_emails(&app).await;
+
+    let response = enable_2fa(&app, &random_email).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_email_2fa_enable().await;
+    assert_eq!(response.status().as_u16(), 409);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "2FA already enabled"
+    );
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_disable_2fa_with_emailed_code() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    app.signup_and_login(&random_email, "password123").await;
+    accept_emails(&app).await;
+
+    let response = enable_2fa(&app, &random_email).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_email_2fa_disable().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let code = get_emailed_code(&app, &random_email, ConfirmationPurpose::DisableEmail2FA).await;
+
+    // A wrong code doesn't turn it off
+    let response = app
+        .post_email_2fa_disable_confirm(&serde_json::json!({ "2FACode": wrong_code(&code) }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_email_2fa_disable_confirm(&serde_json::json!({ "2FACode": code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response
+            .json::<Email2FAResponse>()
+            .await
+            .expect("Could not deserialize response body to Email2FAResponse")
+            .message,
+        "2FA has been turned off"
+    );
+
+    // Recovery codes went with it
+    let response = app.get_recovery_codes().await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response
+            .json::<RecoveryCodesResponse>()
+            .await
+            .expect("Could not deserialize response body to RecoveryCodesResponse")
+            .remaining,
+        0
+    );
+
+    let response = login(&app, &random_email).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_keep_login_codes_apart_from_confirmation_codes() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    app.signup_and_login(&random_email, "password123").await;
+    accept_emails(&app).await;
+
+    let response = enable_2fa(&app, &random_email).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = login(&app, &random_email).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+    let (_, login_code) = app
+        .two_fa_code_store
+        .read()
+        .await
+        .get_code(&app.get_user_id(&random_email).await)
+        .await
+        .expect("No 2FA code sent");
+    let login_code = login_code.as_ref().expose_secret().to_owned();
+
+    let response = app.post_email_2fa_disable().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The login code can't turn 2FA off
+    let response = app
+        .post_email_2fa_disable_confirm(&serde_json::json!({ "2FACode": login_code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // And the pending login can still be finished with it
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": login_code,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_disabling_2fa_that_is_not_enabled() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    app.signup_and_login(&random_email, "password123").await;
+
+    let response = app.post_email_2fa_disable().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .post_email_2fa_disable_confirm(&serde_json::json!({ "2FACode": "123456" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_email_2fa_enable().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app.post_email_2fa_disable().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    TestApp::cleanup(&mut app).await;
+}
@@ -5,18 +5,29 @@ use sqlx::{
     Connection, Executor, PgConnection, PgPool,
 };
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Once};
 use tokio::sync::RwLock;
 use wiremock::MockServer;
 
 use auth_service::{
-    domain::{AppState, BannedTokenStoreType, Email, TwoFACodeStoreType},
+    domain::{
+        AppState, BannedTokenStoreType, ConfirmationCodeStoreType, Email,
+        PasswordResetTokenStoreType, RefreshTokenStoreType, TwoFACodeStoreType, UserId,
+    },
     get_postgres_pool, get_redis_client,
     services::{
-        data_stores::PostgresUserStore, postmark_email_client::PostmarkEmailClient,
-        RedisBannedTokenStore, RedisTwoFACodeStore,
+        data_stores::{
+            HashmapLoginAttemptStore, PostgresRecoveryCodeStore, PostgresSessionStore,
+            PostgresTotpSecretStore, PostgresUserStore, PostgresWebAuthnCredentialStore,
+        },
+        postmark_email_client::PostmarkEmailClient,
+        RedisBannedTokenStore, RedisConfirmationCodeStore, RedisEmailChangeStore,
+        RedisEmailVerificationTokenStore, RedisPasswordResetTokenStore, RedisRefreshTokenStore,
+        RedisTwoFACodeStore, RedisWebAuthnChallengeStore,
+    },
+    utils::constants::{
+        env, test, DATABASE_URL, DEFAULT_REDIS_HOSTNAME, PASSWORD_HASH_PARAMS, PASSWORD_PEPPERS,
     },
-    utils::constants::{test, DATABASE_URL, DEFAULT_REDIS_HOSTNAME},
     Application,
 };
 
@@ -29,36 +40,133 @@ pub struct TestApp {
     pub cookie_jar: Arc<Jar>,
     pub banned_token_store: BannedTokenStoreType,
     pub two_fa_code_store: TwoFACodeStoreType,
+    pub confirmation_code_store: ConfirmationCodeStoreType,
+    pub refresh_token_store: RefreshTokenStoreType,
+    pub password_reset_token_store: PasswordResetTokenStoreType,
     pub http_client: reqwest::Client,
     pub email_server: MockServer,
     pub db_name: String,
+    // For checking what was stored, or setting up data the API can't create
+    pub pg_pool: PgPool,
     pub cleanup_called: bool,
 }
 
-impl<'a> TestApp {
+// Sign tokens with the Ed25519 key in the fixture key ring and accept tokens signed with its
+// previous RSA key, as a deployment part way through a key rotation would, and register a client
+// for token introspection. Passwords are peppered part way through a pepper rotation too, and any
+// password is strong enough, so tests can use simple ones. This has to happen before the
+// configuration is first loaded, and only once for every test in the binary.
+static CONFIGURE_ENVIRONMENT: Once = Once::new();
+
+pub const JWT_KEY_RING_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/jwt_key_ring.json"
+);
+pub const ACTIVE_KEY_ID: &str = "2026-10";
+pub const PREVIOUS_KEY_ID: &str = "2026-04";
+pub const INTROSPECTION_CLIENT_ID: &str = "resource-server";
+pub const INTROSPECTION_CLIENT_SECRET: &str = "resource-server-secret";
+pub const USER_AGENT: &str = "auth-service-tests";
+pub const PREVIOUS_KEY_PATH: &str =
+    concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/jwt_rsa.pem");
+pub const ACTIVE_PEPPER_ID: &str = "2026-10";
+pub const PREVIOUS_PEPPER_ID: &str = "2026-04";
+pub const PREVIOUS_PEPPER: &str = "previous-pepper";
+pub const BREACHED_PASSWORDS_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/breached_passwords.txt"
+);
+
+impl TestApp {
     pub async fn new() -> Self {
+        Self::with_app_state(|_| {}).await
+    }
+
+    // Build an app with settings that differ from the configured defaults
+    pub async fn with_app_state(configure: impl FnOnce(&mut AppState)) -> Self {
+        CONFIGURE_ENVIRONMENT.call_once(|| {
+            std::env::set_var(env::JWT_KEY_RING_PATH_ENV_VAR, JWT_KEY_RING_PATH);
+            std::env::set_var(
+                env::INTROSPECTION_CLIENTS_ENV_VAR,
+                format!("{INTROSPECTION_CLIENT_ID}:{INTROSPECTION_CLIENT_SECRET}"),
+            );
+            std::env::set_var(env::PASSWORD_MIN_STRENGTH_ENV_VAR, "0");
+            std::env::set_var(
+                env::PASSWORD_PEPPERS_ENV_VAR,
+                format!("{ACTIVE_PEPPER_ID}:active-pepper,{PREVIOUS_PEPPER_ID}:{PREVIOUS_PEPPER}"),
+            );
+            std::env::set_var(env::PASSWORD_PEPPER_ID_ENV_VAR, ACTIVE_PEPPER_ID);
+        });
+
         // We are creating a new database for each test case, and we need to ensure each database has a unique name!
         let db_name = Uuid::new_v4().to_string();
         let pg_pool = configure_postgresql(&db_name).await;
         let redis_connection = Arc::new(RwLock::new(configure_redis()));
 
-        let user_store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool)));
+        let user_store = Arc::new(RwLock::new(PostgresUserStore::new(
+            pg_pool.clone(),
+            PASSWORD_HASH_PARAMS.clone(),
+            PASSWORD_PEPPERS.clone(),
+        )));
+        let totp_secret_store =
+            Arc::new(RwLock::new(PostgresTotpSecretStore::new(pg_pool.clone())));
+        let recovery_code_store =
+            Arc::new(RwLock::new(PostgresRecoveryCodeStore::new(pg_pool.clone())));
+        let webauthn_credential_store = Arc::new(RwLock::new(
+            PostgresWebAuthnCredentialStore::new(pg_pool.clone()),
+        ));
+        let session_store = Arc::new(RwLock::new(PostgresSessionStore::new(pg_pool.clone())));
         let banned_token_store = Arc::new(RwLock::new(RedisBannedTokenStore::new(
             redis_connection.clone(),
         )));
-        let two_fa_code_store = Arc::new(RwLock::new(RedisTwoFACodeStore::new(redis_connection)));
+        let two_fa_code_store = Arc::new(RwLock::new(RedisTwoFACodeStore::new(
+            redis_connection.clone(),
+        )));
+        let refresh_token_store = Arc::new(RwLock::new(RedisRefreshTokenStore::new(
+            redis_connection.clone(),
+        )));
+        let password_reset_token_store = Arc::new(RwLock::new(RedisPasswordResetTokenStore::new(
+            redis_connection.clone(),
+        )));
+        let email_verification_token_store = Arc::new(RwLock::new(
+            RedisEmailVerificationTokenStore::new(redis_connection.clone()),
+        ));
+        let webauthn_challenge_store = Arc::new(RwLock::new(RedisWebAuthnChallengeStore::new(
+            redis_connection.clone(),
+        )));
+        let email_change_store = Arc::new(RwLock::new(RedisEmailChangeStore::new(
+            redis_connection.clone(),
+        )));
+        let confirmation_code_store = Arc::new(RwLock::new(RedisConfirmationCodeStore::new(
+            redis_connection,
+        )));
+        // Every test app logs in from 127.0.0.1, so each gets its own login limits rather than
+        // sharing them in Redis
+        let login_attempt_store = Arc::new(RwLock::new(HashmapLoginAttemptStore::default()));
 
         // Set up a mock email server
         let email_server = MockServer::start().await; // New!
         let base_url = email_server.uri(); // New!
         let email_client = Arc::new(configure_postmark_email_client(base_url));
 
-        let app_state = AppState::new(
+        let mut app_state = AppState::new(
             user_store,
             banned_token_store.clone(),
             two_fa_code_store.clone(),
             email_client,
+            refresh_token_store.clone(),
+            password_reset_token_store.clone(),
+            email_verification_token_store,
+            totp_secret_store,
+            recovery_code_store,
+            webauthn_credential_store,
+            webauthn_challenge_store,
+            session_store,
+            login_attempt_store,
+            email_change_store,
+            confirmation_code_store.clone(),
         );
+        configure(&mut app_state);
 
         // println!("App state: {:?}", &app_state);
 
@@ -77,6 +185,7 @@ impl<'a> TestApp {
         let cookie_jar = Arc::new(Jar::default());
         let http_client = reqwest::Client::builder()
             .cookie_provider(cookie_jar.clone())
+            .user_agent(USER_AGENT)
             .build()
             .expect("Failed to build HTTP client with cookie jar.");
 
@@ -86,16 +195,20 @@ impl<'a> TestApp {
             cookie_jar,
             banned_token_store,
             two_fa_code_store,
+            confirmation_code_store,
+            refresh_token_store,
+            password_reset_token_store,
             http_client,
             email_server,
             db_name,
+            pg_pool,
             cleanup_called: false,
         }
     }
 
     pub async fn get_root(&self) -> reqwest::Response {
         self.http_client
-            .get(&format!("{}/", &self.address))
+            .get(format!("{}/", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -106,7 +219,7 @@ impl<'a> TestApp {
         Body: serde::Serialize,
     {
         self.http_client
-            .post(&format!("{}/signup", &self.address))
+            .post(format!("{}/signup", &self.address))
             .json(body)
             .send()
             .await
@@ -118,16 +231,238 @@ impl<'a> TestApp {
         Body: serde::Serialize,
     {
         self.http_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_password_reset_request<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/password-reset/request", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_password_reset_confirm<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/password-reset/confirm", &self.address))
             .json(body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    pub async fn delete_account<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .delete(format!("{}/account", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_account_export(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/account/export", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_account_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/account/password", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_account_email<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/account/email", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_account_email_confirm(&self, token: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/account/email/confirm", &self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_account_email_cancel(&self, token: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/account/email/cancel", &self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_logout(&self) -> reqwest::Response {
         self.http_client
-            .post(&format!("{}/logout", &self.address))
+            .post(format!("{}/logout", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_logout_all(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/logout/all", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_refresh(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/refresh", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_totp_enroll<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/2fa/totp/enroll", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_totp_confirm<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/2fa/totp/confirm", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_email_2fa_enable(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/2fa/email/enable", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_email_2fa_enable_confirm<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/2fa/email/enable/confirm", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_email_2fa_disable(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/2fa/email/disable", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_email_2fa_disable_confirm<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/2fa/email/disable/confirm", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_recovery_codes(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/2fa/recovery-codes", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_recovery_codes<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/2fa/recovery-codes", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_webauthn_register_start<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/2fa/webauthn/register/start", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_webauthn_register_finish<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/2fa/webauthn/register/finish", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_webauthn_verify<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/2fa/webauthn/verify", &self.address))
+            .json(body)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -138,25 +473,168 @@ impl<'a> TestApp {
         Body: serde::Serialize,
     {
         self.http_client
-            .post(&format!("{}/verify-2fa", &self.address))
+            .post(format!("{}/verify-2fa", &self.address))
             .json(body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    pub async fn get_jwks(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/.well-known/jwks.json", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // Introspect a token, authenticating with HTTP Basic if credentials are given
+    pub async fn post_introspect<Body>(
+        &self,
+        body: &Body,
+        credentials: Option<(&str, &str)>,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let request = self
+            .http_client
+            .post(format!("{}/introspect", &self.address))
+            .form(body);
+
+        let request = match credentials {
+            Some((id, secret)) => request.basic_auth(id, Some(secret)),
+            None => request,
+        };
+
+        request.send().await.expect("Failed to execute request.")
+    }
+
+    pub async fn get_sessions(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/sessions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_session(&self, id: &str) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/sessions/{}", &self.address, id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_revoke<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/revoke", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_verify_token<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
     {
         self.http_client
-            .post(&format!("{}/verify-token", &self.address))
+            .post(format!("{}/verify-token", &self.address))
             .json(body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    pub async fn get_verify_email(&self, token: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/verify-email", &self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_resend_verification_email<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/verify-email/resend", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // Sign up a user without 2FA, verify their email and log them in, leaving the auth cookies
+    // in the cookie jar
+    pub async fn signup_and_login(&self, email: &str, password: &str) -> reqwest::Response {
+        let signup_body = serde_json::json!({
+            "email": email,
+            "password": password,
+            "requires2FA": false
+        });
+
+        let response = self.post_signup(&signup_body).await;
+        assert_eq!(response.status().as_u16(), 201);
+        self.verify_email(email).await;
+
+        let login_body = serde_json::json!({
+            "email": email,
+            "password": password,
+        });
+
+        let response = self.post_login(&login_body).await;
+        assert_eq!(response.status().as_u16(), 200);
+
+        response
+    }
+
+    // Find the most recent verification link emailed to `email` and follow it
+    pub async fn verify_email(&self, email: &str) {
+        let token = self
+            .get_verification_token_from_email(email)
+            .await
+            .expect("No verification email sent");
+
+        let response = self.get_verify_email(&token).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // Stores are keyed on the user's id rather than the email address they signed up with
+    pub async fn get_user_id(&self, email: &str) -> UserId {
+        let id: Uuid = sqlx::query_scalar("SELECT id FROM users WHERE email = $1")
+            .bind(email)
+            .fetch_one(&self.pg_pool)
+            .await
+            .expect("No user with this email");
+
+        UserId::from(id)
+    }
+
+    pub async fn get_verification_token_from_email(&self, email: &str) -> Option<String> {
+        let requests = self
+            .email_server
+            .received_requests()
+            .await
+            .expect("Request recording is disabled");
+
+        requests.iter().rev().find_map(|request| {
+            let body: serde_json::Value = request.body_json().ok()?;
+            if body["To"].as_str()? != email {
+                return None;
+            }
+            let text = body["TextBody"].as_str()?;
+            let token = text.split("verify-email?token=").nth(1)?;
+            token.split_whitespace().next().map(str::to_owned)
+        })
+    }
+
     pub async fn cleanup(&mut self) {
         if !self.cleanup_called {
             delete_database(&self.db_name).await;
@@ -165,7 +643,7 @@ impl<'a> TestApp {
     }
 }
 
-impl<'a> Drop for TestApp {
+impl Drop for TestApp {
     fn drop(&mut self) {
         if !self.cleanup_called {
             panic!("TestApp::clean_up was not called before dropping TestApp");
@@ -183,7 +661,7 @@ async fn configure_postgresql(db_name: &str) -> PgPool {
     // We are creating a new database for each test case, and we need to ensure each database has a unique name!
     // let db_name = Uuid::new_v4().to_string();
 
-    configure_database(&postgresql_conn_url, &db_name).await;
+    configure_database(&postgresql_conn_url, db_name).await;
 
     let postgresql_conn_url_with_db = Secret::new(format!(
         "{}/{}",
@@ -263,7 +741,7 @@ async fn delete_database(db_name: &str) {
         .expect("Failed to drop the database.");
 }
 
-fn configure_redis() -> redis::Connection {
+pub fn configure_redis() -> redis::Connection {
     let redis_hostname = DEFAULT_REDIS_HOSTNAME.to_owned();
 
     get_redis_client(redis_hostname)
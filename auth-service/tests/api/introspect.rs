@@ -0,0 +1,128 @@
+use auth_service::{
+    utils::constants::{JWT_AUDIENCE, JWT_COOKIE_NAME, JWT_ISSUER},
+    ErrorResponse,
+};
+
+use crate::helpers::{
+    get_random_email, TestApp, INTROSPECTION_CLIENT_ID, INTROSPECTION_CLIENT_SECRET,
+};
+
+const CREDENTIALS: Option<(&str, &str)> =
+    Some((INTROSPECTION_CLIENT_ID, INTROSPECTION_CLIENT_SECRET));
+
+async fn login_and_get_token(app: &TestApp, email: &str) -> String {
+    let response = app.signup_and_login(email, "password123").await;
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+    token
+}
+
+#[tokio::test]
+async fn should_return_token_details_for_active_token() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+    let token = login_and_get_token(&app, &random_email).await;
+
+    let body = serde_json::json!({ "token": token, "token_type_hint": "access_token" });
+    let response = app.post_introspect(&body, CREDENTIALS).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let introspection = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(introspection["active"], true);
+    // Tokens identify the user by id, so their email address isn't exposed
+    let user_id = app.get_user_id(&random_email).await;
+    assert_eq!(introspection["sub"], user_id.to_string().as_str());
+    assert_eq!(introspection["token_type"], "Bearer");
+    assert_eq!(introspection["scope"], "account");
+    assert_eq!(introspection["client_id"], JWT_AUDIENCE.as_str());
+    assert_eq!(introspection["aud"], JWT_AUDIENCE.as_str());
+    assert_eq!(introspection["iss"], JWT_ISSUER.as_str());
+    assert!(introspection["jti"].is_string());
+
+    let iat = introspection["iat"].as_u64().unwrap();
+    let exp = introspection["exp"].as_u64().unwrap();
+    assert_eq!(exp - iat, 600);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_accept_client_credentials_in_body() {
+    let mut app = TestApp::new().await;
+    let token = login_and_get_token(&app, &get_random_email()).await;
+
+    let body = serde_json::json!({
+        "token": token,
+        "client_id": INTROSPECTION_CLIENT_ID,
+        "client_secret": INTROSPECTION_CLIENT_SECRET,
+    });
+    let response = app.post_introspect(&body, None).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let introspection = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(introspection["active"], true);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_only_active_false_for_invalid_tokens() {
+    let mut app = TestApp::new().await;
+    let token = login_and_get_token(&app, &get_random_email()).await;
+
+    // A token that was banned by logging out is no longer active
+    let response = app.post_logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    for token in [token.as_str(), "invalid_token"] {
+        let body = serde_json::json!({ "token": token });
+        let response = app.post_introspect(&body, CREDENTIALS).await;
+        assert_eq!(response.status().as_u16(), 200);
+
+        let introspection = response.json::<serde_json::Value>().await.unwrap();
+        assert_eq!(introspection, serde_json::json!({ "active": false }));
+    }
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_401_without_valid_client_credentials() {
+    let mut app = TestApp::new().await;
+    let token = login_and_get_token(&app, &get_random_email()).await;
+    let body = serde_json::json!({ "token": token });
+
+    let test_cases = [
+        None,
+        Some((INTROSPECTION_CLIENT_ID, "wrong-secret")),
+        Some(("unknown-client", INTROSPECTION_CLIENT_SECRET)),
+    ];
+
+    for credentials in test_cases {
+        let response = app.post_introspect(&body, credentials).await;
+        assert_eq!(
+            response.status().as_u16(),
+            401,
+            "Failed for credentials: {:?}",
+            credentials
+        );
+        assert!(response
+            .headers()
+            .get("www-authenticate")
+            .is_some_and(|value| value.to_str().unwrap().starts_with("Basic")));
+        assert_eq!(
+            response
+                .json::<ErrorResponse>()
+                .await
+                .expect("Could not deserialize response body to ErrorResponse")
+                .error,
+            "Invalid client".to_owned()
+        );
+    }
+
+    TestApp::cleanup(&mut app).await;
+}
@@ -0,0 +1,163 @@
+use auth_service::{
+    routes::ListSessionsResponse,
+    utils::{
+        auth::Claims,
+        constants::{JWT_AUDIENCE, JWT_COOKIE_NAME, JWT_ISSUER, JWT_SECRET},
+    },
+};
+use jsonwebtoken::{decode, decode_header, encode, jwk::JwkSet, DecodingKey, EncodingKey, Header};
+use secrecy::ExposeSecret;
+
+use crate::helpers::{
+    get_random_email, TestApp, ACTIVE_KEY_ID, PREVIOUS_KEY_ID, PREVIOUS_KEY_PATH,
+};
+
+#[tokio::test]
+async fn should_publish_every_key_in_the_ring() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_jwks().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let jwks = response.json::<serde_json::Value>().await.unwrap();
+    let keys = jwks["keys"].as_array().unwrap();
+    assert_eq!(keys.len(), 2);
+
+    // The active key comes first
+    let active = &keys[0];
+    assert_eq!(active["kid"], ACTIVE_KEY_ID);
+    assert_eq!(active["kty"], "OKP");
+    assert_eq!(active["crv"], "Ed25519");
+    assert_eq!(active["alg"], "EdDSA");
+    assert_eq!(active["use"], "sig");
+
+    let previous = &keys[1];
+    assert_eq!(previous["kid"], PREVIOUS_KEY_ID);
+    assert_eq!(previous["kty"], "RSA");
+    assert_eq!(previous["alg"], "RS256");
+
+    // Only the public halves are published
+    assert!(keys.iter().all(|key| key.get("d").is_none()));
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_verify_auth_token_with_published_key() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    let response = app.signup_and_login(&random_email, "password123").await;
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    // Verify the token the way a downstream service would, using only the JWKS
+    let jwks = app.get_jwks().await.json::<JwkSet>().await.unwrap();
+    let header = decode_header(&token).unwrap();
+    let kid = header.kid.expect("No kid in token header");
+    assert_eq!(kid, ACTIVE_KEY_ID);
+    let jwk = jwks.find(&kid).expect("Token kid not in JWKS");
+
+    let mut validation = jsonwebtoken::Validation::new(header.alg);
+    validation.set_audience(&[JWT_AUDIENCE.as_str()]);
+    validation.set_issuer(&[JWT_ISSUER.as_str()]);
+    let claims = decode::<Claims>(&token, &DecodingKey::from_jwk(jwk).unwrap(), &validation)
+        .unwrap()
+        .claims;
+    assert_eq!(claims.sub, app.get_user_id(&random_email).await.to_string());
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_reject_token_signed_with_shared_secret() {
+    let mut app = TestApp::new().await;
+
+    // A well-formed HS256 token from before the signing key was configured
+    let claims = long_lived_claims(&app).await;
+    let token = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(JWT_SECRET.expose_secret().as_bytes()),
+    )
+    .unwrap();
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+// Claims for a token that is valid apart from its signature, issued to a new user's session
+async fn long_lived_claims(app: &TestApp) -> Claims {
+    let random_email = get_random_email();
+    app.signup_and_login(&random_email, "password123").await;
+
+    let session_id = app
+        .get_sessions()
+        .await
+        .json::<ListSessionsResponse>()
+        .await
+        .expect("Could not deserialize response body to ListSessionsResponse")
+        .sessions[0]
+        .id
+        .clone();
+
+    Claims {
+        iss: JWT_ISSUER.to_owned(),
+        aud: JWT_AUDIENCE.to_owned(),
+        sub: app.get_user_id(&random_email).await.to_string(),
+        exp: 4_102_444_800, // 2100-01-01
+        iat: 1_767_225_600, // 2026-01-01
+        nbf: 1_767_225_600,
+        jti: uuid::Uuid::new_v4().to_string(),
+        sid: session_id,
+        generation: 0,
+    }
+}
+
+async fn sign_with_previous_key(app: &TestApp, kid: &str) -> String {
+    let claims = long_lived_claims(app).await;
+    let pem = std::fs::read(PREVIOUS_KEY_PATH).unwrap();
+    let header = Header {
+        kid: Some(kid.to_owned()),
+        ..Header::new(jsonwebtoken::Algorithm::RS256)
+    };
+    encode(&header, &claims, &EncodingKey::from_rsa_pem(&pem).unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn should_accept_token_signed_with_verification_key() {
+    let mut app = TestApp::new().await;
+
+    // Issued before the RSA key was rotated out, and still within its lifetime
+    let token = sign_with_previous_key(&app, PREVIOUS_KEY_ID).await;
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_reject_token_with_unknown_kid() {
+    let mut app = TestApp::new().await;
+
+    // Correctly signed, but with a key the ring doesn't know by that id
+    let token = sign_with_previous_key(&app, "retired").await;
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    TestApp::cleanup(&mut app).await;
+}
@@ -1,6 +1,21 @@
-use crate::helpers::{get_random_email, TestApp};
+use crate::helpers::{
+    get_random_email, TestApp, ACTIVE_PEPPER_ID, PREVIOUS_PEPPER, PREVIOUS_PEPPER_ID,
+};
+use argon2::{
+    password_hash::{rand_core::OsRng, SaltString},
+    Algorithm, Argon2, Params, PasswordHasher, Version,
+};
 use auth_service::{
-    domain::Email, routes::TwoFactorAuthResponse, utils::constants::JWT_COOKIE_NAME, ErrorResponse,
+    routes::TwoFactorAuthResponse,
+    utils::{
+        constants::{
+            DEFAULT_LOGIN_IP_LIMIT, DEFAULT_LOGIN_IP_WINDOW_SECONDS,
+            DEFAULT_LOGIN_LOCKOUT_BASE_SECONDS, DEFAULT_LOGIN_LOCKOUT_THRESHOLD, JWT_COOKIE_NAME,
+            PASSWORD_HASH_PARAMS,
+        },
+        password_hashing::needs_rehash,
+    },
+    ErrorResponse,
 };
 use secrecy::{ExposeSecret, Secret};
 use wiremock::{
@@ -63,12 +78,9 @@ async fn should_return_400_if_invalid_input() {
 
     let response = app.post_signup(&user).await;
     assert_eq!(response.status().as_u16(), 201);
+    app.verify_email(&random_email).await;
 
     let test_bodies = [
-        serde_json::json!({             // password too short
-            "email": random_email,
-            "password": "pass123",
-        }),
         serde_json::json!({             // empty password field
             "email": random_email,
             "password": "",
@@ -115,12 +127,17 @@ async fn should_return_401_if_incorrect_password() {
 
     let response = app.post_signup(&user).await;
     assert_eq!(response.status().as_u16(), 201);
+    app.verify_email(&random_email).await;
 
     let test_bodies = [
         serde_json::json!({             // valid but incorrect password
             "email": random_email,
             "password": "password1234",
         }),
+        serde_json::json!({             // shorter than a new password could be
+            "email": random_email,
+            "password": "pass123",
+        }),
     ];
 
     for body in test_bodies.iter() {
@@ -158,6 +175,7 @@ async fn should_return_404_if_incorrect_username() {
 
     let response = app.post_signup(&user).await;
     assert_eq!(response.status().as_u16(), 201);
+    app.verify_email(&random_email).await;
 
     let test_bodies = [serde_json::json!({             // valid but incorrect email
         "email": "me@example.com",
@@ -199,6 +217,7 @@ async fn should_return_200_if_valid_credentials_and_2fa_disabled() {
 
     let response = app.post_signup(&signup_body).await;
     assert_eq!(response.status().as_u16(), 201);
+    app.verify_email(&random_email).await;
 
     let login_body = serde_json::json!({
         "email": random_email,
@@ -231,6 +250,7 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
 
     let response = app.post_signup(&signup_body).await;
     assert_eq!(response.status().as_u16(), 201);
+    app.verify_email(&random_email).await;
 
     // Define an expectation for the mock server
     Mock::given(path("/email")) // Expect an HTTP request to the "/email" path
@@ -260,7 +280,7 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
     let two_fa_code_store = app.two_fa_code_store.read().await;
 
     let code_tuple = &two_fa_code_store
-        .get_code(&Email::parse(Secret::new(random_email)).unwrap())
+        .get_code(&app.get_user_id(&random_email).await)
         .await
         .expect("Failed to get 2FA code");
 
@@ -272,3 +292,281 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
 
     TestApp::cleanup(&mut app).await;
 }
+
+#[tokio::test]
+async fn should_return_429_after_too_many_attempts_from_one_ip_address() {
+    let mut app = TestApp::new().await;
+
+    // Every attempt counts against the IP address, whichever account it is for
+    for _ in 0..DEFAULT_LOGIN_IP_LIMIT {
+        let body = serde_json::json!({
+            "email": get_random_email(),
+            "password": "password123",
+        });
+        let response = app.post_login(&body).await;
+        assert_eq!(response.status().as_u16(), 404);
+    }
+
+    let body = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123",
+    });
+    let response = app.post_login(&body).await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    let retry_after: u64 = response.headers()["retry-after"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= DEFAULT_LOGIN_IP_WINDOW_SECONDS);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Too many login attempts".to_owned()
+    );
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_lock_account_after_consecutive_failures() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    let user = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false,
+    });
+    let response = app.post_signup(&user).await;
+    assert_eq!(response.status().as_u16(), 201);
+    app.verify_email(&random_email).await;
+
+    let wrong_password = serde_json::json!({
+        "email": random_email,
+        "password": "password1234",
+    });
+    for _ in 0..DEFAULT_LOGIN_LOCKOUT_THRESHOLD {
+        let response = app.post_login(&wrong_password).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // Even the right password is turned away until the lockout ends
+    let right_password = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&right_password).await;
+    assert_eq!(response.status().as_u16(), 429);
+    assert_eq!(
+        response.headers()["retry-after"],
+        DEFAULT_LOGIN_LOCKOUT_BASE_SECONDS.to_string().as_str()
+    );
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_reset_failures_after_successful_login() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+    app.signup_and_login(&random_email, "password123").await;
+
+    let wrong_password = serde_json::json!({
+        "email": random_email,
+        "password": "password1234",
+    });
+    let right_password = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+
+    // Failures either side of a successful login are not consecutive
+    for _ in 1..DEFAULT_LOGIN_LOCKOUT_THRESHOLD {
+        let response = app.post_login(&wrong_password).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+    let response = app.post_login(&right_password).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_login(&wrong_password).await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app.post_login(&right_password).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_401_for_unknown_user_in_enumeration_safe_mode() {
+    let mut app = TestApp::with_app_state(|state| state.enumeration_safe = true).await;
+    let random_email = get_random_email();
+    app.signup_and_login(&random_email, "password123").await;
+
+    // An unknown user and a wrong password get the same response
+    let test_bodies = [
+        serde_json::json!({
+            "email": get_random_email(),
+            "password": "password123",
+        }),
+        serde_json::json!({
+            "email": random_email,
+            "password": "password1234",
+        }),
+    ];
+
+    for body in test_bodies.iter() {
+        let response = app.post_login(&body).await;
+        assert_eq!(
+            response.status().as_u16(),
+            401,
+            "Failed for input: {:?}",
+            body
+        );
+        assert_eq!(
+            response
+                .json::<ErrorResponse>()
+                .await
+                .expect("Could not deserialize response body to ErrorResponse")
+                .error,
+            "Incorrect credentials".to_owned()
+        );
+    }
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_rehash_password_with_outdated_params_on_login() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    app.signup_and_login(&random_email, "password123").await;
+
+    // As if the password was hashed before the parameters were changed, and before there was a
+    // pepper
+    let salt = SaltString::generate(&mut OsRng);
+    let outdated_hash = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(8192, 1, 1, None).unwrap(),
+    )
+    .hash_password(b"password123", &salt)
+    .unwrap()
+    .to_string();
+    sqlx::query("UPDATE users SET password_hash = $2, password_pepper_id = NULL WHERE email = $1")
+        .bind(&random_email)
+        .bind(&outdated_hash)
+        .execute(&app.pg_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": random_email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let (password_hash, pepper_id): (String, Option<String>) =
+        sqlx::query_as("SELECT password_hash, password_pepper_id FROM users WHERE email = $1")
+            .bind(&random_email)
+            .fetch_one(&app.pg_pool)
+            .await
+            .unwrap();
+    assert_ne!(password_hash, outdated_hash);
+    assert!(!needs_rehash(
+        &Secret::new(password_hash),
+        &PASSWORD_HASH_PARAMS
+    ));
+    assert_eq!(pepper_id.as_deref(), Some(ACTIVE_PEPPER_ID));
+
+    // The new hash still works
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": random_email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_rehash_password_with_previous_pepper_on_login() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    app.signup_and_login(&random_email, "password123").await;
+
+    let pepper_id: Option<String> =
+        sqlx::query_scalar("SELECT password_pepper_id FROM users WHERE email = $1")
+            .bind(&random_email)
+            .fetch_one(&app.pg_pool)
+            .await
+            .unwrap();
+    assert_eq!(pepper_id.as_deref(), Some(ACTIVE_PEPPER_ID));
+
+    // As if the password was hashed before the pepper was rotated
+    let salt = SaltString::generate(&mut OsRng);
+    let previous_hash = Argon2::new_with_secret(
+        PREVIOUS_PEPPER.as_bytes(),
+        Algorithm::Argon2id,
+        Version::V0x13,
+        PASSWORD_HASH_PARAMS.clone(),
+    )
+    .unwrap()
+    .hash_password(b"password123", &salt)
+    .unwrap()
+    .to_string();
+    sqlx::query("UPDATE users SET password_hash = $2, password_pepper_id = $3 WHERE email = $1")
+        .bind(&random_email)
+        .bind(&previous_hash)
+        .bind(PREVIOUS_PEPPER_ID)
+        .execute(&app.pg_pool)
+        .await
+        .unwrap();
+
+    // A wrong password is still wrong with the previous pepper
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": random_email,
+            "password": "password1234",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": random_email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let (password_hash, pepper_id): (String, Option<String>) =
+        sqlx::query_as("SELECT password_hash, password_pepper_id FROM users WHERE email = $1")
+            .bind(&random_email)
+            .fetch_one(&app.pg_pool)
+            .await
+            .unwrap();
+    assert_ne!(password_hash, previous_hash);
+    assert_eq!(pepper_id.as_deref(), Some(ACTIVE_PEPPER_ID));
+
+    // The new hash still works
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": random_email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    TestApp::cleanup(&mut app).await;
+}
@@ -1,9 +1,23 @@
-use auth_service::{utils::constants::JWT_COOKIE_NAME, ErrorResponse};
+use auth_service::{
+    services::{RefreshToken, TokenId},
+    utils::{
+        auth::decode_token,
+        constants::{JWT_COOKIE_NAME, REFRESH_COOKIE_NAME},
+    },
+    ErrorResponse,
+};
 use reqwest::Url;
 use secrecy::Secret;
 
 use crate::helpers::{get_random_email, TestApp};
 
+fn set_cookie(app: &TestApp, name: &str, value: &str) {
+    app.cookie_jar.add_cookie_str(
+        &format!("{}={}; HttpOnly; SameSite=Lax; Path=/", name, value),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+}
+
 #[tokio::test]
 async fn should_return_200_if_valid_jwt_cookie() {
     let mut app = TestApp::new().await;
@@ -18,6 +32,7 @@ async fn should_return_200_if_valid_jwt_cookie() {
 
     let response = app.post_signup(&signup_body).await;
     assert_eq!(response.status().as_u16(), 201);
+    app.verify_email(&random_email).await;
 
     let login_body = serde_json::json!({
         "email": random_email,
@@ -35,6 +50,11 @@ async fn should_return_200_if_valid_jwt_cookie() {
     assert!(!auth_cookie.value().is_empty());
 
     let token = Secret::new(auth_cookie.value().to_owned());
+    let claims = decode_token(&token, app.banned_token_store.clone())
+        .await
+        .unwrap();
+    let token_id = TokenId::parse(claims.jti).unwrap();
+
     let response = app.post_logout().await;
 
     let status = response.status().as_u16();
@@ -48,9 +68,9 @@ async fn should_return_200_if_valid_jwt_cookie() {
     assert!(auth_cookie.value().is_empty());
 
     let banned_token_store = app.banned_token_store.read().await;
-    let is_banned = banned_token_store.is_token_banned(&token).await.is_ok();
+    let is_banned = banned_token_store.is_token_banned(&token_id).await;
 
-    assert!(is_banned);
+    assert_eq!(is_banned, Ok(true));
     drop(banned_token_store);
 
     TestApp::cleanup(&mut app).await;
@@ -70,6 +90,7 @@ async fn should_return_400_if_logout_called_twice_in_a_row() {
 
     let response = app.post_signup(&signup_body).await;
     assert_eq!(response.status().as_u16(), 201);
+    app.verify_email(&random_email).await;
 
     let login_body = serde_json::json!({
         "email": random_email,
@@ -155,3 +176,173 @@ async fn should_return_401_if_invalid_token() {
 
     TestApp::cleanup(&mut app).await;
 }
+
+#[tokio::test]
+async fn should_revoke_refresh_token_on_logout() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+    app.verify_email(&random_email).await;
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let refresh_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == REFRESH_COOKIE_NAME)
+        .expect("No refresh cookie found");
+
+    let token = RefreshToken::parse(Secret::new(refresh_cookie.value().to_owned())).unwrap();
+
+    let response = app.post_logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let refresh_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == REFRESH_COOKIE_NAME)
+        .expect("No refresh cookie found");
+
+    assert!(refresh_cookie.value().is_empty());
+
+    // The refresh token's family is revoked server-side
+    let refresh_token_store = app.refresh_token_store.read().await;
+    let record = refresh_token_store.get_token(&token).await.unwrap();
+    let is_revoked = refresh_token_store
+        .is_family_revoked(&record.family_id)
+        .await
+        .unwrap();
+
+    assert!(is_revoked);
+    drop(refresh_token_store);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_revoke_refresh_token_on_logout_without_refresh_cookie() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    let response = app.signup_and_login(&random_email, "password123").await;
+    let refresh_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == REFRESH_COOKIE_NAME)
+        .expect("No refresh cookie found");
+    let refresh_token = refresh_cookie.value().to_owned();
+
+    // The client logs out without the refresh token it was given
+    set_cookie(&app, REFRESH_COOKIE_NAME, "stripped");
+
+    let response = app.post_logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The session's refresh token is revoked all the same, and the session isn't brought back
+    set_cookie(&app, REFRESH_COOKIE_NAME, &refresh_token);
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let sessions: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sessions")
+        .fetch_one(&app.pg_pool)
+        .await
+        .unwrap();
+    assert_eq!(sessions, 0);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_revoke_every_session_when_logging_out_everywhere() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+
+    // Log in twice, as if from two devices
+    let response = app.signup_and_login(&random_email, "password123").await;
+    let first_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let second_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    let response = app.post_logout_all().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+    assert!(auth_cookie.value().is_empty());
+
+    // The cookies are gone from the client too
+    let response = app.post_logout().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    for token in [first_token, second_token] {
+        let response = app
+            .post_verify_token(&serde_json::json!({ "token": token }))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // Logging in again issues a token that is valid
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_logging_out_everywhere_without_jwt_cookie() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_logout_all().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let body: ErrorResponse = response
+        .json()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    assert_eq!(body.error, "Missing auth token");
+
+    TestApp::cleanup(&mut app).await;
+}
@@ -1,7 +1,20 @@
+mod account;
+mod email_2fa;
 mod helpers;
+mod introspect;
+mod jwks;
 mod login;
 mod logout;
+mod password_reset;
+mod recovery_codes;
+mod refresh;
+mod revoke;
 mod root;
+mod sessions;
 mod signup;
+mod totp;
+mod user_import;
 mod verify_2fa;
+mod verify_email;
 mod verify_token;
+mod webauthn;
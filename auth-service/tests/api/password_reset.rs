@@ -0,0 +1,319 @@
+use std::{sync::Arc, time::Duration};
+
+use auth_service::{
+    domain::{PasswordPolicyViolation, StandardPasswordPolicy},
+    services::{PasswordResetToken, PasswordResetTokenStoreError},
+    utils::constants::JWT_COOKIE_NAME,
+    ErrorResponse,
+};
+use secrecy::Secret;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{get_random_email, TestApp};
+
+async fn signup_and_login(app: &TestApp, email: &str) -> String {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+    app.verify_email(email).await;
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+
+    auth_cookie.value().to_owned()
+}
+
+// Pull the reset token out of the link in the last reset email sent to the mock email server.
+// Reset emails are sent after the response, so wait a little for one to arrive.
+async fn get_reset_token_from_email(app: &TestApp) -> String {
+    for _ in 0..50 {
+        let requests = app
+            .email_server
+            .received_requests()
+            .await
+            .expect("Request recording is disabled");
+        let token = requests.iter().rev().find_map(|request| {
+            let body: serde_json::Value = request.body_json().ok()?;
+            let text = body["TextBody"].as_str()?;
+            let token = text
+                .split("reset_token=")
+                .nth(1)?
+                .split_whitespace()
+                .next()?;
+            Some(token.to_owned())
+        });
+
+        if let Some(token) = token {
+            return token;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    panic!("No password reset email sent");
+}
+
+async fn mount_email_mock(app: &TestApp, expected_emails: u64) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(expected_emails)
+        .mount(&app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn should_return_200_and_send_email_if_user_exists() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    signup_and_login(&app, &random_email).await;
+    mount_email_mock(&app, 1).await;
+
+    let response = app
+        .post_password_reset_request(&serde_json::json!({ "email": random_email }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let token = get_reset_token_from_email(&app).await;
+    assert_eq!(token.len(), 64);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_200_without_sending_email_if_user_does_not_exist() {
+    let mut app = TestApp::new().await;
+
+    mount_email_mock(&app, 0).await;
+
+    let response = app
+        .post_password_reset_request(&serde_json::json!({ "email": get_random_email() }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_invalid_email() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_password_reset_request(&serde_json::json!({ "email": "invalid_email" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_reset_password_and_revoke_existing_sessions() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    let old_auth_token = signup_and_login(&app, &random_email).await;
+    mount_email_mock(&app, 1).await;
+
+    app.post_password_reset_request(&serde_json::json!({ "email": random_email }))
+        .await;
+    let token = get_reset_token_from_email(&app).await;
+
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "password": "new_password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The old password no longer works, the new one does
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": random_email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": random_email,
+            "password": "new_password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Tokens issued before the reset are rejected
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": old_auth_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_reset_token_reused() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    signup_and_login(&app, &random_email).await;
+    mount_email_mock(&app, 1).await;
+
+    app.post_password_reset_request(&serde_json::json!({ "email": random_email }))
+        .await;
+    let token = get_reset_token_from_email(&app).await;
+
+    let confirm_body = serde_json::json!({
+        "token": token,
+        "password": "new_password123",
+    });
+
+    let response = app.post_password_reset_confirm(&confirm_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The token was consumed by the first confirmation
+    let token = PasswordResetToken::parse(Secret::new(token)).unwrap();
+    let result = app
+        .password_reset_token_store
+        .write()
+        .await
+        .take_token(&token)
+        .await;
+    assert_eq!(result, Err(PasswordResetTokenStoreError::TokenNotFound));
+
+    let response = app.post_password_reset_confirm(&confirm_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_invalid_reset_token() {
+    let mut app = TestApp::new().await;
+
+    let test_cases = ["invalid", &"a".repeat(64)];
+
+    for token in test_cases {
+        let response = app
+            .post_password_reset_confirm(&serde_json::json!({
+                "token": token,
+                "password": "new_password123",
+            }))
+            .await;
+        assert_eq!(
+            response.status().as_u16(),
+            401,
+            "Failed for token: {}",
+            token
+        );
+
+        let body: ErrorResponse = response
+            .json()
+            .await
+            .expect("Failed to parse response body");
+        assert_eq!(body.error, "Invalid auth token");
+    }
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_400_and_keep_token_if_invalid_password() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    signup_and_login(&app, &random_email).await;
+    mount_email_mock(&app, 1).await;
+
+    app.post_password_reset_request(&serde_json::json!({ "email": random_email }))
+        .await;
+    let token = get_reset_token_from_email(&app).await;
+
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "password": "short",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    // The token can still be used with a valid password
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "password": "new_password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_400_and_keep_token_if_password_does_not_meet_policy() {
+    let mut app = TestApp::with_app_state(|state| {
+        state.password_policy = Arc::new(StandardPasswordPolicy::default());
+    })
+    .await;
+    let random_email = get_random_email();
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": random_email,
+            "password": "x7#Qm9!vLp2@",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    app.verify_email(&random_email).await;
+    mount_email_mock(&app, 1).await;
+
+    app.post_password_reset_request(&serde_json::json!({ "email": random_email }))
+        .await;
+    let token = get_reset_token_from_email(&app).await;
+
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "password": "password1234",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    let body = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    assert!(matches!(
+        body.reasons[..],
+        [PasswordPolicyViolation::TooWeak { .. }]
+    ));
+
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "password": "Zq8vN3kTw5Rb",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    TestApp::cleanup(&mut app).await;
+}
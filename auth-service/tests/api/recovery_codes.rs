@@ -0,0 +1,221 @@
+use auth_service::{
+    routes::{
+        RecoveryCodesResponse, RegenerateRecoveryCodesResponse, SignupResponse,
+        TwoFactorAuthResponse,
+    },
+    utils::constants::{JWT_COOKIE_NAME, RECOVERY_CODE_COUNT},
+    ErrorResponse,
+};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Sign up with 2FA and verify the email, returning the recovery codes from the signup response
+async fn signup_with_2fa(app: &TestApp, email: &str) -> Vec<String> {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": true
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let body: SignupResponse = response
+        .json()
+        .await
+        .expect("Could not deserialize response body to SignupResponse");
+
+    app.verify_email(email).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    body.recovery_codes
+}
+
+// Log in and complete 2FA with `code` instead of the emailed code
+async fn login_with_code(app: &TestApp, email: &str, code: &str) -> reqwest::Response {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let body: TwoFactorAuthResponse = response
+        .json()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+
+    let verify_2fa_body = serde_json::json!({
+        "email": email,
+        "loginAttemptId": body.login_attempt_id,
+        "2FACode": code,
+    });
+
+    app.post_verify_2fa(&verify_2fa_body).await
+}
+
+async fn remaining_codes(app: &TestApp) -> usize {
+    let response = app.get_recovery_codes().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    response
+        .json::<RecoveryCodesResponse>()
+        .await
+        .expect("Could not deserialize response body to RecoveryCodesResponse")
+        .remaining
+}
+
+#[tokio::test]
+async fn should_login_with_recovery_code_and_burn_it() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    let codes = signup_with_2fa(&app, &email).await;
+    assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+
+    let response = login_with_code(&app, &email, &codes[0]).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+    assert!(!auth_cookie.value().is_empty());
+
+    assert_eq!(remaining_codes(&app).await, RECOVERY_CODE_COUNT - 1);
+
+    let response = login_with_code(&app, &email, &codes[0]).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_accept_recovery_code_without_separator_in_any_case() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    let codes = signup_with_2fa(&app, &email).await;
+    let code = codes[0].replace('-', "").to_uppercase();
+
+    let response = login_with_code(&app, &email, &code).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_unknown_recovery_code() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    signup_with_2fa(&app, &email).await;
+
+    let response = login_with_code(&app, &email, "abcde-fghjk").await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_replace_recovery_codes_on_regenerate() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    let old_codes = signup_with_2fa(&app, &email).await;
+
+    let response = login_with_code(&app, &email, &old_codes[0]).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_recovery_codes(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body: RegenerateRecoveryCodesResponse = response
+        .json()
+        .await
+        .expect("Could not deserialize response body to RegenerateRecoveryCodesResponse");
+    assert_eq!(body.recovery_codes.len(), RECOVERY_CODE_COUNT);
+
+    assert_eq!(remaining_codes(&app).await, RECOVERY_CODE_COUNT);
+
+    let response = login_with_code(&app, &email, &old_codes[1]).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = login_with_code(&app, &email, &body.recovery_codes[0]).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_regenerating_with_wrong_password() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    let old_codes = signup_with_2fa(&app, &email).await;
+
+    let response = login_with_code(&app, &email, &old_codes[0]).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_recovery_codes(&serde_json::json!({ "password": "wrongpassword" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The old codes are still the ones that work
+    assert_eq!(remaining_codes(&app).await, RECOVERY_CODE_COUNT - 1);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_2fa_not_enabled() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    app.signup_and_login(&email, "password123").await;
+
+    assert_eq!(remaining_codes(&app).await, 0);
+
+    let response = app
+        .post_recovery_codes(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "2FA not enabled".to_owned()
+    );
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_not_logged_in() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_recovery_codes().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .post_recovery_codes(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    TestApp::cleanup(&mut app).await;
+}
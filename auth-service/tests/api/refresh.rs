@@ -0,0 +1,186 @@
+use auth_service::{
+    services::{hash_token, RefreshToken},
+    utils::constants::{JWT_COOKIE_NAME, REFRESH_COOKIE_NAME},
+    ErrorResponse,
+};
+use reqwest::Url;
+use secrecy::{ExposeSecret, Secret};
+
+use crate::helpers::{configure_redis, get_random_email, TestApp};
+
+// Sign up and log in a user without 2FA, returning the refresh token issued at login
+async fn login_and_get_refresh_token(app: &TestApp) -> String {
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+    app.verify_email(&random_email).await;
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let refresh_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == REFRESH_COOKIE_NAME)
+        .expect("No refresh cookie found");
+
+    assert!(!refresh_cookie.value().is_empty());
+
+    refresh_cookie.value().to_owned()
+}
+
+fn set_refresh_cookie(app: &TestApp, token: &str) {
+    app.cookie_jar.add_cookie_str(
+        &format!(
+            "{}={}; HttpOnly; SameSite=Lax; Path=/",
+            REFRESH_COOKIE_NAME, token
+        ),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+}
+
+#[tokio::test]
+async fn should_return_200_and_rotate_refresh_token() {
+    let mut app = TestApp::new().await;
+
+    let old_token = login_and_get_refresh_token(&app).await;
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+
+    assert!(!auth_cookie.value().is_empty());
+
+    let refresh_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == REFRESH_COOKIE_NAME)
+        .expect("No refresh cookie found");
+
+    assert_ne!(refresh_cookie.value(), old_token);
+
+    // The old token is kept around, marked as used, so that reuse can be detected
+    let old_token = RefreshToken::parse(Secret::new(old_token)).unwrap();
+    let record = app
+        .refresh_token_store
+        .read()
+        .await
+        .get_token(&old_token)
+        .await
+        .expect("Old refresh token not found");
+
+    assert!(record.used);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_not_keep_refresh_tokens_in_redis() {
+    let mut app = TestApp::new().await;
+
+    let token = login_and_get_refresh_token(&app).await;
+
+    // Only a hash of the token is kept, so reading Redis doesn't give out working tokens
+    let mut conn = configure_redis();
+    let key = format!("refresh_token:{}", token);
+    let token_is_key: bool = redis::Commands::exists(&mut conn, &key).unwrap();
+    assert!(!token_is_key);
+    let key = format!(
+        "refresh_token:{}",
+        hash_token(&Secret::new(token.to_owned()))
+    );
+    let hash_is_key: bool = redis::Commands::exists(&mut conn, &key).unwrap();
+    assert!(hash_is_key);
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_refresh_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let body: ErrorResponse = response
+        .json()
+        .await
+        .expect("Failed to parse response body");
+    assert_eq!(body.error, "Missing auth token");
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_invalid_refresh_token() {
+    let mut app = TestApp::new().await;
+
+    // A malformed token and a well-formed token that was never issued
+    let unknown_token = RefreshToken::default();
+
+    for token in ["invalid", unknown_token.as_ref().expose_secret()] {
+        set_refresh_cookie(&app, token);
+
+        let response = app.post_refresh().await;
+        assert_eq!(
+            response.status().as_u16(),
+            401,
+            "Failed for token: {}",
+            token
+        );
+
+        let body: ErrorResponse = response
+            .json()
+            .await
+            .expect("Failed to parse response body");
+        assert_eq!(body.error, "Invalid auth token");
+    }
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_401_and_revoke_family_if_refresh_token_reused() {
+    let mut app = TestApp::new().await;
+
+    let first_token = login_and_get_refresh_token(&app).await;
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let second_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == REFRESH_COOKIE_NAME)
+        .expect("No refresh cookie found")
+        .value()
+        .to_owned();
+
+    // Replaying the already-rotated token is rejected...
+    set_refresh_cookie(&app, &first_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // ...and takes the legitimately rotated token down with it
+    set_refresh_cookie(&app, &second_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    TestApp::cleanup(&mut app).await;
+}
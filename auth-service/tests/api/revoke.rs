@@ -0,0 +1,102 @@
+use auth_service::utils::constants::{JWT_COOKIE_NAME, REFRESH_COOKIE_NAME};
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Sign up and log in a user without 2FA, returning the auth and refresh tokens issued at login
+async fn login_and_get_tokens(app: &TestApp) -> (String, String) {
+    let response = app
+        .signup_and_login(&get_random_email(), "password123")
+        .await;
+
+    let find_cookie = |name: &str| {
+        response
+            .cookies()
+            .find(|cookie| cookie.name() == name)
+            .map(|cookie| cookie.value().to_owned())
+            .expect("No cookie found")
+    };
+
+    (
+        find_cookie(JWT_COOKIE_NAME),
+        find_cookie(REFRESH_COOKIE_NAME),
+    )
+}
+
+#[tokio::test]
+async fn should_revoke_auth_token() {
+    let mut app = TestApp::new().await;
+    let (auth_token, _) = login_and_get_tokens(&app).await;
+
+    let body = serde_json::json!({ "token": auth_token, "token_type_hint": "access_token" });
+    let response = app.post_revoke(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": auth_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_revoke_refresh_token_with_or_without_hint() {
+    let mut app = TestApp::new().await;
+
+    // The hint only changes which kind of token is looked for first
+    for hint in [Some("refresh_token"), Some("access_token"), None] {
+        let (_, refresh_token) = login_and_get_tokens(&app).await;
+
+        let mut body = serde_json::json!({ "token": refresh_token });
+        if let Some(hint) = hint {
+            body["token_type_hint"] = hint.into();
+        }
+        let response = app.post_revoke(&body).await;
+        assert_eq!(response.status().as_u16(), 200);
+
+        // The refresh cookie from login is still in the cookie jar
+        let response = app.post_refresh().await;
+        assert_eq!(
+            response.status().as_u16(),
+            401,
+            "Failed for hint: {:?}",
+            hint
+        );
+    }
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_200_for_invalid_or_already_revoked_tokens() {
+    let mut app = TestApp::new().await;
+    let (auth_token, _) = login_and_get_tokens(&app).await;
+
+    let body = serde_json::json!({ "token": auth_token });
+    let response = app.post_revoke(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    for token in [auth_token.as_str(), "invalid_token", ""] {
+        let body = serde_json::json!({ "token": token });
+        let response = app.post_revoke(&body).await;
+        assert_eq!(
+            response.status().as_u16(),
+            200,
+            "Failed for token: {}",
+            token
+        );
+    }
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_422_if_token_missing() {
+    let mut app = TestApp::new().await;
+
+    let body = serde_json::json!({ "token_type_hint": "access_token" });
+    let response = app.post_revoke(&body).await;
+    assert_eq!(response.status().as_u16(), 422);
+
+    TestApp::cleanup(&mut app).await;
+}
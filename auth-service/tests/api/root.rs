@@ -10,3 +10,27 @@ async fn root_returns_auth_ui() {
 
     TestApp::cleanup(&mut app).await;
 }
+
+#[tokio::test]
+async fn root_serves_password_reset_form() {
+    let mut app = TestApp::new().await;
+
+    // Password reset emails link to the root with a `reset_token`, which the page submits
+    let response = app.get_root().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response.text().await.expect("Failed to read page");
+    assert!(page.contains(r#"id="reset-form""#));
+
+    let response = app
+        .http_client
+        .get(format!("{}/app.js", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+    let script = response.text().await.expect("Failed to read script");
+    assert!(script.contains("reset_token"));
+    assert!(script.contains("/password-reset/confirm"));
+
+    TestApp::cleanup(&mut app).await;
+}
@@ -0,0 +1,253 @@
+use auth_service::{
+    routes::ListSessionsResponse,
+    utils::constants::{JWT_COOKIE_NAME, REFRESH_COOKIE_NAME},
+    ErrorResponse,
+};
+use reqwest::Url;
+
+use crate::helpers::{get_random_email, TestApp, USER_AGENT};
+
+fn find_cookie(response: &reqwest::Response, name: &str) -> String {
+    response
+        .cookies()
+        .find(|cookie| cookie.name() == name)
+        .map(|cookie| cookie.value().to_owned())
+        .expect("No cookie found")
+}
+
+fn set_cookie(app: &TestApp, name: &str, value: &str) {
+    app.cookie_jar.add_cookie_str(
+        &format!("{}={}; HttpOnly; SameSite=Lax; Path=/", name, value),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+}
+
+async fn get_sessions(app: &TestApp) -> ListSessionsResponse {
+    let response = app.get_sessions().await;
+    assert_eq!(response.status().as_u16(), 200);
+    response
+        .json::<ListSessionsResponse>()
+        .await
+        .expect("Could not deserialize response body to ListSessionsResponse")
+}
+
+async fn login(app: &TestApp, email: &str) -> reqwest::Response {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response
+}
+
+#[tokio::test]
+async fn should_list_sessions_with_current_session_flagged() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    app.signup_and_login(&random_email, "password123").await;
+    login(&app, &random_email).await;
+
+    let sessions = get_sessions(&app).await.sessions;
+    assert_eq!(sessions.len(), 2);
+
+    // Oldest first, and the cookie jar holds the second login's cookies
+    assert!(!sessions[0].current);
+    assert!(sessions[1].current);
+    assert!(sessions[0].created_at <= sessions[1].created_at);
+
+    for session in &sessions {
+        assert_eq!(session.ip_address.as_deref(), Some("127.0.0.1"));
+        assert_eq!(session.user_agent.as_deref(), Some(USER_AGENT));
+    }
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_keep_session_across_refreshes() {
+    let mut app = TestApp::new().await;
+
+    app.signup_and_login(&get_random_email(), "password123")
+        .await;
+    let before = get_sessions(&app).await.sessions;
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let after = get_sessions(&app).await.sessions;
+    assert_eq!(after.len(), 1);
+    assert_eq!(after[0].id, before[0].id);
+    assert_eq!(after[0].created_at, before[0].created_at);
+    assert!(after[0].last_seen >= before[0].last_seen);
+    assert!(after[0].current);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_revoke_other_session() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    let response = app.signup_and_login(&random_email, "password123").await;
+    let other_auth_token = find_cookie(&response, JWT_COOKIE_NAME);
+    let other_refresh_token = find_cookie(&response, REFRESH_COOKIE_NAME);
+
+    let response = login(&app, &random_email).await;
+    let current_refresh_token = find_cookie(&response, REFRESH_COOKIE_NAME);
+
+    let sessions = get_sessions(&app).await.sessions;
+    let other_session = sessions.iter().find(|session| !session.current).unwrap();
+
+    let response = app.delete_session(&other_session.id).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The current session is untouched...
+    let sessions = get_sessions(&app).await.sessions;
+    assert_eq!(sessions.len(), 1);
+    assert!(sessions[0].current);
+
+    // ...while the other session's auth token is banned and its refresh token revoked
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": other_auth_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    set_cookie(&app, REFRESH_COOKIE_NAME, &other_refresh_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    set_cookie(&app, REFRESH_COOKIE_NAME, &current_refresh_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_reject_every_token_issued_to_revoked_session() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    let response = app.signup_and_login(&random_email, "password123").await;
+    let first_auth_token = find_cookie(&response, JWT_COOKIE_NAME);
+
+    // Refreshing moves the session on to a new auth token, leaving the first one unexpired
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let second_auth_token = find_cookie(&response, JWT_COOKIE_NAME);
+
+    login(&app, &random_email).await;
+
+    let sessions = get_sessions(&app).await.sessions;
+    let other_session = sessions.iter().find(|session| !session.current).unwrap();
+
+    let response = app.delete_session(&other_session.id).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    for token in [first_auth_token, second_auth_token] {
+        let response = app
+            .post_verify_token(&serde_json::json!({ "token": token }))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_log_out_when_revoking_current_session() {
+    let mut app = TestApp::new().await;
+
+    app.signup_and_login(&get_random_email(), "password123")
+        .await;
+    let sessions = get_sessions(&app).await.sessions;
+
+    let response = app.delete_session(&sessions[0].id).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(find_cookie(&response, JWT_COOKIE_NAME).is_empty());
+    assert!(find_cookie(&response, REFRESH_COOKIE_NAME).is_empty());
+
+    let response = app.get_sessions().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_not_list_ended_sessions() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    app.signup_and_login(&random_email, "password123").await;
+    login(&app, &random_email).await;
+
+    let response = app.post_logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    login(&app, &random_email).await;
+
+    // The logged out session is gone, leaving the first and the latest
+    let sessions = get_sessions(&app).await.sessions;
+    assert_eq!(sessions.len(), 2);
+    assert!(sessions[1].current);
+
+    // Signing out everywhere ends every session
+    let response = app.post_logout_all().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    login(&app, &random_email).await;
+    let sessions = get_sessions(&app).await.sessions;
+    assert_eq!(sessions.len(), 1);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_404_for_unknown_or_other_users_sessions() {
+    let mut app = TestApp::new().await;
+
+    // Another user's session
+    app.signup_and_login(&get_random_email(), "password123")
+        .await;
+    let other_session_id = get_sessions(&app).await.sessions[0].id.clone();
+
+    app.signup_and_login(&get_random_email(), "password123")
+        .await;
+
+    let unknown_session_id = uuid::Uuid::new_v4().to_string();
+
+    for id in [
+        other_session_id.as_str(),
+        unknown_session_id.as_str(),
+        "not-a-session-id",
+    ] {
+        let response = app.delete_session(id).await;
+        assert_eq!(response.status().as_u16(), 404, "Failed for id: {}", id);
+        assert_eq!(
+            response
+                .json::<ErrorResponse>()
+                .await
+                .expect("Could not deserialize response body to ErrorResponse")
+                .error,
+            "Session not found".to_owned()
+        );
+    }
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_sessions().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app.delete_session(&uuid::Uuid::new_v4().to_string()).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    TestApp::cleanup(&mut app).await;
+}
@@ -1,7 +1,17 @@
-use auth_service::{domain::Email, routes::SignupResponse, ErrorResponse};
+use std::sync::Arc;
+
+use auth_service::{
+    domain::{
+        BreachedPasswords, Email, PasswordPolicySettings, PasswordPolicyViolation,
+        StandardPasswordPolicy,
+    },
+    routes::SignupResponse,
+    utils::constants::RECOVERY_CODE_COUNT,
+    ErrorResponse,
+};
 use secrecy::Secret;
 
-use crate::helpers::{get_random_email, TestApp};
+use crate::helpers::{get_random_email, TestApp, BREACHED_PASSWORDS_PATH};
 
 // Tokio's test macro is used to run the test in an async environment
 #[tokio::test]
@@ -63,21 +73,20 @@ async fn should_return_201_if_valid_input() {
     let response = app.post_signup(&test_case).await;
     assert_eq!(response.status().as_u16(), 201);
 
-    let expected_response = SignupResponse {
-        message: format!(
-            "User {:?} created successfully",
-            Email::parse(Secret::new(random_email)).unwrap(), // neccessary as we can't get the exact email string from the response
-        ),
-    };
-
-    assert_eq!(
-        response
-            .json::<SignupResponse>()
-            .await
-            .expect("Failed to parse response body."),
-        expected_response
+    let expected_message = format!(
+        "User {:?} created successfully",
+        Email::parse(Secret::new(random_email)).unwrap(), // neccessary as we can't get the exact email string from the response
     );
 
+    let response_body = response
+        .json::<SignupResponse>()
+        .await
+        .expect("Failed to parse response body.");
+
+    assert_eq!(response_body.message, expected_message);
+    // Users signing up with 2FA get their recovery codes straight away
+    assert_eq!(response_body.recovery_codes.len(), RECOVERY_CODE_COUNT);
+
     TestApp::cleanup(&mut app).await;
 }
 
@@ -116,11 +125,6 @@ async fn should_return_400_if_invalid_input() {
             "password": "password123",
             "requires2FA": false
         }),
-        serde_json::json!({             // invalid password
-            "email": random_email,
-            "password": "short",
-            "requires2FA": true
-        }),
     ];
 
     for test_case in test_cases.iter() {
@@ -178,3 +182,120 @@ async fn should_return_409_if_email_already_exists() {
 
     TestApp::cleanup(&mut app).await;
 }
+
+#[tokio::test]
+async fn should_return_201_and_email_owner_if_email_already_exists_in_enumeration_safe_mode() {
+    let mut app = TestApp::with_app_state(|state| state.enumeration_safe = true).await;
+
+    let random_email = get_random_email();
+
+    let test_case = serde_json::json!({
+          "email": random_email,
+          "password": "password123",
+          "requires2FA": true
+    });
+
+    let response = app.post_signup(&test_case).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let first_signup = response
+        .json::<SignupResponse>()
+        .await
+        .expect("Could not deserialize response body to SignupResponse");
+
+    // The second signup looks just like the first
+    let response = app.post_signup(&test_case).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let second_signup = response
+        .json::<SignupResponse>()
+        .await
+        .expect("Could not deserialize response body to SignupResponse");
+    assert_eq!(second_signup.message, first_signup.message);
+    assert_eq!(second_signup.recovery_codes.len(), RECOVERY_CODE_COUNT);
+    assert_ne!(second_signup.recovery_codes, first_signup.recovery_codes);
+
+    // The owner of the account is told about it instead
+    let requests = app.email_server.received_requests().await.unwrap();
+    let owner_emails = requests
+        .iter()
+        .filter_map(|request| request.body_json::<serde_json::Value>().ok())
+        .filter(|body| body["To"] == random_email.as_str())
+        .map(|body| body["Subject"].as_str().unwrap().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        owner_emails,
+        ["Verify your email address", "You already have an account"]
+    );
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_400_with_reasons_if_password_does_not_meet_policy() {
+    let mut app = TestApp::with_app_state(|state| {
+        let breached_passwords = BreachedPasswords::from_file(BREACHED_PASSWORDS_PATH).unwrap();
+        state.password_policy = Arc::new(StandardPasswordPolicy::new(
+            PasswordPolicySettings::default(),
+            Some(breached_passwords),
+        ));
+    })
+    .await;
+
+    let random_email = get_random_email();
+    let local_part = random_email.split('@').next().unwrap();
+
+    let test_cases = [
+        (
+            "password123".to_owned(),
+            vec![PasswordPolicyViolation::TooWeak {
+                score: 1,
+                min_score: 2,
+            }],
+        ),
+        (
+            format!("x7#Qm9!vL{}", local_part),
+            vec![PasswordPolicyViolation::ContainsEmail],
+        ),
+        (
+            "correct horse battery staple".to_owned(),
+            vec![PasswordPolicyViolation::Breached],
+        ),
+        (
+            "x7#Qm9!".to_owned(),
+            vec![PasswordPolicyViolation::TooShort { min_length: 8 }],
+        ),
+        (
+            "x7#Qm9!vLp2@".repeat(11),
+            vec![PasswordPolicyViolation::TooLong { max_length: 128 }],
+        ),
+    ];
+
+    for (password, reasons) in test_cases {
+        let response = app
+            .post_signup(&serde_json::json!({
+                "email": random_email,
+                "password": password,
+                "requires2FA": false
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 400, "{}", password);
+
+        let body = response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse");
+        assert_eq!(body.error, "Password does not meet requirements");
+        assert_eq!(body.reasons, reasons, "{}", password);
+    }
+
+    // None of the rejected passwords created the account
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": random_email,
+            "password": "x7#Qm9!vLp2@",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    TestApp::cleanup(&mut app).await;
+}
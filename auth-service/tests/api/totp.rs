@@ -0,0 +1,295 @@
+use auth_service::{
+    routes::{ConfirmTotpResponse, EnrollTotpResponse, TwoFAMethod, TwoFactorAuthResponse},
+    services::TotpSecret,
+    utils::{
+        constants::{MAX_TWO_FA_ATTEMPTS, RECOVERY_CODE_COUNT, TOTP_STEP_SECONDS},
+        totp::generate_totp_code,
+    },
+    ErrorResponse,
+};
+use secrecy::Secret;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{get_random_email, TestApp};
+
+fn now() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
+fn totp_code(secret: &str, time: u64) -> String {
+    let secret = TotpSecret::parse(Secret::new(secret.to_owned())).unwrap();
+    generate_totp_code(&secret, time).unwrap()
+}
+
+// Log in, enroll and confirm an authenticator, returning its secret and the confirmation code
+async fn enroll_totp(app: &TestApp, email: &str) -> (String, String) {
+    app.signup_and_login(email, "password123").await;
+
+    let response = app
+        .post_totp_enroll(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body: EnrollTotpResponse = response
+        .json()
+        .await
+        .expect("Could not deserialize response body to EnrollTotpResponse");
+
+    let code = totp_code(&body.secret, now());
+
+    let response = app
+        .post_totp_confirm(&serde_json::json!({ "code": code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let confirm_body: ConfirmTotpResponse = response
+        .json()
+        .await
+        .expect("Could not deserialize response body to ConfirmTotpResponse");
+    assert_eq!(confirm_body.recovery_codes.len(), RECOVERY_CODE_COUNT);
+
+    (body.secret, code)
+}
+
+async fn login_with_totp(app: &TestApp, email: &str) -> String {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let body: TwoFactorAuthResponse = response
+        .json()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+    assert_eq!(body.two_fa_method, TwoFAMethod::Totp);
+
+    body.login_attempt_id
+}
+
+#[tokio::test]
+async fn should_return_otpauth_uri_on_enroll() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    app.signup_and_login(&random_email, "password123").await;
+
+    let response = app
+        .post_totp_enroll(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body: EnrollTotpResponse = response
+        .json()
+        .await
+        .expect("Could not deserialize response body to EnrollTotpResponse");
+
+    assert!(TotpSecret::parse(Secret::new(body.secret.clone())).is_ok());
+    assert!(body.otpauth_uri.starts_with("otpauth://totp/"));
+    assert!(body
+        .otpauth_uri
+        .contains(&format!("secret={}", body.secret)));
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_not_logged_in() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_totp_enroll(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .post_totp_confirm(&serde_json::json!({ "code": "123456" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_incorrect_confirmation_code() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    app.signup_and_login(&random_email, "password123").await;
+
+    let response = app
+        .post_totp_enroll(&serde_json::json!({ "password": "password123" }))
+        .await;
+    let body: EnrollTotpResponse = response
+        .json()
+        .await
+        .expect("Could not deserialize response body to EnrollTotpResponse");
+
+    // A code from well outside the drift window
+    let stale_code = totp_code(&body.secret, now() - 10 * TOTP_STEP_SECONDS);
+
+    let response = app
+        .post_totp_confirm(&serde_json::json!({ "code": stale_code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The user still logs in without 2FA until the authenticator is confirmed
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_enrolling_with_wrong_password() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    app.signup_and_login(&random_email, "password123").await;
+
+    let response = app
+        .post_totp_enroll(&serde_json::json!({ "password": "wrongpassword" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_429_after_too_many_incorrect_confirmation_codes() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    app.signup_and_login(&random_email, "password123").await;
+
+    let response = app
+        .post_totp_enroll(&serde_json::json!({ "password": "password123" }))
+        .await;
+    let body: EnrollTotpResponse = response
+        .json()
+        .await
+        .expect("Could not deserialize response body to EnrollTotpResponse");
+
+    let stale_code = totp_code(&body.secret, now() - 10 * TOTP_STEP_SECONDS);
+
+    for _ in 1..MAX_TWO_FA_ATTEMPTS {
+        let response = app
+            .post_totp_confirm(&serde_json::json!({ "code": stale_code }))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    let response = app
+        .post_totp_confirm(&serde_json::json!({ "code": stale_code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    // Once locked out, even the right code is refused
+    let code = totp_code(&body.secret, now());
+    let response = app
+        .post_totp_confirm(&serde_json::json!({ "code": code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_409_if_already_enrolled() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    enroll_totp(&app, &random_email).await;
+
+    let response = app
+        .post_totp_enroll(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+
+    let body: ErrorResponse = response
+        .json()
+        .await
+        .expect("Failed to parse response body");
+    assert_eq!(body.error, "TOTP already enrolled");
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_login_with_totp_code_instead_of_email() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    let (secret, _) = enroll_totp(&app, &random_email).await;
+
+    // No code is emailed to users with an authenticator
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let login_attempt_id = login_with_totp(&app, &random_email).await;
+
+    // The code for the current step was used to confirm enrollment, so use the next one
+    let code = totp_code(&secret, now() + TOTP_STEP_SECONDS);
+
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": code,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_totp_code_replayed() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    let (secret, confirmation_code) = enroll_totp(&app, &random_email).await;
+
+    // The code that confirmed enrollment can't be used to log in
+    let login_attempt_id = login_with_totp(&app, &random_email).await;
+
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": confirmation_code,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Nor can a code be used for two logins
+    let code = totp_code(&secret, now() + TOTP_STEP_SECONDS);
+
+    for expected_status in [200, 401] {
+        let login_attempt_id = login_with_totp(&app, &random_email).await;
+
+        let response = app
+            .post_verify_2fa(&serde_json::json!({
+                "email": random_email,
+                "loginAttemptId": login_attempt_id,
+                "2FACode": code,
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), expected_status);
+    }
+
+    TestApp::cleanup(&mut app).await;
+}
@@ -0,0 +1,91 @@
+use auth_service::{
+    services::PostgresUserStore,
+    utils::{
+        constants::{PASSWORD_HASH_PARAMS, PASSWORD_PEPPERS},
+        user_import::{parse_user_import, UserImportFormat, UserImportSummary},
+    },
+};
+
+use crate::helpers::{get_random_email, TestApp, ACTIVE_PEPPER_ID};
+
+// PBKDF2-SHA256 of "password123", as Django stores it
+const DJANGO_PBKDF2_HASH: &str =
+    "pbkdf2_sha256$1000$seasalt$DKtn4wN1JA5g5IiTPMBbOfQEYX4cfOdbEPpqC26lBfU=";
+
+#[tokio::test]
+async fn should_log_in_imported_users_and_upgrade_their_hashes() {
+    let mut app = TestApp::new().await;
+
+    let bcrypt_email = get_random_email();
+    let pbkdf2_email = get_random_email();
+    let contents = format!(
+        "email,hash,requires_2fa\n{},{},false\n{},{},false\n",
+        bcrypt_email,
+        bcrypt::hash("password123", 4).unwrap(),
+        pbkdf2_email,
+        DJANGO_PBKDF2_HASH
+    );
+    let users = parse_user_import(&contents, UserImportFormat::Csv).unwrap();
+
+    let mut user_store = PostgresUserStore::new(
+        app.pg_pool.clone(),
+        PASSWORD_HASH_PARAMS.clone(),
+        PASSWORD_PEPPERS.clone(),
+    );
+    let summary = user_store.import_users(&users).await.unwrap();
+    assert_eq!(
+        summary,
+        UserImportSummary {
+            imported: 2,
+            skipped: 0
+        }
+    );
+
+    for email in [&bcrypt_email, &pbkdf2_email] {
+        let response = app
+            .post_login(&serde_json::json!({
+                "email": email,
+                "password": "password1234",
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+
+        let response = app
+            .post_login(&serde_json::json!({
+                "email": email,
+                "password": "password123",
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+
+        // The hash is upgraded to Argon2id with the active pepper, and still works
+        let (password_hash, pepper_id): (String, Option<String>) =
+            sqlx::query_as("SELECT password_hash, password_pepper_id FROM users WHERE email = $1")
+                .bind(email)
+                .fetch_one(&app.pg_pool)
+                .await
+                .unwrap();
+        assert!(password_hash.starts_with("$argon2id$"));
+        assert_eq!(pepper_id.as_deref(), Some(ACTIVE_PEPPER_ID));
+
+        let response = app
+            .post_login(&serde_json::json!({
+                "email": email,
+                "password": "password123",
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // Importing the same users again leaves their accounts alone
+    let summary = user_store.import_users(&users).await.unwrap();
+    assert_eq!(
+        summary,
+        UserImportSummary {
+            imported: 0,
+            skipped: 2
+        }
+    );
+
+    TestApp::cleanup(&mut app).await;
+}
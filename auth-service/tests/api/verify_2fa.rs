@@ -1,11 +1,10 @@
 use auth_service::{
-    domain::Email,
     routes::TwoFactorAuthResponse,
     services::{LoginAttemptId, TwoFACode},
-    utils::constants::JWT_COOKIE_NAME,
+    utils::constants::{JWT_COOKIE_NAME, MAX_TWO_FA_ATTEMPTS},
     ErrorResponse,
 };
-use secrecy::{ExposeSecret, Secret};
+use secrecy::ExposeSecret;
 use wiremock::{
     matchers::{method, path},
     Mock, ResponseTemplate,
@@ -26,6 +25,7 @@ async fn should_return_200_if_correct_2fa_code() {
 
     let response = app.post_signup(&signup_body).await;
     assert_eq!(response.status().as_u16(), 201);
+    app.verify_email(&random_email).await;
 
     Mock::given(path("/email"))
         .and(method("POST"))
@@ -56,7 +56,7 @@ async fn should_return_200_if_correct_2fa_code() {
         .two_fa_code_store
         .read()
         .await
-        .get_code(&Email::parse(Secret::new(random_email.clone())).unwrap())
+        .get_code(&app.get_user_id(&random_email).await)
         .await
         .unwrap();
 
@@ -144,6 +144,7 @@ async fn should_return_401_if_incorrect_credentials() {
     let response = app.post_signup(&signup_body).await;
 
     assert_eq!(response.status().as_u16(), 201);
+    app.verify_email(&random_email).await;
 
     // --------------------------
 
@@ -177,7 +178,7 @@ async fn should_return_401_if_incorrect_credentials() {
         .two_fa_code_store
         .read()
         .await
-        .get_code(&Email::parse(Secret::new(random_email.clone())).unwrap())
+        .get_code(&app.get_user_id(&random_email).await)
         .await
         .unwrap();
 
@@ -251,6 +252,7 @@ async fn should_return_401_if_old_code() {
     let response = app.post_signup(&signup_body).await;
 
     assert_eq!(response.status().as_u16(), 201);
+    app.verify_email(&random_email).await;
 
     Mock::given(path("/email"))
         .and(method("POST"))
@@ -283,7 +285,7 @@ async fn should_return_401_if_old_code() {
         .two_fa_code_store
         .read()
         .await
-        .get_code(&Email::parse(Secret::new(random_email.clone())).unwrap())
+        .get_code(&app.get_user_id(&random_email).await)
         .await
         .unwrap();
 
@@ -325,6 +327,7 @@ async fn should_return_401_if_same_code_twice() {
     let response = app.post_signup(&signup_body).await;
 
     assert_eq!(response.status().as_u16(), 201);
+    app.verify_email(&random_email).await;
 
     Mock::given(path("/email"))
         .and(method("POST"))
@@ -356,7 +359,7 @@ async fn should_return_401_if_same_code_twice() {
         .two_fa_code_store
         .read()
         .await
-        .get_code(&Email::parse(Secret::new(random_email.clone())).unwrap())
+        .get_code(&app.get_user_id(&random_email).await)
         .await
         .unwrap();
 
@@ -442,3 +445,77 @@ async fn should_return_422_if_malformed_input() {
 
     TestApp::cleanup(&mut app).await;
 }
+
+#[tokio::test]
+async fn should_return_429_and_discard_code_after_too_many_wrong_codes() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": true
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+    app.verify_email(&random_email).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123"
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    let code = app
+        .two_fa_code_store
+        .read()
+        .await
+        .get_code(&app.get_user_id(&random_email).await)
+        .await
+        .unwrap()
+        .1
+        .as_ref()
+        .expose_secret()
+        .to_owned();
+    let wrong_code = format!("{:06}", (code.parse::<u32>().unwrap() + 1) % 1_000_000);
+
+    let wrong_body = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": wrong_code,
+    });
+    for _ in 1..MAX_TWO_FA_ATTEMPTS {
+        let response = app.post_verify_2fa(&wrong_body).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    let response = app.post_verify_2fa(&wrong_body).await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    // The code is gone, so the user has to log in again
+    let right_body = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code,
+    });
+    let response = app.post_verify_2fa(&right_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    TestApp::cleanup(&mut app).await;
+}
@@ -0,0 +1,223 @@
+use std::time::Duration;
+
+use auth_service::{utils::constants::VERIFICATION_EMAIL_LIMIT, ErrorResponse};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{get_random_email, TestApp};
+
+async fn signup(app: &TestApp, email: &str) {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+async fn mount_email_mock(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+async fn login(app: &TestApp, email: &str, password: &str) -> reqwest::Response {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": password,
+    });
+
+    app.post_login(&login_body).await
+}
+
+#[tokio::test]
+async fn should_send_verification_email_on_signup() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    signup(&app, &random_email).await;
+
+    let token = app
+        .get_verification_token_from_email(&random_email)
+        .await
+        .expect("No verification email sent");
+    assert_eq!(token.len(), 64);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_403_if_email_not_verified() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    signup(&app, &random_email).await;
+
+    let response = login(&app, &random_email, "password123").await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    let body: ErrorResponse = response
+        .json()
+        .await
+        .expect("Failed to parse response body");
+    assert_eq!(body.error, "Email not verified");
+
+    // The verification state is only revealed to someone who knows the password
+    let response = login(&app, &random_email, "wrong_password").await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_allow_login_after_email_verified() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    signup(&app, &random_email).await;
+
+    let token = app
+        .get_verification_token_from_email(&random_email)
+        .await
+        .expect("No verification email sent");
+
+    let response = app.get_verify_email(&token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = login(&app, &random_email, "password123").await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Verification links can only be used once
+    let response = app.get_verify_email(&token).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_invalid_verification_token() {
+    let mut app = TestApp::new().await;
+
+    let test_cases = ["invalid", &"a".repeat(64)];
+
+    for token in test_cases {
+        let response = app.get_verify_email(token).await;
+        assert_eq!(
+            response.status().as_u16(),
+            401,
+            "Failed for token: {}",
+            token
+        );
+
+        let body: ErrorResponse = response
+            .json()
+            .await
+            .expect("Failed to parse response body");
+        assert_eq!(body.error, "Invalid auth token");
+    }
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_resend_verification_email() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    mount_email_mock(&app).await;
+    signup(&app, &random_email).await;
+    let first_token = app
+        .get_verification_token_from_email(&random_email)
+        .await
+        .expect("No verification email sent");
+
+    let response = app
+        .post_resend_verification_email(&serde_json::json!({ "email": random_email }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The email is sent after the response, so wait a little for it to arrive
+    let mut second_token = first_token.clone();
+    for _ in 0..50 {
+        second_token = app
+            .get_verification_token_from_email(&random_email)
+            .await
+            .expect("No verification email sent");
+        if second_token != first_token {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_ne!(first_token, second_token);
+
+    let response = app.get_verify_email(&second_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_not_resend_verification_email_if_unknown_or_verified() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    signup(&app, &random_email).await;
+    app.verify_email(&random_email).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    for email in [random_email, get_random_email()] {
+        let response = app
+            .post_resend_verification_email(&serde_json::json!({ "email": email }))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_limit_verification_emails_without_saying_so() {
+    let mut app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    // The email sent at signup counts towards the limit
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(VERIFICATION_EMAIL_LIMIT)
+        .mount(&app.email_server)
+        .await;
+    signup(&app, &random_email).await;
+
+    // Going over the limit looks the same as any other request, so it doesn't give away that
+    // the address has an account waiting to be verified
+    let resend_body = serde_json::json!({ "email": random_email });
+    for _ in 0..VERIFICATION_EMAIL_LIMIT {
+        let response = app.post_resend_verification_email(&resend_body).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // Give the last emails time to be sent, or not
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    TestApp::cleanup(&mut app).await;
+}
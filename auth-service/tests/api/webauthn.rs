@@ -0,0 +1,440 @@
+use auth_service::{
+    routes::{
+        FinishWebAuthnRegistrationResponse, RegenerateRecoveryCodesResponse,
+        StartWebAuthnRegistrationResponse, TwoFAMethod, TwoFactorAuthResponse,
+    },
+    utils::{
+        constants::{JWT_COOKIE_NAME, RECOVERY_CODE_COUNT, WEBAUTHN_ORIGIN, WEBAUTHN_RP_ID},
+        webauthn::{PublicKeyCredentialCreationOptions, PublicKeyCredentialRequestOptions},
+    },
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL, Engine};
+use ciborium::Value;
+use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+use secrecy::ExposeSecret;
+use sha2::{Digest, Sha256};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{get_random_email, TestApp};
+
+// User present, user verified and, when registering, attested credential data included
+const FLAGS_ASSERTION: u8 = 0x05;
+const FLAGS_REGISTRATION: u8 = 0x45;
+
+// A software stand-in for a security key or platform authenticator, producing the same
+// responses a browser would return from `navigator.credentials`
+#[derive(Clone)]
+struct SoftwareAuthenticator {
+    signing_key: SigningKey,
+    credential_id: Vec<u8>,
+    sign_count: u32,
+    origin: String,
+}
+
+impl SoftwareAuthenticator {
+    fn new() -> Self {
+        Self {
+            signing_key: SigningKey::random(&mut p256::elliptic_curve::rand_core::OsRng),
+            credential_id: rand::random::<[u8; 16]>().to_vec(),
+            sign_count: 0,
+            origin: WEBAUTHN_ORIGIN.to_owned(),
+        }
+    }
+
+    fn register(&self, options: &PublicKeyCredentialCreationOptions) -> serde_json::Value {
+        let client_data_json = self.client_data_json("webauthn.create", &options.challenge);
+
+        let point = self.signing_key.verifying_key().to_encoded_point(false);
+        let cose_key = Value::Map(vec![
+            (Value::from(1), Value::from(2)),
+            (Value::from(3), Value::from(-7)),
+            (Value::from(-1), Value::from(1)),
+            (Value::from(-2), Value::from(point.x().unwrap().to_vec())),
+            (Value::from(-3), Value::from(point.y().unwrap().to_vec())),
+        ]);
+
+        let mut auth_data = self.auth_data(&options.rp.id, FLAGS_REGISTRATION);
+        auth_data.extend_from_slice(&[0; 16]); // AAGUID
+        auth_data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+        auth_data.extend_from_slice(&self.credential_id);
+        ciborium::into_writer(&cose_key, &mut auth_data).unwrap();
+
+        let attestation_object = Value::Map(vec![
+            (Value::from("fmt"), Value::from("none")),
+            (Value::from("attStmt"), Value::Map(vec![])),
+            (Value::from("authData"), Value::from(auth_data)),
+        ]);
+        let mut attestation_object_bytes = Vec::new();
+        ciborium::into_writer(&attestation_object, &mut attestation_object_bytes).unwrap();
+
+        serde_json::json!({
+            "id": BASE64_URL.encode(&self.credential_id),
+            "rawId": BASE64_URL.encode(&self.credential_id),
+            "type": "public-key",
+            "response": {
+                "clientDataJSON": BASE64_URL.encode(client_data_json),
+                "attestationObject": BASE64_URL.encode(attestation_object_bytes),
+            },
+        })
+    }
+
+    fn authenticate(&mut self, options: &PublicKeyCredentialRequestOptions) -> serde_json::Value {
+        self.sign_count += 1;
+
+        let client_data_json = self.client_data_json("webauthn.get", &options.challenge);
+        let auth_data = self.auth_data(&options.rp_id, FLAGS_ASSERTION);
+
+        let mut signed_data = auth_data.clone();
+        signed_data.extend_from_slice(&Sha256::digest(client_data_json.as_bytes()));
+        let signature: Signature = self.signing_key.sign(&signed_data);
+
+        serde_json::json!({
+            "id": BASE64_URL.encode(&self.credential_id),
+            "rawId": BASE64_URL.encode(&self.credential_id),
+            "type": "public-key",
+            "response": {
+                "clientDataJSON": BASE64_URL.encode(client_data_json),
+                "authenticatorData": BASE64_URL.encode(auth_data),
+                "signature": BASE64_URL.encode(signature.to_der()),
+            },
+        })
+    }
+
+    fn client_data_json(&self, type_: &str, challenge: &str) -> String {
+        serde_json::json!({
+            "type": type_,
+            "challenge": challenge,
+            "origin": self.origin,
+            "crossOrigin": false,
+        })
+        .to_string()
+    }
+
+    fn auth_data(&self, rp_id: &str, flags: u8) -> Vec<u8> {
+        let mut auth_data = Sha256::digest(rp_id.as_bytes()).to_vec();
+        auth_data.push(flags);
+        auth_data.extend_from_slice(&self.sign_count.to_be_bytes());
+        auth_data
+    }
+}
+
+async fn start_registration(app: &TestApp) -> PublicKeyCredentialCreationOptions {
+    let response = app
+        .post_webauthn_register_start(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    response
+        .json::<StartWebAuthnRegistrationResponse>()
+        .await
+        .expect("Could not deserialize response body to StartWebAuthnRegistrationResponse")
+        .public_key
+}
+
+// Log in, register a passkey and log out again, leaving the user with WebAuthn as their 2FA
+async fn register_passkey(app: &TestApp, email: &str) -> SoftwareAuthenticator {
+    app.signup_and_login(email, "password123").await;
+
+    let authenticator = SoftwareAuthenticator::new();
+    let options = start_registration(app).await;
+
+    let response = app
+        .post_webauthn_register_finish(&authenticator.register(&options))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    authenticator
+}
+
+// Log in with the password and return the login attempt ID and the WebAuthn challenge
+async fn login(app: &TestApp, email: &str) -> (String, PublicKeyCredentialRequestOptions) {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let body: TwoFactorAuthResponse = response
+        .json()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+    assert_eq!(body.two_fa_method, TwoFAMethod::WebAuthn);
+
+    let options = body.public_key.expect("No WebAuthn challenge in response");
+    (body.login_attempt_id, options)
+}
+
+async fn verify(
+    app: &TestApp,
+    email: &str,
+    login_attempt_id: &str,
+    credential: serde_json::Value,
+) -> reqwest::Response {
+    let body = serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id,
+        "credential": credential,
+    });
+
+    app.post_webauthn_verify(&body).await
+}
+
+#[tokio::test]
+async fn should_return_creation_options_on_register_start() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    app.signup_and_login(&email, "password123").await;
+
+    let options = start_registration(&app).await;
+    assert_eq!(options.rp.id, *WEBAUTHN_RP_ID);
+    assert_eq!(options.user.name, email);
+    assert_eq!(options.pub_key_cred_params[0].alg, -7);
+    assert_eq!(BASE64_URL.decode(&options.challenge).unwrap().len(), 32);
+    assert!(options.exclude_credentials.is_empty());
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_recovery_codes_for_first_passkey_only() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    app.signup_and_login(&email, "password123").await;
+
+    for expected_codes in [RECOVERY_CODE_COUNT, 0] {
+        let authenticator = SoftwareAuthenticator::new();
+        let options = start_registration(&app).await;
+
+        let response = app
+            .post_webauthn_register_finish(&authenticator.register(&options))
+            .await;
+        assert_eq!(response.status().as_u16(), 201);
+
+        let body: FinishWebAuthnRegistrationResponse = response
+            .json()
+            .await
+            .expect("Could not deserialize response body to FinishWebAuthnRegistrationResponse");
+        assert_eq!(body.recovery_codes.len(), expected_codes);
+    }
+
+    // Existing passkeys are excluded so the same authenticator isn't registered twice
+    let options = start_registration(&app).await;
+    assert_eq!(options.exclude_credentials.len(), 2);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_regenerate_recovery_codes_for_passkey_users() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    app.signup_and_login(&email, "password123").await;
+
+    let authenticator = SoftwareAuthenticator::new();
+    let options = start_registration(&app).await;
+
+    let response = app
+        .post_webauthn_register_finish(&authenticator.register(&options))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let issued: FinishWebAuthnRegistrationResponse = response
+        .json()
+        .await
+        .expect("Could not deserialize response body to FinishWebAuthnRegistrationResponse");
+
+    // A passkey is the user's only 2FA method, so the codes it came with can be replaced
+    let response = app
+        .post_recovery_codes(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body: RegenerateRecoveryCodesResponse = response
+        .json()
+        .await
+        .expect("Could not deserialize response body to RegenerateRecoveryCodesResponse");
+    assert_eq!(body.recovery_codes.len(), RECOVERY_CODE_COUNT);
+    assert_ne!(body.recovery_codes, issued.recovery_codes);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_login_with_passkey_instead_of_email_code() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    let mut authenticator = register_passkey(&app, &email).await;
+
+    // No code is emailed to passkey users
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let (login_attempt_id, options) = login(&app, &email).await;
+    assert_eq!(options.rp_id, *WEBAUTHN_RP_ID);
+    assert_eq!(
+        options.allow_credentials[0].id,
+        BASE64_URL.encode(&authenticator.credential_id)
+    );
+
+    let credential = authenticator.authenticate(&options);
+    let response = verify(&app, &email, &login_attempt_id, credential).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+    assert!(!auth_cookie.value().is_empty());
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_signed_by_another_key() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    let authenticator = register_passkey(&app, &email).await;
+
+    // Same credential ID, different private key
+    let mut impostor = SoftwareAuthenticator::new();
+    impostor.credential_id = authenticator.credential_id.clone();
+
+    let (login_attempt_id, options) = login(&app, &email).await;
+    let response = verify(
+        &app,
+        &email,
+        &login_attempt_id,
+        impostor.authenticate(&options),
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_origin_does_not_match() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    let mut authenticator = register_passkey(&app, &email).await;
+
+    // A phishing site relaying the challenge can't make the browser report our origin
+    authenticator.origin = "https://auth-service.example.com".to_owned();
+
+    let (login_attempt_id, options) = login(&app, &email).await;
+    let credential = authenticator.authenticate(&options);
+    let response = verify(&app, &email, &login_attempt_id, credential).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_signature_counter_goes_backwards() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    let mut authenticator = register_passkey(&app, &email).await;
+    let mut clone = authenticator.clone();
+
+    let (login_attempt_id, options) = login(&app, &email).await;
+    let credential = authenticator.authenticate(&options);
+    let response = verify(&app, &email, &login_attempt_id, credential).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // A cloned authenticator doesn't know the counter has moved on
+    let (login_attempt_id, options) = login(&app, &email).await;
+    let credential = clone.authenticate(&options);
+    let response = verify(&app, &email, &login_attempt_id, credential).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_reject_2fa_code_for_passkey_users() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    register_passkey(&app, &email).await;
+
+    let (login_attempt_id, _) = login(&app, &email).await;
+
+    // The code generated at login is never sent to passkey users, so it must not work either
+    let code_tuple = app
+        .two_fa_code_store
+        .read()
+        .await
+        .get_code(&app.get_user_id(&email).await)
+        .await
+        .unwrap();
+
+    let verify_2fa_body = serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code_tuple.1.as_ref().expose_secret(),
+    });
+
+    let response = app.post_verify_2fa(&verify_2fa_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_registering_with_wrong_password() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    app.signup_and_login(&email, "password123").await;
+
+    let response = app
+        .post_webauthn_register_start(&serde_json::json!({ "password": "wrongpassword" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    TestApp::cleanup(&mut app).await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_not_logged_in() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_webauthn_register_start(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let credential = serde_json::json!({
+        "id": "AAAA",
+        "rawId": "AAAA",
+        "type": "public-key",
+        "response": {
+            "clientDataJSON": "",
+            "attestationObject": "",
+        },
+    });
+
+    let response = app.post_webauthn_register_finish(&credential).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    TestApp::cleanup(&mut app).await;
+}